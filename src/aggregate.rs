@@ -0,0 +1,247 @@
+use futures_util::future::join_all;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::mcp_process::{McpRequest, McpServer, McpServersConfig, ServerType};
+
+// JSON-RPC の標準エラーコード
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INTERNAL_ERROR: i64 = -32603;
+
+// --- type: "aggregate" のサーバーの設定 ---
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct AggregateConfig {
+    // 束ねるサーバーの名前 (同じ設定ファイル内のキー)
+    #[serde(default)]
+    pub servers: Vec<String>,
+    // ツール名の名前空間の区切り文字 ("github" + "." + "create_issue")
+    #[serde(default = "default_separator")]
+    pub separator: String,
+    // 起動時にセットアップ (post_install / pre_start / initialize) を並行して行うメンバー数
+    #[serde(default = "default_setup_concurrency")]
+    pub setup_concurrency: usize,
+    // 設定ファイルの読み込み時に servers から解決したメンバーの設定
+    #[serde(skip)]
+    pub members: Vec<(String, crate::mcp_process::McpProcessConfig)>,
+}
+
+fn default_separator() -> String {
+    ".".to_string()
+}
+
+fn default_setup_concurrency() -> usize {
+    4
+}
+
+// 集約サーバーの servers を同じ設定ファイルの他のエントリから解決する
+pub fn resolve_members(configs: &mut McpServersConfig) -> Result<(), String> {
+    let snapshot = configs.clone();
+    for (key, config) in configs
+        .iter_mut()
+        .filter(|(_, config)| config.server_type == ServerType::Aggregate)
+    {
+        config.aggregate.members = config
+            .aggregate
+            .servers
+            .iter()
+            .map(|member| match snapshot.get(member) {
+                None => Err(format!(
+                    "Aggregate server '{}' refers to unknown server '{}'",
+                    key, member
+                )),
+                Some(member_config) if member_config.server_type == ServerType::Aggregate => {
+                    Err(format!(
+                        "Aggregate server '{}' cannot include aggregate server '{}'",
+                        key, member
+                    ))
+                }
+                Some(member_config) => Ok((member.clone(), member_config.clone())),
+            })
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(())
+}
+
+// --- 複数の MCP サーバーを1つにまとめる仮想サーバー ---
+// tools/list は全メンバーに問い合わせてツール名に "<サーバー名><separator>" を付けて返し、
+// tools/call はツール名の接頭辞でメンバーを選んで転送する。応答は inbound に送る
+pub struct Aggregator {
+    server_key: String,
+    separator: String,
+    members: Vec<Arc<McpServer>>,
+    inbound: mpsc::Sender<String>,
+}
+
+impl Aggregator {
+    pub fn new(
+        server_key: &str,
+        separator: &str,
+        members: Vec<Arc<McpServer>>,
+        inbound: mpsc::Sender<String>,
+    ) -> Self {
+        Aggregator {
+            server_key: server_key.to_string(),
+            separator: separator.to_string(),
+            members,
+            inbound,
+        }
+    }
+
+    // メッセージを受け付け、応答はバックグラウンドで組み立てて inbound に送る
+    pub fn send(self: &Arc<Self>, message: &str) -> Result<(), String> {
+        let message: Value = serde_json::from_str(message)
+            .map_err(|e| format!("Invalid JSON-RPC message for aggregate server: {}", e))?;
+        let aggregator = Arc::clone(self);
+        tokio::spawn(async move {
+            let response = match message {
+                Value::Array(batch) => {
+                    let responses: Vec<Value> =
+                        join_all(batch.iter().map(|message| aggregator.handle(message)))
+                            .await
+                            .into_iter()
+                            .flatten()
+                            .collect();
+                    (!responses.is_empty()).then_some(Value::Array(responses))
+                }
+                message => aggregator.handle(&message).await,
+            };
+            if let Some(response) = response {
+                let _ = aggregator.inbound.send(response.to_string()).await;
+            }
+        });
+        Ok(())
+    }
+
+    // メンバーのサーバーを停止する
+    pub async fn close(&self) {
+        for member in &self.members {
+            if let Err(e) = member.stop().await {
+                debug!(server = %self.server_key, member = %member.server_key, error = %e, "Aggregate member was not running");
+            }
+        }
+    }
+
+    // 1つのリクエストに対する応答 (通知と応答には何も返さない)
+    async fn handle(&self, message: &Value) -> Option<Value> {
+        let id = message.get("id").filter(|id| !id.is_null())?.clone();
+        let method = message.get("method").and_then(Value::as_str)?;
+        let result = match method {
+            "initialize" => Ok(self.initialize_result(message)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": self.list_tools().await })),
+            "tools/call" => return Some(self.call_tool(id, message).await),
+            _ => Err((
+                METHOD_NOT_FOUND,
+                format!(
+                    "Method '{}' is not supported by aggregate server '{}'",
+                    method, self.server_key
+                ),
+            )),
+        };
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, message),
+        })
+    }
+
+    fn initialize_result(&self, request: &Value) -> Value {
+        let protocol_version = request
+            .pointer("/params/protocolVersion")
+            .cloned()
+            .unwrap_or_else(|| json!("2025-03-26"));
+        json!({
+            "protocolVersion": protocol_version,
+            "capabilities": { "tools": {} },
+            "serverInfo": {
+                "name": self.server_key,
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        })
+    }
+
+    // 全メンバーのツールを名前空間付きで返す (失敗したメンバーのツールは含めない)
+    async fn list_tools(&self) -> Vec<Value> {
+        let lists = join_all(
+            self.members
+                .iter()
+                .map(|member| member.list_all("tools/list", "tools")),
+        )
+        .await;
+        self.members
+            .iter()
+            .zip(lists)
+            .flat_map(|(member, tools)| {
+                let tools = tools.unwrap_or_else(|e| {
+                    warn!(server = %self.server_key, member = %member.server_key, error = %e, "Failed to list tools of aggregate member");
+                    Vec::new()
+                });
+                tools.into_iter().map(move |mut tool| {
+                    let name = tool.get("name").and_then(Value::as_str).unwrap_or_default();
+                    tool["name"] =
+                        format!("{}{}{}", member.server_key, self.separator, name).into();
+                    tool
+                })
+            })
+            .collect()
+    }
+
+    // ツール名の接頭辞からメンバーと元のツール名を求める
+    fn route<'a>(&self, name: &'a str) -> Option<(&Arc<McpServer>, &'a str)> {
+        self.members.iter().find_map(|member| {
+            name.strip_prefix(member.server_key.as_str())?
+                .strip_prefix(self.separator.as_str())
+                .map(|tool| (member, tool))
+        })
+    }
+
+    async fn call_tool(&self, id: Value, request: &Value) -> Value {
+        let name = request
+            .pointer("/params/name")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let Some((member, tool)) = self.route(name) else {
+            return error_response(id, INVALID_PARAMS, format!("Unknown tool '{}'", name));
+        };
+        debug!(server = %self.server_key, member = %member.server_key, tool = %tool, "Routing tool call to aggregate member");
+        let mut forwarded = request.clone();
+        forwarded["params"]["name"] = tool.into();
+        // メンバーの書き換えルールはメンバーのツール名で適用する
+        member.rewrite_message(&mut forwarded);
+        let command = forwarded.to_string();
+        let result = async {
+            member.check_circuit()?;
+            member.acquire().await?.query(&McpRequest { command }).await
+        }
+        .await;
+        let response = result.and_then(|response| {
+            serde_json::from_str::<Value>(&response.result)
+                .map_err(|e| format!("Invalid tools/call response: {}", e))
+        });
+        match response {
+            Ok(mut response) => {
+                response["id"] = id;
+                response
+            }
+            Err(e) => {
+                warn!(server = %self.server_key, member = %member.server_key, tool = %tool, error = %e, "Aggregate member tool call failed");
+                error_response(
+                    id,
+                    INTERNAL_ERROR,
+                    format!("MCP server '{}' failed: {}", member.server_key, e),
+                )
+            }
+        }
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
@@ -0,0 +1,408 @@
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::SaltString};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env, fmt,
+    io::Read,
+    sync::{Arc, LazyLock, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::{debug, info, warn};
+
+// 照合に成功したトークンのキャッシュの上限 (超えたら最も長く使われていないものから捨てる)
+const VERIFIED_CACHE_LIMIT: usize = 4096;
+
+// ハッシュとトークンの組ごとの照合の成功 (キーは両方の SHA-256 で、トークンそのものは保持しない)。
+// 失敗は保存しない (ランダムなトークンを送り続けて成功したトークンを追い出せないようにする)
+static VERIFIED: LazyLock<Mutex<VerifiedCache>> =
+    LazyLock::new(|| Mutex::new(VerifiedCache::new(VERIFIED_CACHE_LIMIT)));
+
+// 同時に実行するハッシュの照合の上限 (API_KEY_VERIFY_CONCURRENCY、既定は 4)。
+// 無効なトークンは毎回照合することになるため、CPU を使い切らないよう制限する
+static VERIFY_PERMITS: LazyLock<Semaphore> = LazyLock::new(|| {
+    let permits = env::var("API_KEY_VERIFY_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(4);
+    Semaphore::new(permits.max(1))
+});
+
+// 照合の空きが無い場合は None (待たずにリクエストを断る)
+pub fn try_verify_permit() -> Option<SemaphorePermit<'static>> {
+    VERIFY_PERMITS.try_acquire().ok()
+}
+
+// ハッシュのキーをどこまで照合するか
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Verification {
+    // 平文のキーと、照合に成功したことがあるトークンのみ (ブロックしない)
+    Cached,
+    // キャッシュに無いトークンはハッシュを照合する (遅い)
+    Full,
+}
+
+// --- 照合に成功したトークンの LRU キャッシュ ---
+struct VerifiedCache {
+    // キーごとの最後に使われた順番
+    entries: HashMap<[u8; 32], u64>,
+    clock: u64,
+    limit: usize,
+}
+
+impl VerifiedCache {
+    fn new(limit: usize) -> Self {
+        VerifiedCache {
+            entries: HashMap::new(),
+            clock: 0,
+            limit,
+        }
+    }
+
+    fn hit(&mut self, key: &[u8; 32]) -> bool {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some(used) => {
+                *used = self.clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, key: [u8; 32]) {
+        self.clock += 1;
+        if self.entries.len() >= self.limit && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, used)| **used)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, self.clock);
+    }
+}
+
+// --- ハッシュで保存できる APIキー ---
+// argon2 ($argon2id$...) と bcrypt ($2b$...) の形式の値はハッシュとして照合し、それ以外は平文のキーとして扱う。
+// docker inspect やプロセスの一覧に平文のキーを出さないために使う
+#[derive(Clone, PartialEq)]
+pub enum StoredKey {
+    Plain(String),
+    Argon2(String),
+    Bcrypt(String),
+}
+
+// Debug でキーを出力しない
+impl fmt::Debug for StoredKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoredKey::Plain(_) => f.write_str("Plain(..)"),
+            StoredKey::Argon2(_) => f.write_str("Argon2(..)"),
+            StoredKey::Bcrypt(_) => f.write_str("Bcrypt(..)"),
+        }
+    }
+}
+
+impl StoredKey {
+    // ハッシュの形式の値が壊れている場合はエラー
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if value.starts_with("$argon2") {
+            let hash =
+                PasswordHash::new(value).map_err(|e| format!("invalid argon2 hash: {}", e))?;
+            if hash.hash.is_none() {
+                return Err("invalid argon2 hash: missing hash value".to_string());
+            }
+            return Ok(StoredKey::Argon2(value.to_string()));
+        }
+        if ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| value.starts_with(prefix))
+        {
+            value
+                .parse::<bcrypt::HashParts>()
+                .map_err(|e| format!("invalid bcrypt hash: {}", e))?;
+            return Ok(StoredKey::Bcrypt(value.to_string()));
+        }
+        Ok(StoredKey::Plain(value.to_string()))
+    }
+
+    pub fn is_hashed(&self) -> bool {
+        !matches!(self, StoredKey::Plain(_))
+    }
+
+    // healthcheck サブコマンドなど、自分で認証するときに使えるキー
+    pub fn plaintext(&self) -> Option<&str> {
+        match self {
+            StoredKey::Plain(key) => Some(key),
+            _ => None,
+        }
+    }
+
+    // ハッシュの照合は遅い (数十〜数百ミリ秒) ため、Verification::Full は非同期のタスクからは spawn_blocking で呼ぶ
+    pub fn matches(&self, token: &str, verification: Verification) -> bool {
+        let hash = match self {
+            StoredKey::Plain(key) => return key == token,
+            StoredKey::Argon2(hash) | StoredKey::Bcrypt(hash) => hash,
+        };
+        let cache_key: [u8; 32] = Sha256::new()
+            .chain_update(hash.as_bytes())
+            .chain_update([0])
+            .chain_update(token.as_bytes())
+            .finalize()
+            .into();
+        if VERIFIED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .hit(&cache_key)
+        {
+            return true;
+        }
+        if verification == Verification::Cached {
+            return false;
+        }
+        let matched = match self {
+            StoredKey::Argon2(hash) => PasswordHash::new(hash).is_ok_and(|hash| {
+                Argon2::default()
+                    .verify_password(token.as_bytes(), &hash)
+                    .is_ok()
+            }),
+            _ => bcrypt::verify(token, hash).unwrap_or(false),
+        };
+        if matched {
+            VERIFIED
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(cache_key);
+        }
+        matched
+    }
+}
+
+// `mcp-http-server hash-key`: 標準入力のキー (末尾の改行は除く) の argon2id ハッシュを出力する
+pub fn hash_stdin() -> Result<String, String> {
+    let mut key = String::new();
+    std::io::stdin()
+        .read_to_string(&mut key)
+        .map_err(|e| format!("Failed to read key from stdin: {}", e))?;
+    let key = key.trim_end_matches(['\r', '\n']);
+    if key.is_empty() {
+        return Err("Key must not be empty".to_string());
+    }
+    // 乱数用の依存を増やさないよう、UUID v4 のランダムなビットを塩に使う
+    let salt = SaltString::encode_b64(uuid::Uuid::new_v4().as_bytes())
+        .map_err(|e| format!("Failed to generate salt: {}", e))?;
+    Argon2::default()
+        .hash_password(key.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Failed to hash key: {}", e))
+}
+
+// --- ファイルから読み込む APIキー (再起動なしのローテーション) ---
+// HTTP_API_KEY_FILE に1行1キー (平文またはハッシュ) で書く (空行と # で始まる行は無視)。
+// ファイルの更新を API_KEY_RELOAD_SECS ごとに確認して読み込み直し、
+// 削除されたキーも API_KEY_OVERLAP_SECS の間は受け付ける (古いキーを使うクライアントを順に切り替えられる)
+#[derive(Clone)]
+pub struct ApiKeyFile(Arc<Inner>);
+
+struct Inner {
+    path: String,
+    reload_interval: Duration,
+    overlap: Duration,
+    keys: RwLock<KeySet>,
+}
+
+#[derive(Default)]
+struct KeySet {
+    current: Vec<StoredKey>,
+    // 削除されたキーと、受け付ける期限
+    retiring: Vec<(StoredKey, Instant)>,
+    // 最後に読み込んだときのファイルの更新時刻とサイズ
+    stamp: Option<(SystemTime, u64)>,
+}
+
+// Debug でキーを出力しない
+impl fmt::Debug for ApiKeyFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys = self.0.keys.read().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("ApiKeyFile")
+            .field("path", &self.0.path)
+            .field("keys", &keys.current.len())
+            .field("retiring", &keys.retiring.len())
+            .finish()
+    }
+}
+
+impl ApiKeyFile {
+    // HTTP_API_KEY_FILE が未設定なら None。起動時に読めない、またはキーが無い場合はエラー
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(path) = env::var("HTTP_API_KEY_FILE")
+            .ok()
+            .filter(|path| !path.is_empty())
+        else {
+            return Ok(None);
+        };
+        let env_secs = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        let key_file = ApiKeyFile(Arc::new(Inner {
+            path,
+            reload_interval: Duration::from_secs(env_secs("API_KEY_RELOAD_SECS", 10)),
+            overlap: Duration::from_secs(env_secs("API_KEY_OVERLAP_SECS", 300)),
+            keys: RwLock::new(KeySet::default()),
+        }));
+        key_file.reload()?;
+        Ok(Some(key_file))
+    }
+
+    pub fn contains(&self, token: &str, verification: Verification) -> bool {
+        let keys = self.0.keys.read().unwrap_or_else(|e| e.into_inner());
+        keys.current
+            .iter()
+            .any(|key| key.matches(token, verification))
+            || keys
+                .retiring
+                .iter()
+                .any(|(key, until)| Instant::now() < *until && key.matches(token, verification))
+    }
+
+    pub fn has_hashed(&self) -> bool {
+        let keys = self.0.keys.read().unwrap_or_else(|e| e.into_inner());
+        keys.current
+            .iter()
+            .chain(keys.retiring.iter().map(|(key, _)| key))
+            .any(StoredKey::is_hashed)
+    }
+
+    // healthcheck サブコマンドなど、自分で認証するときに使う平文のキー
+    pub fn first_key(&self) -> Option<String> {
+        let keys = self.0.keys.read().unwrap_or_else(|e| e.into_inner());
+        keys.current
+            .iter()
+            .find_map(StoredKey::plaintext)
+            .map(str::to_string)
+    }
+
+    // API_KEY_RELOAD_SECS=0 の場合は起動時にのみ読み込む
+    pub fn spawn_reload(&self) {
+        if self.0.reload_interval.is_zero() {
+            return;
+        }
+        let key_file = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(key_file.0.reload_interval);
+            // 初回の tick は即座に完了するため読み飛ばす
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let key_file = key_file.clone();
+                let result = tokio::task::spawn_blocking(move || key_file.reload()).await;
+                match result {
+                    Ok(Ok(())) => {}
+                    // 読み込めない間も直前のキーを使い続ける
+                    Ok(Err(e)) => {
+                        warn!(error = %e, "Failed to reload API key file, keeping the current keys")
+                    }
+                    Err(e) => warn!(error = %e, "API key reload task failed"),
+                }
+            }
+        });
+    }
+
+    // 更新時刻かサイズが変わっていれば読み込み直す
+    fn reload(&self) -> Result<(), String> {
+        let path = &self.0.path;
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| format!("Failed to read API key file '{}': {}", path, e))?;
+        let stamp = (
+            metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            metadata.len(),
+        );
+        if self.0.keys.read().unwrap_or_else(|e| e.into_inner()).stamp == Some(stamp) {
+            debug!(path = %path, "API key file unchanged");
+            return Ok(());
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read API key file '{}': {}", path, e))?;
+        let mut current: Vec<StoredKey> = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let key = StoredKey::parse(line)
+                .map_err(|e| format!("API key file '{}' line {}: {}", path, index + 1, e))?;
+            if !current.contains(&key) {
+                current.push(key);
+            }
+        }
+        // 書き込み途中の空のファイルで全員を締め出さないようにする
+        if current.is_empty() {
+            return Err(format!("API key file '{}' has no keys", path));
+        }
+
+        let mut keys = self.0.keys.write().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let until = now + self.0.overlap;
+        let removed: Vec<StoredKey> = keys
+            .current
+            .iter()
+            .filter(|key| !current.contains(key))
+            .cloned()
+            .collect();
+        keys.retiring
+            .retain(|(key, until)| now < *until && !current.contains(key));
+        let retiring = removed.len();
+        keys.retiring
+            .extend(removed.into_iter().map(|key| (key, until)));
+        let first_load = keys.stamp.is_none();
+        keys.current = current;
+        keys.stamp = Some(stamp);
+        if first_load {
+            info!(path = %path, keys = keys.current.len(), "Loaded API keys from file");
+        } else {
+            info!(
+                path = %path,
+                keys = keys.current.len(),
+                retiring,
+                overlap_secs = self.0.overlap.as_secs(),
+                "Reloaded API keys, removed keys stay valid during the overlap"
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verified_cache_evicts_least_recently_used() {
+        let mut cache = VerifiedCache::new(2);
+        cache.insert([1; 32]);
+        cache.insert([2; 32]);
+        // [1] を使ったので、次に追い出されるのは [2]
+        assert!(cache.hit(&[1; 32]));
+        cache.insert([3; 32]);
+        assert!(cache.hit(&[1; 32]));
+        assert!(!cache.hit(&[2; 32]));
+        assert!(cache.hit(&[3; 32]));
+    }
+
+    #[test]
+    fn only_successful_verifications_are_cached() {
+        let key = StoredKey::parse(&bcrypt::hash("cached-token", 4).unwrap()).unwrap();
+        assert!(!key.matches("cached-token", Verification::Cached));
+        assert!(!key.matches("wrong-token", Verification::Full));
+        assert!(!key.matches("wrong-token", Verification::Cached));
+        assert!(key.matches("cached-token", Verification::Full));
+        assert!(key.matches("cached-token", Verification::Cached));
+    }
+}
@@ -0,0 +1,111 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{collections::HashMap, time::Duration};
+use tracing::{debug, warn};
+
+// JSON-RPC の標準エラーコード
+const METHOD_NOT_FOUND: i64 = -32601;
+const INTERNAL_ERROR: i64 = -32603;
+
+// --- サーバーからクライアントへのリクエストの転送先 ---
+// sampling/createMessage や elicitation/create などを外部の URL に POST し、結果を子プロセスに返す
+#[derive(Deserialize, Debug, Clone)]
+pub struct CallbackConfig {
+    pub url: String,
+    // 転送時に付与する HTTP ヘッダー (認証など)
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    // レスポンス待ちの上限 (MCP リクエスト全体のタイムアウトより短くする)
+    #[serde(default = "default_callback_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_callback_timeout_secs() -> u64 {
+    25
+}
+
+pub struct CallbackHandler {
+    config: Option<CallbackConfig>,
+    client: reqwest::Client,
+}
+
+impl CallbackHandler {
+    pub fn new(config: Option<CallbackConfig>) -> Self {
+        CallbackHandler {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.config.is_some()
+    }
+
+    // サーバーからのリクエストに対する JSON-RPC レスポンスを返す
+    pub async fn respond(&self, server_key: &str, request: &Value) -> Value {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request
+            .get("method")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let Some(config) = &self.config else {
+            // 転送先がなければエラーを返し、子プロセスが応答を待ち続けないようにする
+            debug!(server = %server_key, method = %method, "No callback configured for server request");
+            return error_response(
+                id,
+                METHOD_NOT_FOUND,
+                format!("Client does not support '{}'", method),
+            );
+        };
+        match self.forward(config, request).await {
+            Ok(body) => {
+                debug!(server = %server_key, method = %method, "Relaying callback response to MCP server");
+                into_response(id, body)
+            }
+            Err(e) => {
+                warn!(server = %server_key, method = %method, url = %config.url, error = %e, "Callback request failed");
+                error_response(id, INTERNAL_ERROR, format!("Callback failed: {}", e))
+            }
+        }
+    }
+
+    async fn forward(&self, config: &CallbackConfig, request: &Value) -> Result<Value, String> {
+        let mut builder = self
+            .client
+            .post(&config.url)
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .json(request);
+        for (name, value) in &config.headers {
+            builder = builder.header(name, value);
+        }
+        let response = builder.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("callback returned HTTP {}", status));
+        }
+        response
+            .json::<Value>()
+            .await
+            .map_err(|e| format!("callback returned invalid JSON: {}", e))
+    }
+}
+
+// 転送先は JSON-RPC レスポンスか、result の中身だけを返せばよい
+fn into_response(id: Value, body: Value) -> Value {
+    let is_response = body.get("jsonrpc").is_some()
+        && (body.get("result").is_some() || body.get("error").is_some());
+    if !is_response {
+        return json!({ "jsonrpc": "2.0", "id": id, "result": body });
+    }
+    let mut response = body;
+    response["id"] = id;
+    response
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+// ブレーカーが開いているときのエラーメッセージに含める文言
+pub const CIRCUIT_OPEN_ERROR: &str = "circuit breaker is open";
+
+// --- サーキットブレーカーの設定 ---
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CircuitBreakerConfig {
+    // 連続してタイムアウト・異常終了したらブレーカーを開く回数 (0 で無効)
+    pub failure_threshold: u32,
+    // ブレーカーを開いてから試行を再開するまでの秒数
+    pub cooldown_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: 5,
+            cooldown_secs: 30,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    // クールダウン中 (リクエストを即座に拒否する)
+    Open,
+    // クールダウン明け (次の結果で閉じるか再び開く)
+    HalfOpen,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CircuitSnapshot {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub retry_after_secs: Option<u64>,
+}
+
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+// --- 子プロセスの連続した失敗を検知し、新しいリクエストを即座に失敗させる ---
+pub struct CircuitBreaker {
+    server_key: String,
+    config: CircuitBreakerConfig,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(server_key: &str, config: CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            server_key: server_key.to_string(),
+            config,
+            state: Mutex::new(BreakerState {
+                consecutive_failures: 0,
+                opened_at: None,
+            }),
+        }
+    }
+
+    // クールダウンの残り秒数 (切り上げ、クールダウン明けなら None)
+    fn remaining_secs(&self, opened_at: Instant) -> Option<u64> {
+        Duration::from_secs(self.config.cooldown_secs)
+            .checked_sub(opened_at.elapsed())
+            .filter(|remaining| !remaining.is_zero())
+            .map(|remaining| remaining.as_secs_f64().ceil() as u64)
+    }
+
+    // ブレーカーが開いている間は、再試行までの秒数を Err で返す
+    pub fn check(&self) -> Result<(), u64> {
+        let Ok(state) = self.state.lock() else {
+            return Ok(());
+        };
+        match state
+            .opened_at
+            .and_then(|opened_at| self.remaining_secs(opened_at))
+        {
+            Some(remaining_secs) => Err(remaining_secs),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if state.opened_at.take().is_some() {
+            info!(server = %self.server_key, "Circuit breaker closed");
+        }
+        state.consecutive_failures = 0;
+    }
+
+    // タイムアウト・異常終了のみを失敗として数える
+    pub fn record_failure(&self) {
+        if self.config.failure_threshold == 0 {
+            return;
+        }
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if state.consecutive_failures >= self.config.failure_threshold {
+            if state.opened_at.is_none() {
+                warn!(
+                    server = %self.server_key,
+                    consecutive_failures = state.consecutive_failures,
+                    cooldown_secs = self.config.cooldown_secs,
+                    "Circuit breaker opened"
+                );
+            }
+            // 半開状態での失敗は再びクールダウンからやり直す
+            state.opened_at = Some(Instant::now());
+        }
+    }
+
+    pub fn snapshot(&self) -> CircuitSnapshot {
+        let Ok(state) = self.state.lock() else {
+            return CircuitSnapshot {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                retry_after_secs: None,
+            };
+        };
+        let retry_after_secs = state
+            .opened_at
+            .and_then(|opened_at| self.remaining_secs(opened_at));
+        let circuit_state = match (state.opened_at, retry_after_secs) {
+            (None, _) => CircuitState::Closed,
+            (Some(_), Some(_)) => CircuitState::Open,
+            (Some(_), None) => CircuitState::HalfOpen,
+        };
+        CircuitSnapshot {
+            state: circuit_state,
+            consecutive_failures: state.consecutive_failures,
+            retry_after_secs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(failure_threshold: u32, cooldown_secs: u64) -> CircuitBreaker {
+        CircuitBreaker::new(
+            "test",
+            CircuitBreakerConfig {
+                failure_threshold,
+                cooldown_secs,
+            },
+        )
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures_only() {
+        let breaker = breaker(3, 30);
+        breaker.record_failure();
+        breaker.record_failure();
+        // 成功を挟むと数え直す
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(breaker.check(), Ok(()));
+        assert_eq!(breaker.snapshot().state, CircuitState::Closed);
+
+        breaker.record_failure();
+        assert_eq!(breaker.check(), Err(30));
+        let snapshot = breaker.snapshot();
+        assert_eq!(snapshot.state, CircuitState::Open);
+        assert_eq!(snapshot.consecutive_failures, 3);
+        assert_eq!(snapshot.retry_after_secs, Some(30));
+    }
+
+    #[test]
+    fn half_open_after_cooldown_and_closes_on_success() {
+        let breaker = breaker(1, 0);
+        breaker.record_failure();
+        // クールダウン明けは試行を通す
+        assert_eq!(breaker.check(), Ok(()));
+        assert_eq!(breaker.snapshot().state, CircuitState::HalfOpen);
+
+        breaker.record_success();
+        let snapshot = breaker.snapshot();
+        assert_eq!(snapshot.state, CircuitState::Closed);
+        assert_eq!(snapshot.consecutive_failures, 0);
+    }
+
+    #[test]
+    fn zero_threshold_never_opens() {
+        let breaker = breaker(0, 30);
+        for _ in 0..10 {
+            breaker.record_failure();
+        }
+        assert_eq!(breaker.check(), Ok(()));
+        assert_eq!(breaker.snapshot().consecutive_failures, 0);
+    }
+}
@@ -0,0 +1,81 @@
+use std::env;
+
+// --- コマンドライン引数 ---
+// mcp-http-server [--strict]   HTTP サーバーとして起動する
+// mcp-http-server --mock       設定ファイルを読まず、組み込みのモックサーバーで起動する
+// mcp-http-server encrypt --name <server>/<ENV_VAR>
+//                              標準入力の値を暗号化し、設定の env に書ける enc: の値を出力する
+// mcp-http-server hash-key     標準入力の APIキーの argon2id ハッシュを出力する
+// mcp-http-server validate [--strict] [--resolve]
+//                              子プロセスを起動せずに設定ファイルを検証する
+// mcp-http-server doctor       ランタイム・書き込み先・外部への接続など実行環境を確認する
+// mcp-http-server init <template>... [--force]
+//                              設定ファイルと .env の雛形を書き出す
+// mcp-http-server healthcheck  起動中のサーバーの /readyz を確認する (Docker の HEALTHCHECK 用)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Encrypt,
+    HashKey,
+    Validate,
+    Doctor,
+    Init,
+    Healthcheck,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub command: Command,
+    // --strict (または MCP_CONFIG_STRICT=true) で設定ファイルの未知のキーをエラーにする
+    pub strict: bool,
+    // 設定ファイルの代わりに組み込みのモックサーバー (type: "mock") を使う
+    pub mock: bool,
+    // validate で cwd のチェックアウトが commit / checksum と一致するかも確認する
+    pub resolve: bool,
+    // init で書き出す雛形と、既存のファイルを上書きするか
+    pub templates: Vec<String>,
+    pub force: bool,
+    // encrypt で値を結び付けるサーバーと変数 (<server>/<ENV_VAR>)
+    pub name: Option<String>,
+}
+
+// サブコマンドは最初の引数でだけ受け付ける
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        command: Command::Serve,
+        strict: env::var("MCP_CONFIG_STRICT")
+            .map(|value| value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false),
+        mock: false,
+        resolve: false,
+        templates: Vec::new(),
+        force: false,
+        name: None,
+    };
+    let mut args = args.into_iter().enumerate();
+    while let Some((index, arg)) = args.next() {
+        match arg.as_str() {
+            "--strict" => parsed.strict = true,
+            "--mock" if parsed.command == Command::Serve => parsed.mock = true,
+            "encrypt" if index == 0 => parsed.command = Command::Encrypt,
+            "hash-key" if index == 0 => parsed.command = Command::HashKey,
+            "validate" if index == 0 => parsed.command = Command::Validate,
+            "doctor" if index == 0 => parsed.command = Command::Doctor,
+            "init" if index == 0 => parsed.command = Command::Init,
+            "healthcheck" if index == 0 => parsed.command = Command::Healthcheck,
+            "--resolve" if parsed.command == Command::Validate => parsed.resolve = true,
+            "--force" if parsed.command == Command::Init => parsed.force = true,
+            "--name" if parsed.command == Command::Encrypt => {
+                let (_, name) = args
+                    .next()
+                    .ok_or_else(|| "--name requires a value".to_string())?;
+                parsed.name = Some(name);
+            }
+            _ if parsed.command == Command::Init && !arg.starts_with('-') => {
+                parsed.templates.push(arg)
+            }
+            _ => return Err(format!("Unknown command line argument '{}'", arg)),
+        }
+    }
+    Ok(parsed)
+}
@@ -0,0 +1,1124 @@
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeSet, HashMap},
+    env, fmt,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    config_source,
+    mcp_process::{McpProcessConfig, McpServersConfig, ServerType},
+};
+
+// 設定ファイルの最上位でサーバー名ではなくプロファイル・読み込むファイルの指定として扱うキー
+const PROFILES_KEY: &str = "profiles";
+const INCLUDE_KEY: &str = "include";
+
+// サーバー定義で使えるキー (McpProcessConfig と flatten した構造体のフィールド)。
+// McpProcessConfig にフィールドを追加したらここにも追加する
+const KNOWN_SERVER_KEYS: &[&str] = &[
+    "type",
+    "default",
+    "aliases",
+    "command",
+    "args",
+    "env",
+    "env_policy",
+    "cwd",
+    "language",
+    "entrypoint",
+    "post_install",
+    "pre_start",
+    "hook_timeout_secs",
+    "commit",
+    "checksum",
+    "checksum_path",
+    "sandbox",
+    "limits",
+    "quirks",
+    "framing",
+    "max_response_bytes",
+    "notification_buffer_size",
+    "stderr_buffer_kb",
+    "callback",
+    "retry_on_crash",
+    "circuit_breaker",
+    "response_cache",
+    "restart",
+    "max_restarts",
+    "restart_window_secs",
+    "health_check",
+    "keep_alive",
+    "resource_monitor",
+    "lazy",
+    "idle_timeout_secs",
+    "warm_standby",
+    "max_concurrent_requests",
+    "max_queued_requests",
+    "auto_initialize",
+    "response_timeout_secs",
+    "init_wait_secs",
+    "stop_grace_secs",
+    "allowed_tools",
+    "blocked_tools",
+    "read_only",
+    "destructive_patterns",
+    "rewrite",
+    "scripts",
+    "wasm_plugins",
+    "url",
+    "transport",
+    "headers",
+    "servers",
+    "separator",
+    "setup_concurrency",
+];
+
+// type ごとにしか意味を持たないキー
+const STDIO_ONLY_KEYS: &[&str] = &[
+    "command",
+    "args",
+    "cwd",
+    "language",
+    "entrypoint",
+    "post_install",
+    "pre_start",
+    "commit",
+    "checksum",
+    "checksum_path",
+    "sandbox",
+    "limits",
+    "framing",
+    "stderr_buffer_kb",
+    "resource_monitor",
+    "stop_grace_secs",
+];
+const REMOTE_ONLY_KEYS: &[&str] = &["url", "transport", "headers"];
+const AGGREGATE_ONLY_KEYS: &[&str] = &["servers", "separator", "setup_concurrency"];
+
+// --strict (または MCP_CONFIG_STRICT=true) の場合は未知のキーを警告ではなくエラーにする
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+fn is_strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+// 設定ファイルを読み込み、前処理したサーバー定義 (サーバー名 → JSON) を返す。
+// path は https:// や s3:// の URL でもよい。
+// MCP_CONFIG_DIR の *.json を名前順に重ね、ファイルが1つも無ければ None
+pub async fn read(path: &str) -> Result<Option<Map<String, Value>>, String> {
+    let mut loader = Loader {
+        profile: env::var("MCP_PROFILE").ok().filter(|name| !name.is_empty()),
+        profile_found: false,
+        available_profiles: BTreeSet::new(),
+        stack: Vec::new(),
+        files: Vec::new(),
+    };
+    let mut servers = loader.load_file(&Location::parse(path)).await?;
+    if let Some(dir) = env::var("MCP_CONFIG_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+    {
+        for file in json_files(Path::new(&dir)).await? {
+            let file = Location::File(file);
+            let overlay = loader
+                .load_file(&file)
+                .await?
+                .ok_or_else(|| format!("MCP config file '{}' disappeared while loading", file))?;
+            merge_map(servers.get_or_insert_with(Map::new), overlay);
+        }
+    }
+    let Some(mut servers) = servers else {
+        return Ok(None);
+    };
+    remove_nulls(&mut servers);
+    if let Some(profile) = loader.profile.as_ref().filter(|_| !loader.profile_found) {
+        return Err(format!(
+            "MCP_PROFILE '{}' is not defined in MCP config '{}' (available: {})",
+            profile,
+            path,
+            loader
+                .available_profiles
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if loader.files.len() > 1 {
+        info!(files = ?loader.files, "Merged MCP config files");
+    }
+
+    let mut missing = Vec::new();
+    let servers = interpolate(Value::Object(servers), "", &mut missing);
+    if !missing.is_empty() {
+        return Err(format!(
+            "Unset environment variables in MCP config '{}': {}",
+            path,
+            missing.join(", ")
+        ));
+    }
+    match servers {
+        Value::Object(servers) => Ok(Some(servers)),
+        _ => unreachable!("interpolate preserves the value type"),
+    }
+}
+
+// --- 読み込む設定ファイルの場所 ---
+enum Location {
+    File(PathBuf),
+    // https:// または s3:// (取得した内容はローカルにキャッシュする)
+    Remote(String),
+}
+
+impl Location {
+    fn parse(source: &str) -> Self {
+        match config_source::is_remote(source) {
+            true => Location::Remote(source.to_string()),
+            false => Location::File(PathBuf::from(source)),
+        }
+    }
+
+    // include の指定を読み込むファイルにする (ローカルのディレクトリは中の *.json)
+    async fn resolve_include(&self, include: &str) -> Result<Vec<Location>, String> {
+        if config_source::is_remote(include) {
+            return Ok(vec![Location::Remote(include.to_string())]);
+        }
+        match self {
+            Location::File(path) => {
+                let include = path.parent().unwrap_or(Path::new(".")).join(include);
+                match include.is_dir() {
+                    true => Ok(json_files(&include)
+                        .await?
+                        .into_iter()
+                        .map(Location::File)
+                        .collect()),
+                    false => Ok(vec![Location::File(include)]),
+                }
+            }
+            // URL の設定ファイルからは、絶対パスはローカルのファイル、相対パスは同じ取得元の URL
+            Location::Remote(_) if Path::new(include).is_absolute() => {
+                Ok(vec![Location::File(PathBuf::from(include))])
+            }
+            Location::Remote(url) => {
+                if include.ends_with('/') {
+                    return Err(format!(
+                        "MCP config '{}' cannot include directory '{}' (only local config files can)",
+                        url, include
+                    ));
+                }
+                let resolved = reqwest::Url::parse(url)
+                    .and_then(|base| base.join(include))
+                    .map_err(|e| {
+                        format!(
+                            "Invalid include '{}' in MCP config '{}': {}",
+                            include, url, e
+                        )
+                    })?;
+                Ok(vec![Location::Remote(resolved.to_string())])
+            }
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::File(path) => write!(f, "{}", path.display()),
+            Location::Remote(url) => f.write_str(url),
+        }
+    }
+}
+
+// --- 設定ファイルの読み込み (include・プロファイル) ---
+// include を再帰的に読み込むため、load_file の Future は Box で包む
+type LoadFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Option<Map<String, Value>>, String>> + Send + 'a>>;
+
+struct Loader {
+    profile: Option<String>,
+    // MCP_PROFILE がいずれかのファイルで定義されていたか
+    profile_found: bool,
+    available_profiles: BTreeSet<String>,
+    // include の循環を検出するための読み込み中のファイル
+    stack: Vec<String>,
+    files: Vec<String>,
+}
+
+impl Loader {
+    // include したファイルを先に重ね、その上にファイル自身の定義とプロファイルを重ねる
+    // (ローカルのファイルが無ければ None)
+    fn load_file<'a>(&'a mut self, location: &'a Location) -> LoadFuture<'a> {
+        Box::pin(async move {
+            debug!(config_file = %location, "Reading config file");
+            let content = match location {
+                Location::File(path) => match tokio::fs::read_to_string(path).await {
+                    Ok(content) => content,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                    Err(e) => {
+                        return Err(format!(
+                            "Failed to read MCP config file '{}': {}",
+                            location, e
+                        ));
+                    }
+                },
+                Location::Remote(url) => config_source::fetch(url).await?,
+            };
+            let Value::Object(mut own) = serde_json::from_str(&content)
+                .map_err(|e| format!("Failed to parse MCP config file '{}': {}", location, e))?
+            else {
+                return Err(format!(
+                    "MCP config file '{}' must be a JSON object",
+                    location
+                ));
+            };
+
+            let identity = match location {
+                Location::File(path) => tokio::fs::canonicalize(path)
+                    .await
+                    .unwrap_or_else(|_| path.to_path_buf())
+                    .display()
+                    .to_string(),
+                Location::Remote(url) => url.clone(),
+            };
+            if self.stack.contains(&identity) {
+                let chain: Vec<&str> = self
+                    .stack
+                    .iter()
+                    .chain([&identity])
+                    .map(String::as_str)
+                    .collect();
+                return Err(format!("MCP config include cycle: {}", chain.join(" -> ")));
+            }
+            self.stack.push(identity);
+            self.files.push(location.to_string());
+
+            let mut servers = Map::new();
+            for include in include_paths(own.remove(INCLUDE_KEY), location)? {
+                for file in location.resolve_include(&include).await? {
+                    let overlay = self.load_file(&file).await?.ok_or_else(|| {
+                        format!(
+                            "MCP config file '{}' included from '{}' does not exist",
+                            file, location
+                        )
+                    })?;
+                    merge_map(&mut servers, overlay);
+                }
+            }
+            let profiles = own.remove(PROFILES_KEY);
+            merge_map(&mut servers, own);
+            self.apply_profile(&mut servers, profiles, location)?;
+
+            self.stack.pop();
+            Ok(Some(servers))
+        })
+    }
+
+    // "profiles": { "dev": {...}, "prod": {...} } のうち MCP_PROFILE のものを重ねる
+    fn apply_profile(
+        &mut self,
+        servers: &mut Map<String, Value>,
+        profiles: Option<Value>,
+        location: &Location,
+    ) -> Result<(), String> {
+        let profiles = match profiles {
+            Some(Value::Object(profiles)) => profiles,
+            Some(_) => {
+                return Err(format!(
+                    "'{}' in MCP config '{}' must be an object",
+                    PROFILES_KEY, location
+                ));
+            }
+            None => return Ok(()),
+        };
+        self.available_profiles.extend(profiles.keys().cloned());
+        let Some(overlay) = self
+            .profile
+            .as_ref()
+            .and_then(|profile| profiles.get(profile))
+        else {
+            return Ok(());
+        };
+        let Value::Object(overlay) = overlay.clone() else {
+            return Err(format!(
+                "Profile '{}' in MCP config '{}' must be an object",
+                self.profile.as_deref().unwrap_or_default(),
+                location
+            ));
+        };
+        info!(profile = ?self.profile, config_file = %location, "Applying config profile");
+        self.profile_found = true;
+        merge_map(servers, overlay);
+        Ok(())
+    }
+}
+
+// "include": "a.json" または ["a.json", "teams/"] (${VAR} はここで展開する)
+fn include_paths(include: Option<Value>, location: &Location) -> Result<Vec<String>, String> {
+    let entries = match include {
+        None => return Ok(Vec::new()),
+        Some(Value::String(entry)) => vec![entry],
+        Some(Value::Array(entries)) => entries
+            .into_iter()
+            .map(|entry| match entry {
+                Value::String(entry) => Ok(entry),
+                _ => Err(()),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| {
+                format!(
+                    "'{}' in MCP config '{}' must contain only strings",
+                    INCLUDE_KEY, location
+                )
+            })?,
+        Some(_) => {
+            return Err(format!(
+                "'{}' in MCP config '{}' must be a string or an array of strings",
+                INCLUDE_KEY, location
+            ));
+        }
+    };
+    let mut missing = Vec::new();
+    let paths = entries
+        .iter()
+        .map(|entry| expand(entry, &format!("/{}", INCLUDE_KEY), &mut missing))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "Unset environment variables in MCP config '{}': {}",
+            location,
+            missing.join(", ")
+        ));
+    }
+    Ok(paths)
+}
+
+// ディレクトリ内の *.json をファイル名順に返す
+async fn json_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let read_error = |e: std::io::Error| {
+        format!(
+            "Failed to read MCP config directory '{}': {}",
+            dir.display(),
+            e
+        )
+    };
+    let mut entries = tokio::fs::read_dir(dir).await.map_err(read_error)?;
+    let mut files = Vec::new();
+    while let Some(entry) = entries.next_entry().await.map_err(read_error)? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "json") && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// オブジェクトはキーごとに再帰的に重ね、それ以外は置き換える。
+// null はキーを削除する印としてそのまま残し、後で重ねるファイルからも下のファイルのキーを消せるようにする
+// (すべて重ねた後に remove_nulls で取り除く)
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => merge_map(base, overlay),
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn merge_map(base: &mut Map<String, Value>, overlay: Map<String, Value>) {
+    for (key, value) in overlay {
+        match base.get_mut(&key) {
+            Some(existing) => merge(existing, value),
+            None => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+// 削除の印の null をオブジェクトから取り除く (配列は丸ごと置き換えるため中は変えない)
+fn remove_nulls(map: &mut Map<String, Value>) {
+    map.retain(|_, value| !value.is_null());
+    for value in map.values_mut() {
+        if let Value::Object(inner) = value {
+            remove_nulls(inner);
+        }
+    }
+}
+
+// 文字列 (オブジェクトのキーを含む) の ${VAR} / ${VAR:-default} を展開する。
+// 未設定の変数は JSON ポインターとともに missing に記録する
+fn interpolate(value: Value, pointer: &str, missing: &mut Vec<String>) -> Value {
+    match value {
+        Value::String(text) => Value::String(expand(&text, pointer, missing)),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(index, item)| interpolate(item, &format!("{}/{}", pointer, index), missing))
+                .collect(),
+        ),
+        Value::Object(entries) => Value::Object(
+            entries
+                .into_iter()
+                .map(|(key, item)| {
+                    let path = format!("{}/{}", pointer, escape_pointer(&key));
+                    (
+                        expand(&key, &path, missing),
+                        interpolate(item, &path, missing),
+                    )
+                })
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+// $${ は展開せずに ${ として残す
+fn expand(text: &str, pointer: &str, missing: &mut Vec<String>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix("${") {
+            output.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let Some(body) = after.strip_prefix('{') else {
+            output.push('$');
+            rest = after;
+            continue;
+        };
+        let Some(end) = body.find('}') else {
+            // 閉じていなければそのまま残す
+            output.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+        let expression = &body[..end];
+        let (name, default) = match expression.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expression, None),
+        };
+        // シェルと同じく :- は空文字列も未設定として扱う
+        match (env::var(name).ok(), default) {
+            (Some(value), Some(default)) if value.is_empty() => output.push_str(default),
+            (Some(value), _) => output.push_str(&value),
+            (None, Some(default)) => output.push_str(default),
+            (None, None) => {
+                missing.push(format!("{} (at {})", name, display_pointer(pointer)));
+            }
+        }
+        rest = &body[end + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+// RFC 6901 の JSON ポインター
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn display_pointer(pointer: &str) -> &str {
+    if pointer.is_empty() { "/" } else { pointer }
+}
+
+// --- サーバー定義の検証 ---
+// 最初の誤りで止めず、すべてのサーバーの問題を JSON ポインター付きでまとめて報告する
+struct Problems {
+    source: String,
+    errors: Vec<String>,
+}
+
+impl Problems {
+    fn error(&mut self, pointer: &str, message: impl std::fmt::Display) {
+        self.errors.push(format!("{}: {}", pointer, message));
+    }
+
+    fn into_result(self) -> Result<(), String> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(format!(
+            "Invalid MCP config '{}' ({} problem{}):\n  {}",
+            self.source,
+            self.errors.len(),
+            if self.errors.len() == 1 { "" } else { "s" },
+            self.errors.join("\n  ")
+        ))
+    }
+}
+
+pub fn parse_servers(
+    servers: Map<String, Value>,
+    source: &str,
+) -> Result<McpServersConfig, String> {
+    let mut problems = Problems {
+        source: source.to_string(),
+        errors: Vec::new(),
+    };
+    let mut configs = McpServersConfig::new();
+    for (server_key, entry) in servers {
+        let pointer = format!("/{}", escape_pointer(&server_key));
+        let Some(fields) = entry.as_object() else {
+            problems.error(&pointer, "server definition must be an object");
+            continue;
+        };
+        check_unknown_keys(&pointer, fields, &mut problems);
+        let mut config: McpProcessConfig = match serde_path_to_error::deserialize(&entry) {
+            Ok(config) => config,
+            Err(e) => {
+                let path = path_pointer(e.path());
+                problems.error(&format!("{}{}", pointer, path), e.into_inner());
+                continue;
+            }
+        };
+        if let Err(e) = crate::language::apply(&server_key, &mut config) {
+            problems.error(&format!("{}/language", pointer), e);
+        }
+        check_server(&server_key, &pointer, &config, fields, &mut problems);
+        configs.insert(server_key, config);
+    }
+    check_names(&configs, &mut problems);
+    problems.into_result()?;
+    crate::aggregate::resolve_members(&mut configs)?;
+    Ok(configs)
+}
+
+// default: true は1つの定義だけに付けられ、別名は他のサーバー名・別名と重ならない
+fn check_names(configs: &McpServersConfig, problems: &mut Problems) {
+    let mut server_keys: Vec<&String> = configs.keys().collect();
+    server_keys.sort();
+    let mut default_key: Option<&str> = None;
+    let mut owners: HashMap<&str, &str> = server_keys
+        .iter()
+        .map(|server_key| (server_key.as_str(), server_key.as_str()))
+        .collect();
+    for server_key in server_keys {
+        let config = &configs[server_key];
+        let pointer = format!("/{}", escape_pointer(server_key));
+        if config.default {
+            match default_key {
+                Some(first) => problems.error(
+                    &format!("{}/default", pointer),
+                    format!("'default' is already set on '{}'", first),
+                ),
+                None => default_key = Some(server_key),
+            }
+        }
+        for (index, alias) in config.aliases.iter().enumerate() {
+            let alias_pointer = format!("{}/aliases/{}", pointer, index);
+            if alias.trim().is_empty() {
+                problems.error(&alias_pointer, "alias must not be empty");
+            } else if let Some(owner) = owners.insert(alias, server_key) {
+                problems.error(
+                    &alias_pointer,
+                    format!("alias '{}' is already used by '{}'", alias, owner),
+                );
+            }
+        }
+    }
+}
+
+// 未知のキーは打ち間違いの可能性が高いため、近いキーを候補として示す
+fn check_unknown_keys(pointer: &str, fields: &Map<String, Value>, problems: &mut Problems) {
+    for key in fields
+        .keys()
+        .filter(|key| !KNOWN_SERVER_KEYS.contains(&key.as_str()))
+    {
+        let message = match suggest(key) {
+            Some(known) => format!("unknown field '{}' (did you mean '{}'?)", key, known),
+            None => format!("unknown field '{}'", key),
+        };
+        let key_pointer = format!("{}/{}", pointer, escape_pointer(key));
+        if is_strict() {
+            problems.error(&key_pointer, message);
+        } else {
+            warn!(config_file = %problems.source, pointer = %key_pointer, "{}, ignoring it (use --strict to reject)", message);
+        }
+    }
+}
+
+// type に必要なキーの有無、type と合わないキー、組み合わせても意味のない設定
+fn check_server(
+    server_key: &str,
+    pointer: &str,
+    config: &McpProcessConfig,
+    fields: &Map<String, Value>,
+    problems: &mut Problems,
+) {
+    let (required, type_name) = match config.server_type {
+        // language の問題 (cwd が無い・パッケージ名が読めない) は language::apply で報告する
+        ServerType::Stdio => (
+            (!config.command.is_empty() || config.language.is_some())
+                .then_some(())
+                .ok_or("command"),
+            "stdio",
+        ),
+        ServerType::Remote => (
+            config.remote.url.as_ref().map(|_| ()).ok_or("url"),
+            "remote",
+        ),
+        ServerType::Aggregate => (
+            (!config.aggregate.servers.is_empty())
+                .then_some(())
+                .ok_or("servers"),
+            "aggregate",
+        ),
+        ServerType::Mock => (Ok(()), "mock"),
+    };
+    if let Err(key) = required {
+        problems.error(
+            &format!("{}/{}", pointer, key),
+            format!("'{}' is required for {} servers", key, type_name),
+        );
+    }
+    let misplaced = [
+        (ServerType::Stdio, STDIO_ONLY_KEYS),
+        (ServerType::Remote, REMOTE_ONLY_KEYS),
+        (ServerType::Aggregate, AGGREGATE_ONLY_KEYS),
+    ]
+    .into_iter()
+    .filter(|(server_type, _)| *server_type != config.server_type)
+    .flat_map(|(_, keys)| keys.iter())
+    .filter(|key| fields.contains_key(**key));
+    for key in misplaced {
+        problems.error(
+            &format!("{}/{}", pointer, key),
+            format!("'{}' does not apply to {} servers", key, type_name),
+        );
+    }
+    if config.retry_on_crash
+        && config.restart_policy.restart == Some(crate::restart_policy::RestartMode::Never)
+    {
+        problems.error(
+            &format!("{}/retry_on_crash", pointer),
+            "'retry_on_crash' conflicts with 'restart: never', which never restarts the crashed process",
+        );
+    }
+
+    let validations = [
+        ("env", crate::secrets::validate(server_key, &config.env)),
+        (
+            "env_policy",
+            config.env_policy.validate(server_key, &config.env),
+        ),
+        (
+            "health_check",
+            config
+                .health_check
+                .as_ref()
+                .map_or(Ok(()), |health_check| health_check.validate(server_key)),
+        ),
+        (
+            "resource_monitor",
+            config.resource_monitor.validate(server_key),
+        ),
+        ("commit", config.integrity.validate(server_key)),
+        (
+            "sandbox",
+            config
+                .sandbox
+                .as_ref()
+                .map_or(Ok(()), |sandbox| sandbox.validate(server_key)),
+        ),
+        ("rewrite", config.rewrite.validate(server_key)),
+        (
+            "limits",
+            config
+                .limits
+                .as_ref()
+                .map_or(Ok(()), |limits| limits.validate(server_key)),
+        ),
+    ];
+    for (key, result) in validations {
+        if let Err(e) = result {
+            problems.error(&format!("{}/{}", pointer, key), e);
+        }
+    }
+}
+
+// serde_path_to_error のパスを JSON ポインターにする
+fn path_pointer(path: &serde_path_to_error::Path) -> String {
+    path.iter()
+        .filter_map(|segment| match segment {
+            serde_path_to_error::Segment::Seq { index } => Some(index.to_string()),
+            serde_path_to_error::Segment::Map { key } => Some(escape_pointer(key)),
+            serde_path_to_error::Segment::Enum { variant } => Some(escape_pointer(variant)),
+            serde_path_to_error::Segment::Unknown => None,
+        })
+        .map(|segment| format!("/{}", segment))
+        .collect()
+}
+
+// 編集距離が 2 以下で最も近い既知のキー
+fn suggest(key: &str) -> Option<&'static str> {
+    KNOWN_SERVER_KEYS
+        .iter()
+        .map(|known| (edit_distance(key, known), *known))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+// 隣接文字の入れ替えも1回と数える (Damerau-Levenshtein の簡易版)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::sync::Mutex;
+
+    // read() は MCP_PROFILE / MCP_CONFIG_DIR を読むため、環境変数を変えるテストは順番に実行する
+    static ENV_LOCK: Mutex<()> = Mutex::const_new(());
+
+    fn set_env(name: &str, value: Option<&str>) {
+        // 変数名をテストごとに分けるか、ENV_LOCK を持ったまま呼ぶ (他のテストスレッドは同じ変数を読まない)
+        unsafe {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = env::temp_dir().join(format!("mcp-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, content: &Value) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, content.to_string()).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn expand_substitutes_defaults_and_escapes() {
+        set_env("MCP_CONFIG_TEST_SET", Some("value"));
+        set_env("MCP_CONFIG_TEST_EMPTY", Some(""));
+        let mut missing = Vec::new();
+        let expand = |text: &str, missing: &mut Vec<String>| expand(text, "/srv/env/X", missing);
+
+        assert_eq!(
+            expand("a-${MCP_CONFIG_TEST_SET}-b", &mut missing),
+            "a-value-b"
+        );
+        assert_eq!(
+            expand("${MCP_CONFIG_TEST_UNSET:-fallback}", &mut missing),
+            "fallback"
+        );
+        // :- は空文字列も未設定として扱う
+        assert_eq!(
+            expand("${MCP_CONFIG_TEST_EMPTY:-fallback}", &mut missing),
+            "fallback"
+        );
+        assert_eq!(expand("${MCP_CONFIG_TEST_EMPTY}", &mut missing), "");
+        assert_eq!(
+            expand("$${MCP_CONFIG_TEST_SET}", &mut missing),
+            "${MCP_CONFIG_TEST_SET}"
+        );
+        assert_eq!(expand("$HOME ${unclosed", &mut missing), "$HOME ${unclosed");
+        assert!(missing.is_empty());
+
+        assert_eq!(expand("x${MCP_CONFIG_TEST_UNSET}y", &mut missing), "xy");
+        assert_eq!(missing, ["MCP_CONFIG_TEST_UNSET (at /srv/env/X)"]);
+    }
+
+    #[test]
+    fn interpolate_reports_every_unset_variable_with_its_pointer() {
+        set_env("MCP_CONFIG_TEST_KEY", Some("renamed"));
+        let config = json!({
+            "srv/1": {
+                "env": { "TOKEN": "${MCP_CONFIG_TEST_MISSING_A}" },
+                "args": ["--flag", "${MCP_CONFIG_TEST_MISSING_B}"],
+                "${MCP_CONFIG_TEST_KEY}": 3
+            }
+        });
+        let mut missing = Vec::new();
+        let expanded = interpolate(config, "", &mut missing);
+        // キーも展開し、数値はそのまま残す
+        assert_eq!(expanded["srv/1"]["renamed"], 3);
+        // オブジェクトのキーの順に報告する
+        assert_eq!(
+            missing,
+            [
+                "MCP_CONFIG_TEST_MISSING_B (at /srv~11/args/1)",
+                "MCP_CONFIG_TEST_MISSING_A (at /srv~11/env/TOKEN)",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn profile_overlays_the_config_and_null_removes_keys() {
+        let _guard = ENV_LOCK.lock().await;
+        set_env("MCP_CONFIG_DIR", None);
+        let dir = temp_dir();
+        let path = write(
+            &dir,
+            "config.json",
+            &json!({
+                "search": { "command": "npx", "args": ["server"], "lazy": true },
+                "profiles": {
+                    "prod": { "search": { "args": ["server", "--prod"], "lazy": null } }
+                }
+            }),
+        );
+
+        set_env("MCP_PROFILE", None);
+        let servers = read(&path).await.unwrap().unwrap();
+        assert_eq!(servers["search"]["args"], json!(["server"]));
+        assert!(!servers.contains_key(PROFILES_KEY));
+
+        set_env("MCP_PROFILE", Some("prod"));
+        let servers = read(&path).await.unwrap().unwrap();
+        assert_eq!(
+            servers["search"],
+            json!({ "command": "npx", "args": ["server", "--prod"] })
+        );
+
+        set_env("MCP_PROFILE", Some("staging"));
+        let error = read(&path).await.unwrap_err();
+        assert!(
+            error.contains("MCP_PROFILE 'staging' is not defined"),
+            "{}",
+            error
+        );
+        assert!(error.contains("available: prod"), "{}", error);
+
+        set_env("MCP_PROFILE", None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn unset_variables_fail_the_whole_config() {
+        let _guard = ENV_LOCK.lock().await;
+        set_env("MCP_PROFILE", None);
+        set_env("MCP_CONFIG_DIR", None);
+        let dir = temp_dir();
+        let path = write(
+            &dir,
+            "config.json",
+            &json!({ "a": { "command": "${MCP_CONFIG_TEST_NOPE}" } }),
+        );
+        let error = read(&path).await.unwrap_err();
+        assert!(
+            error.contains("MCP_CONFIG_TEST_NOPE (at /a/command)"),
+            "{}",
+            error
+        );
+        assert_eq!(
+            read(&dir.join("absent.json").display().to_string()).await,
+            Ok(None)
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    fn parse(config: Value) -> Result<McpServersConfig, String> {
+        let Value::Object(servers) = config else {
+            unreachable!()
+        };
+        parse_servers(servers, "test.json")
+    }
+
+    #[test]
+    fn validation_reports_every_problem_with_a_json_pointer() {
+        let error = parse(json!({
+            "search": { "command": "" },
+            "remote": { "type": "remote", "args": ["x"] },
+            "first": { "type": "mock", "default": true, "aliases": ["shared"] },
+            "second": { "type": "mock", "default": true, "aliases": ["shared", "search"] },
+            "typed": { "type": "mock", "max_response_bytes": "big" },
+            "crashy": { "command": "node", "retry_on_crash": true, "restart": "never" }
+        }))
+        .err()
+        .unwrap();
+        for expected in [
+            "Invalid MCP config 'test.json' (8 problems)",
+            "/search/command: 'command' is required for stdio servers",
+            "/remote/url: 'url' is required for remote servers",
+            "/remote/args: 'args' does not apply to remote servers",
+            "/second/default: 'default' is already set on 'first'",
+            "/second/aliases/0: alias 'shared' is already used by 'first'",
+            "/second/aliases/1: alias 'search' is already used by 'search'",
+            "/typed/max_response_bytes: invalid type",
+            "/crashy/retry_on_crash: 'retry_on_crash' conflicts with 'restart: never'",
+        ] {
+            assert!(
+                error.contains(expected),
+                "missing {:?} in:\n{}",
+                expected,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_keys_suggest_a_fix_and_fail_only_in_strict_mode() {
+        let config = json!({ "search": { "command": "npx", "argz": ["server"] } });
+        assert!(parse(config.clone()).is_ok());
+
+        set_strict(true);
+        let result = parse(config);
+        set_strict(false);
+        let error = result.err().unwrap();
+        assert!(
+            error.contains("/search/argz: unknown field 'argz' (did you mean 'args'?)"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn suggestions_count_transpositions_as_one_edit() {
+        assert_eq!(edit_distance("comamnd", "command"), 1);
+        assert_eq!(edit_distance("", "env"), 3);
+        assert_eq!(suggest("comamnd"), Some("command"));
+        assert_eq!(suggest("headres"), Some("headers"));
+        assert_eq!(suggest("completely_different"), None);
+    }
+
+    #[tokio::test]
+    async fn includes_merge_before_the_including_file() {
+        let _guard = ENV_LOCK.lock().await;
+        set_env("MCP_PROFILE", None);
+        set_env("MCP_CONFIG_DIR", None);
+        let dir = temp_dir();
+        std::fs::create_dir(dir.join("teams")).unwrap();
+        write(
+            &dir,
+            "base.json",
+            &json!({ "search": { "command": "npx", "args": ["base"], "lazy": true } }),
+        );
+        write(
+            &dir.join("teams"),
+            "b.json",
+            &json!({ "b": { "type": "mock" } }),
+        );
+        write(
+            &dir.join("teams"),
+            "a.json",
+            &json!({ "a": { "type": "mock" } }),
+        );
+        std::fs::write(dir.join("teams").join("notes.txt"), "ignored").unwrap();
+        let path = write(
+            &dir,
+            "config.json",
+            &json!({
+                "include": ["base.json", "teams"],
+                "search": { "args": ["main"] }
+            }),
+        );
+
+        let servers = read(&path).await.unwrap().unwrap();
+        assert_eq!(
+            servers["search"],
+            json!({ "command": "npx", "args": ["main"], "lazy": true })
+        );
+        assert_eq!(servers.keys().collect::<Vec<_>>(), ["a", "b", "search"]);
+
+        // 含めたファイルが無ければエラー
+        write(&dir, "config.json", &json!({ "include": "missing.json" }));
+        let error = read(&path).await.unwrap_err();
+        assert!(error.contains("missing.json"), "{}", error);
+        assert!(error.contains("does not exist"), "{}", error);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn include_cycles_are_reported_with_the_chain() {
+        let _guard = ENV_LOCK.lock().await;
+        set_env("MCP_PROFILE", None);
+        set_env("MCP_CONFIG_DIR", None);
+        let dir = temp_dir();
+        let path = write(&dir, "a.json", &json!({ "include": "b.json" }));
+        write(&dir, "b.json", &json!({ "include": "a.json" }));
+        let error = read(&path).await.unwrap_err();
+        assert!(error.starts_with("MCP config include cycle: "), "{}", error);
+        assert_eq!(error.matches("a.json").count(), 2, "{}", error);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn config_dir_overlays_files_in_name_order() {
+        let _guard = ENV_LOCK.lock().await;
+        set_env("MCP_PROFILE", None);
+        let dir = temp_dir();
+        let overlays = dir.join("conf.d");
+        std::fs::create_dir(&overlays).unwrap();
+        let path = write(
+            &dir,
+            "config.json",
+            &json!({ "search": { "command": "npx", "args": ["base"] }, "old": { "type": "mock" } }),
+        );
+        write(
+            &overlays,
+            "20-late.json",
+            &json!({ "search": { "args": ["late"] } }),
+        );
+        write(
+            &overlays,
+            "10-early.json",
+            &json!({ "search": { "args": ["early"] }, "old": null, "extra": { "type": "mock" } }),
+        );
+        set_env("MCP_CONFIG_DIR", Some(overlays.to_str().unwrap()));
+
+        let servers = read(&path).await.unwrap().unwrap();
+        assert_eq!(servers["search"]["args"], json!(["late"]));
+        assert_eq!(servers.keys().collect::<Vec<_>>(), ["extra", "search"]);
+
+        // 設定ファイルが無くても MCP_CONFIG_DIR のファイルだけで読み込める
+        let servers = read(&dir.join("absent.json").display().to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(servers["search"]["args"], json!(["late"]));
+
+        set_env("MCP_CONFIG_DIR", None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn remote_includes_resolve_against_the_url() {
+        let base = Location::Remote("https://config.example.com/mcp/servers.json".to_string());
+        let resolved = base.resolve_include("team/a.json").await.unwrap();
+        assert!(matches!(
+            resolved.as_slice(),
+            [Location::Remote(url)] if url == "https://config.example.com/mcp/team/a.json"
+        ));
+        assert!(base.resolve_include("teams/").await.is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use reqwest::{
+    RequestBuilder, StatusCode,
+    header::{AUTHORIZATION, ETAG, IF_NONE_MATCH},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, info, warn};
+
+// 取得を待つ時間
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 既定のキャッシュディレクトリ (MCP_CONFIG_CACHE_DIR で変更できる)
+const DEFAULT_CACHE_DIR: &str = ".mcp-config-cache";
+
+// --- URL で指定された設定ファイルの取得 ---
+// https:// (http://) と s3:// に対応する。取得した内容と ETag をローカルにキャッシュし、
+// 次回の取得は If-None-Match で変更があった場合だけ内容を受け取る。
+// 取得元に到達できない場合はキャッシュした内容を使う
+pub fn is_remote(source: &str) -> bool {
+    ["https://", "http://", "s3://"]
+        .iter()
+        .any(|scheme| source.starts_with(scheme))
+}
+
+pub async fn fetch(source: &str) -> Result<String, String> {
+    let cache = Cache::for_source(source);
+    let cached = cache.load().await;
+    let etag = cached.as_ref().and_then(|(_, etag)| etag.as_deref());
+    match request(source, etag).await {
+        Ok(Fetched::NotModified) => {
+            debug!(source = %source, "MCP config not modified, using cached copy");
+            // If-None-Match はキャッシュがある場合だけ送る
+            Ok(cached.map(|(content, _)| content).unwrap_or_default())
+        }
+        Ok(Fetched::Content { body, etag }) => {
+            info!(source = %source, etag = ?etag, "Fetched MCP config");
+            cache.store(&body, etag.as_deref()).await;
+            Ok(body)
+        }
+        Err(e) => match cached {
+            Some((content, _)) => {
+                warn!(source = %source, cache = %cache.content_path.display(), error = %e, "MCP config source is unreachable, using cached copy");
+                Ok(content)
+            }
+            None => Err(e),
+        },
+    }
+}
+
+enum Fetched {
+    NotModified,
+    Content { body: String, etag: Option<String> },
+}
+
+async fn request(source: &str, etag: Option<&str>) -> Result<Fetched, String> {
+    let client = reqwest::Client::new();
+    let mut request = match source.strip_prefix("s3://") {
+        Some(location) => s3_request(&client, source, location)?,
+        None => {
+            let request = client.get(source);
+            match env::var("MCP_CONFIG_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty())
+            {
+                Some(token) => request.header(AUTHORIZATION, format!("Bearer {}", token)),
+                None => request,
+            }
+        }
+    };
+    request = request.timeout(FETCH_TIMEOUT);
+    if let Some(etag) = etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch MCP config '{}': {}", source, e))?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    }
+    let response = response
+        .error_for_status()
+        .map_err(|e| format!("Failed to fetch MCP config '{}': {}", source, e))?;
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = response
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read MCP config '{}': {}", source, e))?;
+    Ok(Fetched::Content { body, etag })
+}
+
+// 取得した設定ファイルを保存するディレクトリ
+pub fn cache_dir() -> PathBuf {
+    PathBuf::from(
+        env::var("MCP_CONFIG_CACHE_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or_else(|| DEFAULT_CACHE_DIR.to_string()),
+    )
+}
+
+// --- 取得した設定ファイルのキャッシュ ---
+// 取得元の URL ごとに <sha256>.json と <sha256>.etag を保存する
+struct Cache {
+    content_path: PathBuf,
+    etag_path: PathBuf,
+}
+
+impl Cache {
+    fn for_source(source: &str) -> Self {
+        let dir = cache_dir();
+        let name = format!("{:x}", Sha256::digest(source.as_bytes()));
+        Cache {
+            content_path: dir.join(format!("{}.json", name)),
+            etag_path: dir.join(format!("{}.etag", name)),
+        }
+    }
+
+    async fn load(&self) -> Option<(String, Option<String>)> {
+        let content = tokio::fs::read_to_string(&self.content_path).await.ok()?;
+        let etag = tokio::fs::read_to_string(&self.etag_path)
+            .await
+            .ok()
+            .filter(|etag| !etag.is_empty());
+        Some((content, etag))
+    }
+
+    // 書き込みに失敗しても起動は続ける (次回の取得で書き直す)
+    async fn store(&self, content: &str, etag: Option<&str>) {
+        if let Err(e) = self.write(content, etag).await {
+            warn!(cache = %self.content_path.display(), error = %e, "Failed to cache MCP config");
+        }
+    }
+
+    async fn write(&self, content: &str, etag: Option<&str>) -> std::io::Result<()> {
+        if let Some(dir) = self.content_path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // 途中で終了しても壊れた内容を残さないよう、書き終えてから置き換える
+        let partial = self.content_path.with_extension("json.partial");
+        tokio::fs::write(&partial, content).await?;
+        tokio::fs::rename(&partial, &self.content_path).await?;
+        match etag {
+            Some(etag) => tokio::fs::write(&self.etag_path, etag).await,
+            None => match tokio::fs::remove_file(&self.etag_path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+// --- S3 ---
+// s3://bucket/key を GET するリクエスト。AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY があれば
+// 署名バージョン4で署名し、なければ署名せずに (公開バケットとして) 取得する。
+// AWS_ENDPOINT_URL (MinIO など S3 互換のサービス) はパス形式で指定する
+fn s3_request(
+    client: &reqwest::Client,
+    source: &str,
+    location: &str,
+) -> Result<RequestBuilder, String> {
+    let (bucket, key) = location
+        .split_once('/')
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+        .ok_or_else(|| format!("Invalid S3 URL '{}', expected s3://bucket/key", source))?;
+    let region = ["AWS_REGION", "AWS_DEFAULT_REGION"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|region| !region.is_empty()))
+        .unwrap_or_else(|| "us-east-1".to_string());
+    let key = uri_encode_path(key);
+    let (url, host, path) = match env::var("AWS_ENDPOINT_URL")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+    {
+        Some(endpoint) => {
+            let endpoint = endpoint.trim_end_matches('/');
+            let host = endpoint
+                .split_once("://")
+                .map_or(endpoint, |(_, host)| host)
+                .to_string();
+            let path = format!("/{}/{}", uri_encode_path(bucket), key);
+            (format!("{}{}", endpoint, path), host, path)
+        }
+        None => {
+            let host = format!("{}.s3.{}.amazonaws.com", bucket, region);
+            let path = format!("/{}", key);
+            (format!("https://{}{}", host, path), host, path)
+        }
+    };
+
+    let request = client.get(&url);
+    let (Some(access_key), Some(secret_key)) = (
+        env::var("AWS_ACCESS_KEY_ID").ok(),
+        env::var("AWS_SECRET_ACCESS_KEY").ok(),
+    ) else {
+        debug!(source = %source, "No AWS credentials, fetching S3 object unsigned");
+        return Ok(request);
+    };
+
+    let (date, timestamp) = amz_timestamp(SystemTime::now());
+    let payload_hash = format!("{:x}", Sha256::digest(b""));
+    // 署名するヘッダー (名前順)
+    let mut headers = vec![
+        ("host", host),
+        ("x-amz-content-sha256", payload_hash.clone()),
+        ("x-amz-date", timestamp.clone()),
+    ];
+    if let Some(token) = env::var("AWS_SESSION_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+    {
+        headers.push(("x-amz-security-token", token));
+    }
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "GET\n{}\n\n{}\n{}\n{}",
+        path, canonical_headers, signed_headers, payload_hash
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+        timestamp,
+        scope,
+        Sha256::digest(canonical_request.as_bytes())
+    );
+    let mut signing_key = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date.as_bytes());
+    for part in [region.as_str(), "s3", "aws4_request"] {
+        signing_key = hmac_sha256(&signing_key, part.as_bytes());
+    }
+    let signature: String = hmac_sha256(&signing_key, string_to_sign.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    // Host は reqwest が URL から付ける
+    let mut request = request.header(
+        AUTHORIZATION,
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            access_key, scope, signed_headers, signature
+        ),
+    );
+    for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+        request = request.header(name, value);
+    }
+    Ok(request)
+}
+
+// S3 のキーを URL のパスとしてエンコードする (/ はそのまま残す)
+fn uri_encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// 署名に使う日付 (YYYYMMDD) と時刻 (YYYYMMDDTHHMMSSZ)
+fn amz_timestamp(now: SystemTime) -> (String, String) {
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+    // 1970-01-01 からの日数を年月日にする (Howard Hinnant の civil_from_days)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let timestamp = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    );
+    (date, timestamp)
+}
+
+// HMAC-SHA256 (RFC 2104)
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner = Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x36))
+        .chain_update(message)
+        .finalize();
+    let outer = Sha256::new()
+        .chain_update(block.map(|byte| byte ^ 0x5c))
+        .chain_update(inner)
+        .finalize();
+    let mut mac = [0u8; 32];
+    mac.copy_from_slice(&outer);
+    mac
+}
@@ -0,0 +1,229 @@
+use tokio::sync::mpsc;
+
+// キーとして記録する文字列の最大バイト数 ("result" / "content" / "id" が判別できれば足りる)
+const MAX_KEY_BYTES: usize = 16;
+
+// --- 子プロセスの出力を読みながら result.content の要素を切り出す ---
+// tools/call の巨大なレスポンスを1行分バッファせずに、content の要素ごとに送信先へ渡す。
+// content を取り除いた残り (エンベロープ) は通常のレスポンスとして扱う
+pub struct ContentScanner {
+    max_bytes: usize,
+    started: bool,
+    // JSON 以外の行 (バナーなど) は改行までをそのまま返す
+    noise: bool,
+    complete: bool,
+    overflowed: bool,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    // 深さ1・2のオブジェクトで直前に読んだ文字列と、現在の値のキー
+    string: Vec<u8>,
+    keys: [Vec<u8>; 3],
+    // トップレベルの "id" の値
+    id: Option<Vec<u8>>,
+    sender: Option<mpsc::Sender<String>>,
+    in_content: bool,
+    envelope: Vec<u8>,
+    item: Vec<u8>,
+    items: Vec<String>,
+}
+
+impl ContentScanner {
+    pub fn new(max_bytes: usize) -> Self {
+        ContentScanner {
+            max_bytes,
+            started: false,
+            noise: false,
+            complete: false,
+            overflowed: false,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            string: Vec::new(),
+            keys: Default::default(),
+            id: None,
+            sender: None,
+            in_content: false,
+            envelope: Vec::new(),
+            item: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    // エンベロープか content の要素が max_bytes を超えた
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.envelope.is_empty()
+    }
+
+    // 切り出し済みの content の要素と、その送信先
+    pub fn take_items(&mut self) -> (Vec<String>, Option<mpsc::Sender<String>>) {
+        (std::mem::take(&mut self.items), self.sender.clone())
+    }
+
+    pub fn into_envelope(self) -> String {
+        String::from_utf8_lossy(&self.envelope).into_owned()
+    }
+
+    // chunk を読み進め、消費したバイト数を返す (メッセージの終端で止まる)
+    // id を読み取った時点で lookup を呼び、content の送信先を決める
+    pub fn feed(
+        &mut self,
+        chunk: &[u8],
+        lookup: impl Fn(&serde_json::Value) -> Option<mpsc::Sender<String>>,
+    ) -> usize {
+        for (index, &byte) in chunk.iter().enumerate() {
+            if !self.started {
+                match byte {
+                    b'{' | b'[' => self.started = true,
+                    // メッセージ間の空白・改行は読み飛ばす
+                    b' ' | b'\t' | b'\r' | b'\n' => continue,
+                    _ => {
+                        self.noise = true;
+                        self.started = true;
+                    }
+                }
+            }
+            if self.noise {
+                if byte == b'\n' {
+                    self.complete = true;
+                    return index + 1;
+                }
+                self.push_envelope(byte);
+                continue;
+            }
+            self.scan(byte, &lookup);
+            if self.depth == 0 {
+                self.complete = true;
+                return index + 1;
+            }
+        }
+        chunk.len()
+    }
+
+    fn scan(
+        &mut self,
+        byte: u8,
+        lookup: &impl Fn(&serde_json::Value) -> Option<mpsc::Sender<String>>,
+    ) {
+        if self.in_string {
+            match byte {
+                _ if self.escaped => self.escaped = false,
+                b'\\' => self.escaped = true,
+                b'"' => self.in_string = false,
+                _ => {}
+            }
+            if self.in_string && self.depth <= 2 && self.string.len() < MAX_KEY_BYTES {
+                self.string.push(byte);
+            }
+            self.route(byte, self.depth);
+            return;
+        }
+        let depth_before = self.depth;
+        match byte {
+            b'"' => {
+                self.in_string = true;
+                self.string.clear();
+            }
+            b'{' | b'[' => {
+                let opens_content = byte == b'['
+                    && self.depth == 2
+                    && self.keys[1] == b"result"
+                    && self.keys[2] == b"content"
+                    && self.sender.is_some();
+                self.depth += 1;
+                if let Some(key) = self.keys.get_mut(self.depth) {
+                    key.clear();
+                }
+                if opens_content {
+                    self.in_content = true;
+                    self.push_envelope(byte);
+                    return;
+                }
+            }
+            b'}' | b']' => {
+                if self.in_content && self.depth == 3 {
+                    // content の配列の終端
+                    self.flush_item();
+                    self.in_content = false;
+                    self.depth -= 1;
+                    self.push_envelope(byte);
+                    return;
+                }
+                if self.depth == 1 && self.id.is_some() {
+                    self.resolve_id(lookup);
+                }
+                self.depth = self.depth.saturating_sub(1);
+            }
+            b':' if self.depth <= 2 => {
+                self.keys[self.depth] = std::mem::take(&mut self.string);
+                if self.depth == 1 && self.keys[1] == b"id" {
+                    self.id = Some(Vec::new());
+                }
+                self.push_envelope(byte);
+                return;
+            }
+            b',' => {
+                if self.in_content && self.depth == 3 {
+                    self.flush_item();
+                    return;
+                }
+                if self.depth == 1 && self.id.is_some() {
+                    self.resolve_id(lookup);
+                }
+            }
+            _ => {}
+        }
+        self.route(byte, depth_before.max(self.depth));
+    }
+
+    // content の要素の途中なら要素に、それ以外はエンベロープに追加する
+    fn route(&mut self, byte: u8, depth: usize) {
+        // id は ':' の後から値の終わりまでを記録する
+        let top_level = self.depth == 1;
+        if let Some(id) = self.id.as_mut().filter(|_| top_level) {
+            id.push(byte);
+        }
+        let in_item = self.in_content && (depth >= 4 || !byte.is_ascii_whitespace());
+        if !in_item {
+            self.push_envelope(byte);
+            return;
+        }
+        if self.item.len() >= self.max_bytes {
+            self.overflowed = true;
+            return;
+        }
+        self.item.push(byte);
+    }
+
+    fn push_envelope(&mut self, byte: u8) {
+        if self.envelope.len() >= self.max_bytes {
+            self.overflowed = true;
+            return;
+        }
+        self.envelope.push(byte);
+    }
+
+    fn resolve_id(&mut self, lookup: &impl Fn(&serde_json::Value) -> Option<mpsc::Sender<String>>) {
+        let id = self
+            .id
+            .as_deref()
+            .and_then(|id| serde_json::from_slice::<serde_json::Value>(id).ok());
+        self.sender = id.as_ref().and_then(lookup);
+        self.id = None;
+    }
+
+    fn flush_item(&mut self) {
+        let item = std::mem::take(&mut self.item);
+        if !item.is_empty() && !self.overflowed {
+            self.items.push(String::from_utf8_lossy(&item).into_owned());
+        }
+    }
+}
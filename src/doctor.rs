@@ -0,0 +1,237 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    config_file, config_source, mcp_process::McpServersConfig, program, sandbox, validate,
+};
+
+// 設定に関係なく確認するランタイム (npx / uvx / docker などでよく使われる)
+const RUNTIMES: &[&str] = &["git", "node", "npx", "python3", "uvx", "docker"];
+// `--version` と外部への接続を待つ秒数
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECTIVITY_URL: &str = "https://github.com";
+
+// --- `mcp-http-server doctor` ---
+// 実行環境 (ランタイム・書き込み先・外部への接続) を確認し、結果を標準出力に書き出す。
+// 設定ファイルのサーバーが使うものが無い場合に 1 を返す
+pub async fn run(config_file: &str) -> i32 {
+    let mut report = Report::default();
+
+    let configs = match load(config_file).await {
+        Ok(configs) => {
+            report.ok(format!(
+                "MCP config '{}' ({} server(s))",
+                config_file,
+                configs.len()
+            ));
+            configs
+        }
+        Err(e) => {
+            report.fail(e);
+            McpServersConfig::new()
+        }
+    };
+
+    // 既定のランタイムに加え、設定のサーバー・フック・ヘルスチェックのコマンドを確認する
+    let mut programs: BTreeMap<&str, Vec<&str>> =
+        RUNTIMES.iter().map(|name| (*name, Vec::new())).collect();
+    for (server_key, config) in &configs {
+        // パスで指定したプログラムは validate で確認する
+        let names = validate::host_programs(config)
+            .into_iter()
+            .filter(|name| Path::new(name).components().count() == 1);
+        for name in names {
+            programs.entry(name).or_default().push(server_key);
+        }
+    }
+    for (name, servers) in programs {
+        match version(name).await {
+            Some(version) => report.ok(format!("{}: {}", name, version)),
+            // sh のように --version に対応していないプログラムもある
+            None if program::find(name, None).is_some() => {
+                report.ok(format!("{}: found (version unknown)", name))
+            }
+            None if servers.is_empty() => report.skip(format!("{}: not found", name)),
+            None => report.fail(format!(
+                "{}: not found (used by MCP server '{}')",
+                name,
+                servers.join("', '")
+            )),
+        }
+    }
+
+    for (dir, purpose) in writable_dirs(config_file, &configs) {
+        match check_writable(&dir).await {
+            Ok(()) => report.ok(format!("{} is writable ({})", dir.display(), purpose)),
+            Err(e) => report.fail(format!("{} ({})", e, purpose)),
+        }
+    }
+
+    // remote のサーバーにも接続できるか確認する
+    let mut urls: Vec<&str> = configs
+        .values()
+        .filter_map(|config| config.remote.url.as_deref())
+        .collect();
+    urls.sort();
+    urls.dedup();
+    urls.insert(0, CONNECTIVITY_URL);
+    for url in urls {
+        match reachable(url).await {
+            Ok(()) => report.ok(format!("{} is reachable", url)),
+            Err(e) => report.fail(format!("{} is not reachable: {}", url, e)),
+        }
+    }
+
+    report.finish()
+}
+
+#[derive(Default)]
+struct Report {
+    failed: usize,
+}
+
+impl Report {
+    fn ok(&mut self, message: String) {
+        println!("ok    {}", message);
+    }
+
+    // 設定で使われていないため失敗にはしない
+    fn skip(&mut self, message: String) {
+        println!("--    {}", message);
+    }
+
+    fn fail(&mut self, message: String) {
+        self.failed += 1;
+        println!("FAIL  {}", message);
+    }
+
+    fn finish(self) -> i32 {
+        if self.failed > 0 {
+            println!("{} check(s) failed", self.failed);
+            1
+        } else {
+            println!("All checks passed");
+            0
+        }
+    }
+}
+
+// 設定ファイルが無くても他の確認は行う (無い場合は問題として報告する)
+async fn load(config_file: &str) -> Result<McpServersConfig, String> {
+    let servers = config_file::read(config_file)
+        .await?
+        .ok_or_else(|| format!("MCP config file '{}' does not exist", config_file))?;
+    config_file::parse_servers(servers, config_file)
+}
+
+// `<program> --version` の出力の1行目 (python2 などは標準エラー出力に書く)
+async fn version(name: &str) -> Option<String> {
+    let mut command = program::command(name);
+    command
+        .arg("--version")
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true);
+    let output = tokio::time::timeout(CHECK_TIMEOUT, command.output())
+        .await
+        .ok()?
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = if output.stdout.is_empty() {
+        output.stderr
+    } else {
+        output.stdout
+    };
+    Some(
+        String::from_utf8_lossy(&text)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    )
+}
+
+// ブリッジが書き込むディレクトリと、その用途
+fn writable_dirs(config_file: &str, configs: &McpServersConfig) -> Vec<(PathBuf, String)> {
+    let mut dirs = Vec::new();
+    if config_source::is_remote(config_file) {
+        dirs.push((
+            config_source::cache_dir(),
+            "MCP_CONFIG_CACHE_DIR".to_string(),
+        ));
+    }
+    if let Some(path) = env::var("LOG_FILE")
+        .ok()
+        .filter(|path| !path.trim().is_empty())
+    {
+        dirs.push((parent_dir(Path::new(&path)), "LOG_FILE".to_string()));
+    }
+    let sqlite =
+        env::var("STORAGE_BACKEND").is_ok_and(|backend| backend.eq_ignore_ascii_case("sqlite"));
+    if sqlite {
+        let path = env::var("STORAGE_URL").unwrap_or_else(|_| "mcp-http-server.db".to_string());
+        dirs.push((parent_dir(Path::new(&path)), "STORAGE_URL".to_string()));
+    }
+    // post_install の実行記録 (.mcp-setup.json) は cwd に保存する
+    let mut servers: Vec<_> = configs.iter().collect();
+    servers.sort_by_key(|(server_key, _)| *server_key);
+    for (server_key, config) in servers {
+        if let Some(cwd) =
+            sandbox::host_cwd(config).filter(|_| !config.hooks.post_install.is_empty())
+        {
+            dirs.push((cwd, format!("cwd of MCP server '{}'", server_key)));
+        }
+    }
+    dirs
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// 一時ファイルを作成して削除する。まだ無いディレクトリは作成できるかを、存在する親で確認する
+async fn check_writable(dir: &Path) -> Result<(), String> {
+    let mut existing = dir;
+    while !existing.is_dir() {
+        existing = match existing.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+    }
+    let probe = existing.join(format!(".mcp-doctor-{}", std::process::id()));
+    tokio::fs::write(&probe, b"")
+        .await
+        .map_err(|e| format!("{} is not writable: {}", existing.display(), e))?;
+    let _ = tokio::fs::remove_file(&probe).await;
+    Ok(())
+}
+
+// HTTP の応答が返れば (ステータスに関係なく) 到達できるとみなす
+async fn reachable(url: &str) -> Result<(), String> {
+    let client = reqwest::Client::builder()
+        .timeout(CHECK_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    client.head(url).send().await.map(|_| ()).map_err(|e| {
+        // reqwest のエラーだけでは DNS・TLS・接続のどれで失敗したか分からない
+        let mut message = e.to_string();
+        let mut source = std::error::Error::source(&e);
+        while let Some(cause) = source {
+            let cause_message = cause.to_string();
+            if !message.ends_with(&cause_message) {
+                message.push_str(&format!(": {}", cause_message));
+            }
+            source = cause.source();
+        }
+        message
+    })
+}
@@ -0,0 +1,56 @@
+use std::{env, path::PathBuf};
+
+// 既定で読み込む環境変数ファイル (ENV_FILE で変更できる)
+const DEFAULT_ENV_FILE: &str = ".env";
+
+// --- .env ファイルの読み込み ---
+// 起動直後、設定を読む前に呼び出す。プロセスの環境変数に既にある値は上書きしない
+#[derive(Debug)]
+pub struct EnvFileLoad {
+    pub path: PathBuf,
+    // ファイルから設定した変数
+    pub loaded: usize,
+    // プロセスの環境変数が優先されたため無視した変数
+    pub skipped: usize,
+}
+
+// ENV_FILE または既定の .env
+pub fn path() -> PathBuf {
+    PathBuf::from(
+        env::var("ENV_FILE")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .unwrap_or_else(|| DEFAULT_ENV_FILE.to_string()),
+    )
+}
+
+// ENV_FILE で明示したファイルが無い場合はエラー、既定の .env が無い場合は None
+pub fn load() -> Result<Option<EnvFileLoad>, String> {
+    let explicit = env::var("ENV_FILE").is_ok_and(|path| !path.is_empty());
+    let path = path();
+    if !explicit && !path.exists() {
+        return Ok(None);
+    }
+    let entries = dotenvy::from_path_iter(&path)
+        .map_err(|e| format!("Failed to read env file '{}': {}", path.display(), e))?;
+
+    let mut load = EnvFileLoad {
+        path,
+        loaded: 0,
+        skipped: 0,
+    };
+    for entry in entries {
+        let (key, value) =
+            entry.map_err(|e| format!("Invalid env file '{}': {}", load.path.display(), e))?;
+        if env::var_os(&key).is_some() {
+            load.skipped += 1;
+            continue;
+        }
+        // 他のスレッドが環境変数を読み始める前 (main の先頭) にだけ呼び出される
+        unsafe {
+            env::set_var(&key, value);
+        }
+        load.loaded += 1;
+    }
+    Ok(Some(load))
+}
@@ -0,0 +1,140 @@
+use serde::Deserialize;
+use std::{collections::HashMap, env};
+use tracing::debug;
+
+// --- 子プロセスの環境変数の優先順位 ---
+// "env_policy": "config_wins" (既定) | "env_wins" | { "overridable": ["GITHUB_PERSONAL_ACCESS_TOKEN"] }
+// env に書いた変数を、ブリッジのプロセスの環境変数で上書きできるかを決める。
+// env に無い変数は、どの場合もブリッジの環境変数をそのまま引き継ぐ
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvPolicy {
+    // env の値を常に使う
+    #[default]
+    ConfigWins,
+    // プロセスの環境変数があればそちらを使う
+    EnvWins,
+    // 列挙した変数だけプロセスの環境変数で上書きできる (シークレットの注入用)
+    Overridable(Vec<String>),
+}
+
+impl EnvPolicy {
+    fn is_overridable(&self, name: &str) -> bool {
+        match self {
+            EnvPolicy::ConfigWins => false,
+            EnvPolicy::EnvWins => true,
+            EnvPolicy::Overridable(names) => names.iter().any(|overridable| overridable == name),
+        }
+    }
+
+    // 復号済みの env に、上書きできる変数のプロセスの値を反映する (空の値は未設定として扱う)
+    pub fn apply(
+        &self,
+        server_key: &str,
+        mut config_env: HashMap<String, String>,
+    ) -> HashMap<String, String> {
+        for (name, value) in config_env.iter_mut() {
+            if !self.is_overridable(name) {
+                continue;
+            }
+            if let Some(process_value) = env::var(name).ok().filter(|value| !value.is_empty()) {
+                debug!(server = %server_key, env = %name, "Process environment overrides config env");
+                *value = process_value;
+            }
+        }
+        config_env
+    }
+
+    // overridable に env に無い変数を書いても意味がないため、打ち間違いとして扱う
+    pub fn validate(
+        &self,
+        server_key: &str,
+        config_env: &HashMap<String, String>,
+    ) -> Result<(), String> {
+        let EnvPolicy::Overridable(names) = self else {
+            return Ok(());
+        };
+        match names.iter().find(|name| !config_env.contains_key(*name)) {
+            Some(name) => Err(format!(
+                "MCP server '{}' env_policy lists '{}' as overridable, but it is not set in env",
+                server_key, name
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config_env() -> HashMap<String, String> {
+        HashMap::from([
+            (
+                "MCP_ENV_POLICY_TEST_TOKEN".to_string(),
+                "config".to_string(),
+            ),
+            (
+                "MCP_ENV_POLICY_TEST_REGION".to_string(),
+                "config".to_string(),
+            ),
+            (
+                "MCP_ENV_POLICY_TEST_EMPTY".to_string(),
+                "config".to_string(),
+            ),
+        ])
+    }
+
+    fn set_process_env() {
+        // 他のテストが読まない変数名だけを使う
+        unsafe {
+            env::set_var("MCP_ENV_POLICY_TEST_TOKEN", "process");
+            env::set_var("MCP_ENV_POLICY_TEST_REGION", "process");
+            env::set_var("MCP_ENV_POLICY_TEST_EMPTY", "");
+        }
+    }
+
+    #[test]
+    fn policy_parses_from_string_or_object() {
+        let parse = |value| serde_json::from_value::<EnvPolicy>(value).unwrap();
+        assert_eq!(parse(json!("config_wins")), EnvPolicy::ConfigWins);
+        assert_eq!(parse(json!("env_wins")), EnvPolicy::EnvWins);
+        assert_eq!(
+            parse(json!({ "overridable": ["TOKEN"] })),
+            EnvPolicy::Overridable(vec!["TOKEN".to_string()])
+        );
+        assert!(serde_json::from_value::<EnvPolicy>(json!("process_wins")).is_err());
+    }
+
+    #[test]
+    fn apply_overrides_only_what_the_policy_allows() {
+        set_process_env();
+        let value = |env: &HashMap<String, String>, name: &str| env[name].clone();
+
+        let applied = EnvPolicy::ConfigWins.apply("test", config_env());
+        assert_eq!(value(&applied, "MCP_ENV_POLICY_TEST_TOKEN"), "config");
+
+        let applied = EnvPolicy::EnvWins.apply("test", config_env());
+        assert_eq!(value(&applied, "MCP_ENV_POLICY_TEST_TOKEN"), "process");
+        assert_eq!(value(&applied, "MCP_ENV_POLICY_TEST_REGION"), "process");
+        // 空の値は未設定として扱う
+        assert_eq!(value(&applied, "MCP_ENV_POLICY_TEST_EMPTY"), "config");
+
+        let policy = EnvPolicy::Overridable(vec!["MCP_ENV_POLICY_TEST_TOKEN".to_string()]);
+        let applied = policy.apply("test", config_env());
+        assert_eq!(value(&applied, "MCP_ENV_POLICY_TEST_TOKEN"), "process");
+        assert_eq!(value(&applied, "MCP_ENV_POLICY_TEST_REGION"), "config");
+        assert_eq!(applied.len(), 3);
+    }
+
+    #[test]
+    fn overridable_names_must_be_in_env() {
+        let policy = EnvPolicy::Overridable(vec!["MCP_ENV_POLICY_TEST_TOKEN".to_string()]);
+        assert!(policy.validate("test", &config_env()).is_ok());
+        let typo = EnvPolicy::Overridable(vec!["MCP_ENV_POLICY_TEST_TOKN".to_string()]);
+        let error = typo.validate("test", &config_env()).unwrap_err();
+        assert!(error.contains("'MCP_ENV_POLICY_TEST_TOKN'"), "{}", error);
+        assert!(EnvPolicy::EnvWins.validate("test", &HashMap::new()).is_ok());
+    }
+}
@@ -0,0 +1,108 @@
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// --- 一覧の条件付き GET (ETag / If-None-Match) ---
+// 応答の JSON の SHA-256 を ETag にして返し、If-None-Match が一致すれば本文なしの 304 を返す。
+// /tools などをポーリングするクライアントが、変わっていない一覧を毎回受け取らずに済むようにする
+pub fn json_response<T: Serialize>(request_headers: &HeaderMap, value: &T) -> Response {
+    let body = match serde_json::to_vec(value) {
+        Ok(body) => body,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to serialize response: {}", e),
+            )
+                .into_response();
+        }
+    };
+    let etag = format!("\"{:x}\"", Sha256::digest(&body));
+    let etag_header = HeaderValue::from_str(&etag).expect("hex ETag is a valid header value");
+    // 毎回 ETag で検証させ、一覧が変わったらすぐに新しい内容を受け取れるようにする
+    let headers = [
+        (header::ETAG, etag_header),
+        (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+    ];
+    if matches(request_headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    (
+        headers,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        )],
+        body,
+    )
+        .into_response()
+}
+
+// If-None-Match は弱い比較 (W/ を無視) で、カンマ区切りの複数の値と * を受け付ける
+fn matches(request_headers: &HeaderMap, etag: &str) -> bool {
+    request_headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(if_none_match: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in if_none_match {
+            headers.append(header::IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn etag_of(value: &serde_json::Value) -> String {
+        json_response(&HeaderMap::new(), value).headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn etag_is_stable_and_changes_with_the_body() {
+        let tools = json!({ "tools": [{ "name": "echo" }] });
+        let response = json_response(&HeaderMap::new(), &tools);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(etag_of(&tools), etag_of(&tools));
+        assert_ne!(etag_of(&tools), etag_of(&json!({ "tools": [] })));
+    }
+
+    #[test]
+    fn matching_if_none_match_returns_304_with_the_etag() {
+        let tools = json!({ "tools": [] });
+        let etag = etag_of(&tools);
+        for if_none_match in [
+            vec![etag.as_str()],
+            vec![&format!("W/{}", etag)],
+            vec![&format!("\"other\", {}", etag)],
+            vec!["\"other\"", etag.as_str()],
+            vec!["*"],
+        ] {
+            let response = json_response(&request(&if_none_match), &tools);
+            assert_eq!(
+                response.status(),
+                StatusCode::NOT_MODIFIED,
+                "{:?}",
+                if_none_match
+            );
+            assert_eq!(response.headers()[header::ETAG], etag.as_str());
+        }
+        let response = json_response(&request(&["\"other\""]), &tools);
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
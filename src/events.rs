@@ -0,0 +1,98 @@
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::debug;
+
+// 購読者が追いつけない場合に保持するイベント数
+const EVENT_BUS_CAPACITY: usize = 256;
+
+// --- ライフサイクルイベントの種類 ---
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LifecycleEventKind {
+    SetupStarted,
+    SetupFailed {
+        error: String,
+    },
+    ChildSpawned {
+        pid: Option<u32>,
+    },
+    ChildExited {
+        pid: Option<u32>,
+        exit_code: Option<i32>,
+        // stop/restart による意図した終了かどうか
+        expected: bool,
+    },
+    RestartScheduled {
+        reason: String,
+    },
+    // limits の上限を超えて子プロセスが kill された
+    LimitExceeded {
+        detail: String,
+    },
+}
+
+impl LifecycleEventKind {
+    // name() が返す名前の一覧 (WEBHOOK_EVENTS の検証用)
+    pub const NAMES: [&'static str; 6] = [
+        "setup_started",
+        "setup_failed",
+        "child_spawned",
+        "child_exited",
+        "restart_scheduled",
+        "limit_exceeded",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LifecycleEventKind::SetupStarted => "setup_started",
+            LifecycleEventKind::SetupFailed { .. } => "setup_failed",
+            LifecycleEventKind::ChildSpawned { .. } => "child_spawned",
+            LifecycleEventKind::ChildExited { .. } => "child_exited",
+            LifecycleEventKind::RestartScheduled { .. } => "restart_scheduled",
+            LifecycleEventKind::LimitExceeded { .. } => "limit_exceeded",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LifecycleEvent {
+    pub server: String,
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub kind: LifecycleEventKind,
+}
+
+// --- 内部イベントバス ---
+// 送信側は購読者がいなくてもブロックしない (broadcast チャネル)
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<LifecycleEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        EventBus { sender }
+    }
+}
+
+impl EventBus {
+    pub fn emit(&self, server: &str, kind: LifecycleEventKind) {
+        let event = LifecycleEvent {
+            server: server.to_string(),
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            kind,
+        };
+        debug!(server = %event.server, event = event.kind.name(), "Lifecycle event");
+        // 購読者がいない場合のエラーは無視する
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LifecycleEvent> {
+        self.sender.subscribe()
+    }
+}
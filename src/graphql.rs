@@ -0,0 +1,362 @@
+use crate::{
+    mcp_process::{self, McpServer},
+    servers::ServerSet,
+    tenants::Tenant,
+    tool_schema,
+};
+use async_graphql::{
+    Context, EmptySubscription, Error, ErrorExtensions, Json, Object, Schema, SimpleObject,
+};
+use std::sync::Arc;
+use tracing::warn;
+
+// --- GraphQL スキーマ ---
+// REST API と同じ McpServer (子プロセス層) を使って servers / tools / resources / prompts と
+// callTool を提供する。フェデレーションのサブグラフとして組み込めるよう _service / _entities も公開する
+pub type McpSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+pub fn build_schema(servers: Arc<ServerSet>) -> McpSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .data(servers)
+        .enable_federation()
+        .finish()
+}
+
+// server 引数を省略した場合は既定のサーバー (テナントのキーではその default_server) を対象にする。
+// テナントが利用できないサーバーは存在しないものとして扱う
+fn resolve_server(ctx: &Context<'_>, name: Option<&str>) -> async_graphql::Result<Arc<McpServer>> {
+    let servers = ctx.data::<Arc<ServerSet>>()?;
+    let tenant = ctx.data_opt::<Arc<Tenant>>();
+    let name = name
+        .or(tenant.map(|tenant| tenant.default_server.as_str()))
+        .unwrap_or(&servers.default_server().server_key);
+    servers
+        .get(name)
+        .filter(|server| tenant.is_none_or(|tenant| tenant.allows_server(server)))
+        .cloned()
+        .ok_or_else(|| {
+            Error::new(format!("Unknown MCP server '{}'", name))
+                .extend_with(|_, e| e.set("code", "NOT_FOUND"))
+        })
+}
+
+// 停止中は SERVICE_UNAVAILABLE、子プロセス側のエラーは BAD_GATEWAY (REST の 503 / 502 に対応)
+fn mcp_error(e: String) -> Error {
+    let code = if e.contains("not running")
+        || e.contains(crate::circuit_breaker::CIRCUIT_OPEN_ERROR)
+        || e.contains(mcp_process::QUEUE_FULL_ERROR)
+    {
+        "SERVICE_UNAVAILABLE"
+    } else {
+        "BAD_GATEWAY"
+    };
+    Error::new(e).extend_with(|_, ext| ext.set("code", code))
+}
+
+fn string_field(value: &serde_json::Value, field: &str) -> Option<String> {
+    value
+        .get(field)
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+}
+
+fn json_field(value: &serde_json::Value, field: &str) -> Option<Json<serde_json::Value>> {
+    value.get(field).filter(|v| !v.is_null()).cloned().map(Json)
+}
+
+// */list 系の結果を GraphQL の型に変換する
+async fn list<T>(
+    server: &Arc<McpServer>,
+    method: &'static str,
+    field: &str,
+    convert: fn(&serde_json::Value) -> T,
+) -> async_graphql::Result<Vec<T>> {
+    let items = list_items(server, method, field).await?;
+    Ok(items.iter().map(convert).collect())
+}
+
+async fn list_items(
+    server: &Arc<McpServer>,
+    method: &'static str,
+    field: &str,
+) -> async_graphql::Result<Vec<serde_json::Value>> {
+    server.list_all(method, field).await.map_err(|e| {
+        warn!(server = %server.server_key, method, error = %e, "GraphQL list query failed");
+        mcp_error(e)
+    })
+}
+
+// テナントのキーでは tools.allow / tools.deny で許可されたツールだけを返す
+async fn list_tools(
+    ctx: &Context<'_>,
+    server: &Arc<McpServer>,
+) -> async_graphql::Result<Vec<Tool>> {
+    let mut tools = list_items(server, "tools/list", "tools").await?;
+    if let Some(tenant) = ctx.data_opt::<Arc<Tenant>>() {
+        tools = tenant.tools.filter_tools(tools);
+    }
+    Ok(tools.iter().map(Tool::from_json).collect())
+}
+
+// --- 型定義 ---
+pub struct Server(Arc<McpServer>);
+
+#[Object]
+impl Server {
+    async fn name(&self) -> &str {
+        &self.0.server_key
+    }
+
+    async fn running(&self) -> bool {
+        self.0.is_running().await
+    }
+
+    // initialize の結果 (未初期化の場合は null)
+    async fn protocol_version(&self) -> Option<String> {
+        self.0
+            .initialize_result()
+            .and_then(|result| string_field(&result, "protocolVersion"))
+    }
+
+    async fn server_info(&self) -> Option<Json<serde_json::Value>> {
+        self.0
+            .initialize_result()
+            .and_then(|result| json_field(&result, "serverInfo"))
+    }
+
+    async fn capabilities(&self) -> Option<Json<serde_json::Value>> {
+        self.0
+            .initialize_result()
+            .and_then(|result| json_field(&result, "capabilities"))
+    }
+
+    async fn tools(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Tool>> {
+        list_tools(ctx, &self.0).await
+    }
+
+    async fn resources(&self) -> async_graphql::Result<Vec<Resource>> {
+        list(&self.0, "resources/list", "resources", Resource::from_json).await
+    }
+
+    async fn prompts(&self) -> async_graphql::Result<Vec<Prompt>> {
+        list(&self.0, "prompts/list", "prompts", Prompt::from_json).await
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Tool {
+    name: String,
+    title: Option<String>,
+    description: Option<String>,
+    input_schema: Option<Json<serde_json::Value>>,
+    output_schema: Option<Json<serde_json::Value>>,
+    annotations: Option<Json<serde_json::Value>>,
+}
+
+impl Tool {
+    fn from_json(tool: &serde_json::Value) -> Self {
+        Tool {
+            name: string_field(tool, "name").unwrap_or_default(),
+            title: string_field(tool, "title"),
+            description: string_field(tool, "description"),
+            input_schema: json_field(tool, "inputSchema"),
+            output_schema: json_field(tool, "outputSchema"),
+            annotations: json_field(tool, "annotations"),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Resource {
+    uri: String,
+    name: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    mime_type: Option<String>,
+}
+
+impl Resource {
+    fn from_json(resource: &serde_json::Value) -> Self {
+        Resource {
+            uri: string_field(resource, "uri").unwrap_or_default(),
+            name: string_field(resource, "name"),
+            title: string_field(resource, "title"),
+            description: string_field(resource, "description"),
+            mime_type: string_field(resource, "mimeType"),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Prompt {
+    name: String,
+    title: Option<String>,
+    description: Option<String>,
+    arguments: Option<Json<serde_json::Value>>,
+}
+
+impl Prompt {
+    fn from_json(prompt: &serde_json::Value) -> Self {
+        Prompt {
+            name: string_field(prompt, "name").unwrap_or_default(),
+            title: string_field(prompt, "title"),
+            description: string_field(prompt, "description"),
+            arguments: json_field(prompt, "arguments"),
+        }
+    }
+}
+
+// tools/call の結果 (ツール自体のエラーは isError で表し、GraphQL のエラーにはしない)
+#[derive(SimpleObject)]
+pub struct ToolResult {
+    content: Vec<Json<serde_json::Value>>,
+    structured_content: Option<Json<serde_json::Value>>,
+    is_error: bool,
+}
+
+// --- クエリ ---
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn servers(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Server>> {
+        let servers = ctx.data::<Arc<ServerSet>>()?;
+        Ok(servers
+            .iter()
+            .filter_map(|server| resolve_server(ctx, Some(&server.server_key)).ok())
+            .map(Server)
+            .collect())
+    }
+
+    async fn server(&self, ctx: &Context<'_>, name: String) -> Option<Server> {
+        resolve_server(ctx, Some(&name)).ok().map(Server)
+    }
+
+    async fn tools(
+        &self,
+        ctx: &Context<'_>,
+        server: Option<String>,
+    ) -> async_graphql::Result<Vec<Tool>> {
+        let server = resolve_server(ctx, server.as_deref())?;
+        list_tools(ctx, &server).await
+    }
+
+    async fn resources(
+        &self,
+        ctx: &Context<'_>,
+        server: Option<String>,
+    ) -> async_graphql::Result<Vec<Resource>> {
+        let server = resolve_server(ctx, server.as_deref())?;
+        list(&server, "resources/list", "resources", Resource::from_json).await
+    }
+
+    async fn prompts(
+        &self,
+        ctx: &Context<'_>,
+        server: Option<String>,
+    ) -> async_graphql::Result<Vec<Prompt>> {
+        let server = resolve_server(ctx, server.as_deref())?;
+        list(&server, "prompts/list", "prompts", Prompt::from_json).await
+    }
+
+    // resources/read の contents 配列
+    async fn read_resource(
+        &self,
+        ctx: &Context<'_>,
+        uri: String,
+        server: Option<String>,
+    ) -> async_graphql::Result<Vec<Json<serde_json::Value>>> {
+        let server = resolve_server(ctx, server.as_deref())?;
+        let mut result = server
+            .call("resources/read", serde_json::json!({ "uri": uri }))
+            .await
+            .map_err(mcp_error)?;
+        Ok(
+            match result.get_mut("contents").map(serde_json::Value::take) {
+                Some(serde_json::Value::Array(contents)) => {
+                    contents.into_iter().map(Json).collect()
+                }
+                _ => Vec::new(),
+            },
+        )
+    }
+
+    // フェデレーションのエンティティ解決 (Server @key(fields: "name"))
+    #[graphql(entity)]
+    async fn find_server_by_name(&self, ctx: &Context<'_>, name: String) -> Option<Server> {
+        resolve_server(ctx, Some(&name)).ok().map(Server)
+    }
+}
+
+// --- ミューテーション ---
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    // REST の POST /api/v1/tools/{tool_name} と同じくポリシーと inputSchema を検証してから呼び出す
+    async fn call_tool(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        arguments: Option<Json<serde_json::Value>>,
+        server: Option<String>,
+    ) -> async_graphql::Result<ToolResult> {
+        let server = resolve_server(ctx, server.as_deref())?;
+        let arguments = arguments
+            .map(|Json(arguments)| arguments)
+            .unwrap_or_else(|| serde_json::json!({}));
+        // 書き換えルール (rewrite) は tools/call の JSON-RPC リクエストとして、検証より前に適用する
+        let mut request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments },
+        });
+        server.rewrite_message(&mut request);
+        let params = request
+            .get_mut("params")
+            .map(serde_json::Value::take)
+            .unwrap_or_else(|| serde_json::json!({}));
+        let name = params
+            .get("name")
+            .and_then(|name| name.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let arguments = params
+            .get("arguments")
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({}));
+        if let Some(schema) = server.tool_input_schema(&name).await {
+            tool_schema::validate_arguments(&schema, &arguments).map_err(|violations| {
+                Error::new(format!(
+                    "Arguments for tool '{}' do not match its inputSchema",
+                    name
+                ))
+                .extend_with(|_, e| {
+                    e.set("code", "BAD_USER_INPUT");
+                    e.set("violations", violations.clone());
+                })
+            })?;
+        }
+        let tenant_allows = ctx
+            .data_opt::<Arc<Tenant>>()
+            .is_none_or(|tenant| tenant.tools.is_allowed(&name));
+        if !server.is_tool_allowed(&name) || !tenant_allows {
+            warn!(server = %server.server_key, tool = %name, "Rejected call to blocked tool");
+            return Err(Error::new(format!("Tool '{}' is not allowed", name))
+                .extend_with(|_, e| e.set("code", "FORBIDDEN")));
+        }
+
+        let mut result = server.call("tools/call", params).await.map_err(|e| {
+            warn!(server = %server.server_key, tool = %name, error = %e, "Tool call failed");
+            mcp_error(e)
+        })?;
+        Ok(ToolResult {
+            content: match result.get_mut("content").map(serde_json::Value::take) {
+                Some(serde_json::Value::Array(content)) => content.into_iter().map(Json).collect(),
+                _ => Vec::new(),
+            },
+            structured_content: json_field(&result, "structuredContent"),
+            is_error: result.get("isError").and_then(|v| v.as_bool()) == Some(true),
+        })
+    }
+}
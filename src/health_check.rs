@@ -0,0 +1,315 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+use crate::{hooks, mcp_process::McpProcessConfig, program, sandbox};
+
+// --- ヘルスチェックの設定 ---
+// request (JSON-RPC リクエスト) か command (シェルコマンド) のどちらか一方を指定する
+// "health_check": { "request": { "method": "ping" }, "interval_secs": 30, "restart": true }
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HealthCheckConfig {
+    // 子プロセスに送る JSON-RPC リクエスト ({ "method": ..., "params": ... })
+    #[serde(default)]
+    pub request: Option<HealthCheckRequest>,
+    // request の result に含まれているべき値 (オブジェクトは部分一致)
+    #[serde(default)]
+    pub expect: Option<serde_json::Value>,
+    // 終了コード 0 で正常とみなすコマンド (["curl", "-f", "..."] のような引数の配列)
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    // 1回のチェックを待つ秒数
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    // 連続してこの回数失敗したら unhealthy とする
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    // true の場合、unhealthy になったら子プロセスを再起動する
+    #[serde(default)]
+    pub restart: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HealthCheckRequest {
+    pub method: String,
+    #[serde(default)]
+    pub params: serde_json::Value,
+}
+
+fn default_interval_secs() -> u64 {
+    30
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+impl HealthCheckConfig {
+    pub fn validate(&self, server_key: &str) -> Result<(), String> {
+        match (&self.request, &self.command) {
+            (Some(_), None) => Ok(()),
+            (None, Some(command)) if !command.is_empty() => {
+                if self.expect.is_some() {
+                    return Err(format!(
+                        "MCP server '{}' health_check 'expect' requires 'request'",
+                        server_key
+                    ));
+                }
+                Ok(())
+            }
+            _ => Err(format!(
+                "MCP server '{}' health_check needs exactly one of 'request' or 'command'",
+                server_key
+            )),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.max(1))
+    }
+}
+
+// --- アイドル時の keep-alive ping ---
+// リクエストが interval_secs の間なければ子プロセスに MCP の ping を送り、
+// 応答しない (プロセスは生きているがハングした) 子プロセスを検出する
+// "keep_alive": { "interval_secs": 30, "timeout_secs": 10, "failure_threshold": 3 }
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct KeepAliveConfig {
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    // ping の応答を待つ秒数
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    // 連続してこの回数失敗したら unhealthy とし、restart の方針に従って再起動する
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+impl KeepAliveConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.max(1))
+    }
+}
+
+// expected のすべての要素が actual に含まれているか (オブジェクトはキーごとに再帰的に比較する)
+pub fn matches_expected(actual: &serde_json::Value, expected: &serde_json::Value) -> bool {
+    match (actual, expected) {
+        (serde_json::Value::Object(actual), serde_json::Value::Object(expected)) => {
+            expected.iter().all(|(key, expected)| {
+                actual
+                    .get(key)
+                    .is_some_and(|actual| matches_expected(actual, expected))
+            })
+        }
+        _ => actual == expected,
+    }
+}
+
+// command をサーバーの env と cwd で実行し、終了コード 0 以外は失敗とする
+// (タイムアウトは呼び出し側で扱う。打ち切られた場合はコマンドを kill する)
+pub async fn run_command(
+    server_key: &str,
+    command: &[String],
+    config: &McpProcessConfig,
+) -> Result<(), String> {
+    let Some((program, args)) = command.split_first() else {
+        return Err("Empty health check command".to_string());
+    };
+    let mut builder = program::command(program);
+    builder
+        .args(args)
+        .envs(&config.child_env(server_key)?)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true);
+    sandbox::apply(&mut builder, server_key, config)?;
+    let output = builder
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run health check command: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Health check command failed with {}: {}",
+            output.status,
+            hooks::stderr_tail(&output.stderr)
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    // まだチェックしていない (子プロセスの停止中を含む)
+    Unknown,
+    Healthy,
+    Unhealthy,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HealthSnapshot {
+    pub status: HealthStatus,
+    pub consecutive_failures: u32,
+    pub last_checked_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+struct CheckState {
+    status: HealthStatus,
+    consecutive_failures: u32,
+    last_checked_ms: u64,
+    last_error: Option<String>,
+}
+
+// --- ヘルスチェックの結果 ---
+// プロセスの再起動をまたいで保持し、/readyz と /stats から参照される
+pub struct HealthChecker {
+    server_key: String,
+    state: Mutex<CheckState>,
+}
+
+impl HealthChecker {
+    pub fn new(server_key: &str) -> Self {
+        HealthChecker {
+            server_key: server_key.to_string(),
+            state: Mutex::new(CheckState {
+                status: HealthStatus::Unknown,
+                consecutive_failures: 0,
+                last_checked_ms: 0,
+                last_error: None,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CheckState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn is_unhealthy(&self) -> bool {
+        self.lock().status == HealthStatus::Unhealthy
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.lock();
+        if state.status == HealthStatus::Unhealthy {
+            info!(server = %self.server_key, "Health check recovered");
+        }
+        state.status = HealthStatus::Healthy;
+        state.consecutive_failures = 0;
+        state.last_checked_ms = now_ms();
+        state.last_error = None;
+    }
+
+    // unhealthy になった (閾値に達した) 場合に true を返す
+    pub fn record_failure(&self, error: String, failure_threshold: u32) -> bool {
+        let mut state = self.lock();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        state.last_checked_ms = now_ms();
+        warn!(
+            server = %self.server_key,
+            consecutive_failures = state.consecutive_failures,
+            error = %error,
+            "Health check failed"
+        );
+        state.last_error = Some(error);
+        if state.consecutive_failures < failure_threshold.max(1) {
+            return false;
+        }
+        if state.status != HealthStatus::Unhealthy {
+            warn!(server = %self.server_key, "MCP server marked unhealthy");
+        }
+        state.status = HealthStatus::Unhealthy;
+        true
+    }
+
+    // 子プロセスの停止・再起動時に、次のチェックまで未確認に戻す
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.status = HealthStatus::Unknown;
+        state.consecutive_failures = 0;
+    }
+
+    pub fn snapshot(&self) -> HealthSnapshot {
+        let state = self.lock();
+        HealthSnapshot {
+            status: state.status,
+            consecutive_failures: state.consecutive_failures,
+            last_checked_ms: (state.last_checked_ms != 0).then_some(state.last_checked_ms),
+            last_error: state.last_error.clone(),
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+// `mcp-http-server healthcheck` で /readyz の応答を待つ時間
+const READYZ_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// --- `mcp-http-server healthcheck` ---
+// Docker の HEALTHCHECK 用。同じコンテナで動いているブリッジの /readyz を呼び、200 以外なら失敗にする
+// (curl の無いイメージでも使え、/readyz の ping で子プロセスのハングも検出できる)
+pub async fn check_readyz() -> Result<String, String> {
+    let url = env::var("HEALTHCHECK_URL")
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| {
+            let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
+            format!("http://127.0.0.1:{}/readyz", port)
+        });
+    let client = reqwest::Client::builder()
+        .timeout(READYZ_REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let mut request = client.get(&url);
+    // HTTP_API_KEY がない (またはハッシュの) 場合は HTTP_API_KEY_FILE の先頭の平文のキーを使う
+    let api_key = match env::var("HTTP_API_KEY")
+        .ok()
+        .filter(|key| !key.is_empty())
+        .and_then(|key| crate::api_keys::StoredKey::parse(&key).ok())
+        .and_then(|key| key.plaintext().map(str::to_string))
+    {
+        Some(api_key) => Some(api_key),
+        None => crate::api_keys::ApiKeyFile::from_env()?.and_then(|key_file| key_file.first_key()),
+    };
+    if let Some(api_key) = api_key {
+        request = request.bearer_auth(api_key);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("{} is not reachable: {}", url, e))?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(format!("{} returned {}: {}", url, status, body))
+    }
+}
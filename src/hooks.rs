@@ -0,0 +1,245 @@
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+use tokio::{sync::Mutex, time::timeout};
+use tracing::{debug, info};
+
+use crate::{
+    mcp_process::McpProcessConfig,
+    program, sandbox,
+    setup_manifest::{self, SetupManifest},
+};
+
+// サーバーごとの post_install の実行済みフラグ (セッションごとのプロセスや再起動では繰り返さない)。
+// 別のサーバーの post_install とは並行して実行できるよう、サーバーごとにロックを分ける
+static INSTALLED: LazyLock<std::sync::Mutex<HashMap<String, Arc<Mutex<bool>>>>> =
+    LazyLock::new(Default::default);
+
+// 失敗時のエラーメッセージに含める標準エラー出力の末尾の文字数
+const STDERR_TAIL_CHARS: usize = 500;
+
+// --- 子プロセス起動前に実行するコマンド ---
+// 各コマンドは ["npm", "run", "build"] のような引数の配列で、サーバーの env と cwd で実行する
+#[derive(Deserialize, Debug, Clone)]
+pub struct HookConfig {
+    // ブリッジの起動後、最初に子プロセスを起動する前に1回だけ実行する
+    // (cwd がある場合は .mcp-setup.json の記録と一致すればブリッジの再起動後も実行しない)
+    #[serde(default)]
+    pub post_install: Vec<Vec<String>>,
+    // 子プロセスを起動する (再起動を含む) たびに実行する
+    #[serde(default)]
+    pub pre_start: Vec<Vec<String>>,
+    // 1つのコマンドの実行を待つ秒数
+    #[serde(default = "default_hook_timeout_secs")]
+    pub hook_timeout_secs: u64,
+}
+
+fn default_hook_timeout_secs() -> u64 {
+    300
+}
+
+// post_install (未実行の場合) と pre_start を順に実行する。いずれかが失敗したら子プロセスは起動しない
+pub async fn run_before_spawn(server_key: &str, config: &McpProcessConfig) -> Result<(), String> {
+    if !config.hooks.post_install.is_empty() {
+        // 同時に起動したセッションで重複して実行しないよう、完了まで保持する
+        let installed = installed_flag(server_key);
+        let mut installed = installed.lock().await;
+        if !*installed {
+            install(server_key, config).await?;
+            *installed = true;
+        }
+    }
+    for command in &config.hooks.pre_start {
+        run(server_key, "pre_start", command, config).await?;
+    }
+    Ok(())
+}
+
+fn installed_flag(server_key: &str) -> Arc<Mutex<bool>> {
+    Arc::clone(
+        INSTALLED
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .entry(server_key.to_string())
+            .or_default(),
+    )
+}
+
+// 管理APIの update。cwd が git リポジトリなら git pull --ff-only で更新し、
+// post_install を .mcp-setup.json の記録に関わらず実行し直す (子プロセスの再起動は呼び出し側で行う)
+pub async fn update(server_key: &str, config: &McpProcessConfig) -> Result<(), String> {
+    let cwd = sandbox::host_cwd(config);
+    let repo = match &cwd {
+        Some(cwd) => setup_manifest::git(cwd, &["rev-parse", "--show-toplevel"]).await,
+        None => None,
+    };
+    if repo.is_none() && config.hooks.post_install.is_empty() {
+        return Err(format!(
+            "MCP server '{}' has nothing to update: its cwd is not a git repository and it has no post_install hooks",
+            server_key
+        ));
+    }
+    // 起動中のセッションの post_install と重ならないよう、実行済みフラグのロックを保持する
+    let installed = installed_flag(server_key);
+    let mut installed = installed.lock().await;
+    if let (Some(cwd), Some(_)) = (&cwd, &repo) {
+        git_pull(server_key, cwd, config).await?;
+    }
+    for command in &config.hooks.post_install {
+        run(server_key, "post_install", command, config).await?;
+    }
+    if let Some(cwd) = &cwd {
+        SetupManifest::current(server_key, config, cwd)
+            .await
+            .save(cwd)
+            .await;
+    }
+    *installed = true;
+    Ok(())
+}
+
+// git はホストで実行する (サンドボックスの中には無いことがある)
+async fn git_pull(server_key: &str, cwd: &Path, config: &McpProcessConfig) -> Result<(), String> {
+    info!(server = %server_key, cwd = %cwd.display(), "Pulling the MCP server's repository");
+    let hook_timeout = Duration::from_secs(config.hooks.hook_timeout_secs);
+    let output = timeout(
+        hook_timeout,
+        tokio::process::Command::new("git")
+            .arg("-C")
+            .arg(cwd)
+            .args(["pull", "--ff-only"])
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| {
+        format!(
+            "git pull for MCP server '{}' timed out after {} seconds",
+            server_key,
+            hook_timeout.as_secs()
+        )
+    })?
+    .map_err(|e| {
+        format!(
+            "Failed to run git pull for MCP server '{}': {}",
+            server_key, e
+        )
+    })?;
+    if !output.status.success() {
+        return Err(format!(
+            "git pull for MCP server '{}' failed with {}: {}",
+            server_key,
+            output.status,
+            stderr_tail(&output.stderr)
+        ));
+    }
+    Ok(())
+}
+
+// post_install を実行し、cwd があれば結果を記録する
+async fn install(server_key: &str, config: &McpProcessConfig) -> Result<(), String> {
+    let Some(cwd) = &sandbox::host_cwd(config) else {
+        for command in &config.hooks.post_install {
+            run(server_key, "post_install", command, config).await?;
+        }
+        return Ok(());
+    };
+    let mut current = SetupManifest::current(server_key, config, cwd).await;
+    if let Some(previous) = SetupManifest::load(cwd)
+        .await
+        .filter(|previous| previous.is_up_to_date(&current))
+    {
+        info!(
+            server = %server_key,
+            commit = ?current.commit,
+            installed_at_ms = previous.installed_at_ms,
+            "Setup is up to date, skipping post_install"
+        );
+        current.installed_at_ms = previous.installed_at_ms;
+        current.save(cwd).await;
+        return Ok(());
+    }
+    for command in &config.hooks.post_install {
+        run(server_key, "post_install", command, config).await?;
+    }
+    current.save(cwd).await;
+    Ok(())
+}
+
+async fn run(
+    server_key: &str,
+    phase: &str,
+    command: &[String],
+    config: &McpProcessConfig,
+) -> Result<(), String> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(format!(
+            "Empty {} hook for MCP server '{}'",
+            phase, server_key
+        ));
+    };
+    let command_line = command.join(" ");
+    info!(server = %server_key, phase, command = %command_line, "Running setup hook");
+
+    let mut builder = program::command(program);
+    builder
+        .args(args)
+        .envs(&config.child_env(server_key)?)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true);
+    sandbox::apply(&mut builder, server_key, config)?;
+    let hook_timeout = Duration::from_secs(config.hooks.hook_timeout_secs);
+    let output = timeout(hook_timeout, builder.output())
+        .await
+        .map_err(|_| {
+            format!(
+                "{} hook '{}' for MCP server '{}' timed out after {} seconds",
+                phase,
+                command_line,
+                server_key,
+                hook_timeout.as_secs()
+            )
+        })?
+        .map_err(|e| {
+            format!(
+                "Failed to run {} hook '{}' for MCP server '{}': {}",
+                phase, command_line, server_key, e
+            )
+        })?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "{} hook '{}' for MCP server '{}' failed with {}: {}",
+            phase,
+            command_line,
+            server_key,
+            output.status,
+            stderr_tail(&output.stderr)
+        ));
+    }
+    debug!(
+        server = %server_key,
+        phase,
+        command = %command_line,
+        stdout = %String::from_utf8_lossy(&output.stdout).trim(),
+        "Setup hook completed"
+    );
+    Ok(())
+}
+
+// エラーメッセージに含める標準エラー出力の末尾 (STDERR_TAIL_CHARS 文字まで)
+pub fn stderr_tail(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let stderr = stderr.trim();
+    let tail_start = stderr
+        .char_indices()
+        .rev()
+        .nth(STDERR_TAIL_CHARS)
+        .map_or(0, |(index, _)| index);
+    stderr[tail_start..].to_string()
+}
@@ -0,0 +1,135 @@
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::mcp_process::McpResponse;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+// --- Idempotency-Key ヘッダー ---
+// 保存済みのレスポンスはサーバー・テナント・キーの組で引く (別のテナントや別のサーバーとはキーを共有しない)。
+// リクエストボディのハッシュも保存し、同じキーを別のリクエストに使い回した場合は返さない
+pub struct IdempotencyKey {
+    key: String,
+    tenant: Option<String>,
+    body_hash: String,
+}
+
+// ストレージに保存する内容
+#[derive(Serialize, Deserialize)]
+struct IdempotentEntry {
+    body_hash: String,
+    response: McpResponse,
+}
+
+pub enum IdempotentLookup {
+    Hit(McpResponse),
+    // 同じキーで別のリクエストボディのレスポンスが保存されている
+    BodyMismatch,
+}
+
+impl IdempotencyKey {
+    // command はクライアントが送った形のまま (ブリッジが id を補完する前) のリクエスト
+    pub fn from_headers(headers: &HeaderMap, tenant: Option<&str>, command: &str) -> Option<Self> {
+        let key = headers
+            .get(IDEMPOTENCY_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())?;
+        Some(IdempotencyKey {
+            key: key.to_string(),
+            tenant: tenant.map(str::to_string),
+            body_hash: body_hash(command),
+        })
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    // 区切り文字を含む名前どうしで衝突しないよう、JSON 配列にしてからハッシュする
+    pub fn storage_key(&self, server: &str) -> String {
+        let parts = serde_json::json!([server, self.tenant, self.key]);
+        format!("{:x}", Sha256::digest(parts.to_string().as_bytes()))
+    }
+
+    pub fn encode(&self, response: &McpResponse) -> Result<String, String> {
+        let entry = IdempotentEntry {
+            body_hash: self.body_hash.clone(),
+            response: McpResponse {
+                result: response.result.clone(),
+            },
+        };
+        serde_json::to_string(&entry).map_err(|e| e.to_string())
+    }
+
+    pub fn decode(&self, stored: &str) -> Result<IdempotentLookup, String> {
+        let entry: IdempotentEntry = serde_json::from_str(stored).map_err(|e| e.to_string())?;
+        Ok(match entry.body_hash == self.body_hash {
+            true => IdempotentLookup::Hit(entry.response),
+            false => IdempotentLookup::BodyMismatch,
+        })
+    }
+}
+
+// JSON として読める場合は空白やキーの順序の違いを無視する
+fn body_hash(command: &str) -> String {
+    let canonical = serde_json::from_str::<serde_json::Value>(command)
+        .map(|value| value.to_string())
+        .unwrap_or_else(|_| command.to_string());
+    format!("{:x}", Sha256::digest(canonical.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn key(tenant: Option<&str>, command: &str) -> IdempotencyKey {
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, HeaderValue::from_static("retry-1"));
+        IdempotencyKey::from_headers(&headers, tenant, command).unwrap()
+    }
+
+    #[test]
+    fn storage_key_is_scoped_by_server_and_tenant() {
+        let a = key(Some("tenant-a"), "{}");
+        let b = key(Some("tenant-b"), "{}");
+        assert_ne!(a.storage_key("github"), b.storage_key("github"));
+        assert_ne!(a.storage_key("github"), a.storage_key("readability"));
+        assert_eq!(
+            a.storage_key("github"),
+            key(Some("tenant-a"), "{}").storage_key("github")
+        );
+    }
+
+    #[test]
+    fn decode_rejects_a_different_body() {
+        let original = key(None, r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+        let stored = original
+            .encode(&McpResponse {
+                result: "ok".to_string(),
+            })
+            .unwrap();
+        // 空白とキーの順序だけが違うリクエストは同じボディとみなす
+        let reordered = key(
+            None,
+            r#"{"method": "tools/list", "id": 1, "jsonrpc": "2.0"}"#,
+        );
+        assert!(
+            matches!(reordered.decode(&stored), Ok(IdempotentLookup::Hit(response)) if response.result == "ok")
+        );
+        let other = key(None, r#"{"jsonrpc":"2.0","id":1,"method":"prompts/list"}"#);
+        assert!(matches!(
+            other.decode(&stored),
+            Ok(IdempotentLookup::BodyMismatch)
+        ));
+    }
+
+    #[test]
+    fn missing_or_empty_header_is_ignored() {
+        let mut headers = HeaderMap::new();
+        assert!(IdempotencyKey::from_headers(&headers, None, "{}").is_none());
+        headers.insert(IDEMPOTENCY_KEY_HEADER, HeaderValue::from_static(""));
+        assert!(IdempotencyKey::from_headers(&headers, None, "{}").is_none());
+    }
+}
@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use crate::{config_source, env_file};
+
+const DEFAULT_CONFIG_FILE: &str = "mcp_servers.config.json";
+
+// --- `mcp-http-server init <template>...` の雛形 ---
+struct Template {
+    name: &'static str,
+    // 設定ファイルに書くサーバーの定義 (2段目のインデントで書く)
+    config: &'static str,
+    // .env に書く変数 (名前, 説明)。設定からは ${VAR} で参照する
+    env: &'static [(&'static str, &'static str)],
+}
+
+const TEMPLATES: &[Template] = &[
+    Template {
+        name: "readability",
+        config: r#"{
+    "command": "npx",
+    "args": ["-y", "@mizchi/readability", "--mcp"]
+  }"#,
+        env: &[],
+    },
+    Template {
+        name: "github",
+        config: r#"{
+    "command": "npx",
+    "args": ["-y", "@modelcontextprotocol/server-github"],
+    "env": { "GITHUB_PERSONAL_ACCESS_TOKEN": "${GITHUB_PERSONAL_ACCESS_TOKEN}" }
+  }"#,
+        env: &[(
+            "GITHUB_PERSONAL_ACCESS_TOKEN",
+            "GitHub personal access token",
+        )],
+    },
+    Template {
+        name: "filesystem",
+        config: r#"{
+    "command": "npx",
+    "args": ["-y", "@modelcontextprotocol/server-filesystem", "${FILESYSTEM_ROOT}"]
+  }"#,
+        env: &[(
+            "FILESYSTEM_ROOT",
+            "Absolute path of the directory to expose",
+        )],
+    },
+    Template {
+        name: "fetch",
+        config: r#"{
+    "command": "uvx",
+    "args": ["mcp-server-fetch"]
+  }"#,
+        env: &[],
+    },
+];
+
+// 設定ファイルと .env の雛形を書き出し、書き出したファイルを返す。
+// 既存のファイルは force の場合だけ上書きする
+pub fn run(names: &[String], force: bool) -> Result<String, String> {
+    let available = || {
+        TEMPLATES
+            .iter()
+            .map(|template| template.name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if names.is_empty() {
+        return Err(format!(
+            "Usage: mcp-http-server init <template>... [--force] (templates: {})",
+            available()
+        ));
+    }
+    let templates = names
+        .iter()
+        .enumerate()
+        .filter(|(index, name)| !names[..*index].contains(name))
+        .map(|(_, name)| {
+            TEMPLATES
+                .iter()
+                .find(|template| template.name == name)
+                .ok_or_else(|| format!("Unknown template '{}' (available: {})", name, available()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let config_file = std::env::var("MCP_CONFIG_FILE")
+        .ok()
+        .filter(|path| !path.is_empty())
+        .unwrap_or_else(|| DEFAULT_CONFIG_FILE.to_string());
+    if config_source::is_remote(&config_file) {
+        return Err(format!(
+            "MCP_CONFIG_FILE '{}' is a URL; unset it to write a local config",
+            config_file
+        ));
+    }
+    let env_path = env_file::path();
+    if let Some(path) = [Path::new(&config_file), env_path.as_path()]
+        .into_iter()
+        .find(|path| !force && path.exists())
+    {
+        return Err(format!(
+            "'{}' already exists; use --force to overwrite it",
+            path.display()
+        ));
+    }
+
+    // 手で編集するファイルなので、キーの順序と書式は雛形のまま書き出す
+    let entries: Vec<String> = templates
+        .iter()
+        .map(|template| format!("  \"{}\": {}", template.name, template.config))
+        .collect();
+    let config = format!("{{\n{}\n}}\n", entries.join(",\n"));
+    std::fs::write(&config_file, config)
+        .map_err(|e| format!("Failed to write '{}': {}", config_file, e))?;
+    std::fs::write(&env_path, env_skeleton(names, &templates, &config_file))
+        .map_err(|e| format!("Failed to write '{}': {}", env_path.display(), e))?;
+
+    Ok(format!(
+        "Wrote {} and {}\nSet the variables in {}, then run `mcp-http-server validate`",
+        config_file,
+        env_path.display(),
+        env_path.display()
+    ))
+}
+
+// ${VAR} の変数はコメントにしておき、設定するまでは起動時に未設定のエラーになるようにする
+fn env_skeleton(names: &[String], templates: &[&Template], config_file: &str) -> String {
+    let mut lines = vec![
+        format!("# Generated by `mcp-http-server init {}`", names.join(" ")),
+        String::new(),
+        "# HTTP Server Authentication".to_string(),
+        format!("HTTP_API_KEY={}", uuid::Uuid::new_v4().simple()),
+        "DISABLE_AUTH=false".to_string(),
+        String::new(),
+        "# MCP Server Configuration".to_string(),
+        format!("MCP_CONFIG_FILE={}", config_file),
+        format!("MCP_SERVER_NAME={}", templates[0].name),
+        String::new(),
+        "PORT=3000".to_string(),
+        "RUST_LOG=info".to_string(),
+    ];
+    for template in templates.iter().filter(|template| !template.env.is_empty()) {
+        lines.push(String::new());
+        lines.push(format!("# Required by the {} server", template.name));
+        for (name, description) in template.env {
+            lines.push(format!("# {}", description));
+            lines.push(format!("# {}=", name));
+        }
+    }
+    lines.join("\n") + "\n"
+}
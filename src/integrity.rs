@@ -0,0 +1,155 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{env, path::PathBuf};
+use tracing::{info, warn};
+
+use crate::{mcp_process::McpProcessConfig, sandbox, setup_manifest};
+
+// --- 実行するコードの固定 (commit / checksum) ---
+// フックや子プロセスを実行する前に、作業ディレクトリの HEAD とファイルの SHA-256 を設定値と照合する
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct IntegrityConfig {
+    // cwd の git チェックアウトの HEAD (40文字の SHA-1)
+    #[serde(default)]
+    pub commit: Option<String>,
+    // checksum_path (省略時は command) の SHA-256 ("sha256:" の接頭辞は任意)
+    #[serde(default)]
+    pub checksum: Option<String>,
+    #[serde(default)]
+    pub checksum_path: Option<PathBuf>,
+}
+
+impl IntegrityConfig {
+    pub fn validate(&self, server_key: &str) -> Result<(), String> {
+        if self
+            .commit
+            .as_ref()
+            .is_some_and(|commit| !is_hex_digest(commit, 40))
+        {
+            return Err(format!(
+                "MCP server '{}' commit must be a full 40-character SHA",
+                server_key
+            ));
+        }
+        if self.checksum.as_ref().is_some_and(|checksum| {
+            !is_hex_digest(checksum.strip_prefix("sha256:").unwrap_or(checksum), 64)
+        }) {
+            return Err(format!(
+                "MCP server '{}' checksum must be a SHA-256 hex digest",
+                server_key
+            ));
+        }
+        if self.checksum_path.is_some() && self.checksum.is_none() {
+            return Err(format!(
+                "MCP server '{}' checksum_path requires checksum",
+                server_key
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn is_hex_digest(value: &str, len: usize) -> bool {
+    value.len() == len && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// 不一致の場合はエラー (SKIP_INTEGRITY_CHECK=true なら警告して続行する)
+pub async fn verify(server_key: &str, config: &McpProcessConfig) -> Result<(), String> {
+    let integrity = &config.integrity;
+    if integrity.commit.is_none() && integrity.checksum.is_none() {
+        return Ok(());
+    }
+    let result = async {
+        if let Some(expected) = &integrity.commit {
+            verify_commit(server_key, config, expected).await?;
+        }
+        if let Some(expected) = &integrity.checksum {
+            verify_checksum(server_key, config, expected).await?;
+        }
+        Ok(())
+    }
+    .await;
+    match result {
+        Ok(()) => {
+            info!(server = %server_key, "Integrity check passed");
+            Ok(())
+        }
+        Err(e) if skip_requested() => {
+            warn!(server = %server_key, error = %e, "Integrity check failed, continuing because SKIP_INTEGRITY_CHECK is set");
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn skip_requested() -> bool {
+    env::var("SKIP_INTEGRITY_CHECK")
+        .map(|value| value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+async fn verify_commit(
+    server_key: &str,
+    config: &McpProcessConfig,
+    expected: &str,
+) -> Result<(), String> {
+    let cwd = sandbox::host_cwd(config).ok_or_else(|| {
+        format!(
+            "MCP server '{}' pins a commit but has no cwd to check",
+            server_key
+        )
+    })?;
+    let actual = setup_manifest::git(&cwd, &["rev-parse", "HEAD"])
+        .await
+        .ok_or_else(|| {
+            format!(
+                "Failed to resolve the commit of '{}' for MCP server '{}'",
+                cwd.display(),
+                server_key
+            )
+        })?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(format!(
+            "Commit mismatch for MCP server '{}': expected {}, found {}",
+            server_key, expected, actual
+        ));
+    }
+    Ok(())
+}
+
+async fn verify_checksum(
+    server_key: &str,
+    config: &McpProcessConfig,
+    expected: &str,
+) -> Result<(), String> {
+    let expected = expected.strip_prefix("sha256:").unwrap_or(expected);
+    let path = config
+        .integrity
+        .checksum_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(&config.command));
+    // 相対パスは子プロセスと同じく cwd を基準にする
+    let path = match sandbox::host_cwd(config) {
+        Some(cwd) if path.is_relative() => cwd.join(path),
+        _ => path,
+    };
+    let content = tokio::fs::read(&path).await.map_err(|e| {
+        format!(
+            "Failed to read '{}' to verify the checksum of MCP server '{}': {}",
+            path.display(),
+            server_key,
+            e
+        )
+    })?;
+    let actual = format!("{:x}", Sha256::digest(&content));
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(format!(
+            "Checksum mismatch for MCP server '{}' ('{}'): expected sha256:{}, found sha256:{}",
+            server_key,
+            path.display(),
+            expected,
+            actual
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use serde_json::{Map, Value};
+
+// --- 受信した command を JSON-RPC 2.0 メッセージとして検証する ---
+// 問題なければ (必要に応じて id を補完した) 転送用の文字列を、不正ならエラー内容を返す
+pub fn normalize_request(command: &str) -> Result<String, String> {
+    let mut value: Value =
+        serde_json::from_str(command).map_err(|e| format!("command is not valid JSON: {}", e))?;
+    match &mut value {
+        Value::Object(request) => normalize_message(request, None)?,
+        Value::Array(batch) if batch.is_empty() => {
+            return Err("command is an empty JSON-RPC batch".to_string());
+        }
+        Value::Array(batch) => {
+            for (index, message) in batch.iter_mut().enumerate() {
+                let Value::Object(request) = message else {
+                    return Err(format!("batch[{}]: message must be a JSON object", index));
+                };
+                normalize_message(request, Some(index))?;
+            }
+        }
+        _ => return Err("command must be a JSON-RPC object or batch array".to_string()),
+    }
+    Ok(value.to_string())
+}
+
+fn normalize_message(request: &mut Map<String, Value>, index: Option<usize>) -> Result<(), String> {
+    let prefix = index
+        .map(|index| format!("batch[{}]: ", index))
+        .unwrap_or_default();
+    if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(format!("{}\"jsonrpc\" must be \"2.0\"", prefix));
+    }
+    let notification = match request.get("method") {
+        Some(Value::String(method)) if !method.is_empty() => method.starts_with("notifications/"),
+        Some(_) => return Err(format!("{}\"method\" must be a non-empty string", prefix)),
+        None => return Err(format!("{}\"method\" is required", prefix)),
+    };
+    match request.get("params") {
+        None | Some(Value::Object(_)) | Some(Value::Array(_)) => {}
+        Some(_) => return Err(format!("{}\"params\" must be an object or array", prefix)),
+    }
+    match request.get("id") {
+        // null の id は子プロセスに送るときにブリッジの id に置き換え、応答で null に戻す
+        Some(Value::String(_)) | Some(Value::Number(_)) | Some(Value::Null) => {}
+        Some(_) => return Err(format!("{}\"id\" must be a string, number or null", prefix)),
+        // 通知 (notifications/*) は id なしのまま転送する (子プロセスは応答を返さない)
+        None if notification => {}
+        // id がないとレスポンスが返らず待ち続けるため、ブリッジ側で割り当てる
+        None => {
+            request.insert(
+                "id".to_string(),
+                Value::String(format!("mcp-http-server-{}", uuid::Uuid::new_v4())),
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(command: &str) -> Value {
+        serde_json::from_str(&normalize_request(command).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rejects_invalid_json_and_messages() {
+        assert!(
+            normalize_request("{not json")
+                .unwrap_err()
+                .starts_with("command is not valid JSON")
+        );
+        assert_eq!(
+            normalize_request("[]").unwrap_err(),
+            "command is an empty JSON-RPC batch"
+        );
+        assert_eq!(
+            normalize_request(r#"{"jsonrpc":"1.0","id":1,"method":"ping"}"#).unwrap_err(),
+            "\"jsonrpc\" must be \"2.0\""
+        );
+        assert_eq!(
+            normalize_request(r#"[{"jsonrpc":"2.0","method":"ping"},{"jsonrpc":"2.0","id":1}]"#)
+                .unwrap_err(),
+            "batch[1]: \"method\" is required"
+        );
+        assert_eq!(
+            normalize_request(r#"{"jsonrpc":"2.0","id":true,"method":"ping"}"#).unwrap_err(),
+            "\"id\" must be a string, number or null"
+        );
+    }
+
+    #[test]
+    fn assigns_ids_to_requests_without_one() {
+        let request = normalized(r#"{"jsonrpc":"2.0","method":"tools/list"}"#);
+        assert!(
+            request["id"]
+                .as_str()
+                .is_some_and(|id| id.starts_with("mcp-http-server-"))
+        );
+        let batch = normalized(
+            r#"[{"jsonrpc":"2.0","id":7,"method":"ping"},{"jsonrpc":"2.0","method":"ping"}]"#,
+        );
+        assert_eq!(batch[0]["id"], 7);
+        assert!(batch[1]["id"].is_string());
+    }
+
+    #[test]
+    fn leaves_notifications_without_ids() {
+        let notification = normalized(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#);
+        assert!(notification.get("id").is_none());
+        let batch = normalized(
+            r#"[{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":1}},{"jsonrpc":"2.0","method":"ping"}]"#,
+        );
+        assert!(batch[0].get("id").is_none());
+        assert!(batch[1]["id"].is_string());
+    }
+}
@@ -0,0 +1,301 @@
+use serde::Deserialize;
+use std::{env::consts::EXE_SUFFIX, fmt};
+
+use crate::{mcp_process::McpProcessConfig, sandbox};
+
+// go のビルドで作った実行ファイルを置く、cwd の中のディレクトリ
+const GO_BUILD_DIR: &str = ".mcp-build";
+
+// --- language: cwd のソースからビルドして起動するサーバー ---
+// 既定では post_install でビルドし、できた実行ファイルを command として起動する。
+// command を書いた場合は何も補わない
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    // go build -o .mcp-build/<サーバー名> <entrypoint> (entrypoint の既定はモジュールのルート)。
+    // post_install を書いた場合は go run <entrypoint> で起動する
+    Go,
+    // cargo build --release でビルドし、target/release/<entrypoint> を起動する
+    // (entrypoint の既定は Cargo.toml の [package] の name)
+    Rust,
+    // entrypoint の .wasm を組み込みの wasmtime で実行する (command は使わない、wasm_server を参照)
+    Wasm,
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Language::Go => "go",
+            Language::Rust => "rust",
+            Language::Wasm => "wasm",
+        })
+    }
+}
+
+// 設定の読み込み時に、language の既定の command・args・post_install を補う
+pub fn apply(server_key: &str, config: &mut McpProcessConfig) -> Result<(), String> {
+    let Some(language) = config.language else {
+        return Ok(());
+    };
+    if config.cwd.is_none() {
+        return Err(format!(
+            "'cwd' (the source checkout) is required for {} servers",
+            language
+        ));
+    }
+    if language == Language::Wasm {
+        return wasm(config);
+    }
+    if !config.command.is_empty() {
+        return Ok(());
+    }
+    match language {
+        Language::Go => go(server_key, config),
+        Language::Rust => rust(config)?,
+        Language::Wasm => {}
+    }
+    Ok(())
+}
+
+// go build ./... は複数のパッケージに一致すると実行ファイルを書き出さないため、entrypoint だけをビルドする
+fn go(server_key: &str, config: &mut McpProcessConfig) {
+    let entrypoint = config.entrypoint.clone().unwrap_or_else(|| ".".to_string());
+    if !config.hooks.post_install.is_empty() {
+        config.command = "go".to_string();
+        config.args.splice(0..0, ["run".to_string(), entrypoint]);
+        return;
+    }
+    let binary = format!("{}/{}{}", GO_BUILD_DIR, server_key, EXE_SUFFIX);
+    config.hooks.post_install = vec![vec![
+        "go".to_string(),
+        "build".to_string(),
+        "-o".to_string(),
+        binary.clone(),
+        entrypoint,
+    ]];
+    config.command = format!("./{}", binary);
+    config.built_command = true;
+}
+
+// post_install を書いた場合も、できた実行ファイルは target/release にあるものとする
+fn rust(config: &mut McpProcessConfig) -> Result<(), String> {
+    let binary = match &config.entrypoint {
+        Some(entrypoint) => entrypoint.clone(),
+        None => {
+            let manifest = sandbox::host_cwd(config)
+                .unwrap_or_default()
+                .join("Cargo.toml");
+            std::fs::read_to_string(&manifest)
+                .ok()
+                .and_then(|manifest| package_name(&manifest))
+                .ok_or_else(|| {
+                    format!(
+                        "could not read the package name from '{}'; set 'entrypoint' to the binary name",
+                        manifest.display()
+                    )
+                })?
+        }
+    };
+    if config.hooks.post_install.is_empty() {
+        let mut build = vec![
+            "cargo".to_string(),
+            "build".to_string(),
+            "--release".to_string(),
+        ];
+        if config.entrypoint.is_some() {
+            build.extend(["--bin".to_string(), binary.clone()]);
+        }
+        config.hooks.post_install = vec![build];
+    }
+    config.command = format!("./target/release/{}{}", binary, EXE_SUFFIX);
+    config.built_command = true;
+    Ok(())
+}
+
+// ゲストは子プロセスではないため、command と子プロセス向けの制限は使えない
+fn wasm(config: &McpProcessConfig) -> Result<(), String> {
+    if !cfg!(feature = "wasm") {
+        return Err(crate::wasm_server::NOT_COMPILED_IN.to_string());
+    }
+    if config.entrypoint.is_none() {
+        return Err("'entrypoint' (the .wasm file) is required for wasm servers".to_string());
+    }
+    let unsupported = [
+        ("command", !config.command.is_empty()),
+        ("sandbox", config.sandbox.is_some()),
+        (
+            "limits.cpu_percent",
+            config
+                .limits
+                .as_ref()
+                .is_some_and(|limits| limits.cpu_percent.is_some()),
+        ),
+        (
+            "limits.max_open_files",
+            config
+                .limits
+                .as_ref()
+                .is_some_and(|limits| limits.max_open_files.is_some()),
+        ),
+    ];
+    match unsupported.into_iter().find(|(_, set)| *set) {
+        Some((key, _)) => Err(format!("'{}' does not apply to wasm servers", key)),
+        None => Ok(()),
+    }
+}
+
+// Cargo.toml の [package] の name (TOML のパーサーは使わず、name = "..." の行だけを読む)
+fn package_name(manifest: &str) -> Option<String> {
+    let mut in_package = false;
+    for line in manifest.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_package = line == "[package]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=').filter(|_| in_package) else {
+            continue;
+        };
+        if key.trim() != "name" {
+            continue;
+        }
+        let value = value.trim();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        return value[1..]
+            .split(quote)
+            .next()
+            .filter(|name| !name.is_empty())
+            .map(str::to_string);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn applied(server_key: &str, config: serde_json::Value) -> McpProcessConfig {
+        let mut config: McpProcessConfig = serde_json::from_value(config).unwrap();
+        apply(server_key, &mut config).unwrap();
+        config
+    }
+
+    #[test]
+    fn go_builds_the_entrypoint_and_runs_the_binary() {
+        let config = applied(
+            "github",
+            json!({
+                "language": "go",
+                "entrypoint": "./cmd/github-mcp-server",
+                "cwd": "/opt/github-mcp-server",
+                "args": ["stdio"],
+            }),
+        );
+        let binary = format!(".mcp-build/github{}", EXE_SUFFIX);
+        assert_eq!(
+            config.hooks.post_install,
+            vec![vec![
+                "go",
+                "build",
+                "-o",
+                &binary,
+                "./cmd/github-mcp-server"
+            ]]
+        );
+        assert_eq!(config.command, format!("./{}", binary));
+        assert_eq!(config.args, vec!["stdio"]);
+        assert!(config.built_command);
+    }
+
+    #[test]
+    fn go_with_its_own_install_command_uses_go_run() {
+        let config = applied(
+            "github",
+            json!({
+                "language": "go",
+                "cwd": "/opt/github-mcp-server",
+                "args": ["stdio"],
+                "post_install": [["go", "mod", "download"]],
+            }),
+        );
+        assert_eq!(
+            config.hooks.post_install,
+            vec![vec!["go", "mod", "download"]]
+        );
+        assert_eq!(config.command, "go");
+        assert_eq!(config.args, vec!["run", ".", "stdio"]);
+        assert!(!config.built_command);
+    }
+
+    #[test]
+    fn explicit_command_is_left_alone() {
+        let config = applied(
+            "github",
+            json!({ "language": "go", "cwd": "/opt/github", "command": "./bin/server" }),
+        );
+        assert_eq!(config.command, "./bin/server");
+        assert!(config.hooks.post_install.is_empty());
+    }
+
+    #[test]
+    fn language_requires_cwd() {
+        let mut config: McpProcessConfig =
+            serde_json::from_value(json!({ "language": "go" })).unwrap();
+        let error = apply("github", &mut config).unwrap_err();
+        assert!(error.contains("'cwd'"), "{}", error);
+    }
+
+    #[test]
+    fn rust_runs_the_package_binary_from_target_release() {
+        let dir = std::env::temp_dir().join(format!("mcp-rust-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nname = \"weather-mcp\" # the server\nversion = \"0.1.0\"\n\n[dependencies]\nname = \"not-this\"\n",
+        )
+        .unwrap();
+        let config = applied("weather", json!({ "language": "rust", "cwd": dir }));
+        assert_eq!(
+            config.hooks.post_install,
+            vec![vec!["cargo", "build", "--release"]]
+        );
+        assert_eq!(
+            config.command,
+            format!("./target/release/weather-mcp{}", EXE_SUFFIX)
+        );
+        assert!(config.built_command);
+    }
+
+    #[test]
+    fn rust_entrypoint_selects_the_binary() {
+        let config = applied(
+            "weather",
+            json!({ "language": "rust", "entrypoint": "weather-server", "cwd": "/opt/weather" }),
+        );
+        assert_eq!(
+            config.hooks.post_install,
+            vec![vec![
+                "cargo",
+                "build",
+                "--release",
+                "--bin",
+                "weather-server"
+            ]]
+        );
+        assert_eq!(
+            config.command,
+            format!("./target/release/weather-server{}", EXE_SUFFIX)
+        );
+    }
+
+    #[test]
+    fn rust_without_a_readable_package_name_asks_for_entrypoint() {
+        assert_eq!(package_name("[workspace]\nmembers = [\"a\"]\n"), None);
+        let mut config: McpProcessConfig = serde_json::from_value(
+            json!({ "language": "rust", "cwd": "/nonexistent/mcp-rust-server" }),
+        )
+        .unwrap();
+        let error = apply("weather", &mut config).unwrap_err();
+        assert!(error.contains("'entrypoint'"), "{}", error);
+    }
+}
@@ -0,0 +1,327 @@
+use serde::Deserialize;
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio::process::Command;
+use tracing::{debug, info, warn};
+
+// --- 子プロセスのリソース制限 ---
+// "limits": { "memory_mb": 512, "cpu_percent": 50, "max_open_files": 1024 }
+// cgroup v2 が使える場合は memory.max / cpu.max で制限し、使えない場合は RSS を監視して超えたら kill する
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    // 子プロセスが使えるメモリ (MiB)
+    #[serde(default)]
+    pub memory_mb: Option<u64>,
+    // CPU 時間の割合 (100 で1コア分、200 で2コア分)。cgroup v2 が必要
+    #[serde(default)]
+    pub cpu_percent: Option<u32>,
+    // RLIMIT_NOFILE
+    #[serde(default)]
+    pub max_open_files: Option<u64>,
+}
+
+impl LimitsConfig {
+    pub fn validate(&self, server_key: &str) -> Result<(), String> {
+        if self.memory_mb == Some(0)
+            || self.cpu_percent == Some(0)
+            || self.max_open_files == Some(0)
+        {
+            return Err(format!(
+                "MCP server '{}' limits must be greater than 0",
+                server_key
+            ));
+        }
+        if cfg!(not(target_os = "linux"))
+            && (self.memory_mb.is_some() || self.cpu_percent.is_some())
+        {
+            return Err(format!(
+                "MCP server '{}' memory_mb and cpu_percent limits are only supported on Linux",
+                server_key
+            ));
+        }
+        if cfg!(not(unix)) && self.max_open_files.is_some() {
+            return Err(format!(
+                "MCP server '{}' max_open_files is only supported on Unix",
+                server_key
+            ));
+        }
+        Ok(())
+    }
+
+    fn memory_bytes(&self) -> Option<u64> {
+        self.memory_mb.map(|mb| mb.saturating_mul(1024 * 1024))
+    }
+}
+
+// cgroup v2 の階層のマウント先
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+// cpu.max の周期 (マイクロ秒)
+const CPU_PERIOD_US: u64 = 100_000;
+// cgroup が使えない場合に RSS を確認する間隔
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// 同じサーバーの cgroup 名が重ならないようにする連番
+static CGROUP_SEQ: AtomicU64 = AtomicU64::new(0);
+
+// --- 起動した子プロセスの制限の監視 ---
+// spawn_mcp_process の終了監視タスクが保持し、終了後に cgroup を削除する
+pub struct LimitGuard {
+    server_key: String,
+    cgroup: Option<PathBuf>,
+    // cgroup を使わずに監視する RSS の上限
+    watched_memory_bytes: Option<u64>,
+}
+
+// 子プロセスの起動前に制限を設定する (limits 未設定なら None)
+pub fn apply(
+    command: &mut Command,
+    server_key: &str,
+    limits: Option<&LimitsConfig>,
+) -> Result<Option<LimitGuard>, String> {
+    let Some(limits) = limits else {
+        return Ok(None);
+    };
+    let cgroup = if limits.memory_mb.is_some() || limits.cpu_percent.is_some() {
+        match create_cgroup(server_key, limits) {
+            Ok(path) => {
+                info!(server = %server_key, cgroup = %path.display(), "Created cgroup for MCP process");
+                Some(path)
+            }
+            Err(e) => {
+                warn!(server = %server_key, error = %e, "cgroup v2 is not available, falling back to RSS monitoring");
+                if limits.cpu_percent.is_some() {
+                    warn!(server = %server_key, "cpu_percent is not enforced without cgroup v2");
+                }
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let watched_memory_bytes = match cgroup {
+        Some(_) => None,
+        None => limits.memory_bytes(),
+    };
+    apply_process_limits(command, server_key, limits, cgroup.as_ref())?;
+    Ok(Some(LimitGuard {
+        server_key: server_key.to_string(),
+        cgroup,
+        watched_memory_bytes,
+    }))
+}
+
+impl LimitGuard {
+    // 制限を超えた場合に内容を返す (監視するものがなければ完了しない)
+    pub async fn breached(&self, pid: Option<u32>) -> String {
+        let (Some(limit), Some(pid)) = (self.watched_memory_bytes, pid) else {
+            return std::future::pending().await;
+        };
+        let mut interval = tokio::time::interval(MEMORY_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Some(rss) = resident_bytes(pid).await.filter(|rss| *rss > limit) {
+                return format!(
+                    "resident memory {} MiB exceeds memory_mb {}",
+                    rss / (1024 * 1024),
+                    limit / (1024 * 1024)
+                );
+            }
+        }
+    }
+
+    // cgroup の memory.max を超えてカーネルに kill されたか
+    pub async fn oom_killed(&self) -> bool {
+        let Some(cgroup) = &self.cgroup else {
+            return false;
+        };
+        let Ok(events) = tokio::fs::read_to_string(cgroup.join("memory.events")).await else {
+            return false;
+        };
+        events
+            .lines()
+            .filter_map(|line| line.strip_prefix("oom_kill "))
+            .any(|count| count.trim().parse::<u64>().is_ok_and(|count| count > 0))
+    }
+
+    // 子プロセスの終了後に cgroup を削除する
+    pub async fn release(self) {
+        let Some(cgroup) = self.cgroup else {
+            return;
+        };
+        if let Err(e) = tokio::fs::remove_dir(&cgroup).await {
+            debug!(server = %self.server_key, cgroup = %cgroup.display(), error = %e, "Failed to remove cgroup");
+        }
+    }
+}
+
+// /proc/<pid>/status の VmRSS
+async fn resident_bytes(pid: u32) -> Option<u64> {
+    let status = tokio::fs::read_to_string(format!("/proc/{}/status", pid))
+        .await
+        .ok()?;
+    let kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+// ブリッジ自身の cgroup (MCP_CGROUP_PARENT で委譲された cgroup を指定できる) の下に子プロセス用の cgroup を作る
+fn create_cgroup(server_key: &str, limits: &LimitsConfig) -> Result<PathBuf, String> {
+    let parent = match std::env::var("MCP_CGROUP_PARENT") {
+        Ok(path) if !path.is_empty() => PathBuf::from(path),
+        _ => own_cgroup()?,
+    };
+    let controllers_path = parent.join("cgroup.controllers");
+    let available = std::fs::read_to_string(&controllers_path)
+        .map_err(|e| format!("cannot read {}: {}", controllers_path.display(), e))?;
+    let mut controllers = Vec::new();
+    if limits.memory_mb.is_some() {
+        controllers.push("memory");
+    }
+    if limits.cpu_percent.is_some() {
+        controllers.push("cpu");
+    }
+    if let Some(missing) = controllers
+        .iter()
+        .find(|controller| !available.split_whitespace().any(|c| c == **controller))
+    {
+        return Err(format!(
+            "the {} controller is not available in {}",
+            missing,
+            parent.display()
+        ));
+    }
+    // 有効化済みでなければ子 cgroup に委譲する
+    let enabled =
+        std::fs::read_to_string(parent.join("cgroup.subtree_control")).unwrap_or_default();
+    for controller in &controllers {
+        if !enabled.split_whitespace().any(|c| c == *controller) {
+            std::fs::write(
+                parent.join("cgroup.subtree_control"),
+                format!("+{}", controller),
+            )
+            .map_err(|e| {
+                format!(
+                    "cannot enable the {} controller in {}: {}",
+                    controller,
+                    parent.display(),
+                    e
+                )
+            })?;
+        }
+    }
+
+    let name: String = server_key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let path = parent.join(format!(
+        "mcp-{}-{}-{}",
+        name,
+        std::process::id(),
+        CGROUP_SEQ.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir(&path).map_err(|e| format!("cannot create {}: {}", path.display(), e))?;
+    let configured = (|| {
+        if let Some(bytes) = limits.memory_bytes() {
+            std::fs::write(path.join("memory.max"), bytes.to_string())?;
+            // スワップに逃がさず、超えたら cgroup 内のプロセスをまとめて kill させる
+            let _ = std::fs::write(path.join("memory.swap.max"), "0");
+            let _ = std::fs::write(path.join("memory.oom.group"), "1");
+        }
+        if let Some(percent) = limits.cpu_percent {
+            let quota = u64::from(percent) * CPU_PERIOD_US / 100;
+            std::fs::write(path.join("cpu.max"), format!("{} {}", quota, CPU_PERIOD_US))?;
+        }
+        Ok::<(), std::io::Error>(())
+    })();
+    if let Err(e) = configured {
+        let _ = std::fs::remove_dir(&path);
+        return Err(format!("cannot configure {}: {}", path.display(), e));
+    }
+    Ok(path)
+}
+
+// /proc/self/cgroup の "0::<path>" (cgroup v2)
+fn own_cgroup() -> Result<PathBuf, String> {
+    let content = std::fs::read_to_string("/proc/self/cgroup")
+        .map_err(|e| format!("cannot read /proc/self/cgroup: {}", e))?;
+    let relative = content
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .ok_or_else(|| "the bridge is not in a cgroup v2 hierarchy".to_string())?;
+    Ok(PathBuf::from(CGROUP_ROOT).join(relative.trim_start_matches('/')))
+}
+
+#[cfg(unix)]
+fn apply_process_limits(
+    command: &mut Command,
+    server_key: &str,
+    limits: &LimitsConfig,
+    cgroup: Option<&PathBuf>,
+) -> Result<(), String> {
+    use std::os::fd::AsRawFd;
+
+    // chroot 後でも書き込めるよう、cgroup.procs は fork 前に開いておく
+    let procs = cgroup
+        .map(|cgroup| {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(cgroup.join("cgroup.procs"))
+                .map_err(|e| {
+                    format!(
+                        "Failed to open cgroup for MCP server '{}': {}",
+                        server_key, e
+                    )
+                })
+        })
+        .transpose()?;
+    let max_open_files = limits.max_open_files;
+    if procs.is_none() && max_open_files.is_none() {
+        return Ok(());
+    }
+    // fork 後・exec 前の子プロセスで実行する (非同期シグナル安全な libc の呼び出しのみ)
+    let setup = move || -> std::io::Result<()> {
+        unsafe {
+            if let Some(procs) = &procs {
+                // "0" は書き込んだプロセス自身を移動する
+                if libc::write(procs.as_raw_fd(), b"0".as_ptr().cast(), 1) != 1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some(max_open_files) = max_open_files {
+                let limit = libc::rlimit {
+                    rlim_cur: max_open_files as libc::rlim_t,
+                    rlim_max: max_open_files as libc::rlim_t,
+                };
+                if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(())
+    };
+    unsafe {
+        command.pre_exec(setup);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn apply_process_limits(
+    _command: &mut Command,
+    _server_key: &str,
+    _limits: &LimitsConfig,
+    _cgroup: Option<&PathBuf>,
+) -> Result<(), String> {
+    Ok(())
+}
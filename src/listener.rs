@@ -0,0 +1,192 @@
+use crate::systemd::{self, ActivatedListener};
+use axum::Router;
+use std::{env, path::PathBuf};
+use tokio::{net::TcpListener, task::JoinSet};
+use tracing::{debug, info};
+
+// --- HTTP サーバーの待ち受け設定 ---
+// TCP に加えて (または TCP の代わりに) Unix ドメインソケットで待ち受ける
+pub struct ListenConfig {
+    // None の場合は TCP で待ち受けない (DISABLE_TCP=true)
+    tcp_addr: Option<String>,
+    unix_socket: Option<PathBuf>,
+    // ソケットファイルのパーミッション (8進数)
+    unix_socket_mode: u32,
+    // systemd のソケットアクティベーションで渡されたソケット (あれば他の設定より優先)
+    activated: Vec<ActivatedListener>,
+}
+
+// 待ち受け準備済みのリスナー
+pub struct Listeners {
+    tcp: Vec<TcpListener>,
+    #[cfg(unix)]
+    unix: Vec<tokio::net::UnixListener>,
+}
+
+// ソケットファイルのパーミッションの既定値 (所有者とグループのみ読み書き可)
+const DEFAULT_UNIX_SOCKET_MODE: u32 = 0o660;
+
+impl ListenConfig {
+    pub fn from_env() -> Result<Self, String> {
+        // Renderの要件に合わせてホストとポートを設定
+        let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
+        let disable_tcp = env::var("DISABLE_TCP")
+            .map(|value| value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let unix_socket = env::var("LISTEN_UNIX_SOCKET")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+        let unix_socket_mode = match env::var("LISTEN_UNIX_SOCKET_MODE") {
+            Ok(mode) => u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+                .map_err(|e| format!("Invalid LISTEN_UNIX_SOCKET_MODE '{}': {}", mode, e))?,
+            Err(_) => DEFAULT_UNIX_SOCKET_MODE,
+        };
+        let activated = systemd::listen_fds()?;
+        if disable_tcp && unix_socket.is_none() && activated.is_empty() {
+            return Err("DISABLE_TCP=true requires LISTEN_UNIX_SOCKET to be set".to_string());
+        }
+        Ok(ListenConfig {
+            tcp_addr: (!disable_tcp).then(|| format!("0.0.0.0:{}", port)),
+            unix_socket,
+            unix_socket_mode,
+            activated,
+        })
+    }
+}
+
+// 設定されたすべてのリスナーを準備する
+pub async fn bind(config: ListenConfig) -> Result<Listeners, String> {
+    let mut listeners = Listeners {
+        tcp: Vec::new(),
+        #[cfg(unix)]
+        unix: Vec::new(),
+    };
+
+    if !config.activated.is_empty() {
+        info!(
+            count = config.activated.len(),
+            "Using sockets passed by systemd, ignoring PORT and LISTEN_UNIX_SOCKET"
+        );
+        for activated in config.activated {
+            listeners.push_activated(activated)?;
+        }
+        return Ok(listeners);
+    }
+
+    if let Some(listener_addr) = config.tcp_addr {
+        debug!(address = %listener_addr, "Attempting to bind");
+        let listener = TcpListener::bind(&listener_addr)
+            .await
+            .map_err(|e| format!("Failed to bind to address {}: {}", listener_addr, e))?;
+        info!(
+            "HTTP server listening on http://{}",
+            listener
+                .local_addr()
+                .map(|addr| addr.to_string())
+                .unwrap_or(listener_addr)
+        );
+        debug!("Render will forward requests to this port from the public internet.");
+        listeners.tcp.push(listener);
+    }
+
+    if let Some(path) = config.unix_socket {
+        let listener = bind_unix_socket(&path, config.unix_socket_mode)?;
+        info!(
+            path = %path.display(),
+            mode = format!("{:o}", config.unix_socket_mode),
+            "HTTP server listening on Unix socket"
+        );
+        #[cfg(unix)]
+        listeners.unix.push(listener);
+        #[cfg(not(unix))]
+        let _ = listener;
+    }
+
+    Ok(listeners)
+}
+
+impl Listeners {
+    fn push_activated(&mut self, activated: ActivatedListener) -> Result<(), String> {
+        match activated {
+            ActivatedListener::Tcp(listener) => listener
+                .set_nonblocking(true)
+                .and_then(|()| TcpListener::from_std(listener))
+                .map(|listener| {
+                    if let Ok(addr) = listener.local_addr() {
+                        info!("HTTP server listening on http://{} (systemd)", addr);
+                    }
+                    self.tcp.push(listener);
+                })
+                .map_err(|e| format!("Failed to use socket passed by systemd: {}", e)),
+            #[cfg(unix)]
+            ActivatedListener::Unix(listener) => listener
+                .set_nonblocking(true)
+                .and_then(|()| tokio::net::UnixListener::from_std(listener))
+                .map(|listener| {
+                    info!("HTTP server listening on Unix socket (systemd)");
+                    self.unix.push(listener);
+                })
+                .map_err(|e| format!("Failed to use socket passed by systemd: {}", e)),
+        }
+    }
+}
+
+// すべてのリスナーで待ち受け、いずれかが終了するまで返らない
+pub async fn serve(app: Router, listeners: Listeners) -> Result<(), String> {
+    let mut servers = JoinSet::new();
+
+    for listener in listeners.tcp {
+        let app = app.clone();
+        servers.spawn(async move { axum::serve(listener, app.into_make_service()).await });
+    }
+    #[cfg(unix)]
+    for listener in listeners.unix {
+        let app = app.clone();
+        servers.spawn(async move { axum::serve(listener, app.into_make_service()).await });
+    }
+
+    match servers.join_next().await {
+        Some(Ok(Ok(()))) | None => Ok(()),
+        Some(Ok(Err(e))) => Err(format!("Server error: {}", e)),
+        Some(Err(e)) => Err(format!("Server task failed: {}", e)),
+    }
+}
+
+#[cfg(unix)]
+fn bind_unix_socket(path: &std::path::Path, mode: u32) -> Result<tokio::net::UnixListener, String> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    // 前回の起動で残ったソケットファイルは削除する (通常のファイルは誤って消さない)
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            std::fs::remove_file(path)
+                .map_err(|e| format!("Failed to remove stale socket {}: {}", path.display(), e))?;
+        }
+        Ok(_) => {
+            return Err(format!(
+                "Cannot listen on {}: file exists and is not a socket",
+                path.display()
+            ));
+        }
+        Err(_) => {}
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|e| format!("Failed to bind Unix socket {}: {}", path.display(), e))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).map_err(|e| {
+        format!(
+            "Failed to set permissions on Unix socket {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    Ok(listener)
+}
+
+#[cfg(not(unix))]
+fn bind_unix_socket(path: &std::path::Path, _mode: u32) -> Result<TcpListener, String> {
+    Err(format!(
+        "Cannot listen on {}: Unix sockets are not supported on this platform",
+        path.display()
+    ))
+}
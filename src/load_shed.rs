@@ -0,0 +1,285 @@
+use std::{
+    env,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::Instant,
+};
+use tracing::{info, warn};
+
+// --- 負荷制御の設定 ---
+#[derive(Clone, Debug)]
+pub struct LoadShedConfig {
+    pub enabled: bool,
+    // 待機中+処理中のリクエスト数がこれを超えたら過負荷とみなす
+    pub max_queue_depth: usize,
+    // 平均レイテンシ (EWMA) がこれを超えたら過負荷とみなす
+    pub latency_threshold_ms: u64,
+    pub retry_after_secs: u64,
+}
+
+impl LoadShedConfig {
+    pub fn from_env() -> Self {
+        let parse = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        LoadShedConfig {
+            enabled: env::var("LOAD_SHED_ENABLED")
+                .map(|value| value.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            max_queue_depth: parse("LOAD_SHED_MAX_QUEUE_DEPTH", 32) as usize,
+            latency_threshold_ms: parse("LOAD_SHED_LATENCY_MS", 10_000),
+            retry_after_secs: parse("LOAD_SHED_RETRY_AFTER_SECS", 5),
+        }
+    }
+}
+
+// --- リクエストの優先度 ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    // X-Request-Priority ヘッダーの値から判定 (不明な値は normal)
+    pub fn from_header(value: Option<&str>) -> Self {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            Some("low") => Priority::Low,
+            Some("high") => Priority::High,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+// EWMA の平滑化係数
+const LATENCY_EWMA_ALPHA: f64 = 0.2;
+// 過負荷解除のヒステリシス (閾値のこの割合を下回ったら復帰)
+const RECOVERY_RATIO: f64 = 0.8;
+
+// --- 過負荷検出器 ---
+// キュー深さとレイテンシを監視し、閾値を超えたら shed モードに切り替える
+pub struct LoadShedder {
+    config: LoadShedConfig,
+    queue_depth: AtomicUsize,
+    latency_ewma_ms: AtomicU64,
+    shedding: AtomicBool,
+    shed_count: AtomicU64,
+    // 最後にレイテンシを記録した時刻 (started_at からのミリ秒)
+    started_at: Instant,
+    last_sample_ms: AtomicU64,
+}
+
+// キューに入っている間だけ保持するガード (drop でキュー深さを戻す)
+pub struct QueueGuard<'a> {
+    shedder: &'a LoadShedder,
+}
+
+impl Drop for QueueGuard<'_> {
+    fn drop(&mut self) {
+        self.shedder.queue_depth.fetch_sub(1, Ordering::SeqCst);
+        self.shedder.evaluate();
+    }
+}
+
+impl LoadShedder {
+    pub fn new(config: LoadShedConfig) -> Self {
+        if config.enabled {
+            info!(
+                max_queue_depth = config.max_queue_depth,
+                latency_threshold_ms = config.latency_threshold_ms,
+                "Load shedding enabled"
+            );
+        }
+        LoadShedder {
+            config,
+            queue_depth: AtomicUsize::new(0),
+            latency_ewma_ms: AtomicU64::new(0f64.to_bits()),
+            shedding: AtomicBool::new(false),
+            shed_count: AtomicU64::new(0),
+            started_at: Instant::now(),
+            last_sample_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_shedding(&self) -> bool {
+        self.shedding.load(Ordering::SeqCst)
+    }
+
+    pub fn retry_after_secs(&self) -> u64 {
+        self.config.retry_after_secs
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth.load(Ordering::SeqCst)
+    }
+
+    pub fn latency_ewma_ms(&self) -> f64 {
+        f64::from_bits(self.latency_ewma_ms.load(Ordering::SeqCst))
+    }
+
+    pub fn shed_count(&self) -> u64 {
+        self.shed_count.load(Ordering::SeqCst)
+    }
+
+    // shed モード中に受け付けるかどうか (低優先度のみ拒否する)
+    pub fn admit(&self, priority: Priority) -> bool {
+        if !self.config.enabled || priority > Priority::Low {
+            return true;
+        }
+        self.evaluate();
+        if !self.is_shedding() {
+            return true;
+        }
+        self.shed_count.fetch_add(1, Ordering::SeqCst);
+        false
+    }
+
+    pub fn enter_queue(&self) -> QueueGuard<'_> {
+        self.queue_depth.fetch_add(1, Ordering::SeqCst);
+        self.evaluate();
+        QueueGuard { shedder: self }
+    }
+
+    pub fn record_latency(&self, latency_ms: u64) {
+        let previous = self.latency_ewma_ms();
+        let next = if previous == 0.0 {
+            latency_ms as f64
+        } else {
+            previous + LATENCY_EWMA_ALPHA * (latency_ms as f64 - previous)
+        };
+        self.latency_ewma_ms.store(next.to_bits(), Ordering::SeqCst);
+        self.last_sample_ms.store(
+            self.started_at.elapsed().as_millis() as u64,
+            Ordering::SeqCst,
+        );
+        self.evaluate();
+    }
+
+    // 現在の指標から shed モードの切り替えを判定し、状態遷移をイベントとしてログ出力する
+    pub fn evaluate(&self) {
+        if !self.config.enabled {
+            return;
+        }
+        let depth = self.queue_depth();
+        // 低優先度だけが拒否され続けると新しいサンプルが来ないため、
+        // アイドル状態が Retry-After 以上続いたらレイテンシの記録をリセットする
+        let idle_ms = (self.started_at.elapsed().as_millis() as u64)
+            .saturating_sub(self.last_sample_ms.load(Ordering::SeqCst));
+        if depth == 0 && idle_ms >= self.config.retry_after_secs * 1000 {
+            self.latency_ewma_ms.store(0f64.to_bits(), Ordering::SeqCst);
+        }
+        let latency = self.latency_ewma_ms();
+        let max_depth = self.config.max_queue_depth as f64;
+        let max_latency = self.config.latency_threshold_ms as f64;
+
+        if self.is_shedding() {
+            let recovered = (depth as f64) <= max_depth * RECOVERY_RATIO
+                && latency <= max_latency * RECOVERY_RATIO;
+            if recovered && self.shedding.swap(false, Ordering::SeqCst) {
+                info!(
+                    event = "load_shed_exited",
+                    queue_depth = depth,
+                    latency_ewma_ms = latency,
+                    shed_count = self.shed_count(),
+                    "Leaving load shedding mode"
+                );
+            }
+        } else {
+            let overloaded = depth as f64 > max_depth || latency > max_latency;
+            if overloaded && !self.shedding.swap(true, Ordering::SeqCst) {
+                warn!(
+                    event = "load_shed_entered",
+                    queue_depth = depth,
+                    latency_ewma_ms = latency,
+                    "Entering load shedding mode, rejecting low-priority requests"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shedder(max_queue_depth: usize, latency_threshold_ms: u64) -> LoadShedder {
+        LoadShedder::new(LoadShedConfig {
+            enabled: true,
+            max_queue_depth,
+            latency_threshold_ms,
+            retry_after_secs: 60,
+        })
+    }
+
+    #[test]
+    fn priority_header_defaults_to_normal() {
+        assert_eq!(Priority::from_header(Some(" LOW ")), Priority::Low);
+        assert_eq!(Priority::from_header(Some("high")), Priority::High);
+        assert_eq!(Priority::from_header(Some("urgent")), Priority::Normal);
+        assert_eq!(Priority::from_header(None), Priority::Normal);
+    }
+
+    #[test]
+    fn queue_depth_sheds_low_priority_until_it_recovers() {
+        let shedder = shedder(5, 10_000);
+        let mut guards: Vec<_> = (0..5).map(|_| shedder.enter_queue()).collect();
+        assert!(shedder.admit(Priority::Low));
+
+        guards.push(shedder.enter_queue());
+        assert!(shedder.is_shedding());
+        assert!(!shedder.admit(Priority::Low));
+        assert!(shedder.admit(Priority::Normal) && shedder.admit(Priority::High));
+        assert_eq!(shedder.shed_count(), 1);
+
+        // 閾値を下回っただけでは戻らず、80% 以下になってから戻る
+        guards.truncate(5);
+        assert!(shedder.is_shedding());
+        guards.truncate(4);
+        assert!(!shedder.is_shedding());
+        assert!(shedder.admit(Priority::Low));
+    }
+
+    #[test]
+    fn latency_ewma_triggers_shedding() {
+        let shedder = shedder(100, 1_000);
+        shedder.record_latency(500);
+        assert_eq!(shedder.latency_ewma_ms(), 500.0);
+        assert!(!shedder.is_shedding());
+
+        // 500 + 0.2 * (10500 - 500) = 2500
+        shedder.record_latency(10_500);
+        assert_eq!(shedder.latency_ewma_ms(), 2_500.0);
+        assert!(!shedder.admit(Priority::Low));
+    }
+
+    #[test]
+    fn idle_period_resets_the_latency_average() {
+        let shedder = LoadShedder::new(LoadShedConfig {
+            enabled: true,
+            max_queue_depth: 100,
+            latency_threshold_ms: 1_000,
+            retry_after_secs: 0,
+        });
+        shedder.record_latency(5_000);
+        // 新しいサンプルが来なくても、アイドルが Retry-After 以上続けば戻る
+        assert!(shedder.admit(Priority::Low));
+        assert_eq!(shedder.latency_ewma_ms(), 0.0);
+    }
+
+    #[test]
+    fn disabled_admits_everything() {
+        let shedder = LoadShedder::new(LoadShedConfig {
+            enabled: false,
+            max_queue_depth: 0,
+            latency_threshold_ms: 0,
+            retry_after_secs: 5,
+        });
+        let _guard = shedder.enter_queue();
+        shedder.record_latency(60_000);
+        assert!(shedder.admit(Priority::Low));
+        assert!(!shedder.is_shedding());
+    }
+}
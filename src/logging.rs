@@ -0,0 +1,272 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, layer::Layered, prelude::*};
+
+type BoxedLayer = Box<dyn Layer<Layered<EnvFilter, Registry>> + Send + Sync>;
+
+// --- ログ出力形式 ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    fn from_env() -> Self {
+        match env::var("LOG_FORMAT")
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "json" => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+// --- ログファイルのローテーション方式 ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogRotation {
+    Never,
+    Size,
+    Hourly,
+    Daily,
+}
+
+impl LogRotation {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "never" | "none" => Some(LogRotation::Never),
+            "size" => Some(LogRotation::Size),
+            "hourly" => Some(LogRotation::Hourly),
+            "daily" => Some(LogRotation::Daily),
+            _ => None,
+        }
+    }
+
+    // 時間ベースのローテーションで使う期間番号 (UTC基準)
+    fn period(self, now: SystemTime) -> u64 {
+        let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        match self {
+            LogRotation::Hourly => secs / 3600,
+            LogRotation::Daily => secs / 86_400,
+            LogRotation::Never | LogRotation::Size => 0,
+        }
+    }
+}
+
+// --- ログファイル設定 ---
+#[derive(Clone, Debug)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    pub rotation: LogRotation,
+    pub max_size_bytes: u64,
+    pub max_files: usize,
+}
+
+impl LogFileConfig {
+    // LOG_FILE が設定されている場合のみ有効
+    fn from_env() -> Option<Self> {
+        let path = env::var("LOG_FILE").ok().filter(|p| !p.trim().is_empty())?;
+
+        let rotation = match env::var("LOG_ROTATION") {
+            Ok(value) => LogRotation::parse(&value).unwrap_or_else(|| {
+                eprintln!(
+                    "Unknown LOG_ROTATION '{}', falling back to 'daily' (expected never|size|hourly|daily)",
+                    value
+                );
+                LogRotation::Daily
+            }),
+            Err(_) => LogRotation::Daily,
+        };
+
+        let max_size_mb = env::var("LOG_MAX_SIZE_MB")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(10);
+
+        let max_files = env::var("LOG_MAX_FILES")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(7);
+
+        Some(LogFileConfig {
+            path: PathBuf::from(path),
+            rotation,
+            max_size_bytes: max_size_mb * 1024 * 1024,
+            max_files,
+        })
+    }
+}
+
+// --- ローテーション付きファイルライター ---
+// ローテーション時は `<path>.1` が最新、`<path>.<max_files>` が最古となるよう番号をずらす
+pub struct RotatingFileWriter {
+    config: LogFileConfig,
+    file: File,
+    current_size: u64,
+    current_period: u64,
+}
+
+impl RotatingFileWriter {
+    pub fn open(config: LogFileConfig) -> io::Result<Self> {
+        if let Some(parent) = config.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let metadata = file.metadata()?;
+        let current_size = metadata.len();
+        // 既存ファイルの最終更新時刻から期間を決め、再起動をまたいでもローテーションされるようにする
+        let current_period = config
+            .rotation
+            .period(metadata.modified().unwrap_or_else(|_| SystemTime::now()));
+
+        Ok(RotatingFileWriter {
+            config,
+            file,
+            current_size,
+            current_period,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.config.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn needs_rotation(&self, incoming: usize) -> bool {
+        match self.config.rotation {
+            LogRotation::Never => false,
+            LogRotation::Size => {
+                self.current_size > 0
+                    && self.current_size + incoming as u64 > self.config.max_size_bytes
+            }
+            LogRotation::Hourly | LogRotation::Daily => {
+                self.config.rotation.period(SystemTime::now()) != self.current_period
+            }
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.config.max_files == 0 {
+            // 保持数0の場合は現在のファイルを切り詰めるだけ
+            fs::remove_file(&self.config.path).ok();
+        } else {
+            fs::remove_file(self.rotated_path(self.config.max_files)).ok();
+            for index in (1..self.config.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.config.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.path)?;
+        self.current_size = 0;
+        self.current_period = self.config.rotation.period(SystemTime::now());
+        Ok(())
+    }
+}
+
+impl Write for RotatingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let rotation_result = match self.needs_rotation(buf.len()) {
+            true => self.rotate(),
+            false => Ok(()),
+        };
+        if let Err(e) = rotation_result {
+            // ローテーション失敗時もログ出力自体は継続する
+            eprintln!(
+                "Failed to rotate log file '{}': {}",
+                self.config.path.display(),
+                e
+            );
+        }
+        let written = self.file.write(buf)?;
+        self.current_size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn format_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'a> fmt::MakeWriter<'a> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_target(true)
+            .with_writer(writer)
+            .boxed(),
+        LogFormat::Text => fmt::layer()
+            .with_target(true)
+            .with_ansi(ansi)
+            .with_writer(writer)
+            .boxed(),
+    }
+}
+
+// --- tracing subscriber の初期化 ---
+// RUST_LOG でフィルタリング (未設定時は info)、LOG_FORMAT=json で構造化 JSON 出力
+// LOG_FILE が設定されていれば標準出力に加えてファイルにも書き出す
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let format = LogFormat::from_env();
+
+    let mut layers: Vec<BoxedLayer> = vec![format_layer(format, io::stdout, true)];
+
+    let file_config = LogFileConfig::from_env();
+    let mut file_error = None;
+    if let Some(config) = file_config.clone() {
+        match RotatingFileWriter::open(config) {
+            Ok(writer) => layers.push(format_layer(format, Mutex::new(writer), false)),
+            Err(e) => file_error = Some(e),
+        }
+    }
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(layers)
+        .init();
+
+    tracing::debug!(?format, "Logging initialized");
+    match (file_config, file_error) {
+        (Some(config), None) => tracing::info!(
+            path = %config.path.display(),
+            rotation = ?config.rotation,
+            max_size_bytes = config.max_size_bytes,
+            max_files = config.max_files,
+            "Writing logs to file"
+        ),
+        (Some(config), Some(e)) => tracing::error!(
+            path = %config.path.display(),
+            error = %e,
+            "Failed to open log file, logging to stdout only"
+        ),
+        _ => {}
+    }
+}
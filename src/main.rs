@@ -1,242 +1,322 @@
 use axum::{
     Json as AxumJson, Router,
     body::Body,
-    extract::State,
-    http::{HeaderMap, Request, StatusCode},
+    extract::{Extension, Path, Query, State},
+    http::{HeaderMap, HeaderValue, Request, StatusCode, header},
     middleware::{self, Next},
-    response::{IntoResponse, Response},
-    routing::post,
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post},
 };
+use futures_util::stream::{self, Stream};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, env, sync::Arc, time::Instant};
+use std::{collections::HashMap, convert::Infallible, env, sync::Arc, time::Instant};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    process::{ChildStdin, ChildStdout, Command},
-    sync::Mutex,
-    time::{Duration, timeout},
+    sync::{broadcast, mpsc},
+    time::Duration,
 };
+use tracing::{Instrument, debug, error, info, info_span, warn};
+
+mod aggregate;
+mod api_keys;
+mod callbacks;
+mod circuit_breaker;
+mod cli;
+mod config_file;
+mod config_source;
+mod content_stream;
+mod doctor;
+mod env_file;
+mod env_policy;
+mod etag;
+mod events;
+mod graphql;
+mod health_check;
+mod hooks;
+mod idempotency;
+mod init;
+mod integrity;
+mod jsonrpc;
+mod language;
+mod limits;
+mod listener;
+mod load_shed;
+mod logging;
+mod mcp_process;
+mod mock;
+mod notifications;
+mod openapi;
+mod process_tree;
+mod program;
+mod recent_requests;
+mod recording;
+mod registry;
+mod remote;
+mod resource_monitor;
+mod response_cache;
+mod restart_policy;
+mod rewrite;
+mod sandbox;
+mod scripting;
+mod secrets;
+mod servers;
+mod sessions;
+mod setup_manifest;
+mod startup;
+mod stats;
+mod stderr_buffer;
+mod storage;
+mod systemd;
+mod tenants;
+mod tool_policy;
+mod tool_schema;
+mod validate;
+mod wasm_plugins;
+mod wasm_server;
+mod webhooks;
+
+use api_keys::{ApiKeyFile, StoredKey, Verification};
+use events::EventBus;
+use idempotency::{IdempotencyKey, IdempotentLookup};
+use load_shed::{LoadShedConfig, LoadShedder, Priority};
+use mcp_process::{
+    McpProcessConfig, McpRequest, McpResponse, McpServer, ProcessLease, QueryFailure,
+};
+use notifications::NotificationPage;
+use recent_requests::{RecentRequest, RecentRequests};
+use scripting::{RequestHooks, ScriptError};
+use servers::ServerSet;
+use sessions::{SESSION_ID_HEADER, SessionConfig, SessionManager, SessionMode};
+use stats::StatsSnapshot;
+
+use storage::{NAMESPACE_IDEMPOTENCY, SharedStorage};
+use tenants::{Tenant, TenantKeys};
+use tool_policy::PolicyCheck;
+use tool_schema::ToolFormat;
 
 // --- 認証設定構造体 ---
 #[derive(Clone, Debug)]
 struct AuthConfig {
-    api_key: Option<String>,
+    // 平文またはハッシュ (api_keys::StoredKey を参照)
+    api_key: Option<StoredKey>,
+    // HTTP_API_KEY_FILE のキー (HTTP_API_KEY と同じくすべてのサーバーを利用できる)
+    key_file: Option<ApiKeyFile>,
     enabled: bool,
+    // API_KEYS_FILE のテナントごとのキー (管理APIでは使わない)
+    tenants: TenantKeys,
+    // 認証なしで受け付けるパス (AUTH_PUBLIC_PATHS、/servers/{name} 以下も含む)
+    public_paths: Arc<[String]>,
 }
 
-// --- 認証エラーレスポンス構造体 ---
-#[derive(Serialize)]
-struct AuthError {
-    error: String,
-    message: String,
+// 認証に使われたキー
+enum Credential {
+    // HTTP_API_KEY / HTTP_API_KEY_FILE (すべてのサーバーを利用できる)
+    ApiKey,
+    Tenant(Arc<Tenant>),
 }
 
-// --- JSON設定ファイルの構造体 ---
-#[derive(Deserialize, Debug, Clone)]
-struct McpProcessConfig {
-    command: String,
-    args: Vec<String>,
-    #[serde(default)]
-    env: HashMap<String, String>,
-}
+impl AuthConfig {
+    // ハッシュのキーの照合はブロックするため、Verification::Full は spawn_blocking で呼ぶ
+    fn authenticate(&self, token: &str, verification: Verification) -> Option<Credential> {
+        let api_key = self
+            .api_key
+            .as_ref()
+            .is_some_and(|key| key.matches(token, verification))
+            || self
+                .key_file
+                .as_ref()
+                .is_some_and(|key_file| key_file.contains(token, verification));
+        if api_key {
+            return Some(Credential::ApiKey);
+        }
+        self.tenants
+            .get(token, verification)
+            .map(Credential::Tenant)
+    }
 
-type McpServersConfig = HashMap<String, McpProcessConfig>;
+    fn has_hashed_keys(&self) -> bool {
+        self.api_key.as_ref().is_some_and(StoredKey::is_hashed)
+            || self.key_file.as_ref().is_some_and(ApiKeyFile::has_hashed)
+            || self.tenants.has_hashed()
+    }
 
-// --- MCPプロセスとの通信用構造体 ---
-struct McpServerProcess {
-    stdin: ChildStdin,
-    stdout: BufReader<ChildStdout>,
+    fn is_public(&self, path: &str) -> bool {
+        // /servers/{name}/healthz なども同じ扱いにする
+        let path = path
+            .strip_prefix("/servers/")
+            .and_then(|rest| rest.find('/').map(|slash| &rest[slash..]))
+            .unwrap_or(path);
+        self.public_paths.iter().any(|public| public == path)
+    }
 }
 
-impl McpServerProcess {
-    async fn query(&mut self, request: &McpRequest) -> Result<McpResponse, String> {
-        let start_time = Instant::now();
-        println!("[DEBUG] Starting MCP query at {:?}", start_time);
-        println!("[DEBUG] Request payload: {:?}", request);
-
-        let request_json = serde_json::to_string(request)
-            .map_err(|e| format!("Failed to serialize request: {}", e))?;
+// AUTH_PUBLIC_PATHS に指定できるパス (/api/v1 や /admin は常に認証が必要)
+const PUBLIC_PATH_CANDIDATES: &[&str] = &["/healthz", "/readyz", "/metrics", "/version"];
 
-        println!("[DEBUG] Serialized request: {}", request_json);
-
-        // MCPサーバーには JSON.stringify された文字列を展開して送信
-        let mcp_message = &request.command;
-        println!("[DEBUG] Sending to MCP server: {}", mcp_message);
+// --- エラーレスポンス構造体 ---
+#[derive(Serialize)]
+struct ApiError {
+    error: String,
+    message: String,
+}
 
-        // MCPサーバーに送信
-        self.stdin
-            .write_all((mcp_message.to_string() + "\n").as_bytes())
-            .await
-            .map_err(|e| format!("Failed to write to MCP stdin: {}", e))?;
+// --- バッチリクエスト・レスポンス ---
+#[derive(Deserialize, Debug)]
+struct BatchRequest {
+    commands: Vec<String>,
+}
 
-        self.stdin
-            .flush()
-            .await
-            .map_err(|e| format!("Failed to flush MCP stdin: {}", e))?;
+#[derive(Serialize)]
+struct BatchResponse {
+    results: Vec<BatchResult>,
+}
 
-        println!("[DEBUG] Data sent to MCP server, waiting for response...");
+// コマンドごとの結果 (子プロセスのレスポンスか、転送に失敗した理由のどちらか)
+#[derive(Serialize)]
+struct BatchResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
 
-        // タイムアウト付きでレスポンスを読み取り
-        let response_result = timeout(Duration::from_secs(30), async {
-            let mut response_line = String::new();
-            match self.stdout.read_line(&mut response_line).await {
-                Ok(0) => {
-                    println!("[DEBUG] MCP server closed connection (EOF)");
-                    Err("MCP server closed the connection (EOF).".to_string())
-                }
-                Ok(bytes_read) => {
-                    println!("[DEBUG] Read {} bytes from MCP server", bytes_read);
-                    println!("[DEBUG] Raw response: '{}'", response_line.trim());
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+const EVENT_STREAM_CONTENT_TYPE: &str = "text/event-stream";
+// ストリーミング時に、HTTP クライアントへの送信待ちとして保持する content の要素数
+const STREAM_BUFFER_ITEMS: usize = 16;
 
-                    if response_line.trim().is_empty() {
-                        return Err("MCP server returned an empty line.".to_string());
-                    }
+// 1回のバッチリクエストで受け付けるコマンド数の上限
+const MAX_BATCH_COMMANDS: usize = 1000;
 
-                    // レスポンスを文字列として返す（再度JSON化はしない）
-                    Ok(McpResponse {
-                        result: response_line.trim().to_string(),
-                    })
-                }
-                Err(e) => {
-                    println!("[DEBUG] Error reading from MCP stdout: {}", e);
-                    Err(format!("Failed to read from MCP stdout: {}", e))
-                }
-            }
-        })
-        .await;
+// リクエストの対象のサーバーを指定するヘッダー (パスにサーバー名を含まないリクエストのみ)
+const MCP_SERVER_HEADER: &str = "x-mcp-server";
 
-        match response_result {
-            Ok(result) => {
-                let elapsed = start_time.elapsed();
-                println!("[DEBUG] MCP query completed in {:?}", elapsed);
-                result
-            }
-            Err(_) => {
-                println!("[DEBUG] MCP query timed out after 30 seconds");
-                Err("MCP server response timeout (30 seconds)".to_string())
-            }
-        }
-    }
-}
+// MCP_SERVER_NAME が未設定で、default: true の定義もない場合に起動するサーバー
+const DEFAULT_SERVER_NAME: &str = "brave-search";
 
-// --- リクエスト・レスポンスデータ構造 ---
-#[derive(Serialize, Deserialize, Debug)]
-struct McpRequest {
-    command: String,
-}
+// DEBUG_MODE=true の場合にエラーレスポンスに含める stderr の行数
+const STDERR_TAIL_LINES: usize = 20;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct McpResponse {
-    result: String,
+// --- アプリケーション共有状態 ---
+#[derive(Clone)]
+struct AppState {
+    // このルーターが受け付けるサーバー
+    server: Arc<McpServer>,
+    // 名前で引ける全サーバー (管理API と GraphQL が使う)
+    servers: Arc<ServerSet>,
+    storage: SharedStorage,
+    idempotency_ttl: Duration,
+    // true の場合、JSON-RPC の params._meta にリクエストIDを埋め込む
+    inject_request_id_meta: bool,
+    // これより時間のかかったリクエストを警告する (SLOW_REQUEST_MS、None は無効)
+    slow_request_threshold: Option<Duration>,
+    // true の場合、5xx のエラーレスポンスに子プロセスの stderr の末尾を含める (DEBUG_MODE)
+    debug_mode: bool,
+    // GET /admin/requests で返す直近のリクエスト
+    recent_requests: Arc<RecentRequests>,
+    // /readyz で子プロセスに送る ping の応答を待つ時間 (READYZ_PING_TIMEOUT_SECS、None は送らない)
+    readyz_ping_timeout: Option<Duration>,
+    load_shedder: Arc<LoadShedder>,
+    events: EventBus,
+    graphql_schema: graphql::McpSchema,
+    // SESSION_MODE=per_session の場合のみ
+    sessions: Option<Arc<SessionManager>>,
+    // 全サーバーのセッション (/stats がすべてのサーバーの分を返すため。per_session の場合のみ)
+    server_sessions: Arc<HashMap<String, Arc<SessionManager>>>,
 }
 
-// --- MCPサーバープロセス起動関数 ---
-async fn start_mcp_server_from_config(
-    config_file_path: &str,
-    server_key: &str,
-) -> Result<McpServerProcess, Box<dyn std::error::Error + Send + Sync>> {
-    println!("[DEBUG] Reading config file: {}", config_file_path);
-
-    let config_content = tokio::fs::read_to_string(config_file_path)
-        .await
-        .map_err(|e| {
-            format!(
-                "Failed to read MCP config file '{}': {}",
-                config_file_path, e
-            )
-        })?;
+// --- リクエストID ---
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const REQUEST_ID_META_KEY: &str = "x-request-id";
 
-    println!("[DEBUG] Config content: {}", config_content);
+#[derive(Clone, Debug)]
+struct RequestId(String);
 
-    let all_configs: McpServersConfig = serde_json::from_str(&config_content).map_err(|e| {
-        format!(
-            "Failed to parse MCP config file '{}': {}",
-            config_file_path, e
-        )
-    })?;
+// クライアント指定のIDはログやヘッダーに安全に載せられるものだけ受け入れる
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+}
 
-    println!("[DEBUG] Parsed configs: {:?}", all_configs);
+// JSON-RPC リクエストの params._meta にリクエストIDを埋め込む
+fn inject_request_id_meta(command: &str, request_id: &str) -> Option<String> {
+    let mut value: serde_json::Value = serde_json::from_str(command).ok()?;
+    let object = value.as_object_mut()?;
+    object.get("method")?;
+    let params = object
+        .entry("params")
+        .or_insert_with(|| serde_json::Value::Object(Default::default()))
+        .as_object_mut()?;
+    let meta = params
+        .entry("_meta")
+        .or_insert_with(|| serde_json::Value::Object(Default::default()))
+        .as_object_mut()?;
+    meta.insert(
+        REQUEST_ID_META_KEY.to_string(),
+        serde_json::Value::String(request_id.to_string()),
+    );
+    serde_json::to_string(&value).ok()
+}
 
-    let server_config = all_configs.get(server_key).ok_or_else(|| {
-        format!(
-            "MCP server configuration not found for key '{}' in file '{}'",
-            server_key, config_file_path
-        )
-    })?;
+// --- リクエストIDミドルウェア ---
+// X-Request-Id を受け取る (なければ生成) し、ログのスパンとレスポンスヘッダーに付与する
+async fn request_id_middleware(mut request: Request<Body>, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid_request_id(value))
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
 
-    println!(
-        "[DEBUG] Starting MCP server (key: '{}') with command: '{}', args: {:?}, env: {:?}",
-        server_key, &server_config.command, &server_config.args, &server_config.env
+    let span = info_span!(
+        "http_request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
     );
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
 
-    let mut command_builder = Command::new(&server_config.command);
-    command_builder.args(&server_config.args);
-    command_builder.envs(&server_config.env);
-
-    command_builder
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped());
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
 
-    println!("[DEBUG] Spawning MCP process...");
-    let mut child = command_builder.spawn().map_err(|e| {
-        format!(
-            "Failed to spawn MCP process for key '{}' (command: '{}'): {}",
-            server_key, server_config.command, e
-        )
-    })?;
+// --- 負荷制御ミドルウェア ---
+// shed モード中は X-Request-Priority: low のリクエストを 503 で拒否する
+async fn load_shed_middleware(
+    State(load_shedder): State<Arc<LoadShedder>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let priority = Priority::from_header(
+        request
+            .headers()
+            .get("x-request-priority")
+            .and_then(|value| value.to_str().ok()),
+    );
 
-    let stdin = child
-        .stdin
-        .take()
-        .ok_or_else(|| format!("Failed to open stdin for MCP process '{}'", server_key))?;
-    let stdout = child
-        .stdout
-        .take()
-        .ok_or_else(|| format!("Failed to open stdout for MCP process '{}'", server_key))?;
-    let stderr = child
-        .stderr
-        .take()
-        .ok_or_else(|| format!("Failed to open stderr for MCP process '{}'", server_key))?;
-
-    println!("[DEBUG] MCP process spawned successfully, setting up stderr monitoring...");
-
-    let server_key_clone_for_stderr = server_key.to_string();
-    tokio::spawn(async move {
-        let mut reader = BufReader::new(stderr);
-        let mut line = String::new();
-        loop {
-            match reader.read_line(&mut line).await {
-                Ok(0) => {
-                    println!(
-                        "[MCP Server stderr - {}]: EOF, task finishing.",
-                        server_key_clone_for_stderr
-                    );
-                    break;
-                }
-                Ok(_) => {
-                    print!(
-                        "[MCP Server stderr - {}]: {}",
-                        server_key_clone_for_stderr, line
-                    );
-                    line.clear();
-                }
-                Err(e) => {
-                    eprintln!(
-                        "[MCP Server stderr read error - {}]: {}",
-                        server_key_clone_for_stderr, e
-                    );
-                    break;
-                }
-            }
+    if !load_shedder.admit(priority) {
+        debug!(?priority, "Request rejected by load shedding");
+        let error_response = ApiError {
+            error: "Service Unavailable".to_string(),
+            message: "Server is overloaded; low-priority requests are temporarily rejected"
+                .to_string(),
+        };
+        let mut response =
+            (StatusCode::SERVICE_UNAVAILABLE, AxumJson(error_response)).into_response();
+        if let Ok(value) = HeaderValue::from_str(&load_shedder.retry_after_secs().to_string()) {
+            response.headers_mut().insert("retry-after", value);
         }
-    });
-
-    println!("[DEBUG] MCP server setup complete");
+        return response;
+    }
 
-    Ok(McpServerProcess {
-        stdin,
-        stdout: BufReader::new(stdout),
-    })
+    next.run(request).await
 }
 
 // --- Bearer認証ミドルウェア ---
@@ -246,24 +326,18 @@ async fn bearer_auth_middleware(
     request: Request<Body>,
     next: Next,
 ) -> Result<Response, impl IntoResponse> {
-    // 認証が無効化されている場合はスキップ
-    if !auth_config.enabled {
+    // 認証が無効化されている場合と、Kubernetes のプローブなど認証なしで受け付けるパスはスキップ
+    if !auth_config.enabled || auth_config.is_public(request.uri().path()) {
         return Ok(next.run(request).await);
     }
 
-    // APIキーが設定されていない場合はスキップ
-    let expected_api_key = match &auth_config.api_key {
-        Some(key) => key,
-        None => return Ok(next.run(request).await),
-    };
-
     // Authorizationヘッダーを取得
     let auth_header = match headers.get("authorization") {
         Some(header) => match header.to_str() {
             Ok(header_str) => header_str,
             Err(_) => {
-                println!("[DEBUG] Invalid Authorization header format");
-                let error_response = AuthError {
+                debug!("Invalid Authorization header format");
+                let error_response = ApiError {
                     error: "Unauthorized".to_string(),
                     message: "Invalid Authorization header format".to_string(),
                 };
@@ -271,8 +345,8 @@ async fn bearer_auth_middleware(
             }
         },
         None => {
-            println!("[DEBUG] Missing Authorization header");
-            let error_response = AuthError {
+            debug!("Missing Authorization header");
+            let error_response = ApiError {
                 error: "Unauthorized".to_string(),
                 message: "Missing Authorization header".to_string(),
             };
@@ -282,8 +356,8 @@ async fn bearer_auth_middleware(
 
     // Bearer tokenを抽出
     if !auth_header.starts_with("Bearer ") {
-        println!("[DEBUG] Authorization header does not start with 'Bearer '");
-        let error_response = AuthError {
+        debug!("Authorization header does not start with 'Bearer '");
+        let error_response = ApiError {
             error: "Unauthorized".to_string(),
             message: "Authorization header must use Bearer token".to_string(),
         };
@@ -292,146 +366,2902 @@ async fn bearer_auth_middleware(
 
     let provided_token = &auth_header[7..]; // "Bearer "の7文字をスキップ
 
-    // APIキーを比較
-    if provided_token != expected_api_key {
-        println!(
-            "[DEBUG] Invalid API key provided (length: {})",
-            provided_token.len()
+    // APIキーを比較 (HTTP_API_KEY はすべてのサーバーを、テナントのキーは指定のサーバーのみ利用できる)
+    // 平文のキーと照合済みのトークンを先に確認し、ハッシュの照合は同時に実行する数を制限する
+    let credential = match auth_config.authenticate(provided_token, Verification::Cached) {
+        Some(credential) => Some(credential),
+        None if auth_config.has_hashed_keys() => {
+            let Some(permit) = api_keys::try_verify_permit() else {
+                warn!("Too many API key verifications in progress");
+                let error_response = ApiError {
+                    error: "Too Many Requests".to_string(),
+                    message: "Too many API key verifications in progress, retry later".to_string(),
+                };
+                return Err((StatusCode::TOO_MANY_REQUESTS, AxumJson(error_response)));
+            };
+            let token = provided_token.to_string();
+            tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                auth_config.authenticate(&token, Verification::Full)
+            })
+            .await
+            .ok()
+            .flatten()
+        }
+        None => None,
+    };
+    let mut request = request;
+    if let Some(Credential::ApiKey) = credential {
+        debug!("Authentication successful");
+        return Ok(next.run(request).await);
+    }
+    if let Some(Credential::Tenant(tenant)) = credential {
+        debug!(tenant = %tenant.name, "Authentication successful");
+        request.extensions_mut().insert(tenant);
+    } else {
+        debug!(
+            token_length = provided_token.len(),
+            "Invalid API key provided"
         );
-        let error_response = AuthError {
+        let error_response = ApiError {
             error: "Unauthorized".to_string(),
             message: "Invalid API key".to_string(),
         };
         return Err((StatusCode::UNAUTHORIZED, AxumJson(error_response)));
     }
-
-    println!("[DEBUG] Authentication successful");
     Ok(next.run(request).await)
 }
 
+// テナントのキーで認証された場合、このプロセスのサーバーを利用できるか確認する
+async fn tenant_access_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let tenant = request.extensions().get::<Arc<Tenant>>();
+    match tenant_forbidden_response(&state, tenant.map(Arc::as_ref)) {
+        Some(response) => response,
+        None => next.run(request).await,
+    }
+}
+
+// テナントが state のサーバーを利用できない場合の 403 (スクリプトが転送先を変えた場合にも使う)
+fn tenant_forbidden_response(state: &AppState, tenant: Option<&Tenant>) -> Option<Response> {
+    let tenant = tenant.filter(|tenant| !tenant.allows_server(&state.server))?;
+    warn!(tenant = %tenant.name, server = %state.server.server_key, "Rejected request from tenant without access to server");
+    let error_response = ApiError {
+        error: "Forbidden".to_string(),
+        message: format!(
+            "API key is not allowed to access MCP server '{}'",
+            state.server.server_key
+        ),
+    };
+    Some((StatusCode::FORBIDDEN, AxumJson(error_response)).into_response())
+}
+
+// --- X-Mcp-Server ヘッダーによるサーバーの選択 ---
+// URL を変えられないクライアントのために、パスにサーバー名を含まない /api/v1 へのリクエストを
+// ヘッダーで指定したサーバーの /servers/{name}/api/v1 に振り分ける (ルーティングの前に URI を書き換える)
+async fn server_header_middleware(
+    State(servers): State<Arc<ServerSet>>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if path != "/api/v1" && !path.starts_with("/api/v1/") {
+        return next.run(request).await;
+    }
+    let Some(value) = request.headers().get(MCP_SERVER_HEADER) else {
+        return next.run(request).await;
+    };
+    let requested = value.to_str().unwrap_or_default().trim();
+    // 別名で指定された場合も本来の名前のパスに振り分ける
+    let Some(name) = servers
+        .get(requested)
+        .map(|server| server.server_key.clone())
+        .filter(|name| is_path_safe(name))
+    else {
+        warn!(server = %requested, "Rejected request for unknown MCP server in {} header", MCP_SERVER_HEADER);
+        let error_response = ApiError {
+            error: "Not Found".to_string(),
+            message: format!("Unknown MCP server '{}'", requested),
+        };
+        return (StatusCode::NOT_FOUND, AxumJson(error_response)).into_response();
+    };
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map_or(path, |path_and_query| path_and_query.as_str());
+    let rewritten = format!("/servers/{}{}", name, path_and_query);
+    match rewritten.parse() {
+        Ok(uri) => {
+            debug!(server = %name, uri = %rewritten, "Routing request by {} header", MCP_SERVER_HEADER);
+            *request.uri_mut() = uri;
+        }
+        Err(e) => warn!(server = %name, error = %e, "Failed to route request by header"),
+    }
+    next.run(request).await
+}
+
+// --- テナントの既定のサーバーへの振り分け ---
+// テナントのキーで認証された、パスにサーバー名を含まないリクエスト (/api/v1 など) を
+// テナントの default_server の /servers/{name} 以下に振り分ける (認証の後、ルーティングの前に URI を書き換える)
+async fn tenant_default_server_middleware(
+    State(servers): State<Arc<ServerSet>>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if path.starts_with("/servers/") || path == "/version" {
+        return next.run(request).await;
+    }
+    let Some(tenant) = request.extensions().get::<Arc<Tenant>>() else {
+        return next.run(request).await;
+    };
+    // このプロセスに無いサーバーは振り分けない (tenant_access_middleware が 403 を返す)
+    let Some(server) = servers.get(&tenant.default_server) else {
+        return next.run(request).await;
+    };
+    if Arc::ptr_eq(server, servers.default_server()) || !is_path_safe(&server.server_key) {
+        return next.run(request).await;
+    }
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map_or(path, |path_and_query| path_and_query.as_str());
+    let rewritten = format!("/servers/{}{}", server.server_key, path_and_query);
+    match rewritten.parse() {
+        Ok(uri) => {
+            debug!(tenant = %tenant.name, server = %server.server_key, uri = %rewritten, "Routing request to the tenant's default server");
+            *request.uri_mut() = uri;
+        }
+        Err(e) => {
+            warn!(server = %server.server_key, error = %e, "Failed to route request to the tenant's default server")
+        }
+    }
+    next.run(request).await
+}
+
 // --- Axum リクエストハンドラ ---
 async fn handle_mcp_request_shared(
-    State(mcp_process_mutex): State<Arc<Mutex<McpServerProcess>>>,
+    State(state): State<AppState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
+    headers: HeaderMap,
+    tenant: Option<Extension<Arc<Tenant>>>,
     AxumJson(payload): AxumJson<McpRequest>,
-) -> Result<AxumJson<McpResponse>, StatusCode> {
-    println!("[DEBUG] Received HTTP request: {:?}", payload);
+) -> Result<Response, Response> {
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let (state, session_id) =
+        route_session(&state, &headers, &payload.command, &request_id).await?;
+    let response = forward_session_request(
+        &state,
+        &request_id,
+        &headers,
+        tenant.as_deref(),
+        payload,
+        &session_id,
+    )
+    .await?;
+    // 通知には子プロセスの応答が無い
+    let response = match response.result.is_empty() {
+        true => StatusCode::ACCEPTED.into_response(),
+        false => AxumJson(response).into_response(),
+    };
+    Ok(with_session_header(response, session_id))
+}
 
-    let mut mcp_process_guard = mcp_process_mutex.lock().await;
-    println!("[DEBUG] Acquired MCP process mutex lock");
+// JSON-RPC メッセージそのものをボディとして受け取り、子プロセスのレスポンスも JSON のまま返す
+async fn handle_mcp_rpc(
+    State(state): State<AppState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
+    headers: HeaderMap,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    AxumJson(message): AxumJson<serde_json::Value>,
+) -> Result<Response, Response> {
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    let payload = McpRequest {
+        command: message.to_string(),
+    };
+    let (state, session_id) =
+        route_session(&state, &headers, &payload.command, &request_id).await?;
+    let response = forward_session_request(
+        &state,
+        &request_id,
+        &headers,
+        tenant.as_deref(),
+        payload,
+        &session_id,
+    )
+    .await?;
+    let response = match serde_json::from_str::<serde_json::Value>(&response.result) {
+        Ok(value) => AxumJson(value).into_response(),
+        // 通知には子プロセスの応答が無い
+        Err(_) if response.result.is_empty() => StatusCode::ACCEPTED.into_response(),
+        // 子プロセスが JSON 以外を返した場合は 502 でそのまま返す
+        Err(_) => (StatusCode::BAD_GATEWAY, response.result).into_response(),
+    };
+    Ok(with_session_header(response, session_id))
+}
 
-    match mcp_process_guard.query(&payload).await {
-        Ok(response) => {
-            println!("[DEBUG] MCP query successful: {:?}", response);
-            Ok(AxumJson(response))
-        }
+// --- セッションごとの子プロセス (SESSION_MODE=per_session) ---
+// Mcp-Session-Id のあるリクエストはそのセッションの子プロセスで処理する。
+// ヘッダーの無い initialize は新しいセッションを作成し、それ以外は共有の子プロセスで処理する
+async fn route_session(
+    state: &AppState,
+    headers: &HeaderMap,
+    command: &str,
+    request_id: &str,
+) -> Result<(AppState, Option<String>), Response> {
+    let Some(sessions) = &state.sessions else {
+        return Ok((state.clone(), None));
+    };
+    if let Some(session_id) = headers.get(SESSION_ID_HEADER) {
+        let session_id = session_id.to_str().unwrap_or_default();
+        let Some(server) = sessions.get(session_id) else {
+            warn!(session_id = %session_id, "Rejected request for unknown MCP session");
+            return Err(unknown_session_response(session_id, request_id));
+        };
+        debug!(session_id = %session_id, "Routing request to MCP session");
+        return Ok((
+            AppState {
+                server,
+                ..state.clone()
+            },
+            None,
+        ));
+    }
+    if !is_initialize(command) {
+        return Ok((state.clone(), None));
+    }
+    match sessions.create().await {
+        Ok((session_id, server)) => Ok((
+            AppState {
+                server,
+                ..state.clone()
+            },
+            Some(session_id),
+        )),
         Err(e) => {
-            eprintln!("[ERROR] MCP query failed: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            error!(error = %e, "Failed to start MCP session process");
+            Err(mcp_error_body(
+                StatusCode::SERVICE_UNAVAILABLE,
+                None,
+                e,
+                request_id,
+                None,
+                Some(state.load_shedder.retry_after_secs()),
+            ))
         }
     }
 }
 
-// --- 認証設定を作成する関数 ---
-fn create_auth_config() -> AuthConfig {
-    let api_key = env::var("HTTP_API_KEY").ok();
-    let disable_auth = env::var("DISABLE_AUTH")
-        .unwrap_or_else(|_| "false".to_string())
-        .parse::<bool>()
-        .unwrap_or(false);
+// 新しく作成したセッションで initialize が失敗した場合は、子プロセスを残さない
+async fn forward_session_request(
+    state: &AppState,
+    request_id: &str,
+    headers: &HeaderMap,
+    tenant: Option<&Tenant>,
+    payload: McpRequest,
+    session_id: &Option<String>,
+) -> Result<McpResponse, Response> {
+    let result = forward_mcp_request(state, request_id, headers, tenant, payload).await;
+    if let (Err(_), Some(session_id), Some(sessions)) = (&result, session_id, &state.sessions) {
+        sessions.close(session_id).await;
+    }
+    result
+}
+
+fn is_initialize(command: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(command)
+        .ok()
+        .and_then(|request| {
+            request
+                .get("method")
+                .and_then(|method| method.as_str())
+                .map(|method| method == "initialize")
+        })
+        .unwrap_or(false)
+}
 
-    let enabled = !disable_auth && api_key.is_some();
+fn with_session_header(mut response: Response, session_id: Option<String>) -> Response {
+    if let Some(value) = session_id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        response.headers_mut().insert(SESSION_ID_HEADER, value);
+    }
+    response
+}
 
-    if let Some(ref key) = api_key {
-        println!(
-            "[DEBUG] HTTP API Key configured (length: {} chars)",
-            key.len()
+fn unknown_session_response(session_id: &str, request_id: &str) -> Response {
+    mcp_error_body(
+        StatusCode::NOT_FOUND,
+        None,
+        format!("Unknown MCP session '{}'", session_id),
+        request_id,
+        None,
+        None,
+    )
+}
+
+// DELETE /api/v1 (Mcp-Session-Id 付き) でセッションを終了する
+async fn handle_session_delete(
+    State(state): State<AppState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(sessions) = &state.sessions else {
+        return mcp_error_body(
+            StatusCode::METHOD_NOT_ALLOWED,
+            None,
+            "Sessions are not enabled (SESSION_MODE=shared)".to_string(),
+            &request_id,
+            None,
+            None,
+        );
+    };
+    let Some(session_id) = headers
+        .get(SESSION_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return mcp_error_body(
+            StatusCode::BAD_REQUEST,
+            None,
+            "Mcp-Session-Id header is required".to_string(),
+            &request_id,
+            None,
+            None,
         );
+    };
+    if sessions.close(session_id).await {
+        StatusCode::NO_CONTENT.into_response()
     } else {
-        println!("[DEBUG] No HTTP API Key configured (HTTP_API_KEY not set)");
+        unknown_session_response(session_id, &request_id)
     }
+}
 
-    if disable_auth {
-        println!("[DEBUG] Authentication disabled by DISABLE_AUTH=true");
+// 同時実行枠を確保する (停止中・待機数の上限超過は 503)
+async fn acquire_mcp_process<'a>(
+    state: &'a AppState,
+    request_id: &str,
+    span: &tracing::Span,
+) -> Result<ProcessLease<'a>, Response> {
+    match state.server.acquire().await {
+        Ok(mcp_process) => {
+            debug!(parent: span, "Acquired MCP request slot");
+            Ok(mcp_process)
+        }
+        Err(e) => {
+            warn!(parent: span, error = %e, "MCP server is unavailable, rejecting request");
+            Err(mcp_failure_body(
+                state,
+                StatusCode::SERVICE_UNAVAILABLE,
+                e,
+                request_id,
+                Some(state.load_shedder.retry_after_secs()),
+            ))
+        }
     }
+}
 
-    println!("[DEBUG] Authentication enabled: {}", enabled);
+// 子プロセスが失敗し続けている間は、タイムアウトまで待たせずに即座に拒否する
+fn circuit_open_response(state: &AppState, request_id: &str) -> Option<Response> {
+    let retry_after_secs = state.server.circuit_breaker.check().err()?;
+    warn!(
+        retry_after_secs,
+        "Circuit breaker is open, rejecting request"
+    );
+    Some(mcp_error_body(
+        StatusCode::SERVICE_UNAVAILABLE,
+        None,
+        format!(
+            "MCP server '{}' is failing, {}",
+            state.server.server_key,
+            circuit_breaker::CIRCUIT_OPEN_ERROR
+        ),
+        request_id,
+        None,
+        Some(retry_after_secs),
+    ))
+}
 
-    AuthConfig { api_key, enabled }
+// --- 遅いリクエストの警告 ---
+// 利用者からの報告を待たずに気付けるよう、切り分けに必要な情報 (待機時間・子プロセスの状態) を残す
+fn log_slow_request<'a>(
+    state: &AppState,
+    request_id: &str,
+    commands: impl IntoIterator<Item = &'a String>,
+    queue_wait: Duration,
+    latency_ms: u64,
+) {
+    let Some(threshold) = state.slow_request_threshold else {
+        return;
+    };
+    if u128::from(latency_ms) < threshold.as_millis() {
+        return;
+    }
+    let mut methods = Vec::new();
+    let mut payload_bytes = 0;
+    for command in commands {
+        payload_bytes += command.len();
+        methods.extend(command_methods(command));
+    }
+    let stats = state.server.stats.get_stats();
+    warn!(
+        request_id = %request_id,
+        server = %state.server.server_key,
+        rpc_method = %methods.join(","),
+        payload_bytes,
+        latency_ms,
+        queue_wait_ms = queue_wait.as_millis() as u64,
+        threshold_ms = threshold.as_millis() as u64,
+        child_pid = ?stats.pid,
+        child_uptime_secs = stats.uptime_secs,
+        child_requests = stats.request_count,
+        child_errors = stats.error_count,
+        child_timeouts = stats.timeout_count,
+        child_restarts = stats.restart_count,
+        child_p95_latency_ms = stats.p95_latency_ms,
+        "Slow MCP request"
+    );
 }
 
-// --- main関数 ---
-#[tokio::main]
-async fn main() {
-    println!("[DEBUG] Starting MCP HTTP server...");
+// JSON-RPC メッセージ (またはバッチ) の method
+fn command_methods(command: &str) -> Vec<String> {
+    let method = |message: &serde_json::Value| {
+        message
+            .get("method")
+            .and_then(|method| method.as_str())
+            .map(|method| method.to_string())
+    };
+    match serde_json::from_str::<serde_json::Value>(command) {
+        Ok(serde_json::Value::Array(batch)) => batch.iter().filter_map(method).collect(),
+        Ok(message) => method(&message).into_iter().collect(),
+        Err(_) => Vec::new(),
+    }
+}
 
-    // 認証設定を作成
-    let auth_config = create_auth_config();
+// 直近のリクエストとして、結果の HTTP ステータスと一緒に記録する
+fn record_recent_request(
+    state: &AppState,
+    request_id: &str,
+    command: &str,
+    status: StatusCode,
+    start_time: Instant,
+) {
+    state.recent_requests.record(
+        request_id,
+        &state.server.server_key,
+        command_methods(command).join(","),
+        command,
+        status.as_u16(),
+        start_time.elapsed().as_millis() as u64,
+    );
+}
 
-    let config_file =
-        env::var("MCP_CONFIG_FILE").unwrap_or_else(|_| "mcp_servers.config.json".to_string());
-    let mcp_server_key_to_use =
-        env::var("MCP_SERVER_NAME").unwrap_or_else(|_| "brave-search".to_string());
+// --- バッチリクエスト ---
+// 小さなリクエストを大量に送るクライアント向けに、複数のコマンドを1回の HTTP リクエストで転送する
+async fn handle_mcp_batch(
+    State(state): State<AppState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    headers: HeaderMap,
+    AxumJson(payload): AxumJson<BatchRequest>,
+) -> Result<AxumJson<BatchResponse>, Response> {
+    let start_time = Instant::now();
+    // 直近のリクエストには JSON-RPC のバッチとして記録する
+    let command = format!("[{}]", payload.commands.join(","));
+    let tenant = tenant.as_ref().map(|Extension(tenant)| tenant.as_ref());
+    let result = forward_mcp_batch(&state, &request_id, &headers, tenant, payload).await;
+    let status = match &result {
+        Ok(_) => StatusCode::OK,
+        Err(response) => response.status(),
+    };
+    record_recent_request(&state, &request_id, &command, status, start_time);
+    result
+}
 
-    println!(
-        "[DEBUG] Config file: '{}', Server key: '{}'",
-        config_file, mcp_server_key_to_use
-    );
+async fn forward_mcp_batch(
+    state: &AppState,
+    request_id: &str,
+    headers: &HeaderMap,
+    tenant: Option<&Tenant>,
+    payload: BatchRequest,
+) -> Result<AxumJson<BatchResponse>, Response> {
+    // バッチでは新しいセッションを作成せず、既存のセッションへの振り分けだけを行う
+    let (state, _) = route_session(state, headers, "", request_id).await?;
+    let start_time = Instant::now();
+    debug!(commands = payload.commands.len(), "Received batch request");
 
-    let mcp_server_process_mutex =
-        match start_mcp_server_from_config(&config_file, &mcp_server_key_to_use).await {
-            Ok(process) => {
-                println!("[DEBUG] MCP server started successfully");
-                Arc::new(Mutex::new(process))
-            }
-            Err(e) => {
-                eprintln!("[FATAL] Failed to start MCP server process: {}", e);
-                eprintln!("Please ensure:");
-                eprintln!("1. Node.js is installed and npx is available");
-                eprintln!(
-                    "2. The @modelcontextprotocol/server-brave-search package can be downloaded"
-                );
-                eprintln!("3. Network connectivity is available");
-                return;
-            }
+    let bad_request = |message: String| {
+        warn!(error = %message, "Rejected invalid batch request");
+        let error_response = ApiError {
+            error: "Bad Request".to_string(),
+            message,
         };
+        (StatusCode::BAD_REQUEST, AxumJson(error_response)).into_response()
+    };
+    if payload.commands.is_empty() {
+        return Err(bad_request("commands must not be empty".to_string()));
+    }
+    if payload.commands.len() > MAX_BATCH_COMMANDS {
+        return Err(bad_request(format!(
+            "too many commands ({}, max {})",
+            payload.commands.len(),
+            MAX_BATCH_COMMANDS
+        )));
+    }
+    let hooks = state.server.config().request_hooks();
+    let tenant_name = tenant.map(|tenant| tenant.name.as_str());
+    let mut commands = Vec::with_capacity(payload.commands.len());
+    // on_response に渡す、スクリプトが書き換えた後のリクエスト
+    let mut scripted_requests = Vec::new();
+    let mut tenant_checks = Vec::with_capacity(payload.commands.len());
+    for (index, command) in payload.commands.iter().enumerate() {
+        let mut command = jsonrpc::normalize_request(command)
+            .map_err(|message| bad_request(format!("commands[{}]: {}", index, message)))?;
+        if let Some(hooks) = &hooks {
+            // バッチの各コマンドは同じサーバーに送るため、転送先は変えられない
+            let (routed, request) = script_request(&state, hooks, tenant_name, &command)
+                .await
+                .map_err(|e| script_error_response(&state, request_id, e))?;
+            if routed.server.server_key != state.server.server_key {
+                let error = ScriptError::Failed(format!(
+                    "on_request script cannot route a batch command to '{}'",
+                    routed.server.server_key
+                ));
+                return Err(script_error_response(&state, request_id, error));
+            }
+            command = jsonrpc::normalize_request(&request.to_string())
+                .map_err(|message| bad_request(format!("commands[{}]: {}", index, message)))?;
+            scripted_requests.push(request);
+        }
+        command = state.server.rewrite_command(command);
+        if let Some(injected) = state
+            .inject_request_id_meta
+            .then(|| inject_request_id_meta(&command, request_id))
+            .flatten()
+        {
+            command = injected;
+        }
+        if let Some((tool_name, arguments)) = parse_tool_call(&command) {
+            validate_tool_call(&state, &tool_name, &arguments)
+                .await
+                .map_err(IntoResponse::into_response)?;
+        }
+        tenant_checks.push(check_tenant_tools(&state, tenant, &command));
+        commands.push(command);
+    }
 
-    let app = Router::new()
-        .route("/api/v1", post(handle_mcp_request_shared))
-        .layer(middleware::from_fn_with_state(
-            auth_config.clone(),
-            bearer_auth_middleware,
-        ))
-        .with_state(mcp_server_process_mutex);
+    // テナントの tools.allow / tools.deny で拒否したコマンドは転送せず、そのコマンドの結果をエラーにする
+    let forwarded: Vec<String> = commands
+        .iter()
+        .zip(&tenant_checks)
+        .filter_map(|(command, check)| match check {
+            PolicyCheck::Forward => Some(command.clone()),
+            PolicyCheck::Partial(partial) => Some(partial.forwarded.clone()),
+            PolicyCheck::Reject(_) => None,
+        })
+        .collect();
+    let mut forwarded_results = if forwarded.is_empty() {
+        Vec::new()
+    } else {
+        if let Some(rejection) = circuit_open_response(&state, request_id) {
+            return Err(rejection);
+        }
 
-    // Renderの要件に合わせてホストとポートを設定
-    let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let listener_addr = format!("0.0.0.0:{}", port);
+        let queue_guard = state.load_shedder.enter_queue();
+        let server = state.server.server_key.clone();
+        let span = info_span!("mcp_batch", request_id = %request_id, server = %server, commands = forwarded.len());
+        let mcp_process = acquire_mcp_process(&state, request_id, &span).await?;
+        let results = mcp_process
+            .query_batch(&forwarded, state.server.supports_jsonrpc_batch())
+            .instrument(span.clone())
+            .await;
+        let queue_wait = mcp_process.queue_wait();
+        drop(mcp_process);
+        let latency_ms = start_time.elapsed().as_millis() as u64;
+        state.load_shedder.record_latency(latency_ms);
+        drop(queue_guard);
+        log_slow_request(&state, request_id, &forwarded, queue_wait, latency_ms);
 
-    println!("[DEBUG] Attempting to bind to: {}", listener_addr);
+        let failed = results.iter().filter(|result| result.is_err()).count();
+        info!(parent: &span, latency_ms, failed, "MCP batch completed");
+        results
+    }
+    .into_iter();
 
-    match tokio::net::TcpListener::bind(&listener_addr).await {
-        Ok(listener) => {
-            println!(
-                "[DEBUG] HTTP server listening on http://{}",
-                listener.local_addr().unwrap() // ここでは実際のローカルアドレスを表示
-            );
-            println!("[DEBUG] Render will forward requests to this port from the public internet.");
-            println!("[DEBUG] Ready to accept requests at POST /api/v1");
-
-            if auth_config.enabled {
-                println!(
-                    "[DEBUG] Authentication is ENABLED - Authorization: Bearer <token> required"
-                );
-            } else {
-                println!("[DEBUG] Authentication is DISABLED - no authorization required");
+    let mut results = Vec::with_capacity(commands.len());
+    for (command, check) in commands.iter().zip(tenant_checks) {
+        let result = match check {
+            PolicyCheck::Reject(rejection) => Ok(McpResponse { result: rejection }),
+            PolicyCheck::Forward => forwarded_results
+                .next()
+                .unwrap_or_else(|| Err("Missing batch result".to_string())),
+            PolicyCheck::Partial(partial) => forwarded_results
+                .next()
+                .unwrap_or_else(|| Err("Missing batch result".to_string()))
+                .map(|response| McpResponse {
+                    result: partial.merge(&response.result),
+                }),
+        };
+        results.push(result.map(|mut response| {
+            if let Some(filtered) = tenant
+                .and_then(|tenant| tenant.tools.filter_list_response(command, &response.result))
+            {
+                response.result = filtered;
             }
+            response
+        }));
+    }
 
-            if let Err(e) = axum::serve(listener, app.into_make_service()).await {
-                eprintln!("[ERROR] Server error: {}", e);
+    let mut batch_results = Vec::with_capacity(results.len());
+    for (index, result) in results.into_iter().enumerate() {
+        let result = match result {
+            Ok(response) => {
+                script_batch_response(
+                    &state,
+                    &hooks,
+                    tenant_name,
+                    &scripted_requests,
+                    index,
+                    response.result,
+                )
+                .await
             }
+            Err(e) => Err(e),
+        };
+        batch_results.push(match result {
+            Ok(result) => BatchResult {
+                result: Some(result),
+                error: None,
+            },
+            Err(e) => BatchResult {
+                result: None,
+                error: Some(e),
+            },
+        });
+    }
+    Ok(AxumJson(BatchResponse {
+        results: batch_results,
+    }))
+}
+
+// --- rhai スクリプト・WASM プラグインのフック (scripts / wasm_plugins) ---
+// on_request を実行し、転送先のサーバーの AppState (変わらなければそのまま) と、書き換えたリクエストを返す
+async fn script_request(
+    state: &AppState,
+    hooks: &RequestHooks,
+    tenant: Option<&str>,
+    command: &str,
+) -> Result<(AppState, serde_json::Value), ScriptError> {
+    let request = serde_json::from_str(command).unwrap_or(serde_json::Value::Null);
+    let scripted = hooks
+        .on_request_message(&state.server.server_key, tenant, request)
+        .await?;
+    let Some(target) = scripted.target else {
+        return Ok((state.clone(), scripted.request));
+    };
+    let Some(server) = state.servers.get(&target) else {
+        return Err(ScriptError::Failed(format!(
+            "on_request script chose unknown server '{}'",
+            target
+        )));
+    };
+    debug!(from = %state.server.server_key, to = %server.server_key, "Script routed request to another server");
+    let routed = AppState {
+        server: Arc::clone(server),
+        ..state.clone()
+    };
+    Ok((routed, scripted.request))
+}
+
+// throw による拒否はスクリプトが指定したステータス (既定 403)、スクリプト自体の失敗は 500
+fn script_error_response(state: &AppState, request_id: &str, error: ScriptError) -> Response {
+    match error {
+        ScriptError::Rejected {
+            status,
+            code,
+            message,
+            data,
+        } => {
+            warn!(server = %state.server.server_key, code, message = %message, "Script rejected request");
+            mcp_error_body(
+                StatusCode::from_u16(status).unwrap_or(StatusCode::FORBIDDEN),
+                Some(code),
+                message,
+                request_id,
+                data,
+                None,
+            )
         }
-        Err(e) => {
-            eprintln!("[ERROR] Failed to bind to address {}: {}", listener_addr, e);
+        ScriptError::Failed(message) => {
+            error!(server = %state.server.server_key, error = %message, "Script hook failed");
+            mcp_error_body(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                None,
+                message,
+                request_id,
+                None,
+                None,
+            )
+        }
+    }
+}
+
+// バッチの各結果に on_response を適用する (拒否・失敗はそのコマンドのエラーにする)
+async fn script_batch_response(
+    state: &AppState,
+    hooks: &Option<RequestHooks>,
+    tenant: Option<&str>,
+    requests: &[serde_json::Value],
+    index: usize,
+    result: String,
+) -> Result<String, String> {
+    let (Some(hooks), Some(request)) = (hooks, requests.get(index)) else {
+        return Ok(result);
+    };
+    let Ok(response) = serde_json::from_str(&result) else {
+        return Ok(result);
+    };
+    match hooks
+        .on_response_message(&state.server.server_key, tenant, request, response)
+        .await
+    {
+        Ok(response) => Ok(response.to_string()),
+        Err(ScriptError::Rejected { message, .. }) => Err(message),
+        Err(ScriptError::Failed(message)) => {
+            error!(server = %state.server.server_key, error = %message, "Script hook failed");
+            Err(message)
+        }
+    }
+}
+
+async fn forward_mcp_request(
+    state: &AppState,
+    request_id: &str,
+    headers: &HeaderMap,
+    tenant: Option<&Tenant>,
+    payload: McpRequest,
+) -> Result<McpResponse, Response> {
+    let start_time = Instant::now();
+    let command = payload.command.clone();
+    let result = forward_mcp_command(state, request_id, headers, tenant, payload).await;
+    let status = match &result {
+        Ok(_) => StatusCode::OK,
+        Err(response) => response.status(),
+    };
+    record_recent_request(state, request_id, &command, status, start_time);
+    result
+}
+
+async fn forward_mcp_command(
+    state: &AppState,
+    request_id: &str,
+    headers: &HeaderMap,
+    tenant: Option<&Tenant>,
+    mut payload: McpRequest,
+) -> Result<McpResponse, Response> {
+    debug!(?payload, "Received HTTP request");
+    // ボディのハッシュはブリッジが id を補完する前のリクエストから求める
+    let idempotency = IdempotencyKey::from_headers(
+        headers,
+        tenant.map(|tenant| tenant.name.as_str()),
+        &payload.command,
+    );
+
+    // 不正な JSON をそのまま転送するとタイムアウトまで待ち続けるため、先に検証する
+    let invalid_command = |message: String| {
+        warn!(error = %message, "Rejected invalid JSON-RPC command");
+        let error_response = ApiError {
+            error: "Bad Request".to_string(),
+            message,
+        };
+        (StatusCode::BAD_REQUEST, AxumJson(error_response)).into_response()
+    };
+    payload.command = jsonrpc::normalize_request(&payload.command).map_err(invalid_command)?;
+
+    let Some(hooks) = state.server.config().request_hooks() else {
+        return forward_normalized_command(
+            state,
+            request_id,
+            tenant,
+            idempotency.as_ref(),
+            payload,
+        )
+        .await;
+    };
+    // スクリプトが書き換えたリクエストも、転送する前に同じように検証する
+    let tenant_name = tenant.map(|tenant| tenant.name.as_str());
+    let (routed, request) = script_request(state, &hooks, tenant_name, &payload.command)
+        .await
+        .map_err(|e| script_error_response(state, request_id, e))?;
+    if let Some(forbidden) = tenant_forbidden_response(&routed, tenant) {
+        return Err(forbidden);
+    }
+    let state = &routed;
+    payload.command = jsonrpc::normalize_request(&request.to_string()).map_err(invalid_command)?;
+    let mut response =
+        forward_normalized_command(state, request_id, tenant, idempotency.as_ref(), payload)
+            .await?;
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(&response.result) {
+        response.result = hooks
+            .on_response_message(&state.server.server_key, tenant_name, &request, value)
+            .await
+            .map_err(|e| script_error_response(state, request_id, e))?
+            .to_string();
+    }
+    Ok(response)
+}
+
+async fn forward_normalized_command(
+    state: &AppState,
+    request_id: &str,
+    tenant: Option<&Tenant>,
+    idempotency: Option<&IdempotencyKey>,
+    mut payload: McpRequest,
+) -> Result<McpResponse, Response> {
+    // 書き換えルール (rewrite) は検証・Idempotency-Key・レスポンスキャッシュより前に適用する
+    payload.command = state.server.rewrite_command(payload.command);
+
+    if state.inject_request_id_meta {
+        match inject_request_id_meta(&payload.command, request_id) {
+            Some(command) => payload.command = command,
+            None => debug!("Command is not a JSON-RPC request, request id not injected"),
+        }
+    }
+
+    let Some(tenant) = tenant.filter(|tenant| tenant.tools.is_active()) else {
+        return forward_checked_command(state, request_id, idempotency, payload).await;
+    };
+    // テナントの tools.allow / tools.deny で拒否するツール呼び出しは転送しない。
+    // レスポンスキャッシュと Idempotency-Key にはテナントで絞り込む前のレスポンスを保存する
+    let command = payload.command.clone();
+    let partial = match check_tenant_tools(state, Some(tenant), &command) {
+        PolicyCheck::Forward => None,
+        PolicyCheck::Reject(rejection) => {
+            return match tool_rejection_response(&rejection, request_id) {
+                Some(response) => Err(response),
+                None => Ok(McpResponse { result: rejection }),
+            };
+        }
+        PolicyCheck::Partial(partial) => {
+            payload.command = partial.forwarded.clone();
+            Some(partial)
+        }
+    };
+    let mut response = forward_checked_command(state, request_id, idempotency, payload).await?;
+    if let Some(partial) = partial {
+        response.result = partial.merge(&response.result);
+    }
+    if let Some(filtered) = tenant
+        .tools
+        .filter_list_response(&command, &response.result)
+    {
+        response.result = filtered;
+    }
+    Ok(response)
+}
+
+// テナントの tools.allow / tools.deny でリクエスト中の tools/call を検査する
+fn check_tenant_tools(state: &AppState, tenant: Option<&Tenant>, command: &str) -> PolicyCheck {
+    let Some(tenant) = tenant else {
+        return PolicyCheck::Forward;
+    };
+    let check = tenant.tools.check_request(command);
+    if !matches!(check, PolicyCheck::Forward) {
+        warn!(tenant = %tenant.name, server = %state.server.server_key, "Rejected tool call not allowed for tenant");
+    }
+    check
+}
+
+// ツールの許可・拒否リストによる JSON-RPC エラーレスポンスを HTTP のエラーにする
+// (バッチ全体が拒否対象の場合は None。エラーの配列を通常のバッチの応答として返す)
+fn tool_rejection_response(rejection: &str, request_id: &str) -> Option<Response> {
+    JsonRpcError::parse(rejection).map(|rpc_error| {
+        mcp_error_body(
+            rpc_error.status(),
+            Some(rpc_error.code),
+            rpc_error.message,
+            request_id,
+            rpc_error.data,
+            None,
+        )
+    })
+}
+
+async fn forward_checked_command(
+    state: &AppState,
+    request_id: &str,
+    idempotency: Option<&IdempotencyKey>,
+    payload: McpRequest,
+) -> Result<McpResponse, Response> {
+    let start_time = Instant::now();
+
+    // tools/call の引数は子プロセスに転送する前に inputSchema で検証する
+    if let Some((tool_name, arguments)) = parse_tool_call(&payload.command) {
+        validate_tool_call(state, &tool_name, &arguments)
+            .await
+            .map_err(IntoResponse::into_response)?;
+    }
+
+    // 拒否対象のツール呼び出しは、Idempotency-Key の保存済みレスポンスがあっても返さない
+    if let Some(rejection) = state
+        .server
+        .check_tool_policy(&payload.command)
+        .and_then(|rejection| tool_rejection_response(&rejection, request_id))
+    {
+        return Err(rejection);
+    }
+
+    if let Some(rejection) = circuit_open_response(state, request_id) {
+        return Err(rejection);
+    }
+
+    // Idempotency-Key ヘッダーがあれば、以前の成功レスポンスをそのまま返す
+    let replayed = match idempotency {
+        Some(idempotency) => idempotent_response(state, idempotency, request_id).await?,
+        None => None,
+    };
+    if let Some(response) = replayed {
+        return Ok(response);
+    }
+
+    // response_cache 設定時は tools/list などを子プロセスに送らずに返す
+    let cache_config = state.server.config().response_cache.clone();
+    if let Some(result) = state
+        .server
+        .response_cache
+        .get(&cache_config, &payload.command)
+    {
+        debug!(server = %state.server.server_key, "Returning cached MCP response");
+        return Ok(McpResponse { result });
+    }
+
+    let queue_guard = state.load_shedder.enter_queue();
+    let server = state.server.server_key.clone();
+    let span = info_span!("mcp_request", request_id = %request_id, server = %server);
+    let mcp_process = acquire_mcp_process(state, request_id, &span).await?;
+
+    let mut result = mcp_process.query(&payload).instrument(span.clone()).await;
+    // 子プロセスがクエリ中に終了した場合、冪等なリクエストなら再起動して1回だけ再送する
+    let crashed =
+        matches!(&result, Err(e) if QueryFailure::classify(e) == QueryFailure::ProcessGone);
+    if crashed && state.server.retries_on_crash(&payload.command) {
+        warn!(parent: &span, "MCP process died mid-query, respawning and replaying the request");
+        match state.server.replace_crashed(&mcp_process).await {
+            Ok(respawned) => {
+                result = respawned.query(&payload).instrument(span.clone()).await;
+            }
+            Err(e) => error!(parent: &span, error = %e, "Failed to respawn MCP process for replay"),
+        }
+    }
+    let queue_wait = mcp_process.queue_wait();
+    drop(mcp_process);
+    let latency_ms = start_time.elapsed().as_millis() as u64;
+    state.load_shedder.record_latency(latency_ms);
+    drop(queue_guard);
+    log_slow_request(
+        state,
+        request_id,
+        [&payload.command],
+        queue_wait,
+        latency_ms,
+    );
+
+    match result {
+        Ok(response) => {
+            // JSON-RPC エラーレスポンスは対応する HTTP ステータスに変換する
+            if let Some(rpc_error) = JsonRpcError::parse(&response.result) {
+                warn!(parent: &span, latency_ms, code = rpc_error.code, message = %rpc_error.message, "MCP server returned a JSON-RPC error");
+                return Err(mcp_error_body(
+                    rpc_error.status(),
+                    Some(rpc_error.code),
+                    rpc_error.message,
+                    request_id,
+                    rpc_error.data,
+                    None,
+                ));
+            }
+            info!(parent: &span, latency_ms, "MCP query successful");
+            debug!(parent: &span, ?response, "MCP response");
+            state
+                .server
+                .response_cache
+                .store(&cache_config, &payload.command, &response.result);
+            if let Some(idempotency) = idempotency {
+                store_idempotent_response(state, idempotency, &response).await;
+            }
+            Ok(response)
+        }
+        Err(e) => {
+            error!(parent: &span, latency_ms, error = %e, "MCP query failed");
+            let (status, retry_after) = match QueryFailure::classify(&e) {
+                QueryFailure::Timeout => (StatusCode::GATEWAY_TIMEOUT, None),
+                QueryFailure::ProcessGone => (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Some(state.load_shedder.retry_after_secs()),
+                ),
+                QueryFailure::TooLarge => (StatusCode::BAD_GATEWAY, None),
+                QueryFailure::Other => (StatusCode::INTERNAL_SERVER_ERROR, None),
+            };
+            Err(mcp_failure_body(state, status, e, request_id, retry_after))
+        }
+    }
+}
+
+// --- MCP/JSON-RPC エラーの HTTP レスポンス ---
+#[derive(Serialize)]
+struct McpErrorResponse {
+    error: String,
+    // JSON-RPC エラーコード (子プロセスが返した場合のみ)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<i64>,
+    message: String,
+    request_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+    // 子プロセスの stderr の末尾 (DEBUG_MODE=true の場合のみ)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr_tail: Option<Vec<String>>,
+}
+
+fn mcp_error_body(
+    status: StatusCode,
+    code: Option<i64>,
+    message: String,
+    request_id: &str,
+    data: Option<serde_json::Value>,
+    retry_after_secs: Option<u64>,
+) -> Response {
+    let body = McpErrorResponse {
+        error: status.canonical_reason().unwrap_or("Error").to_string(),
+        code,
+        message,
+        request_id: request_id.to_string(),
+        data,
+        stderr_tail: None,
+    };
+    error_response_with_retry_after(status, body, retry_after_secs)
+}
+
+// 子プロセスが応答しない・起動していないなどの 5xx。原因は stderr に出ていることが多いため、
+// DEBUG_MODE=true の場合はその末尾を含める
+fn mcp_failure_body(
+    state: &AppState,
+    status: StatusCode,
+    message: String,
+    request_id: &str,
+    retry_after_secs: Option<u64>,
+) -> Response {
+    let body = McpErrorResponse {
+        error: status.canonical_reason().unwrap_or("Error").to_string(),
+        code: None,
+        message,
+        request_id: request_id.to_string(),
+        data: None,
+        stderr_tail: state
+            .debug_mode
+            .then(|| state.server.stderr_tail(STDERR_TAIL_LINES)),
+    };
+    error_response_with_retry_after(status, body, retry_after_secs)
+}
+
+fn error_response_with_retry_after(
+    status: StatusCode,
+    body: McpErrorResponse,
+    retry_after_secs: Option<u64>,
+) -> Response {
+    let mut response = (status, AxumJson(body)).into_response();
+    if let Some(secs) = retry_after_secs {
+        response
+            .headers_mut()
+            .insert("retry-after", HeaderValue::from(secs));
+    }
+    response
+}
+
+// 子プロセスが返した単一の JSON-RPC エラーレスポンス
+struct JsonRpcError {
+    code: i64,
+    message: String,
+    data: Option<serde_json::Value>,
+}
+
+impl JsonRpcError {
+    fn parse(response: &str) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(response).ok()?;
+        let error = value.get("error")?;
+        Some(JsonRpcError {
+            code: error.get("code")?.as_i64()?,
+            message: error
+                .get("message")
+                .and_then(|message| message.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            data: error.get("data").cloned(),
+        })
+    }
+
+    fn status(&self) -> StatusCode {
+        match self.code {
+            // Parse error / Invalid Request / Invalid params
+            -32700 | -32600 | -32602 => StatusCode::BAD_REQUEST,
+            // Method not found
+            -32601 => StatusCode::NOT_FOUND,
+            // ツールの許可リストによる拒否
+            tool_policy::TOOL_BLOCKED_ERROR_CODE => StatusCode::FORBIDDEN,
+            // scripts の throw による拒否
+            scripting::SCRIPT_REJECTED_ERROR_CODE => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+// --- ヘルスチェックハンドラ ---
+// shed モード中も 200 を返し、status で degraded (amber) を示す
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    load_shedding: bool,
+    queue_depth: usize,
+    latency_ewma_ms: f64,
+    shed_count: u64,
+}
+
+async fn handle_healthz(State(state): State<AppState>) -> AxumJson<HealthResponse> {
+    state.load_shedder.evaluate();
+    let load_shedding = state.load_shedder.is_shedding();
+    AxumJson(HealthResponse {
+        status: if load_shedding { "degraded" } else { "ok" },
+        load_shedding,
+        queue_depth: state.load_shedder.queue_depth(),
+        latency_ewma_ms: state.load_shedder.latency_ewma_ms(),
+        shed_count: state.load_shedder.shed_count(),
+    })
+}
+
+// --- レディネスチェックハンドラ ---
+// 子プロセスが稼働中 (または次のリクエストで起動できる) で、health_check / keep_alive が unhealthy でなければ 200。
+// 稼働中の子プロセスには ping を送り、PID が残っていても応答しない (ハングした) 場合は 503 にする
+// プロセスを起動・初期化している間 (再起動中など) は status: "starting" の 503
+#[derive(Serialize)]
+struct ReadyResponse {
+    status: &'static str,
+    server: String,
+    running: bool,
+    health: Option<health_check::HealthStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ping_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ping_error: Option<String>,
+}
+
+async fn handle_readyz(State(state): State<AppState>) -> Response {
+    // 起動中はプロセスのロックを待たずに starting を返す
+    let starting = state.server.is_starting();
+    let mut ready = state.server.is_ready().await;
+    let ping = match state.readyz_ping_timeout {
+        Some(timeout) if ready => state.server.ping(timeout).await,
+        _ => None,
+    };
+    if let Some(Err(e)) = &ping {
+        warn!(server = %state.server.server_key, error = %e, "Readiness ping failed");
+        ready = false;
+    }
+    let body = ReadyResponse {
+        status: match (ready, starting) {
+            (true, _) => "ready",
+            (false, true) => "starting",
+            (false, false) => "not_ready",
+        },
+        server: state.server.server_key.clone(),
+        running: !starting && state.server.is_running().await,
+        health: state
+            .server
+            .config()
+            .monitors_health()
+            .then(|| state.server.health.snapshot().status),
+        ping_ms: ping
+            .as_ref()
+            .and_then(|ping| ping.as_ref().ok())
+            .map(|elapsed| elapsed.as_millis() as u64),
+        ping_error: ping.and_then(Result::err),
+    };
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, AxumJson(body)).into_response()
+}
+
+// --- MCPサーバー情報ハンドラ ---
+// ブリッジが実行した initialize の結果 (capabilities / serverInfo) を返す
+#[derive(Serialize)]
+struct InfoResponse {
+    server: String,
+    protocol_version: Option<serde_json::Value>,
+    capabilities: Option<serde_json::Value>,
+    server_info: Option<serde_json::Value>,
+    instructions: Option<serde_json::Value>,
+}
+
+async fn handle_info(
+    State(state): State<AppState>,
+) -> Result<AxumJson<InfoResponse>, (StatusCode, AxumJson<ApiError>)> {
+    let cached = match state.server.initialize_result() {
+        Some(result) => Some(result),
+        // lazy 設定で未起動の場合はここで起動してハンドシェイクを行う
+        None => {
+            drop(state.server.acquire().await);
+            state.server.initialize_result()
+        }
+    };
+    let Some(mut result) = cached else {
+        let error_response = ApiError {
+            error: "Service Unavailable".to_string(),
+            message: format!(
+                "MCP server '{}' has not completed the initialize handshake",
+                state.server.server_key
+            ),
+        };
+        return Err((StatusCode::SERVICE_UNAVAILABLE, AxumJson(error_response)));
+    };
+    let mut take = |field: &str| result.get_mut(field).map(serde_json::Value::take);
+    Ok(AxumJson(InfoResponse {
+        server: state.server.server_key.clone(),
+        protocol_version: take("protocolVersion"),
+        capabilities: take("capabilities"),
+        server_info: take("serverInfo"),
+        instructions: take("instructions"),
+    }))
+}
+
+// --- ツール一覧ハンドラ ---
+// tools/list をブリッジ側で実行し、ツール定義の配列だけを返す
+// format=openai|anthropic を指定すると各 LLM API のツール定義形式に変換して返す
+#[derive(Deserialize)]
+struct ToolsQuery {
+    #[serde(default)]
+    format: ToolFormat,
+}
+
+async fn handle_tools(
+    State(state): State<AppState>,
+    Query(query): Query<ToolsQuery>,
+    headers: HeaderMap,
+    tenant: Option<Extension<Arc<Tenant>>>,
+) -> Result<Response, (StatusCode, AxumJson<ApiError>)> {
+    match state.server.list_all("tools/list", "tools").await {
+        Ok(tools) => Ok(etag::json_response(
+            &headers,
+            &tool_schema::convert(tenant_tools(tenant, tools), query.format),
+        )),
+        Err(e) => {
+            warn!(server = %state.server.server_key, error = %e, "Failed to list tools");
+            Err(mcp_error_response(e))
+        }
+    }
+}
+
+// テナントの tools.allow / tools.deny で許可されていないツールを一覧から取り除く
+fn tenant_tools(
+    tenant: Option<Extension<Arc<Tenant>>>,
+    tools: Vec<serde_json::Value>,
+) -> Vec<serde_json::Value> {
+    match tenant {
+        Some(Extension(tenant)) => tenant.tools.filter_tools(tools),
+        None => tools,
+    }
+}
+
+// --- OpenAPI ドキュメント ---
+async fn handle_openapi(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    tenant: Option<Extension<Arc<Tenant>>>,
+) -> Result<Response, (StatusCode, AxumJson<ApiError>)> {
+    let tools = state
+        .server
+        .list_all("tools/list", "tools")
+        .await
+        .map_err(|e| {
+            warn!(server = %state.server.server_key, error = %e, "Failed to build OpenAPI document");
+            mcp_error_response(e)
+        })?;
+    let tools = tenant_tools(tenant, tools);
+    let server_info = state
+        .server
+        .initialize_result()
+        .and_then(|result| result.get("serverInfo").cloned());
+    Ok(etag::json_response(
+        &headers,
+        &openapi::build_spec(&state.server.server_key, &tools, server_info.as_ref()),
+    ))
+}
+
+// --- リソース・プロンプトハンドラ ---
+async fn handle_resources(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, AxumJson<ApiError>)> {
+    state
+        .server
+        .list_all("resources/list", "resources")
+        .await
+        .map(|resources| etag::json_response(&headers, &resources))
+        .map_err(|e| {
+            warn!(server = %state.server.server_key, error = %e, "Failed to list resources");
+            mcp_error_response(e)
+        })
+}
+
+#[derive(Deserialize)]
+struct ReadResourceQuery {
+    uri: String,
+}
+
+// resources/read の contents 配列だけを返す
+async fn handle_resource_read(
+    State(state): State<AppState>,
+    Query(query): Query<ReadResourceQuery>,
+) -> Result<AxumJson<serde_json::Value>, (StatusCode, AxumJson<ApiError>)> {
+    let mut result = state
+        .server
+        .call("resources/read", serde_json::json!({ "uri": query.uri }))
+        .await
+        .map_err(|e| {
+            warn!(server = %state.server.server_key, uri = %query.uri, error = %e, "Failed to read resource");
+            mcp_error_response(e)
+        })?;
+    Ok(AxumJson(
+        result
+            .get_mut("contents")
+            .map(serde_json::Value::take)
+            .unwrap_or_else(|| serde_json::json!([])),
+    ))
+}
+
+async fn handle_prompts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, AxumJson<ApiError>)> {
+    state
+        .server
+        .list_all("prompts/list", "prompts")
+        .await
+        .map(|prompts| etag::json_response(&headers, &prompts))
+        .map_err(|e| {
+            warn!(server = %state.server.server_key, error = %e, "Failed to list prompts");
+            mcp_error_response(e)
+        })
+}
+
+// リクエストボディを arguments として prompts/get を実行し、結果 (description / messages) を返す
+async fn handle_prompt_get(
+    State(state): State<AppState>,
+    Path(prompt_name): Path<String>,
+    body: Option<AxumJson<serde_json::Value>>,
+) -> Result<AxumJson<serde_json::Value>, (StatusCode, AxumJson<ApiError>)> {
+    let arguments = body
+        .map(|AxumJson(arguments)| arguments)
+        .unwrap_or_else(|| serde_json::json!({}));
+    let params = serde_json::json!({ "name": prompt_name, "arguments": arguments });
+    state
+        .server
+        .call("prompts/get", params)
+        .await
+        .map(AxumJson)
+        .map_err(|e| {
+            warn!(server = %state.server.server_key, prompt = %prompt_name, error = %e, "Failed to get prompt");
+            mcp_error_response(e)
+        })
+}
+
+// --- サーバーからの通知 ---
+#[derive(Deserialize)]
+struct NotificationsQuery {
+    #[serde(default)]
+    since: u64,
+}
+
+// バッファ済みの通知のうち since より後のものを返す
+async fn handle_notifications(
+    State(state): State<AppState>,
+    Query(query): Query<NotificationsQuery>,
+) -> AxumJson<NotificationPage> {
+    AxumJson(state.server.notifications.since(query.since))
+}
+
+// --- 通知の long polling ---
+// SSE や WebSocket を通さないプロキシの内側のクライアント向け。
+// cursor より後の通知が届くまで (最大 wait 秒) 応答を保留し、届かなければ空のページを返す
+const DEFAULT_POLL_WAIT_SECS: u64 = 30;
+const MAX_POLL_WAIT_SECS: u64 = 120;
+
+#[derive(Deserialize)]
+struct PollNotificationsQuery {
+    #[serde(default)]
+    cursor: u64,
+    wait: Option<u64>,
+}
+
+async fn handle_notifications_poll(
+    State(state): State<AppState>,
+    Query(query): Query<PollNotificationsQuery>,
+) -> AxumJson<NotificationPage> {
+    let wait = query
+        .wait
+        .unwrap_or(DEFAULT_POLL_WAIT_SECS)
+        .min(MAX_POLL_WAIT_SECS);
+    AxumJson(
+        state
+            .server
+            .notifications
+            .wait_since(query.cursor, Duration::from_secs(wait))
+            .await,
+    )
+}
+
+// --- ツール引数の検証 ---
+#[derive(Serialize)]
+struct ValidationErrorResponse {
+    error: String,
+    message: String,
+    violations: Vec<String>,
+}
+
+// 単一の JSON-RPC tools/call リクエストであればツール名と引数を取り出す
+fn parse_tool_call(command: &str) -> Option<(String, serde_json::Value)> {
+    let request: serde_json::Value = serde_json::from_str(command).ok()?;
+    if request.get("method")?.as_str()? != "tools/call" {
+        return None;
+    }
+    let params = request.get("params")?;
+    let tool_name = params.get("name")?.as_str()?.to_string();
+    let arguments = params
+        .get("arguments")
+        .cloned()
+        .unwrap_or_else(|| serde_json::json!({}));
+    Some((tool_name, arguments))
+}
+
+async fn validate_tool_call(
+    state: &AppState,
+    tool_name: &str,
+    arguments: &serde_json::Value,
+) -> Result<(), (StatusCode, AxumJson<ValidationErrorResponse>)> {
+    let Some(schema) = state.server.tool_input_schema(tool_name).await else {
+        return Ok(());
+    };
+    tool_schema::validate_arguments(&schema, arguments).map_err(|violations| {
+        debug!(tool = %tool_name, ?violations, "Tool arguments failed schema validation");
+        let error_response = ValidationErrorResponse {
+            error: "Bad Request".to_string(),
+            message: format!(
+                "Arguments for tool '{}' do not match its inputSchema",
+                tool_name
+            ),
+            violations,
+        };
+        (StatusCode::BAD_REQUEST, AxumJson(error_response))
+    })
+}
+
+// --- ツール呼び出しハンドラ ---
+// リクエストボディをそのまま arguments として tools/call を実行し、content 配列だけを返す
+#[derive(Deserialize)]
+struct ToolCallQuery {
+    // NDJSON のストリーミング時に、子プロセスの進捗通知も行として返す
+    #[serde(default)]
+    progress: bool,
+}
+
+async fn handle_tool_call(
+    State(state): State<AppState>,
+    Extension(RequestId(request_id)): Extension<RequestId>,
+    Path(tool_name): Path<String>,
+    Query(query): Query<ToolCallQuery>,
+    headers: HeaderMap,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    body: Option<AxumJson<serde_json::Value>>,
+) -> Result<Response, Response> {
+    let arguments = body
+        .map(|AxumJson(arguments)| arguments)
+        .unwrap_or_else(|| serde_json::json!({}));
+    let mut params = serde_json::json!({ "name": tool_name, "arguments": arguments });
+    // scripts / wasm_plugins の on_request には tools/call の JSON-RPC リクエストとして渡す
+    let mut response_script = None;
+    let state = match state.server.config().request_hooks() {
+        Some(hooks) => {
+            let request = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": request_id,
+                "method": "tools/call",
+                "params": params,
+            });
+            let tenant = tenant.as_ref().map(|Extension(tenant)| tenant.as_ref());
+            let tenant_name = tenant.map(|tenant| tenant.name.clone());
+            let (routed, request) =
+                script_request(&state, &hooks, tenant_name.as_deref(), &request.to_string())
+                    .await
+                    .map_err(|e| script_error_response(&state, &request_id, e))?;
+            if let Some(forbidden) = tenant_forbidden_response(&routed, tenant) {
+                return Err(forbidden);
+            }
+            let state = routed;
+            params = request
+                .get("params")
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({}));
+            response_script = hooks.has_on_response().then(|| ResponseScript {
+                hooks,
+                server: state.server.server_key.clone(),
+                tenant: tenant_name,
+                request,
+            });
+            state
+        }
+        None => state,
+    };
+    // 書き換えルール (rewrite) も tools/call の JSON-RPC リクエストとして、検証より前に適用する
+    let mut request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": request_id,
+        "method": "tools/call",
+        "params": params,
+    });
+    state.server.rewrite_message(&mut request);
+    let params = request
+        .get_mut("params")
+        .map(serde_json::Value::take)
+        .unwrap_or_else(|| serde_json::json!({}));
+    let tool_name = params
+        .get("name")
+        .and_then(|name| name.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let arguments = params
+        .get("arguments")
+        .cloned()
+        .unwrap_or_else(|| serde_json::json!({}));
+    // 検証時に tools/list を取得するため、annotations による read_only 判定はその後に行う
+    validate_tool_call(&state, &tool_name, &arguments)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let tenant_allows = tenant
+        .as_ref()
+        .is_none_or(|Extension(tenant)| tenant.tools.is_allowed(&tool_name));
+    if !state.server.is_tool_allowed(&tool_name) || !tenant_allows {
+        warn!(server = %state.server.server_key, tool = %tool_name, "Rejected call to blocked tool");
+        let error_response = ApiError {
+            error: "Forbidden".to_string(),
+            message: format!("Tool '{}' is not allowed", tool_name),
+        };
+        return Err((StatusCode::FORBIDDEN, AxumJson(error_response)).into_response());
+    }
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if accept.contains(EVENT_STREAM_CONTENT_TYPE) {
+        return Ok(sse_tool_call(&state, tool_name, params, response_script));
+    }
+    if accept.contains(NDJSON_CONTENT_TYPE) {
+        return Ok(stream_tool_call(
+            &state,
+            tool_name,
+            params,
+            query.progress,
+            response_script,
+        ));
+    }
+    let mut result = state.server.call("tools/call", params).await.map_err(|e| {
+        warn!(server = %state.server.server_key, tool = %tool_name, error = %e, "Tool call failed");
+        mcp_error_response(e).into_response()
+    })?;
+    if let Some(script) = &response_script {
+        result = script
+            .apply(result)
+            .await
+            .map_err(|e| script_error_response(&state, &request_id, e))?;
+    }
+
+    let content = result
+        .get_mut("content")
+        .map(serde_json::Value::take)
+        .unwrap_or_else(|| serde_json::json!([]));
+    // ツール自体がエラーを返した場合 (isError) は 502 とし、テキスト内容をメッセージにする
+    if is_tool_error(&result) {
+        let error_response = ApiError {
+            error: "Tool Error".to_string(),
+            message: content_text(&content),
+        };
+        return Err((StatusCode::BAD_GATEWAY, AxumJson(error_response)).into_response());
+    }
+    Ok(AxumJson(content).into_response())
+}
+
+// --- ツール呼び出しの結果に適用する on_response ---
+// JSON-RPC レスポンスの形にしてスクリプトに渡し、result を取り出す (error に差し替えられた場合はエラー)
+struct ResponseScript {
+    hooks: RequestHooks,
+    server: String,
+    tenant: Option<String>,
+    request: serde_json::Value,
+}
+
+impl ResponseScript {
+    async fn apply(&self, result: serde_json::Value) -> Result<serde_json::Value, ScriptError> {
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": self.request.get("id"),
+            "result": result,
+        });
+        let mut response = self
+            .hooks
+            .on_response_message(
+                &self.server,
+                self.tenant.as_deref(),
+                &self.request,
+                response,
+            )
+            .await?;
+        if let Some(rpc_error) = JsonRpcError::parse(&response.to_string()) {
+            return Err(ScriptError::Rejected {
+                status: rpc_error.status().as_u16(),
+                code: rpc_error.code,
+                message: rpc_error.message,
+                data: rpc_error.data,
+            });
+        }
+        Ok(response
+            .get_mut("result")
+            .map(serde_json::Value::take)
+            .unwrap_or_default())
+    }
+
+    // ストリーミングでは送信を始めた後のため、エラーはメッセージだけを返す
+    async fn apply_streamed(
+        script: &Option<Self>,
+        result: Result<serde_json::Value, String>,
+    ) -> Result<serde_json::Value, String> {
+        match (script, result) {
+            (Some(script), Ok(result)) => script.apply(result).await.map_err(|e| e.to_string()),
+            (_, result) => result,
+        }
+    }
+}
+
+fn is_tool_error(result: &serde_json::Value) -> bool {
+    result.get("isError").and_then(|v| v.as_bool()) == Some(true)
+}
+
+// content 配列のテキストを改行でつなげる
+fn content_text(content: &serde_json::Value) -> String {
+    content
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.get("text").and_then(|text| text.as_str()))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default()
+}
+
+// --- ツール呼び出し結果のストリーミング ---
+// content の要素を子プロセスから読み取り次第、1要素1行の NDJSON として返す。
+// progress の場合は進捗通知を {"type": "progress", ...} の行として間に挟む。
+// 送信開始後に失敗した場合や isError の場合は、最後の行を ApiError 形式のエラーにする
+fn stream_tool_call(
+    state: &AppState,
+    tool_name: String,
+    params: serde_json::Value,
+    progress: bool,
+    script: Option<ResponseScript>,
+) -> Response {
+    let (items_tx, items_rx) = mpsc::channel(STREAM_BUFFER_ITEMS);
+    let (progress_tx, progress_rx) = mpsc::channel(STREAM_BUFFER_ITEMS);
+    let progress_tx = progress.then_some(progress_tx);
+    // on_response がある場合は結果全体をスクリプトに通すため、要素ごとには送らない
+    let items_tx = script.is_none().then_some(items_tx);
+    let server = Arc::clone(&state.server);
+    let call = tokio::spawn(async move {
+        let result = server
+            .call_streaming("tools/call", params, items_tx, progress_tx)
+            .await;
+        ResponseScript::apply_streamed(&script, result).await
+    });
+    // クライアントが切断して本文のストリームが破棄されたら、呼び出しも打ち切る (子プロセスには取り消しを送る)
+    let abort = AbortOnDrop(call.abort_handle());
+    let lines = stream::unfold(
+        (items_rx, progress_rx, Some(call), abort),
+        move |(mut items_rx, mut progress_rx, call, abort)| {
+            let tool_name = tool_name.clone();
+            async move {
+                // 呼び出しが終わると両方の送信側が破棄されて else に進む
+                let line = tokio::select! {
+                    Some(item) = items_rx.recv() => Some(item + "\n"),
+                    Some(update) = progress_rx.recv() => Some(progress_line(update)),
+                    else => None,
+                };
+                if let Some(line) = line {
+                    return Some((
+                        Ok::<_, Infallible>(line),
+                        (items_rx, progress_rx, call, abort),
+                    ));
+                }
+                // 子プロセスの応答を読み終えたら、残りの要素と結果を書き出して終える
+                let result = call?
+                    .await
+                    .unwrap_or_else(|e| Err(format!("Tool call task failed: {}", e)));
+                Some((
+                    Ok(final_stream_lines(&tool_name, result)),
+                    (items_rx, progress_rx, None, abort),
+                ))
+            }
+        },
+    );
+    (
+        [(header::CONTENT_TYPE, NDJSON_CONTENT_TYPE)],
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
+
+// 進捗通知の params (progress / total / message) に type を付けた NDJSON の行
+fn progress_line(mut update: serde_json::Value) -> String {
+    if let Some(update) = update.as_object_mut() {
+        update.insert("type".to_string(), serde_json::json!("progress"));
+    }
+    ndjson_line(&update)
+}
+
+// --- ツール呼び出しの進捗の SSE ストリーム ---
+// Accept: text/event-stream の場合、子プロセスの進捗通知を progress イベントとして送り、
+// 最後に content 配列を result イベント (失敗や isError の場合は ApiError 形式の error イベント) として送る
+fn sse_tool_call(
+    state: &AppState,
+    tool_name: String,
+    params: serde_json::Value,
+    script: Option<ResponseScript>,
+) -> Response {
+    let (progress_tx, progress_rx) = mpsc::channel(STREAM_BUFFER_ITEMS);
+    let server = Arc::clone(&state.server);
+    let call = tokio::spawn(async move {
+        let result = server
+            .call_streaming("tools/call", params, None, Some(progress_tx))
+            .await;
+        ResponseScript::apply_streamed(&script, result).await
+    });
+    let abort = AbortOnDrop(call.abort_handle());
+    let events = stream::unfold(
+        (progress_rx, Some(call), abort),
+        move |(mut progress_rx, call, abort)| {
+            let tool_name = tool_name.clone();
+            async move {
+                if let Some(update) = progress_rx.recv().await {
+                    let event = Event::default()
+                        .event("progress")
+                        .json_data(&update)
+                        .unwrap_or_else(|_| Event::default().comment("serialization error"));
+                    return Some((Ok::<_, Infallible>(event), (progress_rx, call, abort)));
+                }
+                let result = call?
+                    .await
+                    .unwrap_or_else(|e| Err(format!("Tool call task failed: {}", e)));
+                Some((
+                    Ok(final_sse_event(&tool_name, result)),
+                    (progress_rx, None, abort),
+                ))
+            }
+        },
+    );
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn final_sse_event(tool_name: &str, result: Result<serde_json::Value, String>) -> Event {
+    let error = |error_response: &ApiError| {
+        Event::default()
+            .event("error")
+            .json_data(error_response)
+            .unwrap_or_else(|_| Event::default().comment("serialization error"))
+    };
+    let mut result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            warn!(tool = %tool_name, error = %e, "Tool call with progress failed");
+            let (_, AxumJson(error_response)) = mcp_error_response(e);
+            return error(&error_response);
+        }
+    };
+    let content = result
+        .get_mut("content")
+        .map(serde_json::Value::take)
+        .unwrap_or_else(|| serde_json::json!([]));
+    if is_tool_error(&result) {
+        return error(&ApiError {
+            error: "Tool Error".to_string(),
+            message: content_text(&content),
+        });
+    }
+    Event::default()
+        .event("result")
+        .json_data(&content)
+        .unwrap_or_else(|_| Event::default().comment("serialization error"))
+}
+
+struct AbortOnDrop(tokio::task::AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+// ストリーミングできずに返り値に残った content の要素と、エラーの行
+fn final_stream_lines(tool_name: &str, result: Result<serde_json::Value, String>) -> String {
+    let mut result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            warn!(tool = %tool_name, error = %e, "Streaming tool call failed");
+            let (_, AxumJson(error_response)) = mcp_error_response(e);
+            return ndjson_line(&error_response);
+        }
+    };
+    let content = result
+        .get_mut("content")
+        .map(serde_json::Value::take)
+        .unwrap_or_else(|| serde_json::json!([]));
+    let mut lines: String = content
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(ndjson_line)
+        .collect();
+    if is_tool_error(&result) {
+        // 送信済みの要素にエラーの内容が含まれている場合もある
+        let message = Some(content_text(&content))
+            .filter(|message| !message.is_empty())
+            .unwrap_or_else(|| format!("Tool '{}' returned an error", tool_name));
+        lines += &ndjson_line(&ApiError {
+            error: "Tool Error".to_string(),
+            message,
+        });
+    }
+    lines
+}
+
+fn ndjson_line(value: &impl Serialize) -> String {
+    serde_json::to_string(value).unwrap_or_default() + "\n"
+}
+
+// 停止中は 503、子プロセス側のエラーは 502
+fn mcp_error_response(e: String) -> (StatusCode, AxumJson<ApiError>) {
+    let status = if e.contains("not running")
+        || e.contains(circuit_breaker::CIRCUIT_OPEN_ERROR)
+        || e.contains(mcp_process::QUEUE_FULL_ERROR)
+    {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::BAD_GATEWAY
+    };
+    let error_response = ApiError {
+        error: status.canonical_reason().unwrap_or("Error").to_string(),
+        message: e,
+    };
+    (status, AxumJson(error_response))
+}
+
+// --- 統計情報ハンドラ ---
+async fn handle_stats(
+    State(state): State<AppState>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+) -> AxumJson<HashMap<String, StatsSnapshot>> {
+    let snapshots = visible_stats(&state, tenant)
+        .await
+        .into_iter()
+        .map(|snapshot| (snapshot.server.clone(), snapshot))
+        .collect();
+    AxumJson(snapshots)
+}
+
+// MCP_SERVER_NAME=all の場合も含めて、起動したすべてのサーバーの統計 (名前順)。
+// テナントのキーでは利用できるサーバーだけ
+async fn visible_stats(
+    state: &AppState,
+    tenant: Option<Extension<Arc<Tenant>>>,
+) -> Vec<StatsSnapshot> {
+    let mut snapshots = Vec::new();
+    for server in state.servers.iter() {
+        let allowed = tenant
+            .as_ref()
+            .is_none_or(|Extension(tenant)| tenant.allows_server(server));
+        if allowed {
+            snapshots.push(server_stats(state, server).await);
+        }
+    }
+    snapshots.sort_by(|a, b| a.server.cmp(&b.server));
+    snapshots
+}
+
+async fn server_stats(state: &AppState, server: &McpServer) -> StatsSnapshot {
+    let mut snapshot = server.stats_snapshot().await;
+    snapshot.circuit_breaker = Some(server.circuit_breaker.snapshot());
+    if server.config().response_cache.ttl_secs > 0 {
+        snapshot.response_cache = Some(server.response_cache.snapshot());
+    }
+    if server.config().monitors_health() {
+        snapshot.health_check = Some(server.health.snapshot());
+    }
+    snapshot.sessions = match state.server_sessions.get(&server.server_key) {
+        Some(sessions) => Some(sessions.snapshot().await),
+        None => None,
+    };
+    snapshot
+}
+
+// Prometheus のテキスト形式 (/stats と同じ値とレイテンシのヒストグラム)
+async fn handle_metrics(
+    State(state): State<AppState>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+) -> impl IntoResponse {
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        stats::to_prometheus(&visible_stats(&state, tenant).await),
+    )
+}
+
+// --- バージョン ---
+#[derive(Serialize)]
+struct VersionResponse {
+    name: &'static str,
+    version: &'static str,
+}
+
+async fn handle_version() -> AxumJson<VersionResponse> {
+    AxumJson(VersionResponse {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+// --- ダッシュボード ---
+// 静的ページのみ配信し、データは /stats と /admin をブラウザから認証付きで呼び出す
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+async fn handle_dashboard() -> Html<&'static str> {
+    Html(DASHBOARD_HTML)
+}
+
+// --- GraphQL ---
+async fn handle_graphql(
+    State(state): State<AppState>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    AxumJson(mut request): AxumJson<async_graphql::Request>,
+) -> AxumJson<async_graphql::Response> {
+    if let Some(Extension(tenant)) = tenant {
+        request = request.data(tenant);
+    }
+    AxumJson(state.graphql_schema.execute(request).await)
+}
+
+// GraphiQL (ダッシュボードと同じく静的ページのみで、クエリの実行は認証付き)
+async fn handle_graphiql() -> Html<String> {
+    Html(
+        async_graphql::http::GraphiQLSource::build()
+            .endpoint("/graphql")
+            .finish(),
+    )
+}
+
+// --- 管理APIハンドラ ---
+#[derive(Clone, Copy, Debug)]
+enum AdminAction {
+    Start,
+    Stop,
+    Restart,
+    Update,
+}
+
+#[derive(Serialize)]
+struct AdminActionResponse {
+    server: String,
+    action: String,
+    running: bool,
+}
+
+// 管理APIの対象のサーバー (未知の名前は 404)
+fn admin_server(
+    state: &AppState,
+    name: &str,
+) -> Result<Arc<McpServer>, (StatusCode, AxumJson<ApiError>)> {
+    state.servers.get(name).cloned().ok_or_else(|| {
+        let error_response = ApiError {
+            error: "Not Found".to_string(),
+            message: format!("Unknown MCP server '{}'", name),
+        };
+        (StatusCode::NOT_FOUND, AxumJson(error_response))
+    })
+}
+
+async fn handle_admin_action(
+    state: AppState,
+    name: String,
+    action: AdminAction,
+) -> Result<AxumJson<AdminActionResponse>, (StatusCode, AxumJson<ApiError>)> {
+    let server = admin_server(&state, &name)?;
+
+    info!(server = %name, ?action, "Admin action requested");
+    let result = match action {
+        AdminAction::Start => server.start().await,
+        AdminAction::Stop => server.stop().await,
+        AdminAction::Restart => server.restart("admin").await,
+        AdminAction::Update => update_server(&server).await,
+    };
+
+    match result {
+        Ok(()) => Ok(AxumJson(AdminActionResponse {
+            server: name,
+            action: format!("{:?}", action).to_lowercase(),
+            running: server.is_running().await,
+        })),
+        Err(e) => {
+            warn!(server = %name, ?action, error = %e, "Admin action failed");
+            // 既に起動中/停止中の場合は 409、起動失敗は 500
+            let status = match action {
+                AdminAction::Start | AdminAction::Stop
+                    if e.contains("already running") || e.contains("not running") =>
+                {
+                    StatusCode::CONFLICT
+                }
+                AdminAction::Update if e.contains("nothing to update") => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let error_response = ApiError {
+                error: status.canonical_reason().unwrap_or("Error").to_string(),
+                message: e,
+            };
+            Err((status, AxumJson(error_response)))
+        }
+    }
+}
+
+// git pull と post_install の後、起動中であれば更新したコードで再起動する
+async fn update_server(server: &Arc<McpServer>) -> Result<(), String> {
+    hooks::update(&server.server_key, &server.config()).await?;
+    if !server.is_running().await {
+        return Ok(());
+    }
+    server.restart("update").await
+}
+
+async fn handle_admin_start(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<AxumJson<AdminActionResponse>, (StatusCode, AxumJson<ApiError>)> {
+    handle_admin_action(state, name, AdminAction::Start).await
+}
+
+async fn handle_admin_stop(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<AxumJson<AdminActionResponse>, (StatusCode, AxumJson<ApiError>)> {
+    handle_admin_action(state, name, AdminAction::Stop).await
+}
+
+async fn handle_admin_restart(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<AxumJson<AdminActionResponse>, (StatusCode, AxumJson<ApiError>)> {
+    handle_admin_action(state, name, AdminAction::Restart).await
+}
+
+async fn handle_admin_update(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<AxumJson<AdminActionResponse>, (StatusCode, AxumJson<ApiError>)> {
+    handle_admin_action(state, name, AdminAction::Update).await
+}
+
+// --- 子プロセスの stderr ---
+// stderr_buffer_kb の範囲で保持している末尾をテキストで返す
+async fn handle_admin_stderr(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, AxumJson<ApiError>)> {
+    let server = admin_server(&state, &name)?;
+    let mut text = server.stderr_tail(usize::MAX).join("\n");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], text))
+}
+
+// --- 直近のリクエスト ---
+#[derive(Deserialize)]
+struct RecentRequestsQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct RecentRequestsResponse {
+    requests: Vec<RecentRequest>,
+}
+
+// 新しい順に返す (limit で件数を絞れる)
+async fn handle_admin_requests(
+    State(state): State<AppState>,
+    Query(query): Query<RecentRequestsQuery>,
+) -> Result<AxumJson<RecentRequestsResponse>, (StatusCode, AxumJson<ApiError>)> {
+    match state
+        .recent_requests
+        .latest(query.limit.unwrap_or(usize::MAX))
+        .await
+    {
+        Ok(requests) => Ok(AxumJson(RecentRequestsResponse { requests })),
+        Err(e) => {
+            error!(error = %e, "Failed to read recent requests from storage");
+            let error_response = ApiError {
+                error: "Storage Error".to_string(),
+                message: e,
+            };
+            Err((StatusCode::SERVICE_UNAVAILABLE, AxumJson(error_response)))
+        }
+    }
+}
+
+// --- ライフサイクルイベントの SSE ストリーム ---
+// 購読開始以降のイベントを配信する (過去のイベントは再送しない)
+async fn handle_admin_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.events.subscribe();
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let sse_event = Event::default()
+                        .event(event.kind.name())
+                        .json_data(&event)
+                        .unwrap_or_else(|_| Event::default().comment("serialization error"));
+                    return Some((Ok(sse_event), receiver));
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "Lifecycle event subscriber lagged, events dropped");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+// --- 冪等性キャッシュのレスポンスを引く関数 ---
+// 同じキーを別のリクエストボディに使い回した場合は 422 にする
+async fn idempotent_response(
+    state: &AppState,
+    idempotency: &IdempotencyKey,
+    request_id: &str,
+) -> Result<Option<McpResponse>, Response> {
+    let storage_key = idempotency.storage_key(&state.server.server_key);
+    let cached = match state.storage.get(NAMESPACE_IDEMPOTENCY, &storage_key).await {
+        Ok(Some(cached)) => cached,
+        Ok(None) => return Ok(None),
+        Err(e) => {
+            warn!(error = %e, "Idempotency cache lookup failed");
+            return Ok(None);
+        }
+    };
+    match idempotency.decode(&cached) {
+        Ok(IdempotentLookup::Hit(response)) => {
+            debug!(idempotency_key = %idempotency.key(), "Returning cached response for idempotency key");
+            Ok(Some(response))
+        }
+        Ok(IdempotentLookup::BodyMismatch) => {
+            warn!(idempotency_key = %idempotency.key(), "Rejected idempotency key reused with a different request body");
+            Err(mcp_error_body(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                None,
+                format!(
+                    "Idempotency-Key '{}' was already used with a different request body",
+                    idempotency.key()
+                ),
+                request_id,
+                None,
+                None,
+            ))
+        }
+        Err(e) => {
+            warn!(error = %e, "Discarding unreadable idempotency cache entry");
+            Ok(None)
+        }
+    }
+}
+
+// --- 冪等性キャッシュにレスポンスを保存する関数 ---
+async fn store_idempotent_response(
+    state: &AppState,
+    idempotency: &IdempotencyKey,
+    response: &McpResponse,
+) {
+    let value = match idempotency.encode(response) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!(error = %e, "Failed to serialize response for idempotency cache");
+            return;
+        }
+    };
+    let storage_key = idempotency.storage_key(&state.server.server_key);
+    if let Err(e) = state
+        .storage
+        .put(
+            NAMESPACE_IDEMPOTENCY,
+            &storage_key,
+            &value,
+            Some(state.idempotency_ttl),
+        )
+        .await
+    {
+        warn!(error = %e, "Failed to store idempotency cache entry");
+    }
+}
+
+// --- 秒数の環境変数を読み取る関数 ---
+fn env_secs(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(default)
+}
+
+fn is_auth_disabled() -> bool {
+    env::var("DISABLE_AUTH")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse::<bool>()
+        .unwrap_or(false)
+}
+
+// --- サーバーごとの API ---
+// 対象のサーバーの AppState を持たせたルーター (既定のサーバーはルートに、各サーバーは /servers/{name} に置く)
+fn server_routes(state: AppState, load_shedder: &Arc<LoadShedder>) -> Router {
+    Router::new()
+        .route(
+            "/api/v1",
+            post(handle_mcp_request_shared)
+                .route_layer(middleware::from_fn_with_state(
+                    Arc::clone(load_shedder),
+                    load_shed_middleware,
+                ))
+                .delete(handle_session_delete),
+        )
+        .route(
+            "/api/v1/rpc",
+            post(handle_mcp_rpc).route_layer(middleware::from_fn_with_state(
+                Arc::clone(load_shedder),
+                load_shed_middleware,
+            )),
+        )
+        .route(
+            "/api/v1/batch",
+            post(handle_mcp_batch).route_layer(middleware::from_fn_with_state(
+                Arc::clone(load_shedder),
+                load_shed_middleware,
+            )),
+        )
+        .route(
+            "/graphql",
+            post(handle_graphql).route_layer(middleware::from_fn_with_state(
+                Arc::clone(load_shedder),
+                load_shed_middleware,
+            )),
+        )
+        .route("/api/v1/info", get(handle_info))
+        .route("/api/v1/tools", get(handle_tools))
+        .route("/api/v1/tools/{tool_name}", post(handle_tool_call))
+        .route("/api/v1/resources", get(handle_resources))
+        .route("/api/v1/resources/read", get(handle_resource_read))
+        .route("/api/v1/prompts", get(handle_prompts))
+        .route("/api/v1/prompts/{prompt_name}", post(handle_prompt_get))
+        .route("/api/v1/notifications", get(handle_notifications))
+        .route("/api/v1/notifications/poll", get(handle_notifications_poll))
+        .route("/openapi.json", get(handle_openapi))
+        .route("/healthz", get(handle_healthz))
+        .route("/readyz", get(handle_readyz))
+        .route("/stats", get(handle_stats))
+        .route("/metrics", get(handle_metrics))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            tenant_access_middleware,
+        ))
+        .with_state(state)
+}
+
+// /servers/{name} のパスにそのまま使えるサーバー名か
+fn is_path_safe(server_key: &str) -> bool {
+    !server_key.is_empty()
+        && server_key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+// --- 起動するサーバーの設定の読み込み ---
+// 名前・別名・default の解決は servers::select を参照。
+// MCP_REGISTRY_URL が設定されていれば、レジストリの定義と設定ファイルをマージする
+// (設定ファイルが URL の場合もレジストリと同じく定期的に取得し直すため、Registry も返す)
+async fn load_server_configs(
+    config_file: &str,
+    requested: Option<&str>,
+    start_all: bool,
+) -> Result<Vec<(String, McpProcessConfig, Option<registry::Registry>)>, String> {
+    let uses_registry = registry::Registry::from_env(config_file, "");
+    let definitions = match &uses_registry {
+        Some(registry) => registry.definitions().await?,
+        None => config_file::read(config_file)
+            .await?
+            .ok_or_else(|| format!("MCP config file '{}' does not exist", config_file))?,
+    };
+    let server_keys = servers::select(&definitions, requested, DEFAULT_SERVER_NAME, start_all)
+        .ok_or_else(|| {
+            format!(
+                "MCP server configuration not found for key '{}' in file '{}'",
+                requested.unwrap_or_default(),
+                config_file
+            )
+        })?;
+    if uses_registry.is_none() {
+        let mut configs = config_file::parse_servers(definitions, config_file)?;
+        return server_keys
+            .into_iter()
+            .map(|server_key| match configs.remove(&server_key) {
+                Some(config) => Ok((server_key, config, None)),
+                None => Err(format!(
+                    "MCP server configuration not found for key '{}' in file '{}'",
+                    server_key, config_file
+                )),
+            })
+            .collect();
+    }
+    let mut loaded = Vec::new();
+    for server_key in server_keys {
+        if let Some(registry) = registry::Registry::from_env(config_file, &server_key) {
+            loaded.push((server_key, registry.load().await?, Some(registry)));
+        }
+    }
+    Ok(loaded)
+}
+
+// --- 認証設定を作成する関数 ---
+// HTTP_API_KEY には argon2 / bcrypt のハッシュも書ける (壊れたハッシュはエラー)
+fn create_auth_config(
+    tenants: TenantKeys,
+    key_file: Option<ApiKeyFile>,
+) -> Result<AuthConfig, String> {
+    let api_key = env::var("HTTP_API_KEY")
+        .ok()
+        .map(|key| StoredKey::parse(&key))
+        .transpose()
+        .map_err(|e| format!("HTTP_API_KEY is an {}", e))?;
+    let disable_auth = is_auth_disabled();
+
+    let enabled = !disable_auth && (api_key.is_some() || key_file.is_some() || !tenants.is_empty());
+
+    if let Some(ref key) = api_key {
+        debug!(hashed = key.is_hashed(), "HTTP API Key configured");
+    } else {
+        debug!("No HTTP API Key configured (HTTP_API_KEY not set)");
+    }
+
+    if disable_auth {
+        info!("Authentication disabled by DISABLE_AUTH=true");
+    }
+
+    debug!(enabled, "Authentication configured");
+
+    Ok(AuthConfig {
+        api_key,
+        key_file,
+        enabled,
+        tenants,
+        public_paths: public_paths_from_env(),
+    })
+}
+
+// AUTH_PUBLIC_PATHS (カンマ区切り) を読む。候補以外のパスは無視する
+fn public_paths_from_env() -> Arc<[String]> {
+    let Ok(value) = env::var("AUTH_PUBLIC_PATHS") else {
+        return Arc::from([]);
+    };
+    let mut public_paths = Vec::new();
+    for path in value
+        .split(',')
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+    {
+        if PUBLIC_PATH_CANDIDATES.contains(&path) {
+            public_paths.push(path.to_string());
+        } else {
+            warn!(path = %path, allowed = ?PUBLIC_PATH_CANDIDATES, "Ignoring path in AUTH_PUBLIC_PATHS, it always requires authentication");
+        }
+    }
+    if !public_paths.is_empty() {
+        info!(paths = ?public_paths, "Serving paths without authentication");
+    }
+    public_paths.into()
+}
+
+// --- 管理API用の認証設定を作成する関数 ---
+// ADMIN_API_KEY が設定されていればそれを、なければ HTTP_API_KEY (と HTTP_API_KEY_FILE) を使う
+// (テナントのキーは他のテナントにも影響する管理APIには使えない)。
+// 使えるキーが無い場合と DISABLE_AUTH=true の場合は None (管理APIを公開しない)
+fn create_admin_auth_config(auth_config: &AuthConfig) -> Result<Option<AuthConfig>, String> {
+    let admin_key = env::var("ADMIN_API_KEY")
+        .ok()
+        .filter(|key| !key.is_empty())
+        .map(|key| StoredKey::parse(&key))
+        .transpose()
+        .map_err(|e| format!("ADMIN_API_KEY is an {}", e))?;
+    if is_auth_disabled() {
+        return Ok(None);
+    }
+    match admin_key {
+        Some(admin_key) => {
+            debug!(hashed = admin_key.is_hashed(), "Admin API Key configured");
+            Ok(Some(AuthConfig {
+                enabled: true,
+                api_key: Some(admin_key),
+                key_file: None,
+                tenants: TenantKeys::default(),
+                public_paths: Arc::from([]),
+            }))
+        }
+        None if auth_config.api_key.is_some() || auth_config.key_file.is_some() => {
+            Ok(Some(AuthConfig {
+                tenants: TenantKeys::default(),
+                public_paths: Arc::from([]),
+                ..auth_config.clone()
+            }))
+        }
+        None => Ok(None),
+    }
+}
+
+// --- main関数 ---
+#[tokio::main]
+async fn main() {
+    // ログ設定 (RUST_LOG など) も .env から読めるよう、ロガーより先に読み込む
+    let env_file = env_file::load();
+    let args = cli::parse(env::args().skip(1));
+    // encrypt / hash-key / init / healthcheck は結果だけを標準出力に書き出すため、ロガーを初期化しない
+    let result = match &args {
+        Ok(args) if args.command == cli::Command::Encrypt => Some(match &env_file {
+            Ok(_) => secrets::encrypt_stdin(args.name.as_deref()),
+            Err(e) => Err(e.clone()),
+        }),
+        Ok(args) if args.command == cli::Command::HashKey => Some(api_keys::hash_stdin()),
+        // init は .env を書き出すため、読み込みの失敗は無視する
+        Ok(args) if args.command == cli::Command::Init => {
+            Some(init::run(&args.templates, args.force))
+        }
+        Ok(args) if args.command == cli::Command::Healthcheck => {
+            Some(health_check::check_readyz().await)
+        }
+        _ => None,
+    };
+    if let Some(result) = result {
+        match result {
+            Ok(value) => println!("{}", value),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    logging::init();
+    info!("Starting MCP HTTP server");
+    match env_file {
+        Ok(Some(load)) => info!(
+            path = %load.path.display(),
+            loaded = load.loaded,
+            skipped = load.skipped,
+            "Loaded environment file (process environment takes precedence)"
+        ),
+        Ok(None) => {}
+        Err(e) => {
+            error!(error = %e, "Failed to load environment file");
+            return;
+        }
+    }
+
+    let args = match args {
+        Ok(args) => args,
+        Err(e) => {
+            error!(error = %e, "Invalid command line");
+            return;
+        }
+    };
+    config_file::set_strict(args.strict);
+
+    let config_file =
+        env::var("MCP_CONFIG_FILE").unwrap_or_else(|_| "mcp_servers.config.json".to_string());
+    // validate / doctor は結果を書き出して終了する (設定ファイルの未知のキーの警告などはログに出る)
+    match args.command {
+        cli::Command::Validate => {
+            std::process::exit(validate::run(&config_file, args.resolve).await);
+        }
+        cli::Command::Doctor => std::process::exit(doctor::run(&config_file).await),
+        cli::Command::Serve
+        | cli::Command::Encrypt
+        | cli::Command::HashKey
+        | cli::Command::Init
+        | cli::Command::Healthcheck => {}
+    }
+
+    // 認証設定を作成
+    let tenants = match TenantKeys::from_env() {
+        Ok(tenants) => tenants,
+        Err(e) => {
+            error!(error = %e, "Invalid API keys configuration");
+            return;
+        }
+    };
+    let key_file = match ApiKeyFile::from_env() {
+        Ok(key_file) => key_file,
+        Err(e) => {
+            error!(error = %e, "Invalid API key file");
+            return;
+        }
+    };
+    if let Some(key_file) = &key_file {
+        key_file.spawn_reload();
+    }
+    let auth_config = match create_auth_config(tenants, key_file) {
+        Ok(auth_config) => auth_config,
+        Err(e) => {
+            error!(error = %e, "Invalid API keys configuration");
+            return;
+        }
+    };
+    // 管理APIの設定の誤りも HTTP サーバーを起動する前に検出する
+    let admin_auth_config = match create_admin_auth_config(&auth_config) {
+        Ok(admin_auth_config) => admin_auth_config,
+        Err(e) => {
+            error!(error = %e, "Invalid API keys configuration");
+            return;
+        }
+    };
+
+    let listen_config = match listener::ListenConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            error!(error = %e, "Invalid listener configuration");
+            return;
+        }
+    };
+
+    let webhook_config = match webhooks::WebhookConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            error!(error = %e, "Invalid webhook configuration");
+            return;
+        }
+    };
+
+    let session_config = match SessionConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            error!(error = %e, "Invalid session configuration");
+            return;
+        }
+    };
+
+    let default_server_key = if args.mock {
+        "mock"
+    } else {
+        DEFAULT_SERVER_NAME
+    };
+    // 未設定の場合は default: true の定義 (なければ brave-search) を起動する
+    let requested_server = env::var("MCP_SERVER_NAME")
+        .ok()
+        .filter(|server_key| !server_key.is_empty());
+    let mcp_server_key_to_use = requested_server
+        .clone()
+        .unwrap_or_else(|| default_server_key.to_string());
+    // MCP_START_ALL=true (または MCP_SERVER_NAME=all) の場合は設定ファイルの全サーバーを起動する
+    let start_all = env::var("MCP_START_ALL")
+        .map(|value| value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+
+    info!(
+        config_file = %config_file,
+        server = %requested_server.as_deref().unwrap_or("(default)"),
+        "Resolved MCP server configuration"
+    );
+
+    // --mock の場合は設定ファイルもレジストリも読まず、組み込みのモックサーバーを使う
+    let loaded = match args.mock {
+        true => mcp_process::mock_config().map(|config| {
+            let server_key = match start_all || mcp_server_key_to_use == servers::ALL_SERVERS {
+                true => default_server_key.to_string(),
+                false => mcp_server_key_to_use.clone(),
+            };
+            vec![(server_key, config, None)]
+        }),
+        false => load_server_configs(&config_file, requested_server.as_deref(), start_all).await,
+    };
+    let loaded = match loaded {
+        Ok(loaded) if !loaded.is_empty() => loaded,
+        Ok(_) => {
+            error!(config_file = %config_file, "No MCP servers defined in the configuration");
+            return;
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to load MCP server configuration");
+            return;
+        }
+    };
+
+    // 失敗しうる初期化は MCP サーバーの起動より前に済ませる
+    let storage = match storage::create_storage_from_env().await {
+        Ok(storage) => storage,
+        Err(e) => {
+            error!(error = %e, "Failed to initialize storage backend");
+            return;
+        }
+    };
+
+    let events = EventBus::default();
+    let mut servers = Vec::new();
+    let mut registries = Vec::new();
+    for (server_key, server_config, registry) in loaded {
+        let server = Arc::new(McpServer::new(&server_key, server_config, events.clone()));
+        if let Some(registry) = registry {
+            registries.push((registry, Arc::clone(&server)));
+        }
+        servers.push(server);
+    }
+    if let Some(webhook_config) = webhook_config {
+        webhooks::spawn(webhook_config, &events, &servers);
+    }
+    // パスにサーバー名を含まないリクエストの対象 (ServerSet::new を参照)
+    let server_set = Arc::new(ServerSet::new(servers.clone(), &mcp_server_key_to_use));
+    if start_all || servers.len() > 1 {
+        info!(
+            servers = ?servers.iter().map(|server| &server.server_key).collect::<Vec<_>>(),
+            default_server = %server_set.default_server().server_key,
+            "Bridging every MCP server in the configuration"
+        );
+    }
+
+    if let Err(e) = startup::start_servers(&servers, startup::StartupRetryConfig::from_env()).await
+    {
+        error!(error = %e, "Failed to start MCP server process");
+        error!("Please ensure:");
+        error!("1. Node.js is installed and npx is available");
+        error!("2. The @modelcontextprotocol/server-brave-search package can be downloaded");
+        error!("3. Network connectivity is available");
+        // 起動できたサーバーを止めてから終了する
+        shutdown(&[], &servers).await;
+        return;
+    }
+    // ここから先は return せず、どの場合も main の最後の shutdown を通って子プロセスを止める
+
+    for server in &servers {
+        server.spawn_idle_reaper();
+        server.spawn_health_check();
+        server.spawn_keep_alive();
+    }
+    for (registry, server) in registries {
+        registry.spawn_refresh(server);
+    }
+
+    let idempotency_ttl = Duration::from_secs(env_secs("IDEMPOTENCY_TTL_SECS", 86_400));
+    let recent_requests = Arc::new(RecentRequests::from_env(Arc::clone(&storage)));
+
+    let load_shedder = Arc::new(LoadShedder::new(LoadShedConfig::from_env()));
+
+    let per_session = session_config.mode == SessionMode::PerSession;
+    if per_session {
+        info!(
+            max_sessions = session_config.max_sessions,
+            "Per-session MCP processes enabled, initialize without Mcp-Session-Id starts a new session"
+        );
+    }
+
+    let server_sessions: HashMap<String, Arc<SessionManager>> = servers
+        .iter()
+        .filter(|_| per_session)
+        .map(|server| {
+            let sessions = Arc::new(SessionManager::new(
+                Arc::clone(server),
+                events.clone(),
+                session_config.clone(),
+            ));
+            sessions.spawn_reaper();
+            (server.server_key.clone(), sessions)
+        })
+        .collect();
+
+    let graphql_schema = graphql::build_schema(Arc::clone(&server_set));
+    let shared_state = AppState {
+        server: Arc::clone(server_set.default_server()),
+        servers: Arc::clone(&server_set),
+        storage,
+        idempotency_ttl,
+        inject_request_id_meta: env::var("INJECT_REQUEST_ID_META")
+            .map(|value| value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false),
+        slow_request_threshold: env::var("SLOW_REQUEST_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis),
+        debug_mode: env::var("DEBUG_MODE")
+            .map(|value| value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false),
+        recent_requests,
+        readyz_ping_timeout: Some(env_secs("READYZ_PING_TIMEOUT_SECS", 5))
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs),
+        load_shedder: Arc::clone(&load_shedder),
+        events,
+        graphql_schema,
+        sessions: None,
+        server_sessions: Arc::new(server_sessions),
+    };
+    // サーバーごとの状態 (対象のサーバーと、SESSION_MODE=per_session の場合のセッションだけが異なる)
+    let server_states: Vec<AppState> = servers
+        .iter()
+        .map(|server| AppState {
+            server: Arc::clone(server),
+            sessions: shared_state
+                .server_sessions
+                .get(&server.server_key)
+                .cloned(),
+            ..shared_state.clone()
+        })
+        .collect();
+    let app_state = server_states
+        .iter()
+        .find(|state| Arc::ptr_eq(&state.server, server_set.default_server()))
+        .cloned()
+        .unwrap_or(shared_state);
+    // 終了時に子プロセスを停止するために保持する
+    let running_servers = servers;
+    let running_sessions: Vec<_> = server_states
+        .iter()
+        .filter_map(|state| state.sessions.clone())
+        .collect();
+
+    // 管理APIは専用の認証設定で保護する (キーが無い場合は公開しない)
+    match &admin_auth_config {
+        Some(_) => info!("Admin API enabled at /admin"),
+        None => warn!(
+            "Admin API disabled: set ADMIN_API_KEY or HTTP_API_KEY (with authentication enabled) to use /admin"
+        ),
+    }
+    let admin_routes = admin_auth_config.map(|admin_auth_config| {
+        Router::new()
+            .route("/admin/servers/{name}/start", post(handle_admin_start))
+            .route("/admin/servers/{name}/stop", post(handle_admin_stop))
+            .route("/admin/servers/{name}/restart", post(handle_admin_restart))
+            .route("/admin/servers/{name}/update", post(handle_admin_update))
+            .route("/admin/servers/{name}/stderr", get(handle_admin_stderr))
+            .route("/admin/requests", get(handle_admin_requests))
+            .route("/admin/events", get(handle_admin_events))
+            .layer(middleware::from_fn_with_state(
+                admin_auth_config,
+                bearer_auth_middleware,
+            ))
+    });
+
+    // 既定のサーバーはパスにサーバー名を含めずに、各サーバーは /servers/{name} (と別名) 以下で受け付ける
+    let mut app = server_routes(app_state.clone(), &load_shedder);
+    for state in server_states {
+        let server_key = state.server.server_key.clone();
+        let aliases = state.server.config().aliases.clone();
+        for name in std::iter::once(server_key.clone()).chain(aliases) {
+            if !is_path_safe(&name) {
+                warn!(server = %server_key, name = %name, "MCP server name can't be used in a URL path, skipping");
+                continue;
+            }
+            app = app.nest(
+                &format!("/servers/{}", name),
+                server_routes(state.clone(), &load_shedder),
+            );
+        }
+    }
+    // 認証の後にテナントの default_server へ URI を書き換えるため、ルーティングの前に動く fallback_service として包む
+    let mut app = Router::new()
+        .fallback_service(app.route("/version", get(handle_version)))
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&server_set),
+            tenant_default_server_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            auth_config.clone(),
+            bearer_auth_middleware,
+        ));
+    if let Some(admin_routes) = admin_routes {
+        app = app.merge(admin_routes.with_state(app_state));
+    }
+    // GET /graphql (GraphiQL) は認証なしで返し、POST /graphql は認証を通して転送する
+    // (パスが一致したルートは fallback_service に落ちず 405 になるため、メソッドごとの fallback で渡す)
+    let app = app
+        .clone()
+        .route("/ui", get(handle_dashboard))
+        .route("/graphql", get(handle_graphiql).fallback_service(app))
+        .layer(middleware::from_fn(request_id_middleware));
+    // Router::layer のミドルウェアはルーティングの後に動くため、全体を fallback_service として包み、
+    // ルーティングの前に X-Mcp-Server ヘッダーで URI を書き換える
+    let app = Router::new()
+        .fallback_service(app)
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&server_set),
+            server_header_middleware,
+        ));
+
+    info!("Ready to accept requests at POST /api/v1");
+    if auth_config.enabled {
+        info!("Authentication is ENABLED - Authorization: Bearer <token> required");
+    } else {
+        info!("Authentication is DISABLED - no authorization required");
+    }
+
+    match listener::bind(listen_config).await {
+        Ok(listeners) => {
+            // MCP サーバーの initialize とリスナーの準備が済んだ時点で systemd に通知する
+            systemd::notify_ready();
+            tokio::select! {
+                result = listener::serve(app, listeners) => {
+                    if let Err(e) = result {
+                        error!(error = %e, "Server error");
+                    }
+                }
+                _ = shutdown_signal() => {
+                    info!("Shutdown signal received, stopping MCP servers");
+                }
+            }
+        }
+        Err(e) => error!(error = %e, "Failed to bind listeners"),
+    }
+    shutdown(&running_sessions, &running_servers).await;
+}
+
+// 子プロセスは別のプロセスグループで動いていて端末のシグナルが届かないため、main から戻る前に必ず停止する
+async fn shutdown(sessions: &[Arc<SessionManager>], servers: &[Arc<McpServer>]) {
+    for sessions in sessions {
+        sessions.close_all().await;
+    }
+    for server in servers {
+        server.shutdown().await;
+    }
+}
+
+// Ctrl+C または SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to listen for SIGTERM");
+                let _ = tokio::signal::ctrl_c().await;
+            }
         }
     }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
 }
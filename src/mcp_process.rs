@@ -0,0 +1,3878 @@
+use futures_util::{StreamExt, future::join_all, stream};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Instant,
+};
+use tokio::{
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+        BufReader,
+    },
+    sync::{Mutex, Semaphore, SemaphorePermit, broadcast, mpsc, oneshot, watch},
+    time::{Duration, timeout},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    aggregate::{AggregateConfig, Aggregator},
+    callbacks::{CallbackConfig, CallbackHandler},
+    circuit_breaker::{CIRCUIT_OPEN_ERROR, CircuitBreaker, CircuitBreakerConfig},
+    content_stream::ContentScanner,
+    env_policy::EnvPolicy,
+    events::{EventBus, LifecycleEventKind},
+    health_check::{self, HealthCheckConfig, HealthChecker, KeepAliveConfig},
+    hooks::{self, HookConfig},
+    integrity::{self, IntegrityConfig},
+    language::Language,
+    limits::{self, LimitsConfig},
+    mock::MockServer,
+    notifications::NotificationBuffer,
+    process_tree::{self, ProcessTree},
+    program,
+    recording::{self, Replayer},
+    remote::{REMOTE_UNAVAILABLE_ERROR, RemoteClient, RemoteConfig},
+    resource_monitor::{self, ResourceMonitorConfig},
+    response_cache::{ResponseCache, ResponseCacheConfig},
+    restart_policy::{ProcessExit, RestartBudget, RestartMode, RestartPolicyConfig},
+    rewrite::RewriteRules,
+    sandbox::{self, SandboxConfig},
+    scripting::{RequestHooks, ScriptHooks},
+    secrets,
+    stats::{ServerStats, StatsSnapshot},
+    stderr_buffer::StderrBuffer,
+    tool_policy::{PolicyCheck, ToolPolicy},
+    wasm_plugins::WasmPlugins,
+    wasm_server,
+};
+
+// --- JSON設定ファイルの構造体 ---
+#[derive(Deserialize, Debug, Clone)]
+pub struct McpProcessConfig {
+    // stdio (子プロセス) または remote (HTTP で到達できる MCP サーバー)
+    #[serde(rename = "type", default)]
+    pub server_type: ServerType,
+    // MCP_SERVER_NAME が未設定の場合に起動し、サーバーを指定しないリクエストの対象にする
+    #[serde(default)]
+    pub default: bool,
+    // サーバー名の代わりに使える名前 (設定を整理する前の旧名など)
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    // env の変数をブリッジのプロセスの環境変数で上書きできるか
+    #[serde(default)]
+    pub env_policy: EnvPolicy,
+    // 子プロセスの作業ディレクトリ (未設定ならブリッジと同じ)
+    #[serde(default)]
+    pub cwd: Option<std::path::PathBuf>,
+    // cwd のソースからビルドして起動する言語 (command・post_install を省略できる)
+    #[serde(default)]
+    pub language: Option<Language>,
+    // language のビルド対象 (go はパッケージ、rust はバイナリ名)
+    #[serde(default)]
+    pub entrypoint: Option<String>,
+    // command が language の既定のビルドで作られる実行ファイルか (ビルド前は存在しない)
+    #[serde(skip)]
+    pub built_command: bool,
+    // post_install / pre_start
+    #[serde(flatten)]
+    pub hooks: HookConfig,
+    // commit / checksum / checksum_path
+    #[serde(flatten)]
+    pub integrity: IntegrityConfig,
+    // 子プロセス (とフック・ヘルスチェックのコマンド) を別ユーザー・chroot・ネットワークなしで実行する
+    #[serde(default)]
+    pub sandbox: Option<SandboxConfig>,
+    // 子プロセスのメモリ・CPU・ファイルディスクリプタの上限
+    #[serde(default)]
+    pub limits: Option<LimitsConfig>,
+    #[serde(default)]
+    pub quirks: McpQuirks,
+    // 標準入出力のメッセージ区切り
+    #[serde(default)]
+    pub framing: Framing,
+    // 子プロセスから読み取る1メッセージの最大バイト数
+    #[serde(default = "default_max_response_bytes")]
+    pub max_response_bytes: usize,
+    // GET /api/v1/notifications 用に保持する通知の件数 (0 で無効)
+    #[serde(default = "default_notification_buffer_size")]
+    pub notification_buffer_size: usize,
+    // GET /admin/servers/{name}/stderr 用に保持する stderr の KB 数 (0 で無効)
+    #[serde(default = "default_stderr_buffer_kb")]
+    pub stderr_buffer_kb: usize,
+    // サーバーからのリクエスト (sampling / elicitation) の転送先
+    #[serde(default)]
+    pub callback: Option<CallbackConfig>,
+    // true の場合、子プロセスの異常終了で失敗した冪等なリクエストを再起動後に1回だけ再送する
+    #[serde(default)]
+    pub retry_on_crash: bool,
+    // 連続したタイムアウト・異常終了でリクエストを即座に失敗させる
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    // tools/list などの応答を ttl_secs の間キャッシュする (tools で指定した tools/call も)
+    #[serde(default)]
+    pub response_cache: ResponseCacheConfig,
+    // restart / max_restarts / restart_window_secs
+    #[serde(flatten)]
+    pub restart_policy: RestartPolicyConfig,
+    // 定期的に JSON-RPC リクエストまたはコマンドで正常性を確認する
+    #[serde(default)]
+    pub health_check: Option<HealthCheckConfig>,
+    // アイドル時に ping を送り、応答しない子プロセスを unhealthy にして再起動する
+    #[serde(default)]
+    pub keep_alive: Option<KeepAliveConfig>,
+    // 子プロセスの RSS・CPU 時間・fd 数を定期的に読み取る (Linux のみ)
+    #[serde(default)]
+    pub resource_monitor: ResourceMonitorConfig,
+    // true の場合、起動時ではなく最初のリクエスト受信時に子プロセスを起動する
+    #[serde(default)]
+    pub lazy: bool,
+    // この秒数リクエストがなければ子プロセスを停止し、次のリクエストで再起動する
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    // true の場合、起動済みの予備プロセスを常に1つ保持し、再起動・クラッシュ時に即座に切り替える
+    #[serde(default)]
+    pub warm_standby: bool,
+    // 子プロセスに同時に送信するリクエストの上限 (既定: 1)
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+    // 同時実行枠の空きを待てるリクエスト数の上限 (超えたら即座に 503、未設定なら無制限)
+    #[serde(default)]
+    pub max_queued_requests: Option<usize>,
+    // 起動直後にブリッジ側で initialize ハンドシェイクを行う (既定: true)
+    #[serde(default = "default_auto_initialize")]
+    pub auto_initialize: bool,
+    // リクエストの応答を待つ秒数 (未設定なら RESPONSE_TIMEOUT_SECS)
+    #[serde(default)]
+    pub response_timeout_secs: Option<u64>,
+    // 起動時の initialize の応答を待つ秒数 (未設定なら PROCESS_INIT_WAIT_SECS)
+    #[serde(default)]
+    pub init_wait_secs: Option<u64>,
+    // 停止時に SIGTERM を送ってから SIGKILL するまでの秒数 (未設定なら STOP_GRACE_SECS)
+    #[serde(default)]
+    pub stop_grace_secs: Option<u64>,
+    // allowed_tools / blocked_tools
+    #[serde(flatten)]
+    pub tool_policy: ToolPolicy,
+    // 子プロセスに転送する前にリクエストを書き換えるルール
+    #[serde(default)]
+    pub rewrite: RewriteRules,
+    // リクエスト・レスポンスを書き換える rhai スクリプト (on_request / on_response)
+    #[serde(default)]
+    pub scripts: Option<ScriptHooks>,
+    // リクエスト・レスポンスを検査・書き換える WASM (WASI) プラグイン (on_request / on_response)
+    #[serde(default)]
+    pub wasm_plugins: Option<WasmPlugins>,
+    // type: "remote" の場合の url / transport / headers
+    #[serde(flatten)]
+    pub remote: RemoteConfig,
+    // type: "aggregate" の場合の servers / separator
+    #[serde(flatten)]
+    pub aggregate: AggregateConfig,
+}
+
+impl McpProcessConfig {
+    // サーバーごとの設定がなければ環境変数 RESPONSE_TIMEOUT_SECS (既定: 30秒)
+    pub fn response_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.response_timeout_secs
+                .unwrap_or_else(|| env_secs("RESPONSE_TIMEOUT_SECS", 30)),
+        )
+    }
+
+    // サーバーごとの設定がなければ環境変数 PROCESS_INIT_WAIT_SECS (既定: 応答待ちと同じ)
+    pub fn init_wait(&self) -> Duration {
+        self.init_wait_secs
+            .or_else(|| {
+                std::env::var("PROCESS_INIT_WAIT_SECS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+            })
+            .map(Duration::from_secs)
+            .unwrap_or_else(|| self.response_timeout())
+    }
+
+    // 子プロセス (とフック・ヘルスチェックのコマンド) に渡す env (復号し、env_policy を反映したもの)
+    pub fn child_env(&self, server_key: &str) -> Result<HashMap<String, String>, String> {
+        Ok(self
+            .env_policy
+            .apply(server_key, secrets::decrypt_env(server_key, &self.env)?))
+    }
+
+    // scripts と wasm_plugins (どちらも未設定なら None)
+    pub fn request_hooks(&self) -> Option<RequestHooks> {
+        RequestHooks::new(self.scripts.clone(), self.wasm_plugins.clone())
+    }
+
+    // health_check か keep_alive の結果で unhealthy になりうるか (/readyz と /stats に状態を含める)
+    pub fn monitors_health(&self) -> bool {
+        self.health_check.is_some() || self.keep_alive.is_some()
+    }
+
+    // サーバーごとの設定がなければ環境変数 STOP_GRACE_SECS (既定: 5秒)
+    pub fn stop_grace(&self) -> Duration {
+        Duration::from_secs(
+            self.stop_grace_secs
+                .unwrap_or_else(|| env_secs("STOP_GRACE_SECS", 5)),
+        )
+    }
+}
+
+fn env_secs(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(default)
+}
+
+// --- MCPサーバーの種類 ---
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerType {
+    // command を子プロセスとして起動し、標準入出力で通信する
+    #[default]
+    Stdio,
+    // url の MCP サーバーに HTTP (Streamable HTTP / SSE) で接続する
+    Remote,
+    // servers の MCP サーバーのツールを名前空間付きでまとめる
+    Aggregate,
+    // 子プロセスを起動せず、組み込みの決まった内容で応答する (開発・テスト用)
+    Mock,
+}
+
+fn default_auto_initialize() -> bool {
+    true
+}
+
+fn default_max_concurrent_requests() -> usize {
+    1
+}
+
+fn default_max_response_bytes() -> usize {
+    4 * 1024 * 1024
+}
+
+fn default_notification_buffer_size() -> usize {
+    256
+}
+
+fn default_stderr_buffer_kb() -> usize {
+    64
+}
+
+// --- 標準入出力のメッセージ区切り ---
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    // 改行区切りの JSON (MCP 標準)
+    #[default]
+    Ndjson,
+    // LSP 形式の Content-Length ヘッダー付き
+    Lsp,
+}
+
+// --- 不完全なMCPサーバー向けの互換性オプション ---
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct McpQuirks {
+    // 出力行の先頭に UTF-8 BOM が付くサーバー
+    pub strip_bom: bool,
+    // 改行コードとして CRLF を要求するサーバー
+    pub crlf_line_endings: bool,
+    // レスポンスに id を含めないサーバー (リクエストの id を補完する)
+    pub responses_without_ids: bool,
+    // 標準出力に JSON 形式のログを出力するサーバー (JSON-RPC 以外の JSON の行を info で記録せずに捨てる)
+    pub json_logs_on_stdout: bool,
+}
+
+impl McpQuirks {
+    fn line_ending(&self) -> &'static str {
+        if self.crlf_line_endings { "\r\n" } else { "\n" }
+    }
+
+    fn normalize_line<'a>(&self, line: &'a str) -> &'a str {
+        let line = line.trim();
+        if self.strip_bom {
+            line.trim_start_matches('\u{feff}')
+        } else {
+            line
+        }
+    }
+}
+
+// --- 子プロセスの標準出力の1行の分類 ---
+enum StdoutLine {
+    // JSON-RPC 以外の出力 (バナー、デバッグ出力、JSON ログなど)
+    Noise,
+    // quirks.json_logs_on_stdout の場合の、JSON-RPC 以外の JSON の行
+    JsonLog,
+    // サーバーからの通知 (id なし)
+    Notification(serde_json::Value),
+    // サーバーからのリクエスト (id あり)
+    ServerRequest(serde_json::Value),
+    // バッチレスポンス
+    Batch,
+    // 単一のレスポンス (id が null または無い場合は None)
+    Response(Option<serde_json::Value>),
+}
+
+// "jsonrpc": "2.0" の無い JSON (構造化ログなど) は JSON-RPC のメッセージとして扱わない
+fn classify_stdout_line(line: &str, json_logs: bool) -> StdoutLine {
+    let is_jsonrpc = |message: &serde_json::Value| {
+        message.get("jsonrpc").and_then(serde_json::Value::as_str) == Some("2.0")
+    };
+    match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(message @ serde_json::Value::Object(_)) if is_jsonrpc(&message) => {
+            if message.get("result").is_some() || message.get("error").is_some() {
+                StdoutLine::Response(message.get("id").filter(|id| !id.is_null()).cloned())
+            } else if message.get("method").is_some() {
+                match message.get("id").is_some() {
+                    true => StdoutLine::ServerRequest(message),
+                    false => StdoutLine::Notification(message),
+                }
+            } else {
+                StdoutLine::Noise
+            }
+        }
+        Ok(serde_json::Value::Array(batch))
+            if !batch.is_empty() && batch.iter().all(is_jsonrpc) =>
+        {
+            StdoutLine::Batch
+        }
+        Ok(serde_json::Value::Object(_) | serde_json::Value::Array(_)) if json_logs => {
+            StdoutLine::JsonLog
+        }
+        _ => StdoutLine::Noise,
+    }
+}
+
+// --- 複数行にまたがる JSON の括弧の深さを追跡する ---
+// 整形出力 (pretty-print) されたレスポンスを1メッセージとして組み立てるために使う
+#[derive(Default)]
+struct JsonDepthTracker {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonDepthTracker {
+    fn feed(&mut self, text: &[u8]) {
+        // 構造文字はすべて ASCII のため、バイト単位で走査して問題ない
+        for &byte in text {
+            if self.in_string {
+                match byte {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match byte {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.depth == 0 && !self.in_string
+    }
+}
+
+// 改行までを1行として読み取り、limit を超えた分は読み捨てる
+// 読み取ったバイト数 (読み捨てた分を含む) と、limit を超えたかどうかを返す
+async fn read_line_bounded<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut Vec<u8>,
+    limit: usize,
+    mut tracker: Option<&mut JsonDepthTracker>,
+) -> std::io::Result<(usize, bool)> {
+    let mut consumed = 0;
+    let mut overflowed = false;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            break;
+        }
+        let (chunk, line_end) = match available.iter().position(|&b| b == b'\n') {
+            Some(index) => (&available[..=index], true),
+            None => (available, false),
+        };
+        if let Some(tracker) = tracker.as_deref_mut() {
+            tracker.feed(chunk);
+        }
+        let room = limit.saturating_sub(line.len());
+        overflowed |= chunk.len() > room;
+        line.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        let chunk_len = chunk.len();
+        reader.consume(chunk_len);
+        consumed += chunk_len;
+        if line_end {
+            break;
+        }
+    }
+    Ok((consumed, overflowed))
+}
+
+// --- 上限を超えて読み捨てたメッセージ ---
+// 応答の id が読み取れた場合は、そのリクエストだけを失敗させる
+#[derive(Debug)]
+struct OversizedMessage {
+    max_response_bytes: usize,
+    id: Option<serde_json::Value>,
+}
+
+impl fmt::Display for OversizedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "message exceeded max_response_bytes ({} bytes)",
+            self.max_response_bytes
+        )
+    }
+}
+
+impl std::error::Error for OversizedMessage {}
+
+// 1メッセージの上限超過を表す I/O エラー
+fn too_large_error(max_response_bytes: usize, id: Option<serde_json::Value>) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::FileTooLarge,
+        OversizedMessage {
+            max_response_bytes,
+            id,
+        },
+    )
+}
+
+// 読み捨てるメッセージを先頭から順に渡し、トップレベルの id を読み取る
+struct ResponseIdScanner {
+    // max_bytes を 0 にして、メッセージの中身は溜めない
+    scanner: ContentScanner,
+    id: Option<serde_json::Value>,
+}
+
+impl ResponseIdScanner {
+    fn new() -> Self {
+        ResponseIdScanner {
+            scanner: ContentScanner::new(0),
+            id: None,
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        if self.id.is_some() || self.scanner.is_complete() {
+            return;
+        }
+        let found = RefCell::new(None);
+        self.scanner.feed(chunk, |id| {
+            found.replace(Some(id.clone()));
+            None
+        });
+        self.id = found.into_inner();
+    }
+
+    fn into_id(self) -> Option<serde_json::Value> {
+        self.id
+    }
+}
+
+// 1行目が JSON の途中まで (続きの行が必要) かどうか
+fn is_incomplete_json(line: &str) -> bool {
+    matches!(line.trim_start().as_bytes().first(), Some(b'{' | b'['))
+        && serde_json::from_str::<serde::de::IgnoredAny>(line).is_err_and(|e| e.is_eof())
+}
+
+// --- 送信したメッセージに対する応答待ちの id ---
+// バッチの各要素への応答が個別の行で順不同に返ってきても、リクエストの順に組み立てる
+struct PendingResponses {
+    // 応答を待つ id (送信順)
+    ids: Vec<serde_json::Value>,
+    // id ごとに受け取った応答
+    received: HashMap<String, String>,
+    batch: bool,
+}
+
+impl PendingResponses {
+    fn from_message(message: &str) -> Self {
+        let value = serde_json::from_str::<serde_json::Value>(message).ok();
+        let (ids, batch) = match &value {
+            Some(serde_json::Value::Array(requests)) => (
+                requests
+                    .iter()
+                    .filter_map(|request| request.get("id"))
+                    .filter(|id| !id.is_null())
+                    .cloned()
+                    .collect(),
+                true,
+            ),
+            Some(request) => (request.get("id").cloned().into_iter().collect(), false),
+            None => (Vec::new(), false),
+        };
+        PendingResponses {
+            ids,
+            received: HashMap::new(),
+            batch,
+        }
+    }
+
+    // 応答を待っている id (id のない応答は最初の未応答の id に割り当てる)
+    fn waiting_id(&self, response_id: Option<&serde_json::Value>) -> Option<&serde_json::Value> {
+        match response_id {
+            Some(id) => self.ids.iter().find(|pending| {
+                *pending == id && !self.received.contains_key(&pending.to_string())
+            }),
+            None => self
+                .ids
+                .iter()
+                .find(|pending| !self.received.contains_key(&pending.to_string())),
+        }
+    }
+
+    fn accept(&mut self, id: &serde_json::Value, response: String) {
+        self.received.insert(id.to_string(), response);
+    }
+
+    fn is_complete(&self) -> bool {
+        self.received.len() >= self.ids.len()
+    }
+
+    fn into_response(mut self) -> String {
+        let mut responses = self
+            .ids
+            .iter()
+            .filter_map(|id| self.received.remove(&id.to_string()));
+        if self.batch {
+            format!("[{}]", responses.collect::<Vec<_>>().join(","))
+        } else {
+            responses.next().unwrap_or_default()
+        }
+    }
+}
+
+// id のないレスポンスにリクエストの id を補完する
+fn inject_response_id(line: &str, request_id: &serde_json::Value) -> Option<String> {
+    let mut value: serde_json::Value = serde_json::from_str(line).ok()?;
+    let object = value.as_object_mut()?;
+    let missing_id = object.get("id").is_none_or(|id| id.is_null());
+    if !missing_id || !(object.contains_key("result") || object.contains_key("error")) {
+        return None;
+    }
+    object.insert("id".to_string(), request_id.clone());
+    serde_json::to_string(&value).ok()
+}
+
+// --- ブリッジ側で一意な id ---
+// 呼び出し元どうしで id が重複しても応答を取り違えないよう、子プロセスには通し番号の id で送り、
+// 応答の id を呼び出し元の id に戻す (タイムアウト後に遅れて届いた応答も後続のリクエストに渡らない)
+static NEXT_BRIDGE_ID: AtomicU64 = AtomicU64::new(0);
+
+// id を置き換えたメッセージと、ブリッジの id → 呼び出し元の id を返す (通知と、子プロセスからのリクエストへの応答はそのまま)。
+// "id": null のリクエストも置き換え、応答の id を null に戻す (null のままでは応答を照合できない)
+fn assign_bridge_ids(message: &str) -> (String, HashMap<String, serde_json::Value>) {
+    let mut original_ids = HashMap::new();
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(message) else {
+        return (message.to_string(), original_ids);
+    };
+    let messages = match &mut value {
+        serde_json::Value::Array(batch) => batch.iter_mut().collect(),
+        single => vec![single],
+    };
+    for id in messages
+        .into_iter()
+        .filter(|message| message.get("method").is_some())
+        .filter_map(|message| message.get_mut("id"))
+    {
+        let bridge_id = format!(
+            "mcp-http-server-{}",
+            NEXT_BRIDGE_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        original_ids.insert(bridge_id.clone(), std::mem::replace(id, bridge_id.into()));
+    }
+    match original_ids.is_empty() {
+        true => (message.to_string(), original_ids),
+        false => (value.to_string(), original_ids),
+    }
+}
+
+// 応答 (バッチの場合は各要素) の id を呼び出し元の id に戻す
+fn restore_original_ids(
+    response: String,
+    original_ids: &HashMap<String, serde_json::Value>,
+) -> String {
+    if original_ids.is_empty() {
+        return response;
+    }
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&response) else {
+        return response;
+    };
+    let responses = match &mut value {
+        serde_json::Value::Array(batch) => batch.iter_mut().collect(),
+        single => vec![single],
+    };
+    for id in responses
+        .into_iter()
+        .filter_map(|response| response.get_mut("id"))
+    {
+        if let Some(original) = id
+            .as_str()
+            .and_then(|bridge_id| original_ids.get(bridge_id))
+        {
+            *id = original.clone();
+        }
+    }
+    value.to_string()
+}
+
+pub type McpServersConfig = HashMap<String, McpProcessConfig>;
+
+// --- 応答待ちのリクエストの一覧 ---
+// 標準出力の読み取りタスクが、受け取った応答を id で照合して待機中のリクエストに渡す
+struct PendingRequest {
+    responses: PendingResponses,
+    done: oneshot::Sender<Result<String, String>>,
+    // result.content の要素を読み取り次第送る先 (ストリーミング時のみ)
+    stream: Option<mpsc::Sender<String>>,
+}
+
+#[derive(Default)]
+struct PendingState {
+    // 送信順の通し番号ごとの応答待ち
+    requests: BTreeMap<u64, PendingRequest>,
+    next_slot: u64,
+    // 読み取りタスクが終了した理由 (以降のリクエストは送信せずに失敗させる)
+    closed: Option<String>,
+}
+
+#[derive(Default)]
+struct PendingTable {
+    state: std::sync::Mutex<PendingState>,
+    // ブリッジが付けた progressToken → notifications/progress の params を送る先
+    progress: std::sync::Mutex<HashMap<String, mpsc::Sender<serde_json::Value>>>,
+}
+
+type ResponseReceiver = oneshot::Receiver<Result<String, String>>;
+
+impl PendingTable {
+    fn lock(&self) -> std::sync::MutexGuard<'_, PendingState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn register(
+        &self,
+        responses: PendingResponses,
+        stream: Option<mpsc::Sender<String>>,
+    ) -> Result<(u64, ResponseReceiver), String> {
+        let mut state = self.lock();
+        if let Some(reason) = &state.closed {
+            return Err(reason.clone());
+        }
+        let (done, receiver) = oneshot::channel();
+        state.next_slot += 1;
+        let slot = state.next_slot;
+        state.requests.insert(
+            slot,
+            PendingRequest {
+                responses,
+                done,
+                stream,
+            },
+        );
+        Ok((slot, receiver))
+    }
+
+    fn has_streams(&self) -> bool {
+        self.lock()
+            .requests
+            .values()
+            .any(|request| request.stream.is_some())
+    }
+
+    // id の応答をストリーミングで待っているリクエストの送信先
+    fn stream_sender(&self, id: &serde_json::Value) -> Option<mpsc::Sender<String>> {
+        self.lock()
+            .requests
+            .values()
+            .find(|request| request.responses.waiting_id(Some(id)).is_some())
+            .and_then(|request| request.stream.clone())
+    }
+
+    fn remove(&self, slot: u64) {
+        self.lock().requests.remove(&slot);
+    }
+
+    fn watch_progress(&self, token: String, sender: mpsc::Sender<serde_json::Value>) {
+        self.progress
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(token, sender);
+    }
+
+    fn unwatch_progress(&self, token: &str) {
+        self.progress
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(token);
+    }
+
+    // ブリッジが付けた progressToken の進捗通知なら呼び出し元に渡して None を返す (それ以外はそのまま返す)。
+    // 受け取り側が詰まっている間の進捗は捨てる (最終的な結果の配信を遅らせない)
+    fn route_progress(&self, notification: serde_json::Value) -> Option<serde_json::Value> {
+        if notification
+            .get("method")
+            .and_then(serde_json::Value::as_str)
+            != Some("notifications/progress")
+        {
+            return Some(notification);
+        }
+        let Some(token) = notification
+            .get("params")
+            .and_then(|params| params.get("progressToken"))
+            .and_then(serde_json::Value::as_str)
+        else {
+            return Some(notification);
+        };
+        let Some(sender) = self
+            .progress
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(token)
+            .cloned()
+        else {
+            return Some(notification);
+        };
+        let mut params = notification.get("params").cloned().unwrap_or_default();
+        if let Some(params) = params.as_object_mut() {
+            params.remove("progressToken");
+        }
+        let _ = sender.try_send(params);
+        None
+    }
+
+    // 応答待ちを取り消し、まだ応答を受け取っていない id を返す (応答済みなら空)
+    fn cancel(&self, slot: u64) -> Vec<serde_json::Value> {
+        let Some(request) = self.lock().requests.remove(&slot) else {
+            return Vec::new();
+        };
+        let responses = request.responses;
+        responses
+            .ids
+            .iter()
+            .filter(|id| !responses.received.contains_key(&id.to_string()))
+            .cloned()
+            .collect()
+    }
+
+    // 応答を待機中のリクエストに割り当てる (どのリクエストも待っていない id の場合は false)。
+    // id のない応答は quirks.responses_without_ids の場合だけ最も古い応答待ちに割り当てる
+    fn dispatch(
+        &self,
+        response_id: Option<&serde_json::Value>,
+        line: &str,
+        inject_missing_id: bool,
+    ) -> bool {
+        if response_id.is_none() && !inject_missing_id {
+            return false;
+        }
+        let mut state = self.lock();
+        let matched = state.requests.iter().find_map(|(slot, request)| {
+            request
+                .responses
+                .waiting_id(response_id)
+                .map(|id| (*slot, id.clone()))
+        });
+        let Some((slot, waiting_id)) = matched else {
+            return false;
+        };
+        let Some(request) = state.requests.get_mut(&slot) else {
+            return false;
+        };
+        let patched = Some(&waiting_id)
+            .filter(|_| response_id.is_none())
+            .and_then(|id| inject_response_id(line, id));
+        // レスポンスを文字列として返す（再度JSON化はしない）
+        request
+            .responses
+            .accept(&waiting_id, patched.unwrap_or_else(|| line.to_string()));
+        if !request.responses.is_complete() {
+            return true;
+        }
+        if let Some(request) = state.requests.remove(&slot) {
+            let _ = request.done.send(Ok(request.responses.into_response()));
+        }
+        true
+    }
+
+    // バッチレスポンスを、含まれる id を待っているリクエストに割り当てる
+    fn dispatch_batch(&self, line: &str) -> bool {
+        let ids: Vec<serde_json::Value> = serde_json::from_str::<Vec<serde_json::Value>>(line)
+            .map(|responses| {
+                responses
+                    .iter()
+                    .filter_map(|response| response.get("id"))
+                    .filter(|id| !id.is_null())
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut state = self.lock();
+        let slot = state
+            .requests
+            .iter()
+            .find(|(_, request)| match ids.is_empty() {
+                true => request.responses.batch,
+                false => ids.iter().any(|id| request.responses.ids.contains(id)),
+            })
+            .map(|(slot, _)| *slot);
+        match slot.and_then(|slot| state.requests.remove(&slot)) {
+            Some(request) => {
+                let _ = request.done.send(Ok(line.to_string()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 読み捨てた応答を待っているリクエストを失敗させる。
+    // id が読み取れなかった場合はどのリクエストへの応答か分からないため、応答待ちをすべて失敗させる
+    fn fail_response(&self, response_id: Option<&serde_json::Value>, error: String) {
+        let mut state = self.lock();
+        let slots: Vec<u64> = match response_id {
+            Some(id) => state
+                .requests
+                .iter()
+                .filter(|(_, request)| request.responses.waiting_id(Some(id)).is_some())
+                .map(|(slot, _)| *slot)
+                .take(1)
+                .collect(),
+            None => state.requests.keys().copied().collect(),
+        };
+        for slot in slots {
+            if let Some(request) = state.requests.remove(&slot) {
+                let _ = request.done.send(Err(error.clone()));
+            }
+        }
+    }
+
+    // 応答待ちをすべて失敗させ、以降のリクエストも受け付けない
+    fn close(&self, reason: String) {
+        let mut state = self.lock();
+        state.closed = Some(reason.clone());
+        for (_, request) in std::mem::take(&mut state.requests) {
+            let _ = request.done.send(Err(reason.clone()));
+        }
+    }
+}
+
+// --- 応答待ちの打ち切り ---
+// HTTP クライアントの切断などで query_inner の future が応答の前に破棄されたら、
+// 応答待ちを取り消して子プロセスに notifications/cancelled を送る (誰も読まない処理を続けさせない)
+struct CancelOnDrop<'a> {
+    process: &'a McpServerProcess,
+    slot: u64,
+    original_ids: &'a HashMap<String, serde_json::Value>,
+    armed: bool,
+}
+
+impl CancelOnDrop<'_> {
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            self.process
+                .cancel(self.slot, self.original_ids, "Client disconnected");
+        }
+    }
+}
+
+// 進捗の転送を終えたら (応答の受信・打ち切りのどちらでも) progressToken の登録を外す
+struct ProgressRoute<'a> {
+    pending: &'a PendingTable,
+    token: String,
+}
+
+impl Drop for ProgressRoute<'_> {
+    fn drop(&mut self) {
+        self.pending.unwatch_progress(&self.token);
+    }
+}
+
+// --- 子プロセスの標準入力への書き込み ---
+// 同時に送信されるメッセージが混ざらないよう、1メッセージずつ書き込む
+struct MessageWriter {
+    server_key: String,
+    framing: Framing,
+    line_ending: &'static str,
+    sink: MessageSink,
+}
+
+// メッセージの送信先
+enum MessageSink {
+    // 子プロセス (または WASM のゲスト) の標準入力
+    Stdin(Mutex<Pin<Box<dyn AsyncWrite + Send>>>),
+    // リモートの MCP サーバー (1メッセージを1回の POST で送る)
+    Remote(Arc<RemoteClient>),
+    // 複数の MCP サーバーをまとめた仮想サーバー
+    Aggregate(Arc<Aggregator>),
+    // 組み込みのモックサーバー
+    Mock(Arc<MockServer>),
+    // MCP_REPLAY_FILE の応答を返す
+    Replay(Arc<Replayer>),
+}
+
+impl MessageSink {
+    // 送信先との接続が失われるまで待つ
+    async fn closed(&self) {
+        match self {
+            MessageSink::Remote(remote) => remote.closed().await,
+            MessageSink::Stdin(_)
+            | MessageSink::Aggregate(_)
+            | MessageSink::Mock(_)
+            | MessageSink::Replay(_) => std::future::pending().await,
+        }
+    }
+
+    async fn close(&self) {
+        match self {
+            MessageSink::Remote(remote) => remote.close().await,
+            MessageSink::Aggregate(aggregator) => aggregator.close().await,
+            MessageSink::Stdin(_) | MessageSink::Mock(_) | MessageSink::Replay(_) => {}
+        }
+    }
+}
+
+impl MessageWriter {
+    async fn write(&self, message: &str) -> Result<(), String> {
+        debug!(server = %self.server_key, message = %message, "Sending to MCP server");
+        let stdin = match &self.sink {
+            MessageSink::Stdin(stdin) => stdin,
+            MessageSink::Remote(remote) => return remote.send(message).await,
+            MessageSink::Aggregate(aggregator) => return aggregator.send(message),
+            MessageSink::Mock(mock) => return mock.send(message),
+            MessageSink::Replay(replayer) => return replayer.send(message),
+        };
+        let framed = match self.framing {
+            Framing::Ndjson => message.to_string() + self.line_ending,
+            Framing::Lsp => format!("Content-Length: {}\r\n\r\n{}", message.len(), message),
+        };
+        let mut stdin = stdin.lock().await;
+        stdin
+            .write_all(framed.as_bytes())
+            .await
+            .map_err(|e| format!("Failed to write to MCP stdin: {}", e))?;
+        stdin
+            .flush()
+            .await
+            .map_err(|e| format!("Failed to flush MCP stdin: {}", e))
+    }
+}
+
+// --- MCPサーバーから受信したメッセージの振り分け ---
+// 応答は待機中のリクエストに、通知はバッファに渡し、サーバーからのリクエストには応答する
+struct MessageRouter {
+    server_key: String,
+    quirks: McpQuirks,
+    pending: Arc<PendingTable>,
+    notifications: Arc<NotificationBuffer>,
+    callbacks: Arc<CallbackHandler>,
+    writer: Arc<MessageWriter>,
+}
+
+impl MessageRouter {
+    fn handle_message(&self, message: &str) {
+        let line = self.quirks.normalize_line(message);
+        debug!(
+            server = %self.server_key,
+            bytes_read = message.len(),
+            raw = %line,
+            "Read message from MCP server"
+        );
+        match classify_stdout_line(line, self.quirks.json_logs_on_stdout) {
+            // 空行・バナー・デバッグ出力などは読み飛ばす
+            StdoutLine::Noise => {
+                if !line.is_empty() {
+                    info!(
+                        target: "mcp_child_stdout",
+                        server = %self.server_key,
+                        "{}",
+                        line
+                    );
+                }
+            }
+            // JSON のログは件数が多いため debug でのみ記録する
+            StdoutLine::JsonLog => {
+                debug!(
+                    target: "mcp_child_stdout",
+                    server = %self.server_key,
+                    "{}",
+                    line
+                );
+            }
+            StdoutLine::Notification(notification) => {
+                let Some(notification) = self.pending.route_progress(notification) else {
+                    debug!(server = %self.server_key, raw = %line, "Forwarded progress notification");
+                    return;
+                };
+                debug!(server = %self.server_key, raw = %line, "Buffering server notification");
+                self.notifications.push(notification);
+            }
+            StdoutLine::ServerRequest(server_request) => {
+                // sampling/createMessage などに応答し、子プロセスを待たせない
+                debug!(server = %self.server_key, raw = %line, "Handling server-initiated request");
+                let server_key = self.server_key.clone();
+                let callbacks = Arc::clone(&self.callbacks);
+                let writer = Arc::clone(&self.writer);
+                tokio::spawn(async move {
+                    let response = callbacks.respond(&server_key, &server_request).await;
+                    if let Err(e) = writer.write(&response.to_string()).await {
+                        warn!(server = %server_key, error = %e, "Failed to answer server-initiated request");
+                    }
+                });
+            }
+            StdoutLine::Batch => {
+                if !self.pending.dispatch_batch(line) {
+                    self.buffer_unknown(line, None);
+                }
+            }
+            StdoutLine::Response(response_id) => {
+                let dispatched = self.pending.dispatch(
+                    response_id.as_ref(),
+                    line,
+                    self.quirks.responses_without_ids,
+                );
+                if !dispatched {
+                    self.buffer_unknown(line, response_id);
+                }
+            }
+        }
+    }
+
+    // 以前タイムアウトしたリクエストへの応答など、待っていない id は通知として保持する
+    fn buffer_unknown(&self, line: &str, response_id: Option<serde_json::Value>) {
+        warn!(
+            server = %self.server_key,
+            actual = ?response_id,
+            "Buffering response for an unknown request id"
+        );
+        if let Ok(orphan) = serde_json::from_str(line) {
+            self.notifications.push(orphan);
+        }
+    }
+}
+
+// --- 子プロセスの標準出力の読み取り ---
+// プロセスごとのタスクで読み続け、受信したメッセージを MessageRouter に渡す
+struct StdoutReader {
+    router: MessageRouter,
+    framing: Framing,
+    max_response_bytes: usize,
+    stdout: Pin<Box<dyn AsyncBufRead + Send>>,
+}
+
+impl StdoutReader {
+    async fn run(mut self) {
+        let server_key = self.router.server_key.clone();
+        loop {
+            match self.read_message().await {
+                Ok(None) => {
+                    warn!(server = %server_key, "MCP server closed connection (EOF)");
+                    self.router
+                        .pending
+                        .close(CONNECTION_CLOSED_ERROR.to_string());
+                    break;
+                }
+                Ok(Some(message)) => self.router.handle_message(&message),
+                Err(e) if e.kind() == std::io::ErrorKind::FileTooLarge => {
+                    let response_id = e
+                        .get_ref()
+                        .and_then(|e| e.downcast_ref::<OversizedMessage>())
+                        .and_then(|oversized| oversized.id.clone());
+                    warn!(server = %server_key, error = %e, id = ?response_id, "Discarded oversized message from MCP server");
+                    self.router.pending.fail_response(
+                        response_id.as_ref(),
+                        format!("{}: {}", RESPONSE_TOO_LARGE_ERROR, e),
+                    );
+                }
+                Err(e) => {
+                    error!(server = %server_key, error = %e, "Error reading from MCP stdout");
+                    self.router
+                        .pending
+                        .close(format!("Failed to read from MCP stdout: {}", e));
+                    break;
+                }
+            }
+        }
+    }
+
+    // result.content の要素を読み取り次第ストリーミングの送信先に渡し、残りを1メッセージとして返す
+    async fn read_streaming_message(&mut self) -> std::io::Result<Option<String>> {
+        let mut scanner = ContentScanner::new(self.max_response_bytes);
+        let response_id = RefCell::new(None);
+        while !scanner.is_complete() {
+            let available = self.stdout.fill_buf().await?;
+            if available.is_empty() {
+                break;
+            }
+            let pending = &self.router.pending;
+            let consumed = scanner.feed(available, |id| {
+                response_id.replace(Some(id.clone()));
+                pending.stream_sender(id)
+            });
+            self.stdout.consume(consumed);
+            let (items, sender) = scanner.take_items();
+            if let Some(sender) = sender {
+                for item in items {
+                    // HTTP クライアントが切断していても、応答は最後まで読み進める
+                    let _ = sender.send(item).await;
+                }
+            }
+        }
+        if scanner.overflowed() {
+            return Err(too_large_error(
+                self.max_response_bytes,
+                response_id.into_inner(),
+            ));
+        }
+        match scanner.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(scanner.into_envelope())),
+        }
+    }
+
+    // 子プロセスの標準出力から1メッセージ分を読み取る (EOF の場合は None)
+    async fn read_message(&mut self) -> std::io::Result<Option<String>> {
+        match self.framing {
+            Framing::Ndjson => {
+                // 出力が届いてから読み方を決め、その間に登録されたストリーミングも対象にする
+                if self.stdout.fill_buf().await?.is_empty() {
+                    return Ok(None);
+                }
+                if self.router.pending.has_streams() {
+                    return self.read_streaming_message().await;
+                }
+                let limit = self.max_response_bytes;
+                let mut message = Vec::new();
+                let (bytes_read, overflowed) =
+                    read_line_bounded(&mut self.stdout, &mut message, limit, None).await?;
+                if bytes_read == 0 {
+                    return Ok(None);
+                }
+                if overflowed {
+                    // 残りは読み捨て済みのため、次の行から同期し直せる
+                    return Err(too_large_error(limit, response_id_in(&message)));
+                }
+                let first_line = String::from_utf8_lossy(&message).into_owned();
+                if !is_incomplete_json(self.router.quirks.normalize_line(&first_line)) {
+                    return Ok(Some(first_line));
+                }
+                // 整形出力された JSON は括弧が閉じるまで後続の行をつなげる
+                let mut tracker = JsonDepthTracker::default();
+                tracker.feed(&message);
+                let mut overflowed = false;
+                while !tracker.is_complete() {
+                    // 上限を超えた後も、括弧が閉じるまでは読み捨てて続きの行を消費する
+                    let (bytes_read, line_overflowed) = read_line_bounded(
+                        &mut self.stdout,
+                        &mut message,
+                        limit,
+                        Some(&mut tracker),
+                    )
+                    .await?;
+                    if bytes_read == 0 {
+                        break;
+                    }
+                    overflowed |= line_overflowed;
+                }
+                if overflowed {
+                    return Err(too_large_error(limit, response_id_in(&message)));
+                }
+                Ok(Some(String::from_utf8_lossy(&message).into_owned()))
+            }
+            Framing::Lsp => {
+                read_lsp_message(
+                    &mut self.stdout,
+                    self.max_response_bytes,
+                    &self.router.server_key,
+                )
+                .await
+            }
+        }
+    }
+}
+
+// 上限までに読み取ったメッセージの先頭から、トップレベルの id を読み取る
+fn response_id_in(prefix: &[u8]) -> Option<serde_json::Value> {
+    let mut scanner = ResponseIdScanner::new();
+    scanner.feed(prefix);
+    scanner.into_id()
+}
+
+// LSP 形式のヘッダー1行の上限 (超えた行は読み捨てる)
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+
+// LSP 形式 (Content-Length ヘッダー付き) の1メッセージを読み取る (EOF の場合は None)
+async fn read_lsp_message<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_response_bytes: usize,
+    server_key: &str,
+) -> std::io::Result<Option<String>> {
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut line = Vec::new();
+        let (bytes_read, overflowed) =
+            read_line_bounded(reader, &mut line, MAX_HEADER_BYTES, None).await?;
+        if bytes_read == 0 {
+            return Ok(None);
+        }
+        if overflowed {
+            // 改行までは読み捨て済みのため、次の行から読み直せる
+            warn!(
+                server = %server_key,
+                bytes = bytes_read,
+                "Discarded an overlong header line from MCP server"
+            );
+            continue;
+        }
+        let header = String::from_utf8_lossy(&line);
+        let header = header.trim_end_matches(['\r', '\n']);
+        if header.is_empty() {
+            // ヘッダーの前の空行は無視し、ヘッダーの後の空行で本文に進む
+            match content_length {
+                Some(_) => break,
+                None => continue,
+            }
+        }
+        match header.split_once(':') {
+            Some((name, value)) if name.trim().eq_ignore_ascii_case("content-length") => {
+                let length = value.trim().parse().map_err(|_| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("invalid Content-Length header: {}", header),
+                    )
+                })?;
+                content_length = Some(length);
+            }
+            // Content-Type などのヘッダーは無視する
+            Some(_) => {}
+            None => info!(
+                target: "mcp_child_stdout",
+                server = %server_key,
+                "{}",
+                header
+            ),
+        }
+    }
+    let content_length = content_length.unwrap_or_default();
+    if content_length > max_response_bytes {
+        // 本文を読み捨てて次のメッセージから同期し直す (その間に id を読み取る)
+        let mut remaining = content_length;
+        let mut response_id = ResponseIdScanner::new();
+        while remaining > 0 {
+            let available = reader.fill_buf().await?;
+            if available.is_empty() {
+                break;
+            }
+            let chunk = &available[..available.len().min(remaining)];
+            response_id.feed(chunk);
+            let chunk_len = chunk.len();
+            reader.consume(chunk_len);
+            remaining -= chunk_len;
+        }
+        return Err(too_large_error(max_response_bytes, response_id.into_id()));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+// --- MCPプロセスとの通信用構造体 ---
+// 読み取りタスクが応答を振り分けるため、複数のリクエストを同時に送信できる
+pub struct McpServerProcess {
+    // 監視タスクへの停止要求 (drop されても停止する)
+    kill_tx: std::sync::Mutex<Option<oneshot::Sender<()>>>,
+    // 予期しない終了も含め、子プロセスが終了したら終了コードなどが入る
+    exited: watch::Receiver<Option<ProcessExit>>,
+    pid: Option<u32>,
+    initialize_result: Option<serde_json::Value>,
+    pub server_key: String,
+    tool_policy: ToolPolicy,
+    stats: Arc<ServerStats>,
+    circuit_breaker: Arc<CircuitBreaker>,
+    callbacks: Arc<CallbackHandler>,
+    writer: Arc<MessageWriter>,
+    pending: Arc<PendingTable>,
+    // リクエストと起動時の initialize の応答を待つ時間
+    response_timeout: Duration,
+    init_wait: Duration,
+}
+
+// ブリッジが送信する initialize リクエストの設定
+const MCP_PROTOCOL_VERSION: &str = "2025-03-26";
+const INITIALIZE_REQUEST_ID: &str = "mcp-http-server-initialize";
+
+// タイムアウト時のエラーメッセージの先頭 (統計でタイムアウトを区別するために使う)
+const RESPONSE_TIMEOUT_ERROR: &str = "MCP server response timeout";
+const CONNECTION_CLOSED_ERROR: &str = "MCP server closed the connection (EOF).";
+pub const RESPONSE_TOO_LARGE_ERROR: &str = "MCP server response too large";
+
+// --- クエリ失敗の種類 (HTTP ステータスの決定に使う) ---
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryFailure {
+    Timeout,
+    // 子プロセスが終了している (標準入出力が閉じている)、またはリモートのサーバーに接続できない
+    ProcessGone,
+    // レスポンスが max_response_bytes を超えた
+    TooLarge,
+    Other,
+}
+
+impl QueryFailure {
+    pub fn classify(error: &str) -> Self {
+        if error.starts_with(RESPONSE_TIMEOUT_ERROR) {
+            QueryFailure::Timeout
+        } else if error == CONNECTION_CLOSED_ERROR
+            || error.starts_with("Failed to write to MCP stdin")
+            || error.starts_with("Failed to flush MCP stdin")
+            || error.starts_with(REMOTE_UNAVAILABLE_ERROR)
+        {
+            QueryFailure::ProcessGone
+        } else if error.starts_with(RESPONSE_TOO_LARGE_ERROR) {
+            QueryFailure::TooLarge
+        } else {
+            QueryFailure::Other
+        }
+    }
+}
+
+impl McpServerProcess {
+    pub async fn query(&self, request: &McpRequest) -> Result<McpResponse, String> {
+        self.query_with_stream(request, None).await
+    }
+
+    // stream を指定すると、レスポンスの result.content の要素を読み取り次第そこに送る
+    // (送信済みの要素は返り値の content から取り除かれる)
+    async fn query_with_stream(
+        &self,
+        request: &McpRequest,
+        stream: Option<mpsc::Sender<String>>,
+    ) -> Result<McpResponse, String> {
+        // 拒否対象のツール呼び出しは子プロセスに転送せず JSON-RPC エラーを返す
+        // (バッチの一部だけが拒否対象なら残りを転送し、応答にエラーを差し込む)
+        let partial = match self.tool_policy.check_request(&request.command) {
+            PolicyCheck::Forward => None,
+            PolicyCheck::Reject(rejection) => return Ok(McpResponse { result: rejection }),
+            PolicyCheck::Partial(partial) => Some(partial),
+        };
+        let forwarded;
+        let request = match &partial {
+            Some(partial) => {
+                forwarded = McpRequest {
+                    command: partial.forwarded.clone(),
+                };
+                &forwarded
+            }
+            None => request,
+        };
+        let start_time = Instant::now();
+        let result = self
+            .query_inner(request, stream, self.response_timeout)
+            .await;
+        self.stats.record_request(
+            start_time.elapsed(),
+            result.as_ref().err().map(|e| e.as_str()),
+            matches!(&result, Err(e) if QueryFailure::classify(e) == QueryFailure::Timeout),
+        );
+        match &result {
+            Ok(_) => self.circuit_breaker.record_success(),
+            Err(e) => match QueryFailure::classify(e) {
+                QueryFailure::Timeout | QueryFailure::ProcessGone => {
+                    self.circuit_breaker.record_failure()
+                }
+                QueryFailure::TooLarge | QueryFailure::Other => {}
+            },
+        }
+        result.map(|response| {
+            if let Some(partial) = &partial {
+                return McpResponse {
+                    result: partial.merge(&response.result),
+                };
+            }
+            match self
+                .tool_policy
+                .filter_list_response(&request.command, &response.result)
+            {
+                Some(filtered) => McpResponse { result: filtered },
+                None => response,
+            }
+        })
+    }
+
+    fn has_exited(&self) -> bool {
+        self.exited.borrow().is_some()
+    }
+
+    fn exit(&self) -> Option<ProcessExit> {
+        *self.exited.borrow()
+    }
+
+    // 子プロセスを終了させ、終了を待つ
+    async fn shutdown(&self) {
+        let kill_tx = self
+            .kill_tx
+            .lock()
+            .ok()
+            .and_then(|mut kill_tx| kill_tx.take());
+        if let Some(kill_tx) = kill_tx {
+            let _ = kill_tx.send(());
+        }
+        let mut exited = self.exited.clone();
+        let _ = exited.wait_for(Option::is_some).await;
+    }
+
+    // ブリッジ側で JSON-RPC リクエストを組み立てて送信し、result を取り出す
+    pub async fn call(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        self.call_with_stream(method, params, None, None).await
+    }
+
+    // progress を指定すると params._meta.progressToken を付け、子プロセスの進捗通知の params をそこに送る
+    async fn call_with_stream(
+        &self,
+        method: &str,
+        mut params: serde_json::Value,
+        stream: Option<mpsc::Sender<String>>,
+        progress: Option<mpsc::Sender<serde_json::Value>>,
+    ) -> Result<serde_json::Value, String> {
+        let _progress_route = match (progress, params.as_object_mut()) {
+            (Some(progress), Some(object)) => {
+                let token = format!("mcp-http-server-progress-{}", uuid::Uuid::new_v4());
+                let meta = object
+                    .entry("_meta")
+                    .or_insert_with(|| serde_json::json!({}));
+                if let Some(meta) = meta.as_object_mut() {
+                    meta.insert("progressToken".to_string(), serde_json::json!(token));
+                }
+                self.pending.watch_progress(token.clone(), progress);
+                Some(ProgressRoute {
+                    pending: &self.pending,
+                    token,
+                })
+            }
+            _ => None,
+        };
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": format!("mcp-http-server-{}", uuid::Uuid::new_v4()),
+            "method": method,
+            "params": params,
+        });
+        let response = self
+            .query_with_stream(
+                &McpRequest {
+                    command: request.to_string(),
+                },
+                stream,
+            )
+            .await?;
+        let mut response: serde_json::Value = serde_json::from_str(&response.result)
+            .map_err(|e| format!("Invalid {} response: {}", method, e))?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("MCP {} failed: {}", method, error));
+        }
+        response
+            .get_mut("result")
+            .map(serde_json::Value::take)
+            .ok_or_else(|| format!("MCP {} response has no result", method))
+    }
+
+    // 複数のコマンドを転送し、コマンドの順に結果を返す
+    // jsonrpc_batch が true なら1つの JSON-RPC バッチとして、そうでなければ応答を待たずに続けて送信する。
+    // コマンド間で id が重複しても取り違えないよう、id はブリッジ側で一意なものに置き換えて送信する
+    pub async fn query_batch(
+        &self,
+        commands: &[String],
+        jsonrpc_batch: bool,
+    ) -> Vec<Result<McpResponse, String>> {
+        let batch_id = uuid::Uuid::new_v4();
+        let mut original_ids = HashMap::new();
+        let mut messages = Vec::with_capacity(commands.len());
+        for (index, command) in commands.iter().enumerate() {
+            let mut message = serde_json::from_str::<serde_json::Value>(command).ok();
+            // "id": null のリクエストも応答を照合できるよう置き換える
+            if let Some(id) = message
+                .as_mut()
+                .filter(|message| message.is_object() && message.get("method").is_some())
+                .and_then(|message| message.get_mut("id"))
+            {
+                let bridge_id = format!("mcp-http-server-batch-{}-{}", batch_id, index);
+                original_ids.insert(bridge_id.clone(), std::mem::replace(id, bridge_id.into()));
+            }
+            messages.push(message);
+        }
+
+        let all_objects = messages
+            .iter()
+            .all(|message| message.as_ref().is_some_and(serde_json::Value::is_object));
+        let mut results = match jsonrpc_batch && all_objects {
+            true => {
+                let messages = messages.into_iter().flatten().collect();
+                self.query_as_jsonrpc_batch(commands, messages).await
+            }
+            false => {
+                let requests: Vec<McpRequest> = commands
+                    .iter()
+                    .zip(messages)
+                    .map(|(command, message)| McpRequest {
+                        command: message
+                            .map(|message| message.to_string())
+                            .unwrap_or_else(|| command.clone()),
+                    })
+                    .collect();
+                join_all(requests.iter().map(|request| self.query(request))).await
+            }
+        };
+
+        // 応答の id を元の id に戻す
+        for response in results.iter_mut().flatten() {
+            let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&response.result) else {
+                continue;
+            };
+            let Some(original_id) = value
+                .get("id")
+                .and_then(serde_json::Value::as_str)
+                .and_then(|id| original_ids.get(id))
+            else {
+                continue;
+            };
+            value["id"] = original_id.clone();
+            response.result = value.to_string();
+        }
+        results
+    }
+
+    // 1つの JSON-RPC バッチとして送信し、応答を id でコマンドに振り分ける
+    async fn query_as_jsonrpc_batch(
+        &self,
+        commands: &[String],
+        messages: Vec<serde_json::Value>,
+    ) -> Vec<Result<McpResponse, String>> {
+        let mut results: Vec<Option<Result<McpResponse, String>>> =
+            commands.iter().map(|_| None).collect();
+        let mut indexes = HashMap::new();
+        let mut batch = Vec::new();
+        for (index, (command, message)) in commands.iter().zip(messages).enumerate() {
+            if let PolicyCheck::Reject(rejection) = self.tool_policy.check_request(command) {
+                results[index] = Some(Ok(McpResponse { result: rejection }));
+                continue;
+            }
+            match message.get("id").and_then(serde_json::Value::as_str) {
+                Some(id) => {
+                    indexes.insert(id.to_string(), index);
+                }
+                // 通知には応答が返らない
+                None => {
+                    results[index] = Some(Ok(McpResponse {
+                        result: String::new(),
+                    }))
+                }
+            }
+            batch.push(message);
+        }
+
+        if !batch.is_empty() {
+            let response = self
+                .query(&McpRequest {
+                    command: serde_json::Value::Array(batch).to_string(),
+                })
+                .await
+                .and_then(|response| {
+                    serde_json::from_str::<Vec<serde_json::Value>>(&response.result)
+                        .map_err(|e| format!("Invalid batch response: {}", e))
+                });
+            match response {
+                Ok(responses) => {
+                    for response in responses {
+                        let Some(index) = response
+                            .get("id")
+                            .and_then(serde_json::Value::as_str)
+                            .and_then(|id| indexes.remove(id))
+                        else {
+                            continue;
+                        };
+                        let line = response.to_string();
+                        let result = self
+                            .tool_policy
+                            .filter_list_response(&commands[index], &line)
+                            .unwrap_or(line);
+                        results[index] = Some(Ok(McpResponse { result }));
+                    }
+                }
+                Err(e) => {
+                    for index in indexes.into_values() {
+                        results[index] = Some(Err(e.clone()));
+                    }
+                }
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    Err("MCP server returned no response for this request".to_string())
+                })
+            })
+            .collect()
+    }
+
+    // ヘルスチェックのリクエストを送り、result を返す (統計・サーキットブレーカーには記録しない)
+    async fn probe_call(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+        wait: Duration,
+    ) -> Result<serde_json::Value, String> {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": format!("mcp-http-server-health-{}", uuid::Uuid::new_v4()),
+            "method": method,
+            "params": params,
+        });
+        let response = self
+            .query_inner(
+                &McpRequest {
+                    command: request.to_string(),
+                },
+                None,
+                wait,
+            )
+            .await?;
+        let mut response: serde_json::Value = serde_json::from_str(&response.result)
+            .map_err(|e| format!("Invalid {} response: {}", method, e))?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("MCP {} failed: {}", method, error));
+        }
+        response
+            .get_mut("result")
+            .map(serde_json::Value::take)
+            .ok_or_else(|| format!("MCP {} response has no result", method))
+    }
+
+    // MCP の ping を送り、wait 以内に応答が返るか確認する。
+    // ping に対応していないサーバーもあるため、JSON-RPC エラーでも応答があれば成功とする
+    async fn ping(&self, wait: Duration) -> Result<(), String> {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": format!("mcp-http-server-ping-{}", uuid::Uuid::new_v4()),
+            "method": "ping",
+        });
+        self.query_inner(
+            &McpRequest {
+                command: request.to_string(),
+            },
+            None,
+            wait,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    // auto_initialize: false の子プロセスが応答できるようになるまで init_wait だけ待つ。
+    // ping は initialize の前でも送ってよいため、応答 (JSON-RPC エラーを含む) があれば起動完了とする。
+    // 応答前に終了した (標準入出力が閉じた) 場合は起動失敗とし、時間切れの場合は initialize 前の ping に
+    // 応答しないサーバーとみなして起動を続ける
+    async fn wait_until_responsive(&self) -> Result<(), String> {
+        let started_at = Instant::now();
+        match self.ping(self.init_wait).await {
+            Ok(()) => {
+                info!(server = %self.server_key, elapsed_ms = started_at.elapsed().as_millis() as u64, "MCP process is responding");
+                Ok(())
+            }
+            Err(e) if QueryFailure::classify(&e) == QueryFailure::Timeout && !self.has_exited() => {
+                warn!(server = %self.server_key, error = %e, "MCP process did not answer ping before initialize, assuming it is ready");
+                Ok(())
+            }
+            Err(e) => Err(format!("MCP process failed before it became ready: {}", e)),
+        }
+    }
+
+    // MCP の initialize / notifications/initialized を実行し、initialize の結果を返す
+    async fn initialize(&self) -> Result<serde_json::Value, String> {
+        // 転送先がある場合のみ sampling / elicitation に対応していると宣言する
+        let capabilities = match self.callbacks.is_configured() {
+            true => serde_json::json!({ "sampling": {}, "elicitation": {} }),
+            false => serde_json::json!({}),
+        };
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": INITIALIZE_REQUEST_ID,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": MCP_PROTOCOL_VERSION,
+                "capabilities": capabilities,
+                "clientInfo": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            },
+        });
+        let response = self
+            .query_inner(
+                &McpRequest {
+                    command: request.to_string(),
+                },
+                None,
+                self.init_wait,
+            )
+            .await?;
+        let mut response: serde_json::Value = serde_json::from_str(&response.result)
+            .map_err(|e| format!("Invalid initialize response: {}", e))?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("MCP initialize failed: {}", error));
+        }
+        let result = response
+            .get_mut("result")
+            .map(serde_json::Value::take)
+            .ok_or_else(|| "MCP initialize response has no result".to_string())?;
+
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/initialized",
+        });
+        self.writer.write(&notification.to_string()).await?;
+        info!(
+            server = %self.server_key,
+            protocol_version = ?result.get("protocolVersion"),
+            server_info = ?result.get("serverInfo"),
+            "MCP initialize handshake completed"
+        );
+        Ok(result)
+    }
+
+    async fn query_inner(
+        &self,
+        request: &McpRequest,
+        stream: Option<mpsc::Sender<String>>,
+        wait: Duration,
+    ) -> Result<McpResponse, String> {
+        let start_time = Instant::now();
+        debug!(server = %self.server_key, ?request, "Starting MCP query");
+
+        // MCPサーバーには JSON.stringify された文字列を展開して送信
+        let mcp_message = &request.command;
+        let (bridged_message, original_ids) = assign_bridge_ids(mcp_message);
+        // レスポンスの照合と quirks.responses_without_ids 用にリクエストの id を控えておく
+        let pending = PendingResponses::from_message(&bridged_message);
+        // 通知 (通知だけのバッチ) には応答が返らない
+        if pending.ids.is_empty() {
+            self.writer.write(mcp_message).await?;
+            return Ok(McpResponse {
+                result: match pending.batch {
+                    true => "[]".to_string(),
+                    false => String::new(),
+                },
+            });
+        }
+        // 応答が書き込みより先に届いても取りこぼさないよう、送信前に登録する
+        let (slot, response_rx) = self.pending.register(pending, stream)?;
+        if let Err(e) = self.writer.write(&bridged_message).await {
+            self.pending.remove(slot);
+            return Err(e);
+        }
+
+        debug!(server = %self.server_key, "Data sent to MCP server, waiting for response");
+
+        // タイムアウト付きでレスポンスを待つ
+        let guard = CancelOnDrop {
+            process: self,
+            slot,
+            original_ids: &original_ids,
+            armed: true,
+        };
+        let received = timeout(wait, response_rx).await;
+        guard.disarm();
+        match received {
+            Ok(Ok(result)) => {
+                let latency_ms = start_time.elapsed().as_millis() as u64;
+                debug!(server = %self.server_key, latency_ms, "MCP query completed");
+                let result = result.map(|result| restore_original_ids(result, &original_ids));
+                if let Ok(result) = &result {
+                    recording::record(&self.server_key, mcp_message, result).await;
+                }
+                result.map(|result| McpResponse { result })
+            }
+            // 読み取りタスクが終了した
+            Ok(Err(_)) => Err(CONNECTION_CLOSED_ERROR.to_string()),
+            Err(_) => {
+                // 遅れて届いた応答は通知として保持される
+                self.cancel(slot, &original_ids, "Request timed out");
+                warn!(server = %self.server_key, timeout_secs = wait.as_secs(), "MCP query timed out");
+                Err(format!(
+                    "{} ({} seconds)",
+                    RESPONSE_TIMEOUT_ERROR,
+                    wait.as_secs()
+                ))
+            }
+        }
+    }
+
+    // 応答待ちを取り消し、応答していない id ごとに notifications/cancelled を送る
+    // (Drop から呼ぶため、送信はバックグラウンドで行う。initialize は取り消せない)
+    fn cancel(
+        &self,
+        slot: u64,
+        original_ids: &HashMap<String, serde_json::Value>,
+        reason: &'static str,
+    ) {
+        let notifications: Vec<String> = self
+            .pending
+            .cancel(slot)
+            .into_iter()
+            .filter(|id| {
+                let original = id.as_str().and_then(|id| original_ids.get(id));
+                original.and_then(serde_json::Value::as_str) != Some(INITIALIZE_REQUEST_ID)
+            })
+            .map(|id| {
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "method": "notifications/cancelled",
+                    "params": { "requestId": id, "reason": reason },
+                })
+                .to_string()
+            })
+            .collect();
+        if notifications.is_empty() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        info!(server = %self.server_key, reason, requests = notifications.len(), "Cancelling MCP request");
+        let writer = Arc::clone(&self.writer);
+        runtime.spawn(async move {
+            for notification in notifications {
+                if let Err(e) = writer.write(&notification).await {
+                    debug!(server = %writer.server_key, error = %e, "Failed to send notifications/cancelled");
+                    return;
+                }
+            }
+        });
+    }
+}
+
+// --- リクエスト・レスポンスデータ構造 ---
+#[derive(Serialize, Deserialize, Debug)]
+pub struct McpRequest {
+    pub command: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct McpResponse {
+    pub result: String,
+}
+
+// --mock で起動する組み込みのモックサーバーの設定 (他の項目は既定値)
+pub fn mock_config() -> Result<McpProcessConfig, String> {
+    serde_json::from_value(serde_json::json!({ "type": "mock" }))
+        .map_err(|e| format!("Invalid mock server configuration: {}", e))
+}
+
+// --- MCPサーバープロセス起動関数 ---
+pub fn spawn_mcp_process(
+    server_key: &str,
+    config: &McpProcessConfig,
+    stats: Arc<ServerStats>,
+    notifications: Arc<NotificationBuffer>,
+    stderr_buffer: Arc<StderrBuffer>,
+    circuit_breaker: Arc<CircuitBreaker>,
+    events: EventBus,
+) -> Result<McpServerProcess, String> {
+    if config.command.is_empty() {
+        return Err(format!("MCP server '{}' has no command", server_key));
+    }
+    info!(
+        server = %server_key,
+        command = %config.command,
+        args = ?config.args,
+        cwd = ?config.cwd,
+        sandbox = ?config.sandbox,
+        limits = ?config.limits,
+        env_keys = ?config.env.keys().collect::<Vec<_>>(),
+        "Starting MCP server"
+    );
+
+    let mut command_builder = program::command(&config.command);
+    // McpServerProcess が破棄されたら子プロセスも終了させる
+    command_builder.kill_on_drop(true);
+    // 子孫のプロセスもまとめて終了できるようにする
+    process_tree::isolate(&mut command_builder);
+    command_builder.args(&config.args);
+    command_builder.envs(&config.child_env(server_key)?);
+    sandbox::apply(&mut command_builder, server_key, config)?;
+    let limit_guard = limits::apply(&mut command_builder, server_key, config.limits.as_ref())?;
+
+    command_builder
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+
+    debug!(server = %server_key, "Spawning MCP process");
+    let mut child = command_builder.spawn().map_err(|e| {
+        format!(
+            "Failed to spawn MCP process for key '{}' (command: '{}'): {}",
+            server_key, config.command, e
+        )
+    })?;
+    // 子プロセスが子孫を起動する前にできるだけ早くジョブに入れる (Windows)
+    let tree = ProcessTree::attach(server_key, &child);
+
+    let stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| format!("Failed to open stdin for MCP process '{}'", server_key))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| format!("Failed to open stdout for MCP process '{}'", server_key))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| format!("Failed to open stderr for MCP process '{}'", server_key))?;
+
+    debug!(
+        server = %server_key,
+        pid = ?child.id(),
+        "MCP process spawned successfully, setting up stderr monitoring"
+    );
+
+    let pid = child.id();
+    events.emit(server_key, LifecycleEventKind::ChildSpawned { pid });
+    // 再起動をまたいで保持するため、どのプロセスの出力か分かるように区切りを入れる
+    stderr_buffer.push(&format!(
+        "--- MCP process started (pid {}) ---",
+        pid.map(|pid| pid.to_string()).unwrap_or_default()
+    ));
+
+    // 子プロセスの終了を監視し、停止要求があれば kill する
+    let (kill_tx, kill_rx) = oneshot::channel::<()>();
+    let (exited_tx, exited) = watch::channel(None);
+    if let Some(pid) = pid {
+        tokio::spawn(resource_monitor::run(
+            server_key.to_string(),
+            pid,
+            config.resource_monitor.clone(),
+            Arc::clone(&stats),
+            exited.clone(),
+        ));
+    }
+    let server_key_for_monitor = server_key.to_string();
+    let stats_for_monitor = Arc::clone(&stats);
+    let stop_grace = config.stop_grace();
+    tokio::spawn(async move {
+        let breached = async {
+            match &limit_guard {
+                Some(guard) => guard.breached(pid).await,
+                None => std::future::pending().await,
+            }
+        };
+        let (status, expected, mut breach) = tokio::select! {
+            status = child.wait() => (status, false, None),
+            _ = kill_rx => {
+                let status = tree.terminate(&mut child, stop_grace).await;
+                (status, true, None)
+            }
+            breach = breached => {
+                warn!(server = %server_key_for_monitor, ?pid, breach = %breach, "MCP process exceeded its resource limits, killing it");
+                let status = tree.terminate(&mut child, Duration::ZERO).await;
+                (status, false, Some(breach))
+            }
+        };
+        tree.kill_remaining();
+        if let Some(guard) = limit_guard {
+            if breach.is_none() && guard.oom_killed().await {
+                breach = Some("memory.max exceeded (killed by the kernel OOM killer)".to_string());
+                warn!(server = %server_key_for_monitor, ?pid, "MCP process was killed for exceeding its memory limit");
+            }
+            guard.release().await;
+        }
+        if let Some(detail) = breach {
+            stats_for_monitor.record_limit_breach();
+            events.emit(
+                &server_key_for_monitor,
+                LifecycleEventKind::LimitExceeded { detail },
+            );
+        }
+        let exit_code = status.as_ref().ok().and_then(|status| status.code());
+        if expected {
+            info!(server = %server_key_for_monitor, ?pid, ?exit_code, "MCP process stopped");
+        } else {
+            warn!(server = %server_key_for_monitor, ?pid, ?exit_code, "MCP process exited unexpectedly");
+        }
+        stats_for_monitor.record_exit(pid);
+        events.emit(
+            &server_key_for_monitor,
+            LifecycleEventKind::ChildExited {
+                pid,
+                exit_code,
+                expected,
+            },
+        );
+        let _ = exited_tx.send(Some(ProcessExit {
+            exit_code,
+            expected,
+        }));
+    });
+
+    forward_stderr(server_key, stderr, stderr_buffer);
+
+    debug!(server = %server_key, "MCP server setup complete");
+    let (writer, pending, callbacks) = connect_stdio(
+        server_key,
+        config,
+        Box::pin(stdin),
+        Box::pin(BufReader::new(stdout)),
+        notifications,
+    );
+
+    Ok(McpServerProcess {
+        kill_tx: std::sync::Mutex::new(Some(kill_tx)),
+        exited,
+        pid,
+        initialize_result: None,
+        server_key: server_key.to_string(),
+        tool_policy: config.tool_policy.clone(),
+        stats,
+        circuit_breaker,
+        callbacks,
+        writer,
+        pending,
+        response_timeout: config.response_timeout(),
+        init_wait: config.init_wait(),
+    })
+}
+
+// --- language: "wasm" のサーバーの起動 ---
+// ゲストは子プロセスと同じく標準入出力でつなぎ、終了 (トラップを含む) を子プロセスの終了として扱う
+async fn spawn_wasm_process(
+    server_key: &str,
+    config: &McpProcessConfig,
+    stats: Arc<ServerStats>,
+    notifications: Arc<NotificationBuffer>,
+    stderr_buffer: Arc<StderrBuffer>,
+    circuit_breaker: Arc<CircuitBreaker>,
+    events: EventBus,
+) -> Result<McpServerProcess, String> {
+    let mut guest = wasm_server::start(server_key, config).await?;
+    events.emit(server_key, LifecycleEventKind::ChildSpawned { pid: None });
+    stderr_buffer.push("--- WASM MCP server started ---");
+
+    let (kill_tx, kill_rx) = oneshot::channel::<()>();
+    let (exited_tx, exited) = watch::channel(None);
+    let monitor_server_key = server_key.to_string();
+    let monitor_stats = Arc::clone(&stats);
+    tokio::spawn(async move {
+        let (exit_code, expected) = tokio::select! {
+            result = &mut guest.exit => {
+                let exit_code = match result {
+                    Ok(Ok(exit_code)) => Some(exit_code),
+                    Ok(Err(e)) => {
+                        warn!(server = %monitor_server_key, error = %e, "WASM MCP server failed");
+                        None
+                    }
+                    Err(e) => {
+                        warn!(server = %monitor_server_key, error = %e, "WASM MCP server task failed");
+                        None
+                    }
+                };
+                (exit_code, false)
+            }
+            _ = kill_rx => {
+                guest.exit.abort();
+                (None, true)
+            }
+        };
+        if expected {
+            info!(server = %monitor_server_key, "WASM MCP server stopped");
+        } else {
+            warn!(server = %monitor_server_key, ?exit_code, "WASM MCP server exited unexpectedly");
+        }
+        monitor_stats.record_exit(None);
+        events.emit(
+            &monitor_server_key,
+            LifecycleEventKind::ChildExited {
+                pid: None,
+                exit_code,
+                expected,
+            },
+        );
+        let _ = exited_tx.send(Some(ProcessExit {
+            exit_code,
+            expected,
+        }));
+    });
+    forward_stderr(server_key, guest.stderr, stderr_buffer);
+
+    let (writer, pending, callbacks) = connect_stdio(
+        server_key,
+        config,
+        Box::pin(guest.stdin),
+        Box::pin(BufReader::new(guest.stdout)),
+        notifications,
+    );
+    Ok(McpServerProcess {
+        kill_tx: std::sync::Mutex::new(Some(kill_tx)),
+        exited,
+        pid: None,
+        initialize_result: None,
+        server_key: server_key.to_string(),
+        tool_policy: config.tool_policy.clone(),
+        stats,
+        circuit_breaker,
+        callbacks,
+        writer,
+        pending,
+        response_timeout: config.response_timeout(),
+        init_wait: config.init_wait(),
+    })
+}
+
+// 標準エラー出力の各行をログと stderr のバッファに書く
+fn forward_stderr(
+    server_key: &str,
+    stderr: impl AsyncRead + Send + Unpin + 'static,
+    stderr_buffer: Arc<StderrBuffer>,
+) {
+    let server_key_clone_for_stderr = server_key.to_string();
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(stderr);
+        let mut line = String::new();
+        loop {
+            match reader.read_line(&mut line).await {
+                Ok(0) => {
+                    debug!(
+                        server = %server_key_clone_for_stderr,
+                        "MCP server stderr EOF, task finishing"
+                    );
+                    break;
+                }
+                Ok(_) => {
+                    info!(
+                        target: "mcp_child_stderr",
+                        server = %server_key_clone_for_stderr,
+                        "{}",
+                        line.trim_end()
+                    );
+                    stderr_buffer.push(line.trim_end());
+                    line.clear();
+                }
+                Err(e) => {
+                    error!(
+                        server = %server_key_clone_for_stderr,
+                        error = %e,
+                        "MCP server stderr read error"
+                    );
+                    break;
+                }
+            }
+        }
+    });
+}
+
+// 標準入出力で JSON-RPC をやりとりする (子プロセスと WASM のゲストで共通)
+fn connect_stdio(
+    server_key: &str,
+    config: &McpProcessConfig,
+    stdin: Pin<Box<dyn AsyncWrite + Send>>,
+    stdout: Pin<Box<dyn AsyncBufRead + Send>>,
+    notifications: Arc<NotificationBuffer>,
+) -> (Arc<MessageWriter>, Arc<PendingTable>, Arc<CallbackHandler>) {
+    if config.quirks.strip_bom
+        || config.quirks.crlf_line_endings
+        || config.quirks.responses_without_ids
+        || config.quirks.json_logs_on_stdout
+    {
+        info!(server = %server_key, quirks = ?config.quirks, "Protocol quirks enabled");
+    }
+
+    let writer = Arc::new(MessageWriter {
+        server_key: server_key.to_string(),
+        framing: config.framing,
+        line_ending: config.quirks.line_ending(),
+        sink: MessageSink::Stdin(Mutex::new(stdin)),
+    });
+    let pending = Arc::new(PendingTable::default());
+    let callbacks = Arc::new(CallbackHandler::new(config.callback.clone()));
+    tokio::spawn(
+        StdoutReader {
+            router: MessageRouter {
+                server_key: server_key.to_string(),
+                quirks: config.quirks.clone(),
+                pending: Arc::clone(&pending),
+                notifications,
+                callbacks: Arc::clone(&callbacks),
+                writer: Arc::clone(&writer),
+            },
+            framing: config.framing,
+            max_response_bytes: config.max_response_bytes,
+            stdout,
+        }
+        .run(),
+    );
+    (writer, pending, callbacks)
+}
+
+// 子プロセス以外のサーバーから受信したメッセージのうち、振り分け待ちにできる件数
+const VIRTUAL_INBOUND_BUFFER: usize = 64;
+
+// */list のページングで辿る最大ページ数
+const LIST_MAX_PAGES: usize = 100;
+
+// listChanged の通知と、それによって古くなる一覧のメソッドと結果の配列名
+const LIST_CHANGED_NOTIFICATIONS: &[(&str, &str, &str)] = &[
+    ("notifications/tools/list_changed", "tools/list", "tools"),
+    (
+        "notifications/resources/list_changed",
+        "resources/list",
+        "resources",
+    ),
+    (
+        "notifications/prompts/list_changed",
+        "prompts/list",
+        "prompts",
+    ),
+];
+
+// 再送しても副作用のないメソッド (retry_on_crash の対象)
+const IDEMPOTENT_METHODS: &[&str] = &[
+    "ping",
+    "tools/list",
+    "resources/list",
+    "resources/templates/list",
+    "resources/read",
+    "prompts/list",
+    "prompts/get",
+    "completion/complete",
+];
+
+// JSON-RPC バッチに対応しているプロトコルバージョン (2025-06-18 以降はバッチが廃止された)
+const JSONRPC_BATCH_PROTOCOL_VERSIONS: &[&str] = &["2025-03-26"];
+
+// アイドル状態を確認する間隔
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// スーパーバイザーが再起動に失敗した場合に再試行するまでの待ち時間
+const RESTART_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// 待機中のリクエストが max_queued_requests に達したときのエラーメッセージに含める文言
+pub const QUEUE_FULL_ERROR: &str = "too many queued requests";
+
+// --- 同時実行枠を確保した子プロセスへの参照 ---
+// drop されるまで max_concurrent_requests の枠を1つ占有する
+pub struct ProcessLease<'a> {
+    process: Arc<McpServerProcess>,
+    _permit: SemaphorePermit<'a>,
+    // 枠の待機を含めたリクエスト全体の時間を drop 時に記録する
+    // (起動のためだけに確保して子プロセスを使わなかった場合は記録しない)
+    stats: &'a ServerStats,
+    started_at: Instant,
+    used: AtomicBool,
+    // 枠の空き (と lazy の場合は起動) を待った時間
+    queue_wait: Duration,
+}
+
+impl ProcessLease<'_> {
+    pub fn queue_wait(&self) -> Duration {
+        self.queue_wait
+    }
+}
+
+impl std::ops::Deref for ProcessLease<'_> {
+    type Target = McpServerProcess;
+
+    fn deref(&self) -> &McpServerProcess {
+        self.used.store(true, Ordering::Relaxed);
+        &self.process
+    }
+}
+
+impl Drop for ProcessLease<'_> {
+    fn drop(&mut self) {
+        if self.used.load(Ordering::Relaxed) {
+            self.stats.record_total(self.started_at.elapsed());
+        }
+    }
+}
+
+// 同時実行枠の空きを待っている間だけ保持するガード (drop で待機数を戻す)
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// --- MCPサーバーの管理構造体 ---
+// 設定と統計を保持し、HTTPサーバーを止めずに子プロセスを起動・停止・再起動できるようにする
+pub struct McpServer {
+    pub server_key: String,
+    // レジストリの更新で差し替えられるため、参照は config() で取得する
+    config: std::sync::RwLock<Arc<McpProcessConfig>>,
+    pub stats: Arc<ServerStats>,
+    // サーバーからの通知 (プロセスの再起動をまたいで保持する)
+    pub notifications: Arc<NotificationBuffer>,
+    // 子プロセスの stderr の末尾 (プロセスの再起動をまたいで保持する)
+    stderr: Arc<StderrBuffer>,
+    // 子プロセスの連続した失敗を検知する (プロセスの再起動をまたいで保持する)
+    pub circuit_breaker: Arc<CircuitBreaker>,
+    // health_check の結果 (プロセスの再起動をまたいで保持する)
+    pub health: Arc<HealthChecker>,
+    // restart_window_secs の間の自動再起動の記録
+    restart_budget: RestartBudget,
+    events: EventBus,
+    process: Mutex<Option<Arc<McpServerProcess>>>,
+    // 子プロセスに同時に送信できるリクエスト数 (max_concurrent_requests)
+    concurrency: Semaphore,
+    // 同時実行枠の空きを待っているリクエスト数
+    queued: AtomicUsize,
+    // warm_standby 設定時の予備プロセス
+    standby: Mutex<Option<McpServerProcess>>,
+    // 管理APIで停止された場合は遅延起動しない
+    stopped_by_admin: AtomicBool,
+    // 稼働させるプロセスを起動・初期化している間は true (/readyz がプロセスのロックを待たないように)
+    starting: AtomicBool,
+    // 最後にリクエストを受け付けた時刻 (アイドル停止の判定用)
+    last_used: std::sync::Mutex<Instant>,
+    // 直近の initialize 結果 (capabilities / serverInfo など)
+    initialize_result: std::sync::Mutex<Option<serde_json::Value>>,
+    // tools/list などの結果 (子プロセスの起動ごとに破棄する)
+    list_cache: std::sync::Mutex<HashMap<&'static str, Vec<serde_json::Value>>>,
+    // POST /api/v1 の tools/list などの応答 (response_cache 設定時のみ、子プロセスの起動ごとに破棄する)
+    pub response_cache: ResponseCache,
+    // listChanged の通知を受け取るタスクを開始済みか
+    watching_list_changes: AtomicBool,
+    // type: "aggregate" の場合にまとめるサーバー (集約サーバーの再起動をまたいで保持する)
+    members: Vec<Arc<McpServer>>,
+}
+
+impl McpServer {
+    pub fn new(server_key: &str, config: McpProcessConfig, events: EventBus) -> Self {
+        let members = config
+            .aggregate
+            .members
+            .iter()
+            .map(|(member_key, member_config)| {
+                Arc::new(McpServer::new(
+                    member_key,
+                    member_config.clone(),
+                    events.clone(),
+                ))
+            })
+            .collect();
+        McpServer {
+            server_key: server_key.to_string(),
+            stats: Arc::new(ServerStats::new(server_key)),
+            notifications: Arc::new(NotificationBuffer::new(config.notification_buffer_size)),
+            stderr: Arc::new(StderrBuffer::new(config.stderr_buffer_kb * 1024)),
+            circuit_breaker: Arc::new(CircuitBreaker::new(
+                server_key,
+                config.circuit_breaker.clone(),
+            )),
+            health: Arc::new(HealthChecker::new(server_key)),
+            restart_budget: RestartBudget::new(server_key),
+            concurrency: Semaphore::new(config.max_concurrent_requests.max(1)),
+            queued: AtomicUsize::new(0),
+            config: std::sync::RwLock::new(Arc::new(config)),
+            events,
+            process: Mutex::new(None),
+            standby: Mutex::new(None),
+            stopped_by_admin: AtomicBool::new(false),
+            starting: AtomicBool::new(false),
+            last_used: std::sync::Mutex::new(Instant::now()),
+            initialize_result: std::sync::Mutex::new(None),
+            list_cache: std::sync::Mutex::new(HashMap::new()),
+            response_cache: ResponseCache::default(),
+            watching_list_changes: AtomicBool::new(false),
+            members,
+        }
+    }
+
+    pub fn config(&self) -> Arc<McpProcessConfig> {
+        Arc::clone(
+            &self
+                .config
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        )
+    }
+
+    // レジストリで定義が変わった場合に設定を差し替え、起動中なら新しい設定で再起動する。
+    // 起動時に確保するもの (max_concurrent_requests / notification_buffer_size /
+    // stderr_buffer_kb / circuit_breaker / 集約サーバーのメンバー) はプロセスの再起動まで変わらない
+    pub async fn update_config(self: &Arc<Self>, config: McpProcessConfig) -> Result<(), String> {
+        *self
+            .config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(config);
+        if !self.is_running().await {
+            return Ok(());
+        }
+        self.restart("configuration updated").await
+    }
+
+    // stderr の末尾 count 行。集約サーバーはメンバーの出力を [メンバー名] 付きで並べる
+    pub fn stderr_tail(&self, count: usize) -> Vec<String> {
+        let mut lines = self.stderr.tail(count);
+        for member in &self.members {
+            lines.extend(
+                member
+                    .stderr_tail(count)
+                    .into_iter()
+                    .map(|line| format!("[{}] {}", member.server_key, line)),
+            );
+        }
+        lines
+    }
+
+    // type: "aggregate" の場合にまとめているサーバー
+    pub fn members(&self) -> &[Arc<McpServer>] {
+        &self.members
+    }
+
+    pub fn is_tool_allowed(&self, tool_name: &str) -> bool {
+        self.config().tool_policy.is_allowed(tool_name)
+    }
+
+    // ツールの許可・拒否リストで拒否する場合の JSON-RPC エラーレスポンス
+    // (バッチの一部だけが拒否対象の場合は None。query で残りを転送する)
+    pub fn check_tool_policy(&self, command: &str) -> Option<String> {
+        match self.config().tool_policy.check_request(command) {
+            PolicyCheck::Reject(rejection) => Some(rejection),
+            PolicyCheck::Forward | PolicyCheck::Partial(_) => None,
+        }
+    }
+
+    // 書き換えルール (rewrite) を適用する。HTTP の入口で、スキーマの検証やキャッシュの参照より前に行う
+    pub fn rewrite_command(&self, command: String) -> String {
+        self.config().rewrite.apply(&command).unwrap_or(command)
+    }
+
+    pub fn rewrite_message(&self, message: &mut serde_json::Value) {
+        self.config().rewrite.apply_message(message);
+    }
+
+    pub fn is_lazy(&self) -> bool {
+        self.config().lazy
+    }
+
+    // 停止中のサーバーをリクエスト受信時に起動するかどうか
+    fn spawns_on_demand(&self) -> bool {
+        let config = self.config();
+        (config.lazy || config.idle_timeout_secs.is_some() || config.warm_standby)
+            && !self.stopped_by_admin.load(Ordering::SeqCst)
+    }
+
+    fn touch(&self) {
+        if let Ok(mut last_used) = self.last_used.lock() {
+            *last_used = Instant::now();
+        }
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used
+            .lock()
+            .map(|last_used| last_used.elapsed())
+            .unwrap_or_default()
+    }
+
+    // idle_timeout_secs が設定されていれば、アイドル状態の子プロセスを停止する監視タスクを起動する
+    pub fn spawn_idle_reaper(self: &Arc<Self>) {
+        for member in &self.members {
+            member.spawn_idle_reaper();
+        }
+        let Some(idle_timeout) = self.config().idle_timeout_secs.map(Duration::from_secs) else {
+            return;
+        };
+        info!(
+            server = %self.server_key,
+            idle_timeout_secs = idle_timeout.as_secs(),
+            "Idle shutdown enabled"
+        );
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL.min(idle_timeout));
+            loop {
+                interval.tick().await;
+                // リクエスト処理中は同時実行枠が埋まっているので次回に持ち越す
+                let Ok(_drained) = server.concurrency.try_acquire_many(server.max_concurrent())
+                else {
+                    continue;
+                };
+                let Ok(mut process) = server.process.try_lock() else {
+                    continue;
+                };
+                if process.is_none() || server.idle_for() < idle_timeout {
+                    continue;
+                }
+                if let Some(running) = process.take() {
+                    info!(
+                        server = %server.server_key,
+                        idle_secs = server.idle_for().as_secs(),
+                        "Stopping idle MCP process"
+                    );
+                    running.shutdown().await;
+                    server.stop_standby().await;
+                }
+            }
+        });
+    }
+
+    // health_check が設定されていれば、稼働中の子プロセスを定期的に確認する監視タスクを起動する
+    pub fn spawn_health_check(self: &Arc<Self>) {
+        for member in &self.members {
+            member.spawn_health_check();
+        }
+        let Some(health_check) = self.config().health_check.clone() else {
+            return;
+        };
+        info!(
+            server = %self.server_key,
+            interval_secs = health_check.interval().as_secs(),
+            restart = health_check.restart,
+            "Health check enabled"
+        );
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(health_check.interval());
+            // 起動直後のチェックは initialize の完了後 (次の tick) から行う
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                // レジストリで更新された場合は最新の設定で確認する
+                let Some(health_check) = server.config().health_check.clone() else {
+                    continue;
+                };
+                // 停止中 (lazy で未起動・アイドル停止・管理APIでの停止) は確認しない
+                let Some(process) = server.process.lock().await.clone() else {
+                    server.health.reset();
+                    continue;
+                };
+                let result = match timeout(health_check.timeout(), server.probe(&process)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(format!(
+                        "Health check timed out after {} seconds",
+                        health_check.timeout().as_secs()
+                    )),
+                };
+                let error = match result {
+                    Ok(()) => {
+                        server.health.record_success();
+                        continue;
+                    }
+                    Err(e) => e,
+                };
+                if !server
+                    .health
+                    .record_failure(error, health_check.failure_threshold)
+                    || !health_check.restart
+                {
+                    continue;
+                }
+                if let Err(e) = server.restart("health check failed").await {
+                    error!(server = %server.server_key, error = %e, "Failed to restart unhealthy MCP server");
+                }
+            }
+        });
+    }
+
+    // keep_alive が設定されていれば、アイドル状態の子プロセスに ping を送る監視タスクを起動する。
+    // failure_threshold 回連続して応答がなければ unhealthy とし、restart の方針と回数の上限が許せば再起動する
+    pub fn spawn_keep_alive(self: &Arc<Self>) {
+        for member in &self.members {
+            member.spawn_keep_alive();
+        }
+        let Some(keep_alive) = self.config().keep_alive.clone() else {
+            return;
+        };
+        info!(
+            server = %self.server_key,
+            interval_secs = keep_alive.interval().as_secs(),
+            failure_threshold = keep_alive.failure_threshold,
+            "Keep-alive ping enabled"
+        );
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(keep_alive.interval());
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                // レジストリで更新された場合は最新の設定で確認する
+                let Some(keep_alive) = server.config().keep_alive.clone() else {
+                    continue;
+                };
+                // 直近にリクエストを受け付けていれば送らない
+                if server.idle_for() < keep_alive.interval() {
+                    continue;
+                }
+                // 停止中 (lazy で未起動・アイドル停止・管理APIでの停止) は確認しない
+                let Some(process) = server.process.lock().await.clone() else {
+                    server.health.reset();
+                    continue;
+                };
+                // 終了したプロセスは supervise やリクエスト時の再起動に任せる
+                if process.has_exited() {
+                    continue;
+                }
+                // ハングした子プロセスを検出するためのものなので、同時実行枠は待たずに送る
+                let error = match process.ping(keep_alive.timeout()).await {
+                    Ok(()) => {
+                        server.health.record_success();
+                        continue;
+                    }
+                    Err(e) => format!("Keep-alive ping failed: {}", e),
+                };
+                // 再起動を試みるのは unhealthy になったときの1回だけ (restart: never では応答が戻るまで unhealthy のまま)
+                let was_unhealthy = server.health.is_unhealthy();
+                if !server
+                    .health
+                    .record_failure(error, keep_alive.failure_threshold)
+                    || was_unhealthy
+                {
+                    continue;
+                }
+                let restart_policy = server.config().restart_policy.clone();
+                if restart_policy.restart == Some(RestartMode::Never) {
+                    warn!(server = %server.server_key, "MCP process is not answering keep-alive pings, not restarting due to restart policy");
+                    continue;
+                }
+                // 終了状態の分からない失敗として回数の上限を確認する
+                if !server.restart_budget.try_restart(&restart_policy, None) {
+                    continue;
+                }
+                warn!(server = %server.server_key, "MCP process is not answering keep-alive pings, restarting");
+                if let Err(e) = server.restart("keep-alive ping failed").await {
+                    error!(server = %server.server_key, error = %e, "Failed to restart hung MCP server");
+                }
+            }
+        });
+    }
+
+    // health_check の request を送る、または command を実行する
+    async fn probe(&self, process: &McpServerProcess) -> Result<(), String> {
+        let config = self.config();
+        let Some(health_check) = &config.health_check else {
+            return Ok(());
+        };
+        if process.has_exited() {
+            return Err("MCP process has exited".to_string());
+        }
+        if let Some(command) = &health_check.command {
+            return health_check::run_command(&self.server_key, command, &config).await;
+        }
+        let Some(request) = &health_check.request else {
+            return Ok(());
+        };
+        // 処理中のリクエストと同じ同時実行枠を使う
+        let _permit = self
+            .concurrency
+            .acquire()
+            .await
+            .map_err(|e| format!("MCP server '{}' is closing: {}", self.server_key, e))?;
+        let result = process
+            .probe_call(
+                &request.method,
+                request.params.clone(),
+                health_check.timeout(),
+            )
+            .await?;
+        match &health_check.expect {
+            Some(expected) if !health_check::matches_expected(&result, expected) => Err(format!(
+                "Unexpected health check response to '{}': {}",
+                request.method, result
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // /readyz 用: 稼働中の子プロセス (集約サーバーはメンバーも) に ping を送り、応答までの時間を返す。
+    // 停止中は None (lazy などで起動していないサーバーを ping のために起動しない)。
+    // 子プロセスが止まっていることを検出するためのものなので、同時実行枠は待たずに送る
+    pub async fn ping(&self, wait: Duration) -> Option<Result<Duration, String>> {
+        let process = self.process.lock().await.clone()?;
+        let started_at = Instant::now();
+        if process.has_exited() {
+            return Some(Err("MCP process has exited".to_string()));
+        }
+        if let Err(e) = process.ping(wait).await {
+            return Some(Err(e));
+        }
+        for member in &self.members {
+            if let Some(Err(e)) = Box::pin(member.ping(wait)).await {
+                return Some(Err(format!("member '{}': {}", member.server_key, e)));
+            }
+        }
+        Some(Ok(started_at.elapsed()))
+    }
+
+    // /readyz 用: unhealthy でなく、稼働中または次のリクエストで起動できる
+    // (再起動などでプロセスを起動・初期化している間は準備中とする)
+    pub async fn is_ready(&self) -> bool {
+        !self.is_starting()
+            && !self.health.is_unhealthy()
+            && (self.is_running().await || self.spawns_on_demand())
+    }
+
+    pub fn is_starting(&self) -> bool {
+        self.starting.load(Ordering::SeqCst)
+    }
+
+    // 子プロセスを起動し、セットアップ開始・失敗をイベントとして通知する
+    async fn spawn(&self) -> Result<McpServerProcess, String> {
+        self.events
+            .emit(&self.server_key, LifecycleEventKind::SetupStarted);
+        let result = self.spawn_and_initialize().await;
+        if let Err(e) = &result {
+            self.events.emit(
+                &self.server_key,
+                LifecycleEventKind::SetupFailed { error: e.clone() },
+            );
+        }
+        result
+    }
+
+    async fn spawn_and_initialize(&self) -> Result<McpServerProcess, String> {
+        let config = self.config();
+        // MCP_REPLAY_FILE がある場合は種類に関係なく、記録した応答を返す仮想サーバーにする
+        let mut process = match (recording::replay_file(), config.server_type) {
+            (Some(path), _) => self.start_replay(&path).await?,
+            (None, ServerType::Stdio) => {
+                integrity::verify(&self.server_key, &config).await?;
+                hooks::run_before_spawn(&self.server_key, &config).await?;
+                let process = match config.language {
+                    Some(Language::Wasm) => {
+                        spawn_wasm_process(
+                            &self.server_key,
+                            &config,
+                            Arc::clone(&self.stats),
+                            Arc::clone(&self.notifications),
+                            Arc::clone(&self.stderr),
+                            Arc::clone(&self.circuit_breaker),
+                            self.events.clone(),
+                        )
+                        .await?
+                    }
+                    _ => spawn_mcp_process(
+                        &self.server_key,
+                        &config,
+                        Arc::clone(&self.stats),
+                        Arc::clone(&self.notifications),
+                        Arc::clone(&self.stderr),
+                        Arc::clone(&self.circuit_breaker),
+                        self.events.clone(),
+                    )?,
+                };
+                // ハンドシェイクをしない場合も、起動直後のリクエストが失敗しないよう応答を待つ
+                if !config.auto_initialize {
+                    process.wait_until_responsive().await?;
+                }
+                process
+            }
+            (None, ServerType::Remote) => self.connect_remote().await?,
+            (None, ServerType::Aggregate) => self.start_aggregate().await?,
+            (None, ServerType::Mock) => self.start_mock(),
+        };
+        if config.auto_initialize {
+            process.initialize_result = Some(process.initialize().await?);
+        }
+        Ok(process)
+    }
+
+    // 子プロセスの代わりにリモートの MCP サーバーに HTTP で接続する
+    async fn connect_remote(&self) -> Result<McpServerProcess, String> {
+        let config = self.config();
+        info!(
+            server = %self.server_key,
+            url = ?config.remote.url,
+            transport = ?config.remote.transport,
+            header_names = ?config.remote.headers.keys().collect::<Vec<_>>(),
+            "Connecting to remote MCP server"
+        );
+        let (inbound_tx, inbound_rx) = mpsc::channel(VIRTUAL_INBOUND_BUFFER);
+        let client = RemoteClient::connect(
+            &self.server_key,
+            &config.remote,
+            config.max_response_bytes,
+            config.response_timeout(),
+            inbound_tx,
+        )
+        .await?;
+        Ok(self.start_virtual_process(MessageSink::Remote(Arc::new(client)), inbound_rx))
+    }
+
+    // メンバーのサーバーを起動し、それらをまとめる仮想サーバーを開始する
+    async fn start_aggregate(&self) -> Result<McpServerProcess, String> {
+        if self.members.is_empty() {
+            return Err(format!(
+                "Aggregate server '{}' has no servers",
+                self.server_key
+            ));
+        }
+        let setup_concurrency = self.config().aggregate.setup_concurrency.max(1);
+        info!(
+            server = %self.server_key,
+            members = ?self.config().aggregate.servers,
+            setup_concurrency,
+            "Starting aggregate MCP server"
+        );
+        // メンバーのセットアップは setup_concurrency 個ずつ並行して行う。
+        // 起動に失敗したメンバーがあっても、他のメンバーのツールは提供する
+        let total = self.members.len();
+        let started_at = Instant::now();
+        let mut results = stream::iter(self.members.clone())
+            .map(|member| async move {
+                let member_started_at = Instant::now();
+                // resume → start → spawn と同じ関数に戻るため Box で包む
+                let result = Box::pin(member.resume()).await;
+                (member, result, member_started_at.elapsed())
+            })
+            .buffer_unordered(setup_concurrency);
+        let mut completed = 0;
+        let mut failed = 0;
+        while let Some((member, result, elapsed)) = results.next().await {
+            completed += 1;
+            match result {
+                Ok(()) => info!(
+                    server = %self.server_key,
+                    member = %member.server_key,
+                    progress = %format!("{}/{}", completed, total),
+                    elapsed_ms = elapsed.as_millis() as u64,
+                    "Aggregate member ready"
+                ),
+                Err(e) => {
+                    failed += 1;
+                    warn!(
+                        server = %self.server_key,
+                        member = %member.server_key,
+                        progress = %format!("{}/{}", completed, total),
+                        elapsed_ms = elapsed.as_millis() as u64,
+                        error = %e,
+                        "Failed to start aggregate member"
+                    );
+                }
+            }
+        }
+        info!(
+            server = %self.server_key,
+            members = total,
+            failed,
+            elapsed_ms = started_at.elapsed().as_millis() as u64,
+            "Aggregate member setup finished"
+        );
+        let (inbound_tx, inbound_rx) = mpsc::channel(VIRTUAL_INBOUND_BUFFER);
+        let aggregator = Aggregator::new(
+            &self.server_key,
+            &self.config().aggregate.separator,
+            self.members.clone(),
+            inbound_tx,
+        );
+        Ok(self.start_virtual_process(MessageSink::Aggregate(Arc::new(aggregator)), inbound_rx))
+    }
+
+    async fn start_replay(&self, path: &str) -> Result<McpServerProcess, String> {
+        info!(server = %self.server_key, path = %path, "Replaying recorded MCP server traffic");
+        let (inbound_tx, inbound_rx) = mpsc::channel(VIRTUAL_INBOUND_BUFFER);
+        let replayer = Replayer::load(&self.server_key, path, inbound_tx).await?;
+        Ok(self.start_virtual_process(MessageSink::Replay(Arc::new(replayer)), inbound_rx))
+    }
+
+    fn start_mock(&self) -> McpServerProcess {
+        info!(server = %self.server_key, "Starting built-in mock MCP server");
+        let (inbound_tx, inbound_rx) = mpsc::channel(VIRTUAL_INBOUND_BUFFER);
+        let mock = MockServer::new(&self.server_key, inbound_tx);
+        self.start_virtual_process(MessageSink::Mock(Arc::new(mock)), inbound_rx)
+    }
+
+    // 子プロセス以外のサーバー (リモート・集約・モック・再生) で、受信したメッセージを振り分けるタスクを起動する。
+    // 受信したメッセージは子プロセスの標準出力と同じく振り分けるため、以降の処理
+    // (応答待ち・タイムアウト・統計・サーキットブレーカー) は stdio のサーバーと共通になる
+    fn start_virtual_process(
+        &self,
+        sink: MessageSink,
+        mut inbound_rx: mpsc::Receiver<String>,
+    ) -> McpServerProcess {
+        let config = self.config();
+        self.events.emit(
+            &self.server_key,
+            LifecycleEventKind::ChildSpawned { pid: None },
+        );
+        let writer = Arc::new(MessageWriter {
+            server_key: self.server_key.clone(),
+            framing: config.framing,
+            line_ending: config.quirks.line_ending(),
+            sink,
+        });
+        let pending = Arc::new(PendingTable::default());
+        let callbacks = Arc::new(CallbackHandler::new(config.callback.clone()));
+        let router = MessageRouter {
+            server_key: self.server_key.clone(),
+            quirks: config.quirks.clone(),
+            pending: Arc::clone(&pending),
+            notifications: Arc::clone(&self.notifications),
+            callbacks: Arc::clone(&callbacks),
+            writer: Arc::clone(&writer),
+        };
+
+        // 停止要求か接続断で終了する
+        let (kill_tx, kill_rx) = oneshot::channel::<()>();
+        let (exited_tx, exited) = watch::channel(None);
+        let server_key = self.server_key.clone();
+        let stats = Arc::clone(&self.stats);
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            let expected = tokio::select! {
+                _ = kill_rx => true,
+                _ = router.writer.sink.closed() => false,
+                _ = async {
+                    while let Some(message) = inbound_rx.recv().await {
+                        router.handle_message(&message);
+                    }
+                } => false,
+            };
+            router.writer.sink.close().await;
+            router.pending.close(CONNECTION_CLOSED_ERROR.to_string());
+            if expected {
+                info!(server = %server_key, "MCP server connection closed");
+            } else {
+                warn!(server = %server_key, "Lost connection to MCP server");
+            }
+            stats.record_exit(None);
+            events.emit(
+                &server_key,
+                LifecycleEventKind::ChildExited {
+                    pid: None,
+                    exit_code: None,
+                    expected,
+                },
+            );
+            let _ = exited_tx.send(Some(ProcessExit {
+                exit_code: None,
+                expected,
+            }));
+        });
+
+        McpServerProcess {
+            kill_tx: std::sync::Mutex::new(Some(kill_tx)),
+            exited,
+            pid: None,
+            initialize_result: None,
+            server_key: self.server_key.clone(),
+            tool_policy: config.tool_policy.clone(),
+            stats: Arc::clone(&self.stats),
+            circuit_breaker: Arc::clone(&self.circuit_breaker),
+            callbacks,
+            writer,
+            pending,
+            response_timeout: config.response_timeout(),
+            init_wait: config.init_wait(),
+        }
+    }
+
+    // キャッシュ済みの initialize 結果 (未初期化または auto_initialize 無効時は None)
+    pub fn initialize_result(&self) -> Option<serde_json::Value> {
+        self.initialize_result
+            .lock()
+            .ok()
+            .and_then(|result| result.clone())
+    }
+
+    // initialize で合意したプロトコルバージョンが JSON-RPC バッチに対応しているか
+    pub fn supports_jsonrpc_batch(&self) -> bool {
+        self.initialize_result()
+            .as_ref()
+            .and_then(|result| result.get("protocolVersion"))
+            .and_then(serde_json::Value::as_str)
+            .is_some_and(|version| JSONRPC_BATCH_PROTOCOL_VERSIONS.contains(&version))
+    }
+
+    // 予備プロセスがあれば昇格させ、なければ新たに起動する。
+    // 起動したプロセスを稼働中として統計に記録し、予備プロセスを補充する
+    async fn activate(self: &Arc<Self>) -> Result<Arc<McpServerProcess>, String> {
+        let standby = self
+            .standby
+            .lock()
+            .await
+            .take()
+            .filter(|standby| !standby.has_exited());
+        let process = match standby {
+            Some(standby) => {
+                info!(server = %self.server_key, pid = ?standby.pid, "Promoting warm standby MCP process");
+                standby
+            }
+            None => {
+                self.starting.store(true, Ordering::SeqCst);
+                let spawned = self.spawn().await;
+                self.starting.store(false, Ordering::SeqCst);
+                spawned?
+            }
+        };
+        self.stats.record_spawn(process.pid);
+        self.health.reset();
+        if let Ok(mut cached) = self.initialize_result.lock() {
+            *cached = process.initialize_result.clone();
+        }
+        if let Ok(mut cache) = self.list_cache.lock() {
+            cache.clear();
+        }
+        self.response_cache.clear();
+        self.watch_list_changes();
+        self.refill_standby();
+        let process = Arc::new(process);
+        self.supervise(&process);
+        Ok(process)
+    }
+
+    // restart が always / on-failure の場合、予期せず終了した子プロセスを即座に起動し直す
+    fn supervise(self: &Arc<Self>, process: &Arc<McpServerProcess>) {
+        if !self.config().restart_policy.supervises() {
+            return;
+        }
+        let server = Arc::clone(self);
+        let supervised = Arc::clone(process);
+        tokio::spawn(async move {
+            let mut exited = supervised.exited.clone();
+            let exit = match exited.wait_for(Option::is_some).await {
+                Ok(exit) => *exit,
+                Err(_) => return,
+            };
+            // stop / restart / アイドル停止による終了は対象外
+            let Some(exit) = exit.filter(|exit| !exit.expected) else {
+                return;
+            };
+            let mut process = server.process.lock().await;
+            // 別の経路 (リクエスト時の置き換えなど) で置き換え済み
+            if !process
+                .as_ref()
+                .is_some_and(|current| Arc::ptr_eq(current, &supervised))
+            {
+                return;
+            }
+            loop {
+                // 上限に達した場合は終了したプロセスを残し、リクエストを失敗させる
+                if !server
+                    .restart_budget
+                    .try_restart(&server.config().restart_policy, Some(&exit))
+                {
+                    return;
+                }
+                server.events.emit(
+                    &server.server_key,
+                    LifecycleEventKind::RestartScheduled {
+                        reason: "crash".to_string(),
+                    },
+                );
+                if let Some(crashed) = process.take() {
+                    crashed.shutdown().await;
+                }
+                server.stats.record_restart();
+                match server.activate().await {
+                    Ok(respawned) => {
+                        info!(server = %server.server_key, exit_code = ?exit.exit_code, "Restarted MCP process after unexpected exit");
+                        *process = Some(respawned);
+                        return;
+                    }
+                    Err(e) => {
+                        error!(server = %server.server_key, error = %e, "Failed to restart MCP process")
+                    }
+                }
+                drop(process);
+                tokio::time::sleep(RESTART_RETRY_DELAY).await;
+                process = server.process.lock().await;
+                // 待機中に管理APIやリクエストで起動・停止された
+                if process.is_some() || server.stopped_by_admin.load(Ordering::SeqCst) {
+                    return;
+                }
+            }
+        });
+    }
+
+    // 終了した子プロセスを自動で起動し直してよいか (restart と回数の上限を確認する)
+    fn allows_restart(&self, crashed: &McpServerProcess) -> bool {
+        self.restart_budget
+            .try_restart(&self.config().restart_policy, crashed.exit().as_ref())
+    }
+
+    // 予備プロセスをバックグラウンドで起動する (warm_standby 設定時のみ)
+    fn refill_standby(self: &Arc<Self>) {
+        if !self.config().warm_standby {
+            return;
+        }
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut standby = server.standby.lock().await;
+            if standby
+                .as_ref()
+                .is_some_and(|standby| !standby.has_exited())
+            {
+                return;
+            }
+            match server.spawn().await {
+                Ok(spawned) => {
+                    info!(server = %server.server_key, pid = ?spawned.pid, "Warm standby MCP process ready");
+                    *standby = Some(spawned);
+                }
+                Err(e) => {
+                    warn!(server = %server.server_key, error = %e, "Failed to spawn warm standby")
+                }
+            }
+        });
+    }
+
+    async fn stop_standby(&self) {
+        if let Some(standby) = self.standby.lock().await.take() {
+            standby.shutdown().await;
+        }
+    }
+
+    fn max_concurrent(&self) -> u32 {
+        self.config().max_concurrent_requests.max(1) as u32
+    }
+
+    // 同時実行枠を確保して子プロセスを取得する
+    // 枠が埋まっている間は待機し、待機数が max_queued_requests に達していれば即座にエラーを返す
+    pub async fn acquire(self: &Arc<Self>) -> Result<ProcessLease<'_>, String> {
+        let started_at = Instant::now();
+        let permit = match self.concurrency.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                let queued = self.queued.fetch_add(1, Ordering::SeqCst);
+                let _queued = QueuedGuard(&self.queued);
+                if self
+                    .config()
+                    .max_queued_requests
+                    .is_some_and(|max_queued| queued >= max_queued)
+                {
+                    warn!(server = %self.server_key, queued, "Request queue is full, rejecting request");
+                    return Err(format!(
+                        "MCP server '{}' has {}",
+                        self.server_key, QUEUE_FULL_ERROR
+                    ));
+                }
+                debug!(server = %self.server_key, queued = queued + 1, "Waiting for a free request slot");
+                self.concurrency
+                    .acquire()
+                    .await
+                    .map_err(|e| format!("MCP server '{}' is closing: {}", self.server_key, e))?
+            }
+        };
+        match self.current_process().await {
+            Some(process) => {
+                let queue_wait = started_at.elapsed();
+                self.stats.record_queue_wait(queue_wait);
+                Ok(ProcessLease {
+                    process,
+                    _permit: permit,
+                    stats: &self.stats,
+                    started_at,
+                    used: AtomicBool::new(false),
+                    queue_wait,
+                })
+            }
+            None => Err(format!("MCP server '{}' is not running", self.server_key)),
+        }
+    }
+
+    // 稼働中の子プロセス (停止中は None)
+    // lazy / idle_timeout_secs 設定のサーバーは未起動ならここで起動する。起動中はロックを保持するため、
+    // 同時に届いたリクエストは起動完了まで待機する
+    async fn current_process(self: &Arc<Self>) -> Option<Arc<McpServerProcess>> {
+        let mut process = self.process.lock().await;
+        self.touch();
+        // 予期せず終了した子プロセスは破棄し、再起動の対象にする
+        if self.spawns_on_demand()
+            && process
+                .as_ref()
+                .is_some_and(|running| running.has_exited() && self.allows_restart(running))
+        {
+            warn!(server = %self.server_key, "Active MCP process has exited, replacing it");
+            process.take();
+            self.stats.record_restart();
+        }
+        if process.is_none() && self.spawns_on_demand() {
+            info!(server = %self.server_key, "Spawning MCP server on demand");
+            match self.activate().await {
+                Ok(spawned) => *process = Some(spawned),
+                Err(e) => error!(server = %self.server_key, error = %e, "On-demand spawn failed"),
+            }
+        }
+        process.clone()
+    }
+
+    // */list 系メソッドをページングしながら全件取得し、キャッシュする
+    // (method は "tools/list" など、field は結果の配列名 "tools" など)
+    pub async fn list_all(
+        self: &Arc<Self>,
+        method: &'static str,
+        field: &str,
+    ) -> Result<Vec<serde_json::Value>, String> {
+        if let Some(items) = self
+            .list_cache
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(method).cloned())
+        {
+            return Ok(items);
+        }
+
+        self.check_circuit()?;
+        let running = self.acquire().await?;
+        let mut items = Vec::new();
+        let mut cursor: Option<serde_json::Value> = None;
+        for _ in 0..LIST_MAX_PAGES {
+            let mut params = serde_json::Map::new();
+            if let Some(cursor) = cursor.take() {
+                params.insert("cursor".to_string(), cursor);
+            }
+            let mut result = running
+                .call(method, serde_json::Value::Object(params))
+                .await?;
+            if let Some(serde_json::Value::Array(page_items)) =
+                result.get_mut(field).map(serde_json::Value::take)
+            {
+                items.extend(page_items);
+            }
+            cursor = result
+                .get_mut("nextCursor")
+                .map(serde_json::Value::take)
+                .filter(|cursor| !cursor.is_null());
+            if cursor.is_none() {
+                break;
+            }
+        }
+        if cursor.is_some() {
+            warn!(
+                server = %self.server_key,
+                method,
+                max_pages = LIST_MAX_PAGES,
+                "Pagination limit reached, returning partial list"
+            );
+        }
+
+        debug!(server = %self.server_key, method, count = items.len(), "Cached list result");
+        if let Ok(mut cache) = self.list_cache.lock() {
+            cache.insert(method, items.clone());
+        }
+        Ok(items)
+    }
+
+    // notifications/*/list_changed を受け取ったら一覧のキャッシュを破棄する (最初の起動時に開始する)。
+    // 集約サーバーはメンバーの一覧をまとめて返すため、メンバーの通知も受け取る
+    fn watch_list_changes(self: &Arc<Self>) {
+        if self.watching_list_changes.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let buffers = std::iter::once(&self.notifications)
+            .chain(self.members.iter().map(|member| &member.notifications));
+        for buffer in buffers {
+            let mut receiver = buffer.subscribe();
+            let server = Arc::downgrade(self);
+            tokio::spawn(async move {
+                loop {
+                    let changed: Vec<_> = match receiver.recv().await {
+                        Ok(notification) => {
+                            let method = notification.get("method").and_then(|m| m.as_str());
+                            LIST_CHANGED_NOTIFICATIONS
+                                .iter()
+                                .filter(|(name, _, _)| Some(*name) == method)
+                                .collect()
+                        }
+                        // 取りこぼした通知に listChanged があったかもしれないため、すべて破棄する
+                        Err(broadcast::error::RecvError::Lagged(_)) => {
+                            LIST_CHANGED_NOTIFICATIONS.iter().collect()
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return,
+                    };
+                    // サーバーが破棄されたら終了する
+                    let Some(server) = server.upgrade() else {
+                        return;
+                    };
+                    for (_, method, field) in changed {
+                        server.list_changed(method, field);
+                    }
+                }
+            });
+        }
+    }
+
+    // 一覧のキャッシュを破棄し、response_cache の prewarm 設定時は取得し直す
+    // (tools/list が変わった場合は、キャッシュしている tools/call の結果も破棄する)
+    fn list_changed(self: &Arc<Self>, method: &'static str, field: &'static str) {
+        info!(server = %self.server_key, method, "MCP server list changed, invalidating cached results");
+        if let Ok(mut cache) = self.list_cache.lock() {
+            cache.remove(method);
+        }
+        match method {
+            "tools/list" => self.response_cache.invalidate(&[method, "tools/call"]),
+            _ => self.response_cache.invalidate(&[method]),
+        }
+        let config = self.config().response_cache.clone();
+        if !config.prewarm {
+            return;
+        }
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            if let Err(e) = server.list_all(method, field).await {
+                warn!(server = %server.server_key, method, error = %e, "Failed to prewarm list cache");
+                return;
+            }
+            if config.ttl_secs == 0 {
+                return;
+            }
+            let params = serde_json::json!({});
+            match server.call(method, params.clone()).await {
+                Ok(result) => {
+                    server
+                        .response_cache
+                        .store_result(&config, method, params, result);
+                    debug!(server = %server.server_key, method, "Prewarmed response cache");
+                }
+                Err(e) => {
+                    warn!(server = %server.server_key, method, error = %e, "Failed to prewarm response cache")
+                }
+            }
+        });
+    }
+
+    // キャッシュ済みの tools/list からツールの inputSchema を取り出す (取得できなければ None)
+    pub async fn tool_input_schema(self: &Arc<Self>, tool_name: &str) -> Option<serde_json::Value> {
+        self.list_all("tools/list", "tools")
+            .await
+            .ok()?
+            .into_iter()
+            .find(|tool| tool.get("name").and_then(|name| name.as_str()) == Some(tool_name))?
+            .get_mut("inputSchema")
+            .map(serde_json::Value::take)
+    }
+
+    // 任意の JSON-RPC メソッドを呼び出し、result を返す
+    pub async fn call(
+        self: &Arc<Self>,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        self.check_circuit()?;
+        self.acquire().await?.call(method, params).await
+    }
+
+    // call と同じだが、result.content の要素を子プロセスから読み取り次第 items に送る
+    // (送信済みの要素は返り値の content から取り除かれる。ストリーミングできない場合はすべて返り値に残る)。
+    // progress を指定すると progressToken を付けて送り、notifications/progress の params をそこに送る
+    pub async fn call_streaming(
+        self: &Arc<Self>,
+        method: &str,
+        params: serde_json::Value,
+        items: Option<mpsc::Sender<String>>,
+        progress: Option<mpsc::Sender<serde_json::Value>>,
+    ) -> Result<serde_json::Value, String> {
+        self.check_circuit()?;
+        self.acquire()
+            .await?
+            .call_with_stream(method, params, items, progress)
+            .await
+    }
+
+    // サーキットブレーカーが開いていればエラーを返す
+    pub fn check_circuit(&self) -> Result<(), String> {
+        self.circuit_breaker.check().map_err(|retry_after_secs| {
+            format!(
+                "MCP server '{}' is failing, {} (retry after {}s)",
+                self.server_key, CIRCUIT_OPEN_ERROR, retry_after_secs
+            )
+        })
+    }
+
+    pub async fn is_running(&self) -> bool {
+        self.process.lock().await.is_some()
+    }
+
+    // /stats 用の統計。running は PID ではなく is_running() で判定する
+    pub async fn stats_snapshot(&self) -> StatsSnapshot {
+        let mut snapshot = self.stats.get_stats();
+        snapshot.running = self.is_running().await;
+        snapshot
+    }
+
+    pub async fn start(self: &Arc<Self>) -> Result<(), String> {
+        let mut process = self.process.lock().await;
+        if process.is_some() {
+            return Err(format!(
+                "MCP server '{}' is already running",
+                self.server_key
+            ));
+        }
+        *process = Some(self.activate().await?);
+        self.stopped_by_admin.store(false, Ordering::SeqCst);
+        self.circuit_breaker.record_success();
+        Ok(())
+    }
+
+    // 集約サーバーの起動に合わせて停止状態を解除する (lazy でなければ起動する)
+    pub async fn resume(self: &Arc<Self>) -> Result<(), String> {
+        self.stopped_by_admin.store(false, Ordering::SeqCst);
+        if self.config().lazy || self.is_running().await {
+            return Ok(());
+        }
+        self.start().await
+    }
+
+    pub async fn stop(&self) -> Result<(), String> {
+        // 処理中のリクエストが終わるのを待ってから停止する
+        let _drained = self.drain().await?;
+        let mut process = self.process.lock().await;
+        let Some(running) = process.take() else {
+            return Err(format!("MCP server '{}' is not running", self.server_key));
+        };
+        running.shutdown().await;
+        self.stop_standby().await;
+        self.stopped_by_admin.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    // ブリッジの終了時に処理中のリクエストを待たずに子プロセス (と子孫) を終了させる
+    pub async fn shutdown(&self) {
+        if let Some(running) = self.process.lock().await.take() {
+            running.shutdown().await;
+        }
+        self.stop_standby().await;
+    }
+
+    pub async fn restart(self: &Arc<Self>, reason: &str) -> Result<(), String> {
+        self.events.emit(
+            &self.server_key,
+            LifecycleEventKind::RestartScheduled {
+                reason: reason.to_string(),
+            },
+        );
+        let _drained = self.drain().await?;
+        let mut process = self.process.lock().await;
+        if let Some(running) = process.take() {
+            running.shutdown().await;
+        }
+        *process = Some(self.activate().await?);
+        self.stopped_by_admin.store(false, Ordering::SeqCst);
+        self.circuit_breaker.record_success();
+        self.stats.record_restart();
+        Ok(())
+    }
+
+    // 同時実行枠をすべて確保し、処理中のリクエストがない状態にする
+    async fn drain(&self) -> Result<SemaphorePermit<'_>, String> {
+        self.concurrency
+            .acquire_many(self.max_concurrent())
+            .await
+            .map_err(|e| format!("MCP server '{}' is closing: {}", self.server_key, e))
+    }
+
+    // 子プロセスの異常終了で失敗したこのリクエストを再送してよいか
+    pub fn retries_on_crash(&self, command: &str) -> bool {
+        if !self.config().retry_on_crash {
+            return false;
+        }
+        match serde_json::from_str::<serde_json::Value>(command) {
+            Ok(serde_json::Value::Array(batch)) => batch
+                .iter()
+                .all(|request| self.is_idempotent_request(request)),
+            Ok(request) => self.is_idempotent_request(&request),
+            Err(_) => false,
+        }
+    }
+
+    fn is_idempotent_request(&self, request: &serde_json::Value) -> bool {
+        let method = request
+            .get("method")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or_default();
+        if method != "tools/call" {
+            return IDEMPOTENT_METHODS.contains(&method);
+        }
+        // tools/call は取得済みのツール定義で readOnlyHint / idempotentHint が true のものだけ
+        let Some(tool_name) = request
+            .get("params")
+            .and_then(|params| params.get("name"))
+            .and_then(serde_json::Value::as_str)
+        else {
+            return false;
+        };
+        let cache = match self.list_cache.lock() {
+            Ok(cache) => cache,
+            Err(_) => return false,
+        };
+        cache
+            .get("tools/list")
+            .and_then(|tools| {
+                tools.iter().find(|tool| {
+                    tool.get("name").and_then(serde_json::Value::as_str) == Some(tool_name)
+                })
+            })
+            .and_then(|tool| tool.get("annotations"))
+            .is_some_and(|annotations| {
+                ["readOnlyHint", "idempotentHint"].iter().any(|hint| {
+                    annotations.get(*hint).and_then(serde_json::Value::as_bool) == Some(true)
+                })
+            })
+    }
+
+    // クエリ中に終了した子プロセスを置き換える (retry_on_crash 用)
+    // 同時に失敗した別のリクエストが置き換え済みであれば、その子プロセスを使う
+    pub async fn replace_crashed(
+        self: &Arc<Self>,
+        crashed: &McpServerProcess,
+    ) -> Result<Arc<McpServerProcess>, String> {
+        if self.stopped_by_admin.load(Ordering::SeqCst) {
+            return Err(format!("MCP server '{}' is not running", self.server_key));
+        }
+        let mut process = self.process.lock().await;
+        match process.as_ref() {
+            Some(current) if !std::ptr::eq(Arc::as_ptr(current), crashed) => {
+                return Ok(Arc::clone(current));
+            }
+            _ => {}
+        }
+        if !self.allows_restart(crashed) {
+            return Err(format!(
+                "MCP server '{}' exited and was not restarted",
+                self.server_key
+            ));
+        }
+        self.events.emit(
+            &self.server_key,
+            LifecycleEventKind::RestartScheduled {
+                reason: "crash".to_string(),
+            },
+        );
+        if let Some(crashed) = process.take() {
+            crashed.shutdown().await;
+        }
+        self.stats.record_restart();
+        let respawned = self.activate().await?;
+        *process = Some(Arc::clone(&respawned));
+        Ok(respawned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lsp(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[tokio::test]
+    async fn read_line_bounded_reads_one_line_at_a_time() {
+        let mut reader: &[u8] = b"first\nsecond\ntail";
+        let mut line = Vec::new();
+        assert_eq!(
+            read_line_bounded(&mut reader, &mut line, 1024, None)
+                .await
+                .unwrap(),
+            (6, false)
+        );
+        assert_eq!(line, b"first\n");
+        line.clear();
+        read_line_bounded(&mut reader, &mut line, 1024, None)
+            .await
+            .unwrap();
+        assert_eq!(line, b"second\n");
+        // 改行の無い最後の行は EOF までを1行とする
+        line.clear();
+        assert_eq!(
+            read_line_bounded(&mut reader, &mut line, 1024, None)
+                .await
+                .unwrap(),
+            (4, false)
+        );
+        assert_eq!(line, b"tail");
+        line.clear();
+        assert_eq!(
+            read_line_bounded(&mut reader, &mut line, 1024, None)
+                .await
+                .unwrap(),
+            (0, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn read_line_bounded_discards_the_overflow() {
+        let mut reader: &[u8] = b"abcdefgh\nnext\n";
+        let mut line = Vec::new();
+        let mut tracker = JsonDepthTracker::default();
+        assert_eq!(
+            read_line_bounded(&mut reader, &mut line, 4, Some(&mut tracker))
+                .await
+                .unwrap(),
+            (9, true)
+        );
+        assert_eq!(line, b"abcd");
+        // 読み捨てた後は次の行から読める
+        line.clear();
+        assert_eq!(
+            read_line_bounded(&mut reader, &mut line, 4, None)
+                .await
+                .unwrap(),
+            (5, true)
+        );
+        assert_eq!(line, b"next");
+    }
+
+    #[test]
+    fn classify_stdout_line_requires_jsonrpc_2_0() {
+        assert!(matches!(
+            classify_stdout_line(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#, false),
+            StdoutLine::Response(Some(id)) if id == 1
+        ));
+        assert!(matches!(
+            classify_stdout_line(
+                r#"{"jsonrpc":"2.0","error":{"code":-32700,"message":"Parse error"}}"#,
+                false
+            ),
+            StdoutLine::Response(None)
+        ));
+        assert!(matches!(
+            classify_stdout_line(
+                r#"{"jsonrpc":"2.0","method":"notifications/message"}"#,
+                false
+            ),
+            StdoutLine::Notification(_)
+        ));
+        assert!(matches!(
+            classify_stdout_line(
+                r#"{"jsonrpc":"2.0","id":"s1","method":"sampling/createMessage"}"#,
+                false
+            ),
+            StdoutLine::ServerRequest(_)
+        ));
+        assert!(matches!(
+            classify_stdout_line(r#"[{"jsonrpc":"2.0","id":1,"result":{}}]"#, false),
+            StdoutLine::Batch
+        ));
+        // 構造化ログの error / result キーは応答とみなさない
+        for line in [
+            r#"{"level":"error","error":"connection refused"}"#,
+            r#"{"result":"ok","id":1}"#,
+            r#"{"jsonrpc":"1.0","id":1,"result":{}}"#,
+            r#"{"method":"log","msg":"started"}"#,
+            r#"[{"level":"info"}]"#,
+            "[]",
+            "Server listening on stdio",
+        ] {
+            assert!(
+                matches!(classify_stdout_line(line, false), StdoutLine::Noise),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn json_logs_on_stdout_separates_json_logs_from_other_noise() {
+        for line in [
+            r#"{"level":"error","error":"connection refused"}"#,
+            r#"[{"level":"info"}]"#,
+        ] {
+            assert!(matches!(
+                classify_stdout_line(line, true),
+                StdoutLine::JsonLog
+            ));
+        }
+        // JSON 以外の行と JSON-RPC のメッセージはこれまでどおり
+        assert!(matches!(
+            classify_stdout_line("Server listening on stdio", true),
+            StdoutLine::Noise
+        ));
+        assert!(matches!(
+            classify_stdout_line(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#, true),
+            StdoutLine::Response(_)
+        ));
+    }
+
+    #[test]
+    fn is_incomplete_json_detects_pretty_printed_messages() {
+        assert!(is_incomplete_json("{\n"));
+        assert!(is_incomplete_json("  {\"jsonrpc\": \"2.0\","));
+        assert!(is_incomplete_json("[{\"id\": 1},"));
+        assert!(!is_incomplete_json(
+            "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{}}"
+        ));
+        // JSON 以外のログ行や、壊れた JSON は続きの行を待たない
+        assert!(!is_incomplete_json("Server started on stdio"));
+        assert!(!is_incomplete_json("{\"a\":1}}"));
+        assert!(!is_incomplete_json(""));
+    }
+
+    #[tokio::test]
+    async fn read_lsp_message_parses_content_length_frames() {
+        let input = format!(
+            "\r\n{}content-length: 2\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n[]",
+            lsp(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#)
+        );
+        let mut reader = input.as_bytes();
+        assert_eq!(
+            read_lsp_message(&mut reader, 1024, "test").await.unwrap(),
+            Some(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#.to_string())
+        );
+        // ヘッダー名の大文字・小文字と Content-Type は問わない
+        assert_eq!(
+            read_lsp_message(&mut reader, 1024, "test").await.unwrap(),
+            Some("[]".to_string())
+        );
+        assert_eq!(
+            read_lsp_message(&mut reader, 1024, "test").await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn read_lsp_message_rejects_bad_and_oversized_frames() {
+        let mut reader: &[u8] = b"Content-Length: abc\r\n\r\n{}";
+        let error = read_lsp_message(&mut reader, 1024, "test")
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        // 上限を超えた本文は読み捨て、次のメッセージから読み直す
+        let input = format!("{}{}", lsp(&"x".repeat(64)), lsp("{}"));
+        let mut reader = input.as_bytes();
+        let error = read_lsp_message(&mut reader, 16, "test").await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::FileTooLarge);
+        assert_eq!(
+            read_lsp_message(&mut reader, 16, "test").await.unwrap(),
+            Some("{}".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn read_lsp_message_reports_the_id_of_an_oversized_response() {
+        let body = format!(
+            r#"{{"jsonrpc":"2.0","id":"bridge-7","result":{{"text":"{}"}}}}"#,
+            "x".repeat(256)
+        );
+        let input = lsp(&body);
+        let mut reader = input.as_bytes();
+        let error = read_lsp_message(&mut reader, 64, "test").await.unwrap_err();
+        let oversized = error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<OversizedMessage>())
+            .unwrap();
+        assert_eq!(oversized.id, Some(serde_json::json!("bridge-7")));
+        assert!(reader.is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_lsp_message_skips_overlong_header_lines() {
+        let input = format!("{}\r\n{}", "x".repeat(MAX_HEADER_BYTES * 2), lsp("{}"));
+        let mut reader = input.as_bytes();
+        assert_eq!(
+            read_lsp_message(&mut reader, 1024, "test").await.unwrap(),
+            Some("{}".to_string())
+        );
+    }
+
+    #[test]
+    fn response_id_is_read_from_the_kept_prefix() {
+        assert_eq!(
+            response_id_in(br#"{"jsonrpc":"2.0","id":3,"result":{"content":[{"type":"te"#),
+            Some(serde_json::json!(3))
+        );
+        // 入れ子の id は応答の id ではない
+        assert_eq!(
+            response_id_in(br#"{"jsonrpc":"2.0","result":{"id":3,"content":["#),
+            None
+        );
+        assert_eq!(response_id_in(b"not json"), None);
+    }
+
+    fn pending_request(table: &PendingTable, id: &str) -> ResponseReceiver {
+        let message = format!(r#"{{"jsonrpc":"2.0","id":"{}","method":"ping"}}"#, id);
+        table
+            .register(PendingResponses::from_message(&message), None)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn oversized_response_fails_only_its_request() {
+        let table = PendingTable::default();
+        let mut first = pending_request(&table, "a");
+        let mut second = pending_request(&table, "b");
+        table.fail_response(Some(&serde_json::json!("b")), "too large".to_string());
+        assert_eq!(second.try_recv().unwrap(), Err("too large".to_string()));
+        assert!(first.try_recv().is_err());
+
+        // id が分からなければ応答待ちをすべて失敗させる
+        let mut third = pending_request(&table, "c");
+        table.fail_response(None, "too large".to_string());
+        assert_eq!(first.try_recv().unwrap(), Err("too large".to_string()));
+        assert_eq!(third.try_recv().unwrap(), Err("too large".to_string()));
+    }
+
+    #[test]
+    fn bridge_ids_are_unique_and_restored() {
+        let (first, first_ids) = assign_bridge_ids(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+        let (second, second_ids) = assign_bridge_ids(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+        assert_ne!(first, second);
+        let bridge_id = |message: &str| {
+            serde_json::from_str::<serde_json::Value>(message).unwrap()["id"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        };
+        let response = format!(
+            r#"{{"jsonrpc":"2.0","id":"{}","result":{{}}}}"#,
+            bridge_id(&first)
+        );
+        let restored: serde_json::Value =
+            serde_json::from_str(&restore_original_ids(response, &first_ids)).unwrap();
+        assert_eq!(restored["id"], 1);
+        assert_eq!(second_ids.len(), 1);
+        // null の id も置き換え、応答で null に戻す
+        let (bridged, null_ids) =
+            assign_bridge_ids(r#"{"jsonrpc":"2.0","id":null,"method":"ping"}"#);
+        let response = format!(
+            r#"{{"jsonrpc":"2.0","id":"{}","result":{{}}}}"#,
+            bridge_id(&bridged)
+        );
+        let restored: serde_json::Value =
+            serde_json::from_str(&restore_original_ids(response, &null_ids)).unwrap();
+        assert!(restored["id"].is_null());
+        assert_eq!(null_ids.len(), 1);
+        // 通知は id を割り当てずにそのまま送る
+        let notification = r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#;
+        let (unchanged, ids) = assign_bridge_ids(notification);
+        assert_eq!(unchanged, notification);
+        assert!(ids.is_empty());
+    }
+}
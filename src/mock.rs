@@ -0,0 +1,224 @@
+use futures_util::future::join_all;
+use serde_json::{Value, json};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+
+// JSON-RPC の標準エラーコード
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// sleep ツールで待てる上限 (誤った値でテストが止まらないように)
+const MAX_SLEEP_MS: u64 = 60_000;
+
+const README_URI: &str = "mock://readme";
+const README_TEXT: &str = "This is the built-in mock MCP server of mcp-http-server.";
+
+// --- type: "mock" の組み込み MCP サーバー ---
+// 子プロセスを起動せずに、決まった内容のツール・リソース・プロンプトを返す。
+// Node.js などを用意せずにローカルでの開発や HTTP 層の結合テストができるようにする
+pub struct MockServer {
+    server_key: String,
+    inbound: mpsc::Sender<String>,
+}
+
+impl MockServer {
+    pub fn new(server_key: &str, inbound: mpsc::Sender<String>) -> Self {
+        MockServer {
+            server_key: server_key.to_string(),
+            inbound,
+        }
+    }
+
+    // メッセージを受け付け、応答はバックグラウンドで組み立てて inbound に送る
+    pub fn send(self: &Arc<Self>, message: &str) -> Result<(), String> {
+        let message: Value = serde_json::from_str(message)
+            .map_err(|e| format!("Invalid JSON-RPC message for mock server: {}", e))?;
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let response = match message {
+                Value::Array(batch) => {
+                    let responses: Vec<Value> =
+                        join_all(batch.iter().map(|message| server.handle(message)))
+                            .await
+                            .into_iter()
+                            .flatten()
+                            .collect();
+                    (!responses.is_empty()).then_some(Value::Array(responses))
+                }
+                message => server.handle(&message).await,
+            };
+            if let Some(response) = response {
+                let _ = server.inbound.send(response.to_string()).await;
+            }
+        });
+        Ok(())
+    }
+
+    // 1つのリクエストに対する応答 (通知と応答には何も返さない)
+    async fn handle(&self, message: &Value) -> Option<Value> {
+        let id = message.get("id").filter(|id| !id.is_null())?.clone();
+        let method = message.get("method").and_then(Value::as_str)?;
+        let params = message.get("params").cloned().unwrap_or_else(|| json!({}));
+        let result = match method {
+            "initialize" => Ok(self.initialize_result(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => call_tool(&params).await,
+            "resources/list" => Ok(json!({
+                "resources": [{
+                    "uri": README_URI,
+                    "name": "readme",
+                    "mimeType": "text/plain",
+                }],
+            })),
+            "resources/read" => read_resource(&params),
+            "prompts/list" => Ok(json!({
+                "prompts": [{
+                    "name": "greeting",
+                    "description": "Greets the given name",
+                    "arguments": [{ "name": "name", "required": true }],
+                }],
+            })),
+            "prompts/get" => get_prompt(&params),
+            _ => Err((
+                METHOD_NOT_FOUND,
+                format!(
+                    "Method '{}' is not supported by mock server '{}'",
+                    method, self.server_key
+                ),
+            )),
+        };
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        })
+    }
+
+    fn initialize_result(&self, params: &Value) -> Value {
+        let protocol_version = params
+            .get("protocolVersion")
+            .cloned()
+            .unwrap_or_else(|| json!("2025-03-26"));
+        json!({
+            "protocolVersion": protocol_version,
+            "capabilities": { "tools": {}, "resources": {}, "prompts": {} },
+            "serverInfo": {
+                "name": self.server_key,
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        })
+    }
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "echo",
+            "description": "Returns the given message",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "message": { "type": "string" } },
+                "required": ["message"],
+            },
+        },
+        {
+            "name": "add",
+            "description": "Adds two numbers",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "a": { "type": "number" }, "b": { "type": "number" } },
+                "required": ["a", "b"],
+            },
+        },
+        {
+            "name": "sleep",
+            "description": "Waits for the given milliseconds before answering",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "ms": { "type": "integer", "minimum": 0 } },
+                "required": ["ms"],
+            },
+        },
+        {
+            "name": "fail",
+            "description": "Always returns a tool error",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+    ])
+}
+
+fn text_result(text: String, is_error: bool) -> Value {
+    json!({ "content": [{ "type": "text", "text": text }], "isError": is_error })
+}
+
+async fn call_tool(params: &Value) -> Result<Value, (i64, String)> {
+    let name = params
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let arguments = params
+        .get("arguments")
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+    let invalid = |message: &str| (INVALID_PARAMS, format!("{}: {}", name, message));
+    match name {
+        "echo" => {
+            let message = arguments
+                .get("message")
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid("'message' must be a string"))?;
+            Ok(text_result(message.to_string(), false))
+        }
+        "add" => {
+            let number = |key: &str| arguments.get(key).and_then(Value::as_f64);
+            let (Some(a), Some(b)) = (number("a"), number("b")) else {
+                return Err(invalid("'a' and 'b' must be numbers"));
+            };
+            Ok(text_result((a + b).to_string(), false))
+        }
+        "sleep" => {
+            let ms = arguments
+                .get("ms")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid("'ms' must be a non-negative integer"))?
+                .min(MAX_SLEEP_MS);
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok(text_result(format!("Slept {} ms", ms), false))
+        }
+        "fail" => Ok(text_result("Mock tool failure".to_string(), true)),
+        _ => Err((INVALID_PARAMS, format!("Unknown tool '{}'", name))),
+    }
+}
+
+fn read_resource(params: &Value) -> Result<Value, (i64, String)> {
+    match params.get("uri").and_then(Value::as_str) {
+        Some(README_URI) => Ok(json!({
+            "contents": [{ "uri": README_URI, "mimeType": "text/plain", "text": README_TEXT }],
+        })),
+        uri => Err((
+            INVALID_PARAMS,
+            format!("Unknown resource '{}'", uri.unwrap_or_default()),
+        )),
+    }
+}
+
+fn get_prompt(params: &Value) -> Result<Value, (i64, String)> {
+    if params.get("name").and_then(Value::as_str) != Some("greeting") {
+        return Err((INVALID_PARAMS, "Unknown prompt".to_string()));
+    }
+    let name = params
+        .pointer("/arguments/name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| (INVALID_PARAMS, "greeting: 'name' is required".to_string()))?;
+    Ok(json!({
+        "description": "Greets the given name",
+        "messages": [{
+            "role": "user",
+            "content": { "type": "text", "text": format!("Say hello to {}.", name) },
+        }],
+    }))
+}
@@ -0,0 +1,132 @@
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::broadcast, time::Instant};
+
+// 購読しているタスクが処理しきれずに取りこぼすまでの通知の数
+const SUBSCRIBER_CAPACITY: usize = 256;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BufferedNotification {
+    // 1 から始まる通し番号 (GET /api/v1/notifications の since に指定する)
+    pub cursor: u64,
+    pub timestamp_ms: u64,
+    pub message: serde_json::Value,
+}
+
+#[derive(Serialize, Debug)]
+pub struct NotificationPage {
+    pub notifications: Vec<BufferedNotification>,
+    // 次回の since に指定する値
+    pub next_cursor: u64,
+    // since より後の通知の一部が保持件数を超えて破棄されている場合は true
+    pub truncated: bool,
+}
+
+struct BufferState {
+    entries: VecDeque<BufferedNotification>,
+    last_cursor: u64,
+}
+
+// --- サーバーからの通知のリングバッファ ---
+// プロセスの再起動をまたいで共有され、クライアントはカーソルでポーリングする
+pub struct NotificationBuffer {
+    capacity: usize,
+    state: Mutex<BufferState>,
+    // 受信した通知をそのまま受け取るタスク (listChanged によるキャッシュの破棄など)。
+    // バッファを無効にしていても送る
+    sender: broadcast::Sender<serde_json::Value>,
+}
+
+impl NotificationBuffer {
+    pub fn new(capacity: usize) -> Self {
+        NotificationBuffer {
+            capacity,
+            state: Mutex::new(BufferState {
+                entries: VecDeque::with_capacity(capacity),
+                last_cursor: 0,
+            }),
+            sender: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<serde_json::Value> {
+        self.sender.subscribe()
+    }
+
+    pub fn push(&self, message: serde_json::Value) {
+        // 購読しているタスクには、バッファに入れてから送る (受け取った long polling が since で読めるように)。
+        // 購読しているタスクがなければ送れないが、問題ない
+        if self.capacity > 0 {
+            self.store(message.clone());
+        }
+        let _ = self.sender.send(message);
+    }
+
+    fn store(&self, message: serde_json::Value) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.last_cursor += 1;
+        let cursor = state.last_cursor;
+        if state.entries.len() == self.capacity {
+            state.entries.pop_front();
+        }
+        state.entries.push_back(BufferedNotification {
+            cursor,
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            message,
+        });
+    }
+
+    // since より後の通知を古い順に返す
+    pub fn since(&self, since: u64) -> NotificationPage {
+        let Ok(state) = self.state.lock() else {
+            return NotificationPage {
+                notifications: Vec::new(),
+                next_cursor: since,
+                truncated: false,
+            };
+        };
+        let notifications: Vec<BufferedNotification> = state
+            .entries
+            .iter()
+            .filter(|entry| entry.cursor > since)
+            .cloned()
+            .collect();
+        let oldest_cursor = state
+            .entries
+            .front()
+            .map(|entry| entry.cursor)
+            .unwrap_or(state.last_cursor + 1);
+        NotificationPage {
+            next_cursor: notifications.last().map_or(since, |entry| entry.cursor),
+            truncated: oldest_cursor > since + 1 && state.last_cursor > since,
+            notifications,
+        }
+    }
+
+    // since より後の通知が届くまで最大 wait だけ待ってから返す (GET /api/v1/notifications/poll)。
+    // バッファを無効にしている場合は待たない
+    pub async fn wait_since(&self, since: u64, wait: Duration) -> NotificationPage {
+        // 確認と待機の間に届いた通知を取りこぼさないよう、先に購読する
+        let mut receiver = self.subscribe();
+        let deadline = Instant::now() + wait;
+        loop {
+            let page = self.since(since);
+            if !page.notifications.is_empty() || self.capacity == 0 {
+                return page;
+            }
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => {}
+                Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => return page,
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use serde_json::{Map, Value, json};
+
+const OPENAPI_VERSION: &str = "3.1.0";
+
+// --- tools/list から OpenAPI ドキュメントを生成する ---
+// 各ツールを POST /api/v1/tools/{name} の操作として公開し、inputSchema をリクエストボディにする
+pub fn build_spec(server_key: &str, tools: &[Value], server_info: Option<&Value>) -> Value {
+    let title = server_info
+        .and_then(|info| info.get("name"))
+        .and_then(Value::as_str)
+        .unwrap_or(server_key);
+    let version = server_info
+        .and_then(|info| info.get("version"))
+        .and_then(Value::as_str)
+        .unwrap_or(env!("CARGO_PKG_VERSION"));
+
+    let mut paths = Map::new();
+    for tool in tools {
+        let Some(name) = tool.get("name").and_then(Value::as_str) else {
+            continue;
+        };
+        paths.insert(
+            format!("/api/v1/tools/{}", name),
+            json!({ "post": tool_operation(name, tool) }),
+        );
+    }
+
+    json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": title,
+            "version": version,
+            "description": format!("Tools of the MCP server '{}' exposed over HTTP", server_key),
+        },
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "bearerAuth": { "type": "http", "scheme": "bearer" },
+            },
+            "schemas": {
+                "ContentItem": {
+                    "type": "object",
+                    "properties": {
+                        "type": { "type": "string" },
+                        "text": { "type": "string" },
+                    },
+                    "required": ["type"],
+                    "additionalProperties": true,
+                },
+                "Error": {
+                    "type": "object",
+                    "properties": {
+                        "error": { "type": "string" },
+                        "message": { "type": "string" },
+                    },
+                    "required": ["error", "message"],
+                },
+            },
+        },
+        "security": [{ "bearerAuth": [] }],
+    })
+}
+
+fn tool_operation(name: &str, tool: &Value) -> Value {
+    let summary = tool
+        .get("title")
+        .or_else(|| tool.get("annotations").and_then(|a| a.get("title")))
+        .and_then(Value::as_str)
+        .unwrap_or(name);
+    let input_schema = tool
+        .get("inputSchema")
+        .cloned()
+        .unwrap_or_else(|| json!({ "type": "object" }));
+    let error_response = |description: &str| {
+        json!({
+            "description": description,
+            "content": {
+                "application/json": { "schema": { "$ref": "#/components/schemas/Error" } },
+            },
+        })
+    };
+
+    let mut operation = json!({
+        "operationId": operation_id(name),
+        "summary": summary,
+        "requestBody": {
+            "required": false,
+            "content": { "application/json": { "schema": input_schema } },
+        },
+        "responses": {
+            "200": {
+                "description": "Tool result content",
+                "content": {
+                    "application/json": {
+                        "schema": {
+                            "type": "array",
+                            "items": { "$ref": "#/components/schemas/ContentItem" },
+                        },
+                    },
+                },
+            },
+            "401": error_response("Missing or invalid API key"),
+            "502": error_response("The tool or the MCP server returned an error"),
+            "503": error_response("The MCP server is not running"),
+        },
+    });
+    if let Some(description) = tool.get("description").and_then(Value::as_str) {
+        operation["description"] = json!(description);
+    }
+    operation
+}
+
+// operationId に使えない文字を '_' に置き換える
+fn operation_id(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '_' {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
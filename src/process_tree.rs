@@ -0,0 +1,174 @@
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tracing::warn;
+
+// --- 子プロセスとその子孫の終了 ---
+// Unix では子プロセスを新しいセッション (プロセスグループ) で起動し、停止時はグループ全体にシグナルを送る。
+// Windows では Job Object に入れ、ジョブごと終了させる。
+// npx や python のエントリーポイントが起動したプロセスが孤児として残らないようにする
+
+// 子プロセスをプロセスグループのリーダーとして起動する (pgid = 子プロセスの PID)
+#[cfg(unix)]
+pub fn isolate(command: &mut Command) {
+    unsafe {
+        command.pre_exec(|| {
+            // 端末からのシグナル (Ctrl+C) も子プロセスに直接は届かなくなる
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+pub fn isolate(_command: &mut Command) {}
+
+// 起動した子プロセスのプロセスグループ (Unix) または Job Object (Windows)
+pub struct ProcessTree {
+    server_key: String,
+    #[cfg(unix)]
+    pgid: Option<u32>,
+    #[cfg(windows)]
+    job: Option<job::Job>,
+}
+
+impl ProcessTree {
+    pub fn attach(server_key: &str, child: &Child) -> Self {
+        ProcessTree {
+            server_key: server_key.to_string(),
+            #[cfg(unix)]
+            pgid: child.id(),
+            #[cfg(windows)]
+            job: job::Job::assign(server_key, child),
+        }
+    }
+
+    // Unix では SIGTERM をグループに送り、grace の間に終了しなければ SIGKILL する。
+    // Windows にはプロセスに終了を促す手段がないため、ジョブを即座に終了させる
+    pub async fn terminate(
+        &self,
+        child: &mut Child,
+        grace: Duration,
+    ) -> std::io::Result<std::process::ExitStatus> {
+        #[cfg(unix)]
+        if let Some(pgid) = self.pgid {
+            signal_group(&self.server_key, pgid, libc::SIGTERM);
+            match tokio::time::timeout(grace, child.wait()).await {
+                Ok(status) => {
+                    // リーダーが終了してもグループの残りが SIGTERM を無視している場合がある
+                    signal_group(&self.server_key, pgid, libc::SIGKILL);
+                    return status;
+                }
+                Err(_) => {
+                    warn!(
+                        server = %self.server_key,
+                        pgid,
+                        grace_secs = grace.as_secs(),
+                        "MCP process did not exit after SIGTERM, sending SIGKILL"
+                    );
+                    signal_group(&self.server_key, pgid, libc::SIGKILL);
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = grace;
+        self.kill_remaining();
+        if let Err(e) = child.kill().await {
+            warn!(server = %self.server_key, error = %e, "Failed to kill MCP process");
+        }
+        child.wait().await
+    }
+
+    // 子プロセスが自分で終了した後に残った子孫を終了させる
+    pub fn kill_remaining(&self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.pgid {
+            signal_group(&self.server_key, pgid, libc::SIGKILL);
+        }
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            job.terminate(&self.server_key);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn signal_group(server_key: &str, pgid: u32, signal: libc::c_int) {
+    let Ok(pgid) = libc::pid_t::try_from(pgid) else {
+        return;
+    };
+    if unsafe { libc::killpg(pgid, signal) } == -1 {
+        let e = std::io::Error::last_os_error();
+        // ESRCH はグループに残っているプロセスがない
+        if e.raw_os_error() != Some(libc::ESRCH) {
+            warn!(server = %server_key, pgid, signal, error = %e, "Failed to signal MCP process group");
+        }
+        return;
+    }
+    tracing::debug!(server = %server_key, pgid, signal, "Signaled MCP process group");
+}
+
+#[cfg(windows)]
+mod job {
+    use tokio::process::Child;
+    use tracing::warn;
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, HANDLE},
+        System::JobObjects::{
+            AssignProcessToJobObject, CreateJobObjectW, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+            JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JobObjectExtendedLimitInformation,
+            SetInformationJobObject, TerminateJobObject,
+        },
+    };
+
+    // ハンドルを閉じるとジョブ内のプロセスも終了する (ブリッジが異常終了した場合も残らない)
+    pub struct Job(HANDLE);
+
+    // HANDLE はスレッド間で共有できるカーネルオブジェクトへの参照
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        // 子プロセスをジョブに入れる (失敗した場合は子孫の終了を諦めて None)。
+        // 割り当てより前に子プロセスが起動したプロセスはジョブに含まれない
+        pub fn assign(server_key: &str, child: &Child) -> Option<Self> {
+            let process = child.raw_handle()?;
+            unsafe {
+                let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+                if handle.is_null() {
+                    warn!(server = %server_key, error = %std::io::Error::last_os_error(), "Failed to create Job Object");
+                    return None;
+                }
+                let job = Job(handle);
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+                info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                let configured = SetInformationJobObject(
+                    job.0,
+                    JobObjectExtendedLimitInformation,
+                    (&info as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION).cast(),
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                );
+                if configured == 0 || AssignProcessToJobObject(job.0, process as HANDLE) == 0 {
+                    warn!(server = %server_key, error = %std::io::Error::last_os_error(), "Failed to assign MCP process to a Job Object");
+                    return None;
+                }
+                Some(job)
+            }
+        }
+
+        pub fn terminate(&self, server_key: &str) {
+            if unsafe { TerminateJobObject(self.0, 1) } == 0 {
+                warn!(server = %server_key, error = %std::io::Error::last_os_error(), "Failed to terminate MCP process Job Object");
+            }
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
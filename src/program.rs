@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+// --- 子プロセス・フック・ヘルスチェックで実行するプログラムの解決 ---
+// Unix ではそのまま渡す。Windows の std は PATH から .exe しか探さないため、
+// npx.cmd のようなバッチファイルも見つかるよう PATHEXT を補って探す
+// (.cmd / .bat は std が引数をエスケープして cmd.exe /c 経由で実行する)
+pub fn command(program: &str) -> Command {
+    #[cfg(windows)]
+    if let Some(resolved) = windows::resolve(program) {
+        tracing::debug!(program, resolved = %resolved.display(), "Resolved program");
+        return Command::new(resolved);
+    }
+    Command::new(program)
+}
+
+// 起動せずにプログラムの実体を探す (validate 用)。パスを含む場合は cwd からの相対パスとして扱う
+pub fn find(program: &str, cwd: Option<&Path>) -> Option<PathBuf> {
+    let path = Path::new(program);
+    let path = match cwd {
+        Some(cwd) if path.is_relative() && path.components().count() > 1 => cwd.join(path),
+        _ => path.to_path_buf(),
+    };
+    #[cfg(windows)]
+    return windows::resolve(&path.to_string_lossy());
+    #[cfg(not(windows))]
+    {
+        if path.components().count() > 1 {
+            return is_executable(&path).then_some(path);
+        }
+        std::env::var_os("PATH").and_then(|paths| {
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(&path))
+                .find(|candidate| is_executable(candidate))
+        })
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{
+        env,
+        path::{Path, PathBuf},
+    };
+
+    // PATHEXT が未設定の場合の既定値
+    const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD";
+
+    pub fn resolve(program: &str) -> Option<PathBuf> {
+        let path = Path::new(program);
+        let extensions: Vec<String> = env::var("PATHEXT")
+            .unwrap_or_else(|_| DEFAULT_PATHEXT.to_string())
+            .split(';')
+            .filter(|ext| !ext.is_empty())
+            .map(str::to_string)
+            .collect();
+        // パスを含む場合は PATH を探さず、拡張子だけ補う
+        if path.components().count() > 1 {
+            return with_extensions(path, &extensions);
+        }
+        let dirs: Vec<PathBuf> = env::var_os("PATH")
+            .map(|paths| env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        aliases(program).iter().find_map(|name| {
+            dirs.iter()
+                .find_map(|dir| with_extensions(&dir.join(name), &extensions))
+        })
+    }
+
+    // Python の Windows 版インストーラーは python3.exe を入れないため python.exe も探す
+    fn aliases(program: &str) -> Vec<&str> {
+        match program {
+            "python3" => vec!["python3", "python"],
+            "pip3" => vec!["pip3", "pip"],
+            _ => vec![program],
+        }
+    }
+
+    // 拡張子がなければ PATHEXT の順に試す
+    fn with_extensions(path: &Path, extensions: &[String]) -> Option<PathBuf> {
+        if path.extension().is_some() {
+            return is_program(path).then(|| path.to_path_buf());
+        }
+        extensions.iter().find_map(|ext| {
+            let mut candidate = path.as_os_str().to_os_string();
+            candidate.push(ext);
+            let candidate = PathBuf::from(candidate);
+            is_program(&candidate).then_some(candidate)
+        })
+    }
+
+    // WindowsApps にある Microsoft Store を開くだけのエイリアス (サイズ 0) は除く
+    fn is_program(path: &Path) -> bool {
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.len() > 0)
+    }
+}
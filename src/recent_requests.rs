@@ -0,0 +1,116 @@
+use crate::storage::{NAMESPACE_REQUESTS, SharedStorage};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+// 既定で保持するリクエスト数と、1件ごとに残す command のバイト数
+const DEFAULT_CAPACITY: usize = 100;
+const DEFAULT_PAYLOAD_BYTES: usize = 512;
+// ストレージ上のリストのキー (全サーバーで共有し、各エントリの server で区別する)
+const STORAGE_KEY: &str = "recent";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecentRequest {
+    pub timestamp_ms: u64,
+    pub request_id: String,
+    pub server: String,
+    // JSON-RPC の method (バッチはカンマ区切り)
+    pub method: String,
+    // 先頭 RECENT_REQUESTS_PAYLOAD_BYTES バイトまでの command
+    pub payload: String,
+    pub payload_bytes: usize,
+    pub payload_truncated: bool,
+    pub status: u16,
+    pub latency_ms: u64,
+}
+
+// --- 直近のリクエストの履歴 ---
+// リクエストログを有効にしていなくても、障害の直前にどんなリクエストが来ていたかを
+// GET /admin/requests で確認できるようにする。STORAGE_BACKEND のリストに保持するため、
+// sqlite / redis では再起動後も残り、同じストレージを使うレプリカの履歴もまとめて見られる
+pub struct RecentRequests {
+    capacity: usize,
+    payload_bytes: usize,
+    storage: SharedStorage,
+}
+
+impl RecentRequests {
+    // RECENT_REQUESTS_SIZE (0 で無効) / RECENT_REQUESTS_PAYLOAD_BYTES
+    pub fn from_env(storage: SharedStorage) -> Self {
+        let env_usize = |name: &str, default: usize| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<usize>().ok())
+                .unwrap_or(default)
+        };
+        let capacity = env_usize("RECENT_REQUESTS_SIZE", DEFAULT_CAPACITY);
+        RecentRequests {
+            capacity,
+            payload_bytes: env_usize("RECENT_REQUESTS_PAYLOAD_BYTES", DEFAULT_PAYLOAD_BYTES),
+            storage,
+        }
+    }
+
+    // payload はここで切り詰める (呼び出し側で全体を複製しなくてよいように参照で受け取る)。
+    // ストレージへの書き込みはレスポンスを待たせないようにバックグラウンドで行う
+    pub fn record(
+        &self,
+        request_id: &str,
+        server: &str,
+        method: String,
+        payload: &str,
+        status: u16,
+        latency_ms: u64,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut end = payload.len().min(self.payload_bytes);
+        while !payload.is_char_boundary(end) {
+            end -= 1;
+        }
+        let entry = RecentRequest {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis() as u64)
+                .unwrap_or(0),
+            request_id: request_id.to_string(),
+            server: server.to_string(),
+            method,
+            payload: payload[..end].to_string(),
+            payload_bytes: payload.len(),
+            payload_truncated: end < payload.len(),
+            status,
+            latency_ms,
+        };
+        let Ok(value) = serde_json::to_string(&entry) else {
+            return;
+        };
+        let storage = self.storage.clone();
+        let capacity = self.capacity;
+        tokio::spawn(async move {
+            if let Err(e) = storage
+                .push(NAMESPACE_REQUESTS, STORAGE_KEY, &value, capacity)
+                .await
+            {
+                warn!(error = %e, "Failed to record recent request");
+            }
+        });
+    }
+
+    // 新しい順に最大 limit 件
+    pub async fn latest(&self, limit: usize) -> Result<Vec<RecentRequest>, String> {
+        let values = self
+            .storage
+            .latest(NAMESPACE_REQUESTS, STORAGE_KEY, limit.min(self.capacity))
+            .await?;
+        // 読めないエントリ (別のバージョンが書いたものなど) は飛ばす
+        Ok(values
+            .iter()
+            .filter_map(|value| serde_json::from_str(value).ok())
+            .collect())
+    }
+}
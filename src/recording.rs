@@ -0,0 +1,240 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, LazyLock},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{Mutex, mpsc},
+};
+use tracing::{info, warn};
+
+const INTERNAL_ERROR: i64 = -32603;
+
+// --- 子プロセスとのやり取りの記録と再生 ---
+// MCP_RECORD_FILE: 子プロセス (リモート・集約を含む) に送ったリクエストと応答の組を NDJSON で追記する
+// MCP_REPLAY_FILE: 子プロセスを起動せず、記録したファイルの応答を返す
+// 1行は { "server": ..., "request": ..., "response": ... }
+#[derive(Serialize, Deserialize)]
+struct Exchange {
+    server: String,
+    request: Value,
+    response: Value,
+}
+
+static RECORDER: LazyLock<Option<Recorder>> = LazyLock::new(|| {
+    let path = env::var("MCP_RECORD_FILE")
+        .ok()
+        .filter(|path| !path.is_empty())?;
+    info!(path = %path, "Recording MCP server traffic");
+    Some(Recorder {
+        path,
+        file: Mutex::new(None),
+    })
+});
+
+struct Recorder {
+    path: String,
+    // 最初の記録時に開く
+    file: Mutex<Option<tokio::fs::File>>,
+}
+
+// 応答が返ったリクエストを記録する (MCP_RECORD_FILE が未設定なら何もしない)
+pub async fn record(server_key: &str, request: &str, response: &str) {
+    let Some(recorder) = RECORDER.as_ref() else {
+        return;
+    };
+    let (Ok(request), Ok(response)) = (
+        serde_json::from_str(request),
+        serde_json::from_str(response),
+    ) else {
+        return;
+    };
+    let exchange = Exchange {
+        server: server_key.to_string(),
+        request,
+        response,
+    };
+    let Ok(line) = serde_json::to_string(&exchange) else {
+        return;
+    };
+    let mut file = recorder.file.lock().await;
+    if file.is_none() {
+        match tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&recorder.path)
+            .await
+        {
+            Ok(opened) => *file = Some(opened),
+            Err(e) => {
+                warn!(path = %recorder.path, error = %e, "Failed to open MCP_RECORD_FILE");
+                return;
+            }
+        }
+    }
+    let Some(file) = file.as_mut() else {
+        return;
+    };
+    if let Err(e) = file.write_all((line + "\n").as_bytes()).await {
+        warn!(path = %recorder.path, error = %e, "Failed to record MCP server traffic");
+    }
+}
+
+pub fn replay_file() -> Option<String> {
+    env::var("MCP_REPLAY_FILE")
+        .ok()
+        .filter(|path| !path.is_empty())
+}
+
+// リクエストの照合に使うキー (id を除いた method と params。serde_json のオブジェクトはキー順に並ぶ)
+fn request_key(request: &Value) -> Option<String> {
+    let method = request.get("method")?;
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    Some(json!([method, params]).to_string())
+}
+
+// 同じリクエストの応答が複数記録されていれば記録順に返し、使い切ったら最後の応答を返し続ける
+struct Responses {
+    responses: Vec<Value>,
+    next: usize,
+}
+
+// --- MCP_REPLAY_FILE の応答を返す仮想サーバー ---
+pub struct Replayer {
+    server_key: String,
+    responses: std::sync::Mutex<HashMap<String, Responses>>,
+    inbound: mpsc::Sender<String>,
+}
+
+impl Replayer {
+    pub async fn load(
+        server_key: &str,
+        path: &str,
+        inbound: mpsc::Sender<String>,
+    ) -> Result<Self, String> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| format!("Failed to read MCP_REPLAY_FILE '{}': {}", path, e))?;
+        let mut responses: HashMap<String, Responses> = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exchange: Exchange = serde_json::from_str(line).map_err(|e| {
+                format!(
+                    "Invalid MCP_REPLAY_FILE '{}' line {}: {}",
+                    path,
+                    index + 1,
+                    e
+                )
+            })?;
+            if exchange.server != server_key {
+                continue;
+            }
+            for (request, response) in split_batch(exchange.request, exchange.response) {
+                if let Some(key) = request_key(&request) {
+                    responses
+                        .entry(key)
+                        .or_insert_with(|| Responses {
+                            responses: Vec::new(),
+                            next: 0,
+                        })
+                        .responses
+                        .push(response);
+                }
+            }
+        }
+        if responses.is_empty() {
+            return Err(format!(
+                "MCP_REPLAY_FILE '{}' has no recorded exchanges for MCP server '{}'",
+                path, server_key
+            ));
+        }
+        Ok(Replayer {
+            server_key: server_key.to_string(),
+            responses: std::sync::Mutex::new(responses),
+            inbound,
+        })
+    }
+
+    // メッセージを受け付け、応答は inbound に送る
+    pub fn send(self: &Arc<Self>, message: &str) -> Result<(), String> {
+        let message: Value = serde_json::from_str(message)
+            .map_err(|e| format!("Invalid JSON-RPC message for replay: {}", e))?;
+        let replayer = Arc::clone(self);
+        tokio::spawn(async move {
+            let response = match message {
+                Value::Array(batch) => {
+                    let responses: Vec<Value> = batch
+                        .iter()
+                        .filter_map(|message| replayer.handle(message))
+                        .collect();
+                    (!responses.is_empty()).then_some(Value::Array(responses))
+                }
+                message => replayer.handle(&message),
+            };
+            if let Some(response) = response {
+                let _ = replayer.inbound.send(response.to_string()).await;
+            }
+        });
+        Ok(())
+    }
+
+    // 記録した応答の id を受信したリクエストの id に置き換えて返す (通知には何も返さない)
+    fn handle(&self, message: &Value) -> Option<Value> {
+        let id = message.get("id").filter(|id| !id.is_null())?.clone();
+        let recorded = request_key(message).and_then(|key| {
+            let mut responses = self.responses.lock().ok()?;
+            let entry = responses.get_mut(&key)?;
+            let response = entry
+                .responses
+                .get(entry.next.min(entry.responses.len() - 1))
+                .cloned();
+            entry.next += 1;
+            response
+        });
+        Some(match recorded {
+            Some(mut response) => {
+                response["id"] = id;
+                response
+            }
+            None => {
+                let method = message.get("method").and_then(Value::as_str);
+                warn!(server = %self.server_key, method = ?method, "No recorded response for request");
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {
+                        "code": INTERNAL_ERROR,
+                        "message": format!(
+                            "No recorded response for this '{}' request to MCP server '{}'",
+                            method.unwrap_or_default(),
+                            self.server_key
+                        ),
+                    },
+                })
+            }
+        })
+    }
+}
+
+// バッチは id で1組ずつのリクエストと応答に分ける
+fn split_batch(request: Value, response: Value) -> Vec<(Value, Value)> {
+    match (request, response) {
+        (Value::Array(requests), Value::Array(responses)) => requests
+            .into_iter()
+            .filter_map(|request| {
+                let id = request.get("id").filter(|id| !id.is_null())?;
+                let response = responses
+                    .iter()
+                    .find(|response| response.get("id") == Some(id))?
+                    .clone();
+                Some((request, response))
+            })
+            .collect(),
+        (request, response) => vec![(request, response)],
+    }
+}
@@ -0,0 +1,221 @@
+use reqwest::header::{ACCEPT, AUTHORIZATION};
+use serde_json::{Map, Value};
+use std::{env, sync::Arc, time::Duration};
+use tracing::{debug, info, warn};
+
+use crate::{
+    config_file, config_source,
+    mcp_process::{McpProcessConfig, McpServer},
+};
+
+// レジストリの取得を待つ時間
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+// --- サーバー定義のレジストリ ---
+// MCP_REGISTRY_URL の JSON (設定ファイルと同じ形式、または { "mcpServers": { ... } }) を取得し、
+// ローカルの設定ファイルとマージする。同じ名前の定義はローカルの設定ファイルを優先する。
+// 設定ファイルが URL (https:// / s3://) の場合は、レジストリがなくても設定ファイルを定期的に取得し直す
+pub struct Registry {
+    // MCP_REGISTRY_URL (未設定なら設定ファイルの再取得のみ)
+    url: Option<String>,
+    token: Option<String>,
+    // 0 の場合は起動時にのみ取得する
+    refresh_interval: Option<Duration>,
+    config_file: String,
+    server_key: String,
+    client: reqwest::Client,
+    // 直近に適用したブリッジ対象のサーバー (と集約サーバーのメンバー) の定義
+    current: std::sync::Mutex<Option<Value>>,
+}
+
+impl Registry {
+    // MCP_REGISTRY_URL が未設定で、設定ファイルも URL でない場合は None (設定ファイルを起動時に読むだけ)
+    pub fn from_env(config_file: &str, server_key: &str) -> Option<Self> {
+        let url = env::var("MCP_REGISTRY_URL")
+            .ok()
+            .filter(|url| !url.is_empty());
+        if url.is_none() && !config_source::is_remote(config_file) {
+            return None;
+        }
+        let refresh_var = match url {
+            Some(_) => "MCP_REGISTRY_REFRESH_SECS",
+            None => "MCP_CONFIG_REFRESH_SECS",
+        };
+        let refresh_secs = env::var(refresh_var)
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(300);
+        Some(Registry {
+            url,
+            token: env::var("MCP_REGISTRY_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            refresh_interval: Some(refresh_secs)
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            config_file: config_file.to_string(),
+            server_key: server_key.to_string(),
+            client: reqwest::Client::new(),
+            current: std::sync::Mutex::new(None),
+        })
+    }
+
+    // 起動時の読み込み。レジストリを取得できなくても、設定ファイルに定義があれば起動できる
+    pub async fn load(&self) -> Result<McpProcessConfig, String> {
+        let registry = self.fetch().await.unwrap_or_else(|e| {
+            warn!(url = ?self.url, error = %e, "Failed to fetch MCP server registry, using local config only");
+            Map::new()
+        });
+        let merged = self.merge(registry).await?;
+        let definition = self.definition(&merged)?;
+        let config = parse_definition(&self.server_key, definition.clone())?;
+        *self
+            .current
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(definition);
+        Ok(config)
+    }
+
+    // 起動するサーバーを選ぶための、レジストリと設定ファイルをマージした全定義
+    // (取得できなければ load と同じく設定ファイルだけを使う)
+    pub async fn definitions(&self) -> Result<Map<String, Value>, String> {
+        let registry = self.fetch().await.unwrap_or_else(|e| {
+            warn!(url = ?self.url, error = %e, "Failed to fetch MCP server registry, using local config only");
+            Map::new()
+        });
+        self.merge(registry).await
+    }
+
+    // 定期的にレジストリを取得し直し、定義が変わっていればサーバーに適用する
+    pub fn spawn_refresh(self, server: Arc<McpServer>) {
+        let Some(interval) = self.refresh_interval else {
+            return;
+        };
+        info!(
+            url = ?self.url,
+            config_file = %self.config_file,
+            interval_secs = interval.as_secs(),
+            "Starting MCP server registry refresh"
+        );
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // 初回の tick は即座に完了するため読み飛ばす
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.refresh(&server).await {
+                    warn!(url = ?self.url, server = %self.server_key, error = %e, "Failed to refresh MCP server registry");
+                }
+            }
+        });
+    }
+
+    async fn refresh(&self, server: &Arc<McpServer>) -> Result<(), String> {
+        let merged = self.merge(self.fetch().await?).await?;
+        let definition = self.definition(&merged)?;
+        let unchanged = self
+            .current
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .as_ref()
+            == Some(&definition);
+        if unchanged {
+            debug!(server = %self.server_key, "MCP server definition unchanged");
+            return Ok(());
+        }
+        let config = parse_definition(&self.server_key, definition.clone())?;
+        *self
+            .current
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(definition);
+        info!(server = %self.server_key, "MCP server definition changed, applying update");
+        server.update_config(config).await
+    }
+
+    async fn fetch(&self) -> Result<Map<String, Value>, String> {
+        let Some(url) = &self.url else {
+            return Ok(Map::new());
+        };
+        info!(url = %url, "Fetching MCP server registry");
+        let mut request = self
+            .client
+            .get(url)
+            .header(ACCEPT, "application/json")
+            .timeout(FETCH_TIMEOUT);
+        if let Some(token) = &self.token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to fetch registry '{}': {}", url, e))?;
+        let document: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse registry '{}': {}", url, e))?;
+        match document {
+            Value::Object(mut document) => match document.remove("mcpServers") {
+                Some(Value::Object(servers)) => Ok(servers),
+                Some(_) => Err(format!("Registry '{}' has a non-object 'mcpServers'", url)),
+                None => Ok(document),
+            },
+            _ => Err(format!("Registry '{}' is not a JSON object", url)),
+        }
+    }
+
+    // 設定ファイル (無くてもよい) の定義でレジストリの定義を上書きする
+    async fn merge(&self, mut servers: Map<String, Value>) -> Result<Map<String, Value>, String> {
+        let local = match config_file::read(&self.config_file).await? {
+            Some(local) => local,
+            None => {
+                debug!(config_file = %self.config_file, "No local MCP config file, using registry only");
+                Map::new()
+            }
+        };
+        servers.extend(local);
+        Ok(servers)
+    }
+
+    // ブリッジ対象のサーバーと、集約サーバーの場合はそのメンバーの定義だけを取り出す
+    // (レジストリにある無関係な定義の誤りで起動できなくならないようにする)
+    fn definition(&self, merged: &Map<String, Value>) -> Result<Value, String> {
+        let entry = merged
+            .get(&self.server_key)
+            .ok_or_else(|| match &self.url {
+                Some(url) => format!(
+                    "MCP server configuration not found for key '{}' in file '{}' or registry '{}'",
+                    self.server_key, self.config_file, url
+                ),
+                None => format!(
+                    "MCP server configuration not found for key '{}' in file '{}'",
+                    self.server_key, self.config_file
+                ),
+            })?;
+        let members = entry
+            .get("servers")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str);
+        let mut definition = Map::new();
+        for key in members.chain([self.server_key.as_str()]) {
+            if let Some(entry) = merged.get(key) {
+                definition.insert(key.to_string(), entry.clone());
+            }
+        }
+        Ok(Value::Object(definition))
+    }
+}
+
+fn parse_definition(server_key: &str, definition: Value) -> Result<McpProcessConfig, String> {
+    let Value::Object(servers) = definition else {
+        return Err(format!(
+            "Invalid definition for MCP server '{}'",
+            server_key
+        ));
+    };
+    config_file::parse_servers(servers, &format!("definition of '{}'", server_key))?
+        .remove(server_key)
+        .ok_or_else(|| format!("MCP server '{}' is not defined", server_key))
+}
@@ -0,0 +1,391 @@
+use reqwest::{
+    StatusCode, Url,
+    header::{ACCEPT, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue},
+};
+use serde::Deserialize;
+use std::{collections::HashMap, time::Duration};
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    task::JoinSet,
+    time::timeout,
+};
+use tracing::{debug, info, warn};
+
+use crate::mcp_process::RESPONSE_TOO_LARGE_ERROR;
+
+// リモートの MCP サーバーに接続できない (接続エラー・5xx・セッション切れ) ときのエラーメッセージの先頭
+// 子プロセスの異常終了と同じく扱い、サーキットブレーカーと retry_on_crash の対象にする
+pub const REMOTE_UNAVAILABLE_ERROR: &str = "Remote MCP server unavailable";
+
+// セッション終了 (DELETE) の待ち時間
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+const SESSION_ID_HEADER: &str = "mcp-session-id";
+
+// --- リモートの MCP サーバーへの接続方式 ---
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteTransport {
+    // Streamable HTTP (1つのエンドポイントに POST し、JSON または SSE で応答を受け取る)
+    #[default]
+    StreamableHttp,
+    // 旧来の HTTP+SSE (GET で SSE を開き、endpoint イベントで通知された URL に POST する)
+    Sse,
+}
+
+// --- type: "remote" のサーバーの設定 ---
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RemoteConfig {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub transport: RemoteTransport,
+    // すべてのリクエストに付与する HTTP ヘッダー (認証など)
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+// --- リモートの MCP サーバーとの接続 ---
+// 送信は HTTP の POST で行い、受信したメッセージ (JSON のボディと SSE のイベント) は
+// inbound に送る。読み取り側は子プロセスの標準出力と同じように振り分ける
+pub struct RemoteClient {
+    server_key: String,
+    transport: RemoteTransport,
+    // Streamable HTTP のエンドポイント、または SSE の endpoint イベントで通知された URL
+    endpoint: Url,
+    headers: HeaderMap,
+    client: reqwest::Client,
+    max_response_bytes: usize,
+    // HTTP リクエストのヘッダー受信までの待ち時間 (サーバーの応答待ちの時間に合わせる)
+    request_timeout: Duration,
+    session_id: std::sync::Mutex<Option<String>>,
+    inbound: mpsc::Sender<String>,
+    // 接続が失われたら true になる
+    disconnected: watch::Sender<bool>,
+    // SSE の読み取りタスク (切断時に中断する)
+    streams: std::sync::Mutex<JoinSet<()>>,
+}
+
+impl RemoteClient {
+    pub async fn connect(
+        server_key: &str,
+        config: &RemoteConfig,
+        max_response_bytes: usize,
+        request_timeout: Duration,
+        inbound: mpsc::Sender<String>,
+    ) -> Result<Self, String> {
+        let url = config
+            .url
+            .as_deref()
+            .ok_or_else(|| format!("Remote MCP server '{}' has no url", server_key))?;
+        let url =
+            Url::parse(url).map_err(|e| format!("Invalid remote MCP url '{}': {}", url, e))?;
+        let headers = parse_headers(&config.headers)?;
+        let client = RemoteClient {
+            server_key: server_key.to_string(),
+            transport: config.transport,
+            endpoint: url.clone(),
+            headers,
+            client: reqwest::Client::new(),
+            max_response_bytes,
+            request_timeout,
+            session_id: std::sync::Mutex::new(None),
+            inbound,
+            disconnected: watch::Sender::new(false),
+            streams: std::sync::Mutex::new(JoinSet::new()),
+        };
+        match config.transport {
+            RemoteTransport::StreamableHttp => Ok(client),
+            RemoteTransport::Sse => client.open_sse(url).await,
+        }
+    }
+
+    // GET で SSE を開き、POST 先を通知する endpoint イベントを待つ
+    async fn open_sse(mut self, url: Url) -> Result<Self, String> {
+        let response = timeout(
+            self.request_timeout,
+            self.client
+                .get(url.clone())
+                .headers(self.headers.clone())
+                .header(ACCEPT, "text/event-stream")
+                .send(),
+        )
+        .await
+        .map_err(|_| unavailable("timed out opening the SSE stream"))?
+        .map_err(|e| unavailable(&e.to_string()))?;
+        check_status(response.status(), false)?;
+
+        let (endpoint_tx, endpoint_rx) = oneshot::channel();
+        self.spawn_event_stream(response, Some(endpoint_tx), true);
+        let endpoint = timeout(self.request_timeout, endpoint_rx)
+            .await
+            .map_err(|_| unavailable("no endpoint event received on the SSE stream"))?
+            .map_err(|_| unavailable("SSE stream closed before the endpoint event"))?;
+        self.endpoint = url.join(&endpoint).map_err(|e| {
+            format!(
+                "Invalid endpoint '{}' from remote MCP server: {}",
+                endpoint, e
+            )
+        })?;
+        info!(server = %self.server_key, endpoint = %self.endpoint, "Remote MCP SSE session established");
+        Ok(self)
+    }
+
+    // メッセージを POST し、応答があれば inbound に送る
+    pub async fn send(&self, message: &str) -> Result<(), String> {
+        let mut builder = self
+            .client
+            .post(self.endpoint.clone())
+            .headers(self.headers.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json, text/event-stream")
+            .body(message.to_string());
+        let session_id = self.session_id();
+        if let Some(session_id) = &session_id {
+            builder = builder.header(SESSION_ID_HEADER, session_id);
+        }
+        let response = timeout(self.request_timeout, builder.send())
+            .await
+            .map_err(|_| unavailable("timed out sending the request"))?
+            .map_err(|e| unavailable(&e.to_string()))?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND && session_id.is_some() {
+            // セッションが失効した (次の接続で initialize からやり直す)
+            warn!(server = %self.server_key, "Remote MCP session expired");
+            self.disconnected.send_replace(true);
+            return Err(unavailable("session expired"));
+        }
+        check_status(status, true)?;
+        if let Some(session_id) = response
+            .headers()
+            .get(SESSION_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|_| self.transport == RemoteTransport::StreamableHttp)
+        {
+            self.set_session_id(session_id);
+        }
+        // 旧来の SSE 方式では応答は GET のストリームで届く
+        if self.transport == RemoteTransport::Sse || status == StatusCode::ACCEPTED {
+            return Ok(());
+        }
+
+        let is_event_stream = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("text/event-stream"));
+        if is_event_stream {
+            self.spawn_event_stream(response, None, false);
+            return Ok(());
+        }
+        let body = self.read_body(response).await?;
+        if !body.trim().is_empty() {
+            let _ = self.inbound.send(body).await;
+        }
+        Ok(())
+    }
+
+    async fn read_body(&self, mut response: reqwest::Response) -> Result<String, String> {
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| unavailable(&e.to_string()))?
+        {
+            if body.len() + chunk.len() > self.max_response_bytes {
+                return Err(format!(
+                    "{}: message exceeded max_response_bytes ({} bytes)",
+                    RESPONSE_TOO_LARGE_ERROR, self.max_response_bytes
+                ));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    // SSE のイベントを読み、message イベントを inbound に送るタスクを起動する
+    // endpoint が指定されていれば最初の endpoint イベントの URL をそこに送る。
+    // persistent が true (旧来の SSE 方式の GET) の場合、ストリームの終了を切断として扱う
+    fn spawn_event_stream(
+        &self,
+        mut response: reqwest::Response,
+        mut endpoint: Option<oneshot::Sender<String>>,
+        persistent: bool,
+    ) {
+        let server_key = self.server_key.clone();
+        let inbound = self.inbound.clone();
+        let disconnected = self.disconnected.clone();
+        let max_response_bytes = self.max_response_bytes;
+        let mut streams = self
+            .streams
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        // 終了済みのタスクを片付ける
+        while streams.try_join_next().is_some() {}
+        streams.spawn(async move {
+            let mut parser = SseParser::default();
+            loop {
+                let chunk = match response.chunk().await {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!(server = %server_key, error = %e, "Error reading SSE stream from remote MCP server");
+                        break;
+                    }
+                };
+                for event in parser.feed(&chunk) {
+                    match event.event.as_str() {
+                        "endpoint" => {
+                            if let Some(endpoint) = endpoint.take() {
+                                let _ = endpoint.send(event.data);
+                            }
+                        }
+                        "" | "message" if event.data.len() > max_response_bytes => {
+                            warn!(
+                                server = %server_key,
+                                bytes = event.data.len(),
+                                max_response_bytes,
+                                "Discarded oversized message from remote MCP server"
+                            );
+                        }
+                        "" | "message" => {
+                            if inbound.send(event.data).await.is_err() {
+                                return;
+                            }
+                        }
+                        other => {
+                            debug!(server = %server_key, event = %other, "Ignoring SSE event from remote MCP server")
+                        }
+                    }
+                }
+            }
+            if persistent {
+                warn!(server = %server_key, "Remote MCP SSE stream closed");
+                disconnected.send_replace(true);
+            }
+        });
+    }
+
+    // 接続が失われるまで待つ
+    pub async fn closed(&self) {
+        let mut disconnected = self.disconnected.subscribe();
+        let _ = disconnected.wait_for(|disconnected| *disconnected).await;
+    }
+
+    // SSE の読み取りを止め、Streamable HTTP のセッションがあれば終了を通知する
+    pub async fn close(&self) {
+        self.streams
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .abort_all();
+        let Some(session_id) = self.session_id() else {
+            return;
+        };
+        let request = self
+            .client
+            .delete(self.endpoint.clone())
+            .headers(self.headers.clone())
+            .header(SESSION_ID_HEADER, session_id)
+            .send();
+        match timeout(CLOSE_TIMEOUT, request).await {
+            Ok(Ok(_)) => debug!(server = %self.server_key, "Terminated remote MCP session"),
+            Ok(Err(e)) => {
+                debug!(server = %self.server_key, error = %e, "Failed to terminate remote MCP session")
+            }
+            Err(_) => debug!(server = %self.server_key, "Timed out terminating remote MCP session"),
+        }
+    }
+
+    fn session_id(&self) -> Option<String> {
+        self.session_id
+            .lock()
+            .ok()
+            .and_then(|session_id| session_id.clone())
+    }
+
+    fn set_session_id(&self, session_id: &str) {
+        if let Ok(mut current) = self.session_id.lock() {
+            if current.as_deref() != Some(session_id) {
+                debug!(server = %self.server_key, session_id = %session_id, "Remote MCP session started");
+            }
+            *current = Some(session_id.to_string());
+        }
+    }
+}
+
+fn unavailable(reason: &str) -> String {
+    format!("{}: {}", REMOTE_UNAVAILABLE_ERROR, reason)
+}
+
+// 5xx (と 429) は接続不可、それ以外の失敗は要求の拒否として扱う
+fn check_status(status: StatusCode, allow_accepted: bool) -> Result<(), String> {
+    if status.is_success() && (allow_accepted || status != StatusCode::ACCEPTED) {
+        return Ok(());
+    }
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(unavailable(&format!("HTTP {}", status)));
+    }
+    Err(format!(
+        "Remote MCP server rejected the request: HTTP {}",
+        status
+    ))
+}
+
+pub fn parse_headers(headers: &HashMap<String, String>) -> Result<HeaderMap, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| format!("Invalid remote header name '{}': {}", name, e))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| format!("Invalid value for remote header '{}': {}", name, e))?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+// --- SSE (text/event-stream) の解析 ---
+struct SseEvent {
+    event: String,
+    data: String,
+}
+
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+    event: String,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    // 受信したチャンクを追加し、完成したイベントを返す
+    fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(index) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=index).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                // 空行でイベントが確定する
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: std::mem::take(&mut self.event),
+                        data: std::mem::take(&mut self.data).join("\n"),
+                    });
+                }
+                self.event.clear();
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.event = value.to_string(),
+                "data" => self.data.push(value.to_string()),
+                // id / retry / コメント行は使わない
+                _ => {}
+            }
+        }
+        events
+    }
+}
@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::{restart_policy::ProcessExit, stats::ServerStats};
+
+// --- 子プロセスのリソース使用量の監視 ---
+// "resource_monitor": { "interval_secs": 15, "warn_rss_mb": 512, "warn_cpu_percent": 90, "warn_open_fds": 1000 }
+// /proc から子プロセスのセッション (npx などが起動した子孫を含む) の RSS・CPU 時間・fd 数を定期的に読み取り、
+// /stats と /metrics で参照できるようにする。warn_* を超えたら警告ログを出す (Linux のみ)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceMonitorConfig {
+    // 0 で監視しない
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub warn_rss_mb: Option<u64>,
+    // 100 で1コア分
+    #[serde(default)]
+    pub warn_cpu_percent: Option<f64>,
+    #[serde(default)]
+    pub warn_open_fds: Option<u64>,
+}
+
+fn default_interval_secs() -> u64 {
+    15
+}
+
+impl Default for ResourceMonitorConfig {
+    fn default() -> Self {
+        ResourceMonitorConfig {
+            interval_secs: default_interval_secs(),
+            warn_rss_mb: None,
+            warn_cpu_percent: None,
+            warn_open_fds: None,
+        }
+    }
+}
+
+impl ResourceMonitorConfig {
+    pub fn validate(&self, server_key: &str) -> Result<(), String> {
+        if self.warn_rss_mb == Some(0)
+            || self.warn_open_fds == Some(0)
+            || self.warn_cpu_percent.is_some_and(|percent| percent <= 0.0)
+        {
+            return Err(format!(
+                "MCP server '{}' resource_monitor thresholds must be greater than 0",
+                server_key
+            ));
+        }
+        let has_thresholds = self.warn_rss_mb.is_some()
+            || self.warn_cpu_percent.is_some()
+            || self.warn_open_fds.is_some();
+        if cfg!(not(target_os = "linux")) && has_thresholds {
+            return Err(format!(
+                "MCP server '{}' resource_monitor thresholds are only supported on Linux",
+                server_key
+            ));
+        }
+        Ok(())
+    }
+}
+
+// 最後に読み取ったリソース使用量 (子孫のプロセスを含む合計)
+#[derive(Serialize, Clone, Debug)]
+pub struct ResourceUsage {
+    pub sampled_at_ms: u64,
+    pub processes: u64,
+    pub rss_bytes: u64,
+    // 起動からの CPU 時間 (user + system)
+    pub cpu_seconds: f64,
+    // 前回の読み取りからの CPU 使用率 (初回は None)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_percent: Option<f64>,
+    pub open_fds: u64,
+}
+
+// 子プロセスが終了するまで interval_secs ごとに使用量を記録する
+pub async fn run(
+    server_key: String,
+    pid: u32,
+    config: ResourceMonitorConfig,
+    stats: Arc<ServerStats>,
+    mut exited: watch::Receiver<Option<ProcessExit>>,
+) {
+    if config.interval_secs == 0 || cfg!(not(target_os = "linux")) {
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs));
+    let mut previous: Option<(Instant, f64)> = None;
+    let mut alerts = Alerts::default();
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = exited.changed() => break,
+        }
+        if exited.borrow().is_some() {
+            break;
+        }
+        let Some(mut usage) = sample(pid).await else {
+            continue;
+        };
+        let now = Instant::now();
+        if let Some((sampled_at, cpu_seconds)) = previous {
+            let elapsed = now.duration_since(sampled_at).as_secs_f64();
+            if elapsed > 0.0 {
+                usage.cpu_percent =
+                    Some(((usage.cpu_seconds - cpu_seconds) / elapsed * 100.0).max(0.0));
+            }
+        }
+        previous = Some((now, usage.cpu_seconds));
+        alerts.check(&server_key, pid, &config, &usage);
+        stats.record_resources(Some(usage));
+    }
+    stats.record_resources(None);
+}
+
+// しきい値を超えている間は警告を繰り返さず、超えたときと戻ったときだけログに出す
+#[derive(Default)]
+struct Alerts {
+    rss: bool,
+    cpu: bool,
+    open_fds: bool,
+}
+
+impl Alerts {
+    fn check(
+        &mut self,
+        server_key: &str,
+        pid: u32,
+        config: &ResourceMonitorConfig,
+        usage: &ResourceUsage,
+    ) {
+        let rss_mb = usage.rss_bytes / (1024 * 1024);
+        let checks = [
+            (
+                &mut self.rss,
+                "rss_mb",
+                config
+                    .warn_rss_mb
+                    .map(|limit| (rss_mb as f64, limit as f64)),
+            ),
+            (
+                &mut self.cpu,
+                "cpu_percent",
+                config
+                    .warn_cpu_percent
+                    .zip(usage.cpu_percent)
+                    .map(|(limit, percent)| (percent, limit)),
+            ),
+            (
+                &mut self.open_fds,
+                "open_fds",
+                config
+                    .warn_open_fds
+                    .map(|limit| (usage.open_fds as f64, limit as f64)),
+            ),
+        ];
+        for (alerting, resource, values) in checks {
+            let Some((value, limit)) = values else {
+                continue;
+            };
+            if value > limit && !*alerting {
+                warn!(server = %server_key, pid, resource, value, limit, "MCP process resource usage exceeded the warning threshold");
+            } else if value <= limit && *alerting {
+                info!(server = %server_key, pid, resource, value, limit, "MCP process resource usage is back under the warning threshold");
+            }
+            *alerting = value > limit;
+        }
+    }
+}
+
+// 子プロセスはセッションリーダーとして起動しているため (process_tree::isolate)、
+// セッション ID が子プロセスの PID と一致するプロセスを合計する
+#[cfg(target_os = "linux")]
+async fn sample(pid: u32) -> Option<ResourceUsage> {
+    // /proc の stat はクロックティックとページ単位
+    let (ticks_per_sec, page_size) = unsafe {
+        (
+            libc::sysconf(libc::_SC_CLK_TCK),
+            libc::sysconf(libc::_SC_PAGESIZE),
+        )
+    };
+    if ticks_per_sec <= 0 || page_size <= 0 {
+        return None;
+    }
+    let mut usage = ResourceUsage {
+        sampled_at_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0),
+        processes: 0,
+        rss_bytes: 0,
+        cpu_seconds: 0.0,
+        cpu_percent: None,
+        open_fds: 0,
+    };
+    let mut entries = tokio::fs::read_dir("/proc").await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Some(member) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        // 読み取り中に終了したプロセスは数えない
+        let Ok(stat) = tokio::fs::read_to_string(format!("/proc/{}/stat", member)).await else {
+            continue;
+        };
+        let Some(process) = parse_stat(&stat) else {
+            continue;
+        };
+        if process.session != pid {
+            continue;
+        }
+        usage.processes += 1;
+        usage.rss_bytes += process.rss_pages * page_size as u64;
+        usage.cpu_seconds += (process.utime + process.stime) as f64 / ticks_per_sec as f64;
+        usage.open_fds += count_fds(member).await;
+    }
+    (usage.processes > 0).then_some(usage)
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn sample(_pid: u32) -> Option<ResourceUsage> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+struct ProcStat {
+    session: u32,
+    utime: u64,
+    stime: u64,
+    rss_pages: u64,
+}
+
+// /proc/<pid>/stat の session (6) / utime (14) / stime (15) / rss (24)。
+// comm (2) は空白や括弧を含みうるため、最後の ')' より後ろを数える
+#[cfg(target_os = "linux")]
+fn parse_stat(stat: &str) -> Option<ProcStat> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let field = |number: usize| fields.get(number - 3)?.parse::<u64>().ok();
+    Some(ProcStat {
+        session: u32::try_from(field(6)?).ok()?,
+        utime: field(14)?,
+        stime: field(15)?,
+        rss_pages: field(24)?,
+    })
+}
+
+#[cfg(target_os = "linux")]
+async fn count_fds(pid: u32) -> u64 {
+    let Ok(mut entries) = tokio::fs::read_dir(format!("/proc/{}/fd", pid)).await else {
+        return 0;
+    };
+    let mut count = 0;
+    while let Ok(Some(_)) = entries.next_entry().await {
+        count += 1;
+    }
+    count
+}
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+// 常にキャッシュの対象にする (子プロセスを再起動するまで変わらない) メソッド
+const CACHED_METHODS: [&str; 3] = ["tools/list", "resources/list", "prompts/list"];
+
+// --- 応答のキャッシュの設定 ---
+// "response_cache": { "ttl_secs": 300, "tools": ["get_weather"] }
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResponseCacheConfig {
+    // キャッシュした応答を返す秒数 (0 で無効)
+    pub ttl_secs: u64,
+    // tools/call もキャッシュするツール (引数ごと)。読み取り専用のツールだけを指定する
+    pub tools: Vec<String>,
+    // 保持する応答の上限 (超えたら期限の近いものから捨てる)
+    pub max_entries: usize,
+    // listChanged の通知で破棄した一覧を、次のリクエストを待たずに取得し直す
+    pub prewarm: bool,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        ResponseCacheConfig {
+            ttl_secs: 0,
+            tools: Vec::new(),
+            max_entries: 1000,
+            prewarm: false,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ResponseCacheSnapshot {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// --- tools/list などの応答のキャッシュ ---
+// JSON-RPC の result だけを保持し、返すときにリクエストの id を付け直す。
+// 子プロセスを起動するたびに空にする (McpServer::activate を参照)
+#[derive(Default)]
+pub struct ResponseCache {
+    // メソッドと params (_meta を除く) → 期限と result
+    entries: Mutex<HashMap<String, (Instant, Value)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    // キャッシュ済みで期限内の応答 (リクエストの id を付けた JSON-RPC レスポンス)
+    pub fn get(&self, config: &ResponseCacheConfig, command: &str) -> Option<String> {
+        let request: Value = serde_json::from_str(command).ok()?;
+        let key = cache_key(config, &request)?;
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(result) = entries
+            .get(&key)
+            .filter(|(expires_at, _)| Instant::now() < *expires_at)
+            .map(|(_, result)| result.clone())
+        else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": request.get("id").cloned().unwrap_or(Value::Null),
+            "result": result,
+        });
+        Some(response.to_string())
+    }
+
+    // 成功した応答だけを保持する (JSON-RPC エラーと isError: true のツールの結果は除く)
+    pub fn store(&self, config: &ResponseCacheConfig, command: &str, response: &str) {
+        let Some(key) = serde_json::from_str::<Value>(command)
+            .ok()
+            .and_then(|request| cache_key(config, &request))
+        else {
+            return;
+        };
+        let Some(result) = serde_json::from_str::<Value>(response)
+            .ok()
+            .and_then(|mut response| response.get_mut("result").map(Value::take))
+        else {
+            return;
+        };
+        self.insert(config, key, result);
+    }
+
+    // ブリッジ自身が取得した result を保持する (listChanged 後の prewarm 用)
+    pub fn store_result(
+        &self,
+        config: &ResponseCacheConfig,
+        method: &str,
+        params: Value,
+        result: Value,
+    ) {
+        let request = serde_json::json!({ "id": 0, "method": method, "params": params });
+        if let Some(key) = cache_key(config, &request) {
+            self.insert(config, key, result);
+        }
+    }
+
+    // methods の応答をすべて破棄する (listChanged の通知を受け取ったとき)
+    pub fn invalidate(&self, methods: &[&str]) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|key, _| {
+                !methods.iter().any(|method| {
+                    key.strip_prefix(method)
+                        .is_some_and(|rest| rest.starts_with(' '))
+                })
+            });
+    }
+
+    fn insert(&self, config: &ResponseCacheConfig, key: String, result: Value) {
+        if result.get("isError").and_then(Value::as_bool) == Some(true) {
+            return;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (expires_at, _)| now < *expires_at);
+        while entries.len() >= config.max_entries.max(1) {
+            let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, (expires_at, _))| *expires_at)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+        entries.insert(key, (now + Duration::from_secs(config.ttl_secs), result));
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    pub fn snapshot(&self) -> ResponseCacheSnapshot {
+        ResponseCacheSnapshot {
+            entries: self.entries.lock().unwrap_or_else(|e| e.into_inner()).len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// キャッシュの対象であればキー (メソッドと、_meta を除いた params) を返す。
+// 通知 (id なし) とキャッシュしないメソッドは None
+fn cache_key(config: &ResponseCacheConfig, request: &Value) -> Option<String> {
+    if config.ttl_secs == 0 || request.get("id").is_none_or(Value::is_null) {
+        return None;
+    }
+    let method = request.get("method")?.as_str()?;
+    // params の省略と {} は同じものとして扱う
+    let mut params = request
+        .get("params")
+        .filter(|params| !params.is_null())
+        .cloned()
+        .unwrap_or_else(|| Value::Object(Default::default()));
+    if let Some(params) = params.as_object_mut() {
+        params.remove("_meta");
+    }
+    let cached = CACHED_METHODS.contains(&method)
+        || (method == "tools/call"
+            && params
+                .get("name")
+                .and_then(Value::as_str)
+                .is_some_and(|name| config.tools.iter().any(|tool| tool == name)));
+    // serde_json の Map はキーの順に並ぶため、同じ params は同じ文字列になる
+    cached.then(|| format!("{} {}", method, params))
+}
@@ -0,0 +1,192 @@
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::error;
+
+// --- 子プロセスが予期せず終了した場合の再起動方針 ---
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartMode {
+    // 終了コードにかかわらず再起動する
+    Always,
+    // 終了コード 0 以外 (シグナルによる終了・接続断を含む) の場合のみ再起動する
+    OnFailure,
+    // 再起動しない (次のリクエストやクラッシュ時の再送でも起動し直さない)
+    Never,
+}
+
+// 子プロセスの終了 (stop / restart による終了は expected)
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessExit {
+    pub exit_code: Option<i32>,
+    pub expected: bool,
+}
+
+impl ProcessExit {
+    fn is_failure(&self) -> bool {
+        self.exit_code != Some(0)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RestartPolicyConfig {
+    // 未設定の場合は従来どおり (lazy / idle_timeout_secs / warm_standby では次のリクエストで、
+    // retry_on_crash では再送時に起動し直し、回数の上限はない)
+    #[serde(default)]
+    pub restart: Option<RestartMode>,
+    // restart_window_secs の間に自動で再起動する回数の上限 (0 で無制限)
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: u32,
+    #[serde(default = "default_restart_window_secs")]
+    pub restart_window_secs: u64,
+}
+
+fn default_max_restarts() -> u32 {
+    5
+}
+
+fn default_restart_window_secs() -> u64 {
+    60
+}
+
+impl RestartPolicyConfig {
+    // 予期しない終了をスーパーバイザーが即座に再起動するか
+    pub fn supervises(&self) -> bool {
+        matches!(
+            self.restart,
+            Some(RestartMode::Always | RestartMode::OnFailure)
+        )
+    }
+
+    fn restarts_after(&self, exit: Option<&ProcessExit>) -> bool {
+        match self.restart {
+            None | Some(RestartMode::Always) => true,
+            // 終了状態が分からない場合は失敗として扱う
+            Some(RestartMode::OnFailure) => exit.is_none_or(ProcessExit::is_failure),
+            Some(RestartMode::Never) => false,
+        }
+    }
+}
+
+// --- 自動再起動の回数制限 ---
+// プロセスの再起動をまたいで保持し、restart_window_secs の間の再起動時刻を記録する
+pub struct RestartBudget {
+    server_key: String,
+    restarts: Mutex<VecDeque<Instant>>,
+}
+
+impl RestartBudget {
+    pub fn new(server_key: &str) -> Self {
+        RestartBudget {
+            server_key: server_key.to_string(),
+            restarts: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // 方針と回数の上限の両方を満たせば再起動を記録して true を返す
+    pub fn try_restart(&self, config: &RestartPolicyConfig, exit: Option<&ProcessExit>) -> bool {
+        if !config.restarts_after(exit) {
+            error!(
+                server = %self.server_key,
+                exit_code = ?exit.and_then(|exit| exit.exit_code),
+                restart = ?config.restart,
+                "MCP process exited, not restarting due to restart policy"
+            );
+            return false;
+        }
+        // 未設定の場合は上限を設けない
+        if config.restart.is_none() || config.max_restarts == 0 {
+            return true;
+        }
+        let window = Duration::from_secs(config.restart_window_secs);
+        let mut restarts = self
+            .restarts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        while restarts
+            .front()
+            .is_some_and(|restarted_at| restarted_at.elapsed() >= window)
+        {
+            restarts.pop_front();
+        }
+        if restarts.len() >= config.max_restarts as usize {
+            error!(
+                server = %self.server_key,
+                max_restarts = config.max_restarts,
+                restart_window_secs = config.restart_window_secs,
+                "MCP process restart limit reached, leaving it stopped"
+            );
+            return false;
+        }
+        restarts.push_back(Instant::now());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(restart: Option<RestartMode>, max_restarts: u32) -> RestartPolicyConfig {
+        RestartPolicyConfig {
+            restart,
+            max_restarts,
+            restart_window_secs: 60,
+        }
+    }
+
+    fn exit(exit_code: Option<i32>) -> ProcessExit {
+        ProcessExit {
+            exit_code,
+            expected: false,
+        }
+    }
+
+    #[test]
+    fn mode_decides_which_exits_restart() {
+        let budget = RestartBudget::new("test");
+        let always = policy(Some(RestartMode::Always), 0);
+        let on_failure = policy(Some(RestartMode::OnFailure), 0);
+        let never = policy(Some(RestartMode::Never), 0);
+
+        assert!(budget.try_restart(&always, Some(&exit(Some(0)))));
+        assert!(!budget.try_restart(&on_failure, Some(&exit(Some(0)))));
+        assert!(budget.try_restart(&on_failure, Some(&exit(Some(1)))));
+        // シグナルによる終了と、終了状態が分からない場合は失敗として扱う
+        assert!(budget.try_restart(&on_failure, Some(&exit(None))));
+        assert!(budget.try_restart(&on_failure, None));
+        assert!(!budget.try_restart(&never, Some(&exit(Some(1)))));
+
+        assert!(always.supervises() && on_failure.supervises());
+        assert!(!never.supervises() && !policy(None, 5).supervises());
+    }
+
+    #[test]
+    fn budget_limits_restarts_within_the_window() {
+        let budget = RestartBudget::new("test");
+        let config = policy(Some(RestartMode::Always), 2);
+        assert!(budget.try_restart(&config, None));
+        assert!(budget.try_restart(&config, None));
+        assert!(!budget.try_restart(&config, None));
+
+        // 期間を過ぎた再起動は数えない
+        let expired = RestartPolicyConfig {
+            restart_window_secs: 0,
+            ..config
+        };
+        assert!(budget.try_restart(&expired, None));
+    }
+
+    #[test]
+    fn unset_mode_and_zero_max_restarts_are_unlimited() {
+        let budget = RestartBudget::new("test");
+        for config in [policy(None, 1), policy(Some(RestartMode::Always), 0)] {
+            for _ in 0..10 {
+                assert!(budget.try_restart(&config, Some(&exit(Some(1)))));
+            }
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use tracing::debug;
+
+// --- リクエストの書き換えルール ---
+// "rewrite": [{
+//   "match": { "method": "tools/call", "tool": ["create_issue", "search_*"], "paths": { "/params/arguments/visibility": "public" } },
+//   "set": { "/params/arguments/owner": "our-org" },
+//   "remove": ["/params/arguments/debug"],
+//   "transform": { "/params/arguments/query": { "prefix": "org:our-org " } }
+// }]
+// HTTP の入口で、一致したルールを定義の順にすべて適用する (inputSchema の検証・ツールの許可・拒否リスト・
+// Idempotency-Key・レスポンスキャッシュより前)
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct RewriteRules(Vec<RewriteRule>);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RewriteRule {
+    // 省略するとすべてのリクエストに一致する
+    #[serde(rename = "match", default)]
+    matcher: RewriteMatch,
+    // JSON ポインター → 値 (途中のオブジェクトがなければ作る)
+    #[serde(default)]
+    set: BTreeMap<String, Value>,
+    #[serde(default)]
+    remove: Vec<String>,
+    // JSON ポインター → 文字列の変換 (文字列以外の値と存在しない値はそのまま)
+    #[serde(default)]
+    transform: BTreeMap<String, Transform>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+struct RewriteMatch {
+    #[serde(default)]
+    method: Option<String>,
+    // tools/call のツール名 (末尾などに * を使える)。指定すると tools/call だけに一致する
+    #[serde(default, deserialize_with = "one_or_many")]
+    tool: Option<Vec<String>>,
+    // JSON ポインター → 値 (すべて等しい場合に一致)
+    #[serde(default)]
+    paths: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum Transform {
+    Lowercase,
+    Uppercase,
+    Trim,
+    // 既に付いている場合は付けない (何度適用しても同じ結果になるようにする)
+    Prefix(String),
+    Suffix(String),
+    Replace { from: String, to: String },
+}
+
+// "tool": "name" と "tool": ["a", "b"] のどちらも受け付ける
+fn one_or_many<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(Some(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(name) => vec![name],
+        OneOrMany::Many(names) => names,
+    }))
+}
+
+impl RewriteRules {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // JSON ポインターの形式と、変換の指定を検証する
+    pub fn validate(&self, server_key: &str) -> Result<(), String> {
+        for (index, rule) in self.0.iter().enumerate() {
+            let pointers = rule
+                .matcher
+                .paths
+                .keys()
+                .chain(rule.set.keys())
+                .chain(rule.remove.iter())
+                .chain(rule.transform.keys());
+            for pointer in pointers {
+                if !pointer.starts_with('/') {
+                    return Err(format!(
+                        "MCP server '{}' rewrite[{}]: '{}' is not a JSON pointer (must start with '/')",
+                        server_key, index, pointer
+                    ));
+                }
+            }
+            let empty_replace = rule.transform.values().any(
+                |transform| matches!(transform, Transform::Replace { from, .. } if from.is_empty()),
+            );
+            if empty_replace {
+                return Err(format!(
+                    "MCP server '{}' rewrite[{}]: replace 'from' must not be empty",
+                    server_key, index
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // 書き換えた場合だけ新しい文字列を返す (JSON-RPC のバッチは各メッセージに適用する)
+    pub fn apply(&self, command: &str) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut value: Value = serde_json::from_str(command).ok()?;
+        self.apply_message(&mut value).then(|| value.to_string())
+    }
+
+    pub fn apply_message(&self, message: &mut Value) -> bool {
+        let mut changed = false;
+        if let Value::Array(batch) = message {
+            for message in batch {
+                changed |= self.apply_message(message);
+            }
+            return changed;
+        }
+        for (index, rule) in self.0.iter().enumerate() {
+            if !rule.matcher.matches(message) {
+                continue;
+            }
+            let before = message.clone();
+            rule.apply(message);
+            if *message != before {
+                debug!(rule = index, "Rewrote request by rewrite rule");
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+impl RewriteMatch {
+    fn matches(&self, message: &Value) -> bool {
+        let method = message.get("method").and_then(Value::as_str);
+        if self
+            .method
+            .as_deref()
+            .is_some_and(|expected| method != Some(expected))
+        {
+            return false;
+        }
+        if let Some(patterns) = &self.tool {
+            let tool = message
+                .pointer("/params/name")
+                .and_then(Value::as_str)
+                .filter(|_| method == Some("tools/call"));
+            let Some(tool) = tool else {
+                return false;
+            };
+            if !patterns.iter().any(|pattern| glob_match(pattern, tool)) {
+                return false;
+            }
+        }
+        self.paths
+            .iter()
+            .all(|(pointer, expected)| message.pointer(pointer) == Some(expected))
+    }
+}
+
+impl RewriteRule {
+    fn apply(&self, message: &mut Value) {
+        for (pointer, value) in &self.set {
+            set_pointer(message, pointer, value.clone());
+        }
+        for pointer in &self.remove {
+            remove_pointer(message, pointer);
+        }
+        for (pointer, transform) in &self.transform {
+            if let Some(Value::String(text)) = message.pointer_mut(pointer) {
+                *text = transform.apply(text);
+            }
+        }
+    }
+}
+
+impl Transform {
+    fn apply(&self, text: &str) -> String {
+        match self {
+            Transform::Lowercase => text.to_lowercase(),
+            Transform::Uppercase => text.to_uppercase(),
+            Transform::Trim => text.trim().to_string(),
+            Transform::Prefix(prefix) if text.starts_with(prefix.as_str()) => text.to_string(),
+            Transform::Prefix(prefix) => format!("{}{}", prefix, text),
+            Transform::Suffix(suffix) if text.ends_with(suffix.as_str()) => text.to_string(),
+            Transform::Suffix(suffix) => format!("{}{}", text, suffix),
+            Transform::Replace { from, to } => text.replace(from.as_str(), to),
+        }
+    }
+}
+
+// JSON ポインターのトークン (~1 → /、~0 → ~)
+fn pointer_tokens(pointer: &str) -> Vec<String> {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect()
+}
+
+// 途中のオブジェクト (null も) がなければ作る。配列は既存の要素だけを辿る
+fn set_pointer(message: &mut Value, pointer: &str, value: Value) {
+    let tokens = pointer_tokens(pointer);
+    let Some((last, parents)) = tokens.split_last() else {
+        return;
+    };
+    let mut current = message;
+    for token in parents {
+        if current.is_null() {
+            *current = Value::Object(Map::new());
+        }
+        current = match current {
+            Value::Object(object) => object
+                .entry(token.clone())
+                .or_insert_with(|| Value::Object(Map::new())),
+            Value::Array(items) => match token.parse::<usize>().ok().and_then(|i| items.get_mut(i))
+            {
+                Some(item) => item,
+                None => return,
+            },
+            _ => return,
+        };
+    }
+    if current.is_null() {
+        *current = Value::Object(Map::new());
+    }
+    match current {
+        Value::Object(object) => {
+            object.insert(last.clone(), value);
+        }
+        Value::Array(items) => {
+            if let Some(item) = last.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                *item = value;
+            }
+        }
+        _ => {}
+    }
+}
+
+fn remove_pointer(message: &mut Value, pointer: &str) {
+    let Some((parent, last)) = pointer.rsplit_once('/') else {
+        return;
+    };
+    let last = last.replace("~1", "/").replace("~0", "~");
+    match message.pointer_mut(parent) {
+        Some(Value::Object(object)) => {
+            object.remove(&last);
+        }
+        Some(Value::Array(items)) => {
+            if let Some(index) = last.parse::<usize>().ok().filter(|i| *i < items.len()) {
+                items.remove(index);
+            }
+        }
+        _ => {}
+    }
+}
+
+// * を任意の文字列として扱うツール名のパターン
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // * を含まない場合は完全一致
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn glob_match_handles_wildcards() {
+        assert!(glob_match("search_issues", "search_issues"));
+        assert!(!glob_match("search_issues", "search_issues_v2"));
+        assert!(glob_match("search_*", "search_issues"));
+        assert!(glob_match("search_*", "search_"));
+        assert!(!glob_match("search_*", "list_issues"));
+        assert!(glob_match("*_issues", "list_issues"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("a*b*c", "a-b-c"));
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(!glob_match("a*b*c", "a-c-b"));
+        // 前後のパターンが重なる場合は一致しない
+        assert!(!glob_match("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn set_pointer_creates_objects_and_replaces_null() {
+        let mut message = json!({ "params": null });
+        set_pointer(&mut message, "/params/arguments/owner", json!("our-org"));
+        assert_eq!(
+            message,
+            json!({ "params": { "arguments": { "owner": "our-org" } } })
+        );
+    }
+
+    #[test]
+    fn set_pointer_only_follows_existing_array_items() {
+        let mut message = json!({ "items": [{ "a": 1 }, 2] });
+        set_pointer(&mut message, "/items/0/b", json!(true));
+        set_pointer(&mut message, "/items/1", json!(3));
+        set_pointer(&mut message, "/items/5/c", json!(false));
+        set_pointer(&mut message, "/items/x", json!(false));
+        assert_eq!(message, json!({ "items": [{ "a": 1, "b": true }, 3] }));
+    }
+
+    #[test]
+    fn remove_pointer_unescapes_tokens() {
+        let mut message = json!({ "a/b": { "c~d": 1, "keep": 2 }, "list": [1, 2, 3] });
+        remove_pointer(&mut message, "/a~1b/c~0d");
+        remove_pointer(&mut message, "/list/1");
+        remove_pointer(&mut message, "/list/9");
+        assert_eq!(message, json!({ "a/b": { "keep": 2 }, "list": [1, 3] }));
+    }
+
+    #[test]
+    fn prefix_and_suffix_are_idempotent() {
+        let prefix = Transform::Prefix("org:our-org ".to_string());
+        let once = prefix.apply("is:open");
+        assert_eq!(once, "org:our-org is:open");
+        assert_eq!(prefix.apply(&once), once);
+
+        let suffix = Transform::Suffix(".md".to_string());
+        let once = suffix.apply("README");
+        assert_eq!(once, "README.md");
+        assert_eq!(suffix.apply(&once), once);
+    }
+
+    #[test]
+    fn rules_apply_to_matching_tool_calls_in_a_batch() {
+        let rules: RewriteRules = serde_json::from_value(json!([{
+            "match": { "tool": "create_*" },
+            "set": { "/params/arguments/owner": "our-org" },
+        }]))
+        .unwrap();
+        let mut batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": { "name": "create_issue" } },
+            { "jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": { "name": "list_issues" } },
+        ]);
+        assert!(rules.apply_message(&mut batch));
+        assert_eq!(batch[0]["params"]["arguments"]["owner"], "our-org");
+        assert!(batch[1]["params"].get("arguments").is_none());
+    }
+}
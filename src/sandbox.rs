@@ -0,0 +1,268 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::mcp_process::McpProcessConfig;
+
+// --- 子プロセスのサンドボックス (Linux のみ) ---
+// "sandbox": { "uid": 1000, "gid": 1000, "root": "/srv/jail", "bind_paths": ["/usr", "/lib"], "network": false }
+// root / bind_paths / network: false には root 権限 (CAP_SYS_ADMIN / CAP_SYS_CHROOT) が必要
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub struct SandboxConfig {
+    // 実行するユーザー・グループ (補助グループは外す)
+    #[serde(default)]
+    pub uid: Option<u32>,
+    #[serde(default)]
+    pub gid: Option<u32>,
+    // 子プロセスのルートディレクトリ (chroot)。cwd はこの中のパスとして扱う
+    #[serde(default)]
+    pub root: Option<PathBuf>,
+    // root の中の同じパスに読み取り専用でバインドマウントするホストのパス
+    #[serde(default)]
+    pub bind_paths: Vec<PathBuf>,
+    // bind_paths と同じだが書き込みを許可する
+    #[serde(default)]
+    pub bind_paths_rw: Vec<PathBuf>,
+    // false の場合はネットワーク名前空間を分け、ループバックも含めて通信できなくする
+    #[serde(default = "default_network")]
+    pub network: bool,
+}
+
+fn default_network() -> bool {
+    true
+}
+
+impl SandboxConfig {
+    pub fn validate(&self, server_key: &str) -> Result<(), String> {
+        if cfg!(not(target_os = "linux")) {
+            return Err(format!(
+                "MCP server '{}' uses a sandbox, which is only supported on Linux",
+                server_key
+            ));
+        }
+        let binds = self.bind_paths.iter().chain(&self.bind_paths_rw);
+        if self.root.is_none() && binds.clone().next().is_some() {
+            return Err(format!(
+                "MCP server '{}' sandbox bind_paths require root",
+                server_key
+            ));
+        }
+        if let Some(path) = self
+            .root
+            .iter()
+            .chain(binds)
+            .find(|path| !path.is_absolute())
+        {
+            return Err(format!(
+                "MCP server '{}' sandbox path '{}' must be absolute",
+                server_key,
+                path.display()
+            ));
+        }
+        Ok(())
+    }
+}
+
+// 子プロセス・フック・ヘルスチェックのコマンドに作業ディレクトリとサンドボックスを設定する
+pub fn apply(
+    command: &mut Command,
+    server_key: &str,
+    config: &McpProcessConfig,
+) -> Result<(), String> {
+    let root = config
+        .sandbox
+        .as_ref()
+        .and_then(|sandbox| sandbox.root.as_deref());
+    if let Some(host_cwd) = host_cwd(config) {
+        // spawn のエラーだけではコマンドと作業ディレクトリのどちらが無いのか分からない
+        if !host_cwd.is_dir() {
+            return Err(format!(
+                "Working directory '{}' for MCP server '{}' does not exist",
+                host_cwd.display(),
+                server_key
+            ));
+        }
+        // root がある場合は chroot 後に移動する
+        if root.is_none() {
+            command.current_dir(host_cwd);
+        }
+    }
+    match &config.sandbox {
+        Some(sandbox) => apply_sandbox(command, server_key, sandbox, config.cwd.as_deref()),
+        None => Ok(()),
+    }
+}
+
+// ブリッジから見た作業ディレクトリ (sandbox.root がある場合は cwd を root の中のパスとして解決する)
+pub fn host_cwd(config: &McpProcessConfig) -> Option<PathBuf> {
+    let cwd = config.cwd.as_deref()?;
+    match config
+        .sandbox
+        .as_ref()
+        .and_then(|sandbox| sandbox.root.as_deref())
+    {
+        Some(root) => Some(jailed(root, cwd)),
+        None => Some(cwd.to_path_buf()),
+    }
+}
+
+// root の中のパス (ホスト側から見たパス)
+fn jailed(root: &Path, path: &Path) -> PathBuf {
+    root.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+#[cfg(target_os = "linux")]
+fn apply_sandbox(
+    command: &mut Command,
+    server_key: &str,
+    sandbox: &SandboxConfig,
+    cwd: Option<&Path>,
+) -> Result<(), String> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let to_cstring = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes()).map_err(|e| {
+            format!(
+                "Invalid sandbox path '{}' for MCP server '{}': {}",
+                path.display(),
+                server_key,
+                e
+            )
+        })
+    };
+
+    // chroot 後の作業ディレクトリ
+    let workdir = match &sandbox.root {
+        Some(_) => Some(to_cstring(cwd.unwrap_or(Path::new("/")))?),
+        None => None,
+    };
+    let root = sandbox.root.as_deref().map(to_cstring).transpose()?;
+
+    // マウント先は fork 前に作っておく (ファイルならば空のファイル)
+    let mut mounts = Vec::new();
+    let binds = sandbox
+        .bind_paths
+        .iter()
+        .map(|path| (path, false))
+        .chain(sandbox.bind_paths_rw.iter().map(|path| (path, true)));
+    for (source, writable) in binds {
+        let Some(root) = &sandbox.root else {
+            break;
+        };
+        let target = jailed(root, source);
+        let created = if source.is_dir() {
+            std::fs::create_dir_all(&target)
+        } else {
+            target
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| {
+                    std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&target)
+                        .map(|_| ())
+                })
+        };
+        created.map_err(|e| {
+            format!(
+                "Failed to prepare sandbox mount '{}' for MCP server '{}': {}",
+                target.display(),
+                server_key,
+                e
+            )
+        })?;
+        mounts.push((to_cstring(source)?, to_cstring(&target)?, writable));
+    }
+
+    let uid = sandbox.uid;
+    let gid = sandbox.gid;
+    let isolate_network = !sandbox.network;
+
+    // fork 後・exec 前の子プロセスで実行する (非同期シグナル安全な libc の呼び出しのみ)。
+    // std の uid/gid は pre_exec より先に適用されて chroot できなくなるため、ここでまとめて行う
+    let setup = move || -> std::io::Result<()> {
+        let check = |result: libc::c_int| {
+            if result == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        };
+        let mut flags = 0;
+        if isolate_network {
+            flags |= libc::CLONE_NEWNET;
+        }
+        if !mounts.is_empty() {
+            flags |= libc::CLONE_NEWNS;
+        }
+        unsafe {
+            if flags != 0 {
+                check(libc::unshare(flags))?;
+            }
+            if !mounts.is_empty() {
+                // 子プロセスのマウントがホストに伝播しないようにする
+                check(libc::mount(
+                    std::ptr::null(),
+                    c"/".as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    std::ptr::null(),
+                ))?;
+            }
+            for (source, target, writable) in &mounts {
+                check(libc::mount(
+                    source.as_ptr(),
+                    target.as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_BIND | libc::MS_REC,
+                    std::ptr::null(),
+                ))?;
+                if !writable {
+                    check(libc::mount(
+                        std::ptr::null(),
+                        target.as_ptr(),
+                        std::ptr::null(),
+                        libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | libc::MS_REC,
+                        std::ptr::null(),
+                    ))?;
+                }
+            }
+            if let Some(root) = &root {
+                check(libc::chroot(root.as_ptr()))?;
+            }
+            if let Some(workdir) = &workdir {
+                check(libc::chdir(workdir.as_ptr()))?;
+            }
+            if uid.is_some() || gid.is_some() {
+                check(libc::setgroups(0, std::ptr::null()))?;
+            }
+            if let Some(gid) = gid {
+                check(libc::setgid(gid))?;
+            }
+            if let Some(uid) = uid {
+                check(libc::setuid(uid))?;
+            }
+        }
+        Ok(())
+    };
+    unsafe {
+        command.pre_exec(setup);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_sandbox(
+    _command: &mut Command,
+    server_key: &str,
+    _sandbox: &SandboxConfig,
+    _cwd: Option<&Path>,
+) -> Result<(), String> {
+    Err(format!(
+        "MCP server '{}' uses a sandbox, which is only supported on Linux",
+        server_key
+    ))
+}
@@ -0,0 +1,378 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::{fmt, path::PathBuf};
+
+use crate::wasm_plugins::WasmPlugins;
+
+#[cfg(feature = "scripting")]
+use std::sync::Arc;
+#[cfg(feature = "scripting")]
+use tracing::{debug, info};
+
+// スクリプトが throw で拒否した場合の既定の JSON-RPC エラーコードと HTTP ステータス
+pub const SCRIPT_REJECTED_ERROR_CODE: i64 = -32003;
+#[cfg(feature = "scripting")]
+const SCRIPT_REJECTED_STATUS: u16 = 403;
+
+// 1回の実行で評価できる式の数の上限 (無限ループでリクエストを止めないようにする)
+fn default_max_operations() -> u64 {
+    100_000
+}
+
+// --- 設定ファイルのスクリプトの指定 ---
+// "scripts": { "on_request": "scripts/route.rhai", "on_response": "scripts/redact.rhai" }
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct ScriptPaths {
+    #[serde(default)]
+    on_request: Option<PathBuf>,
+    #[serde(default)]
+    on_response: Option<PathBuf>,
+    #[serde(default = "default_max_operations")]
+    max_operations: u64,
+}
+
+// --- rhai スクリプトによるリクエスト・レスポンスのフック ---
+// 設定の読み込み時にファイルを読んでコンパイルしておき、リクエストごとに実行する。
+// on_request は request を書き換え、target で転送先のサーバーを選び、throw で拒否できる。
+// on_response は response を書き換え、throw でエラーに差し替えられる
+#[derive(Deserialize, Clone)]
+#[serde(try_from = "ScriptPaths")]
+pub struct ScriptHooks {
+    paths: ScriptPaths,
+    #[cfg(feature = "scripting")]
+    compiled: Arc<Compiled>,
+}
+
+// Debug でスクリプトの中身を出力しない
+impl fmt::Debug for ScriptHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptHooks")
+            .field("on_request", &self.paths.on_request)
+            .field("on_response", &self.paths.on_response)
+            .field("max_operations", &self.paths.max_operations)
+            .finish()
+    }
+}
+
+#[cfg(feature = "scripting")]
+struct Compiled {
+    engine: rhai::Engine,
+    on_request: Option<rhai::AST>,
+    on_response: Option<rhai::AST>,
+}
+
+#[cfg(feature = "scripting")]
+impl TryFrom<ScriptPaths> for ScriptHooks {
+    type Error = String;
+
+    fn try_from(paths: ScriptPaths) -> Result<Self, String> {
+        let mut engine = rhai::Engine::new();
+        engine.set_max_operations(paths.max_operations);
+        engine.on_print(|text| info!(target: "script", "{}", text));
+        engine.on_debug(|text, source, position| {
+            debug!(target: "script", source = ?source, position = %position, "{}", text)
+        });
+        let compile = |path: &Option<PathBuf>| -> Result<Option<rhai::AST>, String> {
+            let Some(path) = path else {
+                return Ok(None);
+            };
+            let source = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read script '{}': {}", path.display(), e))?;
+            engine
+                .compile(source)
+                .map(Some)
+                .map_err(|e| format!("Failed to compile script '{}': {}", path.display(), e))
+        };
+        let on_request = compile(&paths.on_request)?;
+        let on_response = compile(&paths.on_response)?;
+        Ok(ScriptHooks {
+            paths,
+            compiled: Arc::new(Compiled {
+                engine,
+                on_request,
+                on_response,
+            }),
+        })
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+impl TryFrom<ScriptPaths> for ScriptHooks {
+    type Error = String;
+
+    fn try_from(_paths: ScriptPaths) -> Result<Self, String> {
+        Err("scripts are not compiled in; rebuild with `--features scripting`".to_string())
+    }
+}
+
+// on_request の結果 (target はスクリプトが選んだ転送先のサーバー名)
+pub struct ScriptedRequest {
+    pub request: Value,
+    pub target: Option<String>,
+}
+
+// スクリプトが throw した拒否、またはスクリプト自体の失敗
+#[derive(Debug)]
+pub enum ScriptError {
+    Rejected {
+        status: u16,
+        code: i64,
+        message: String,
+        data: Option<Value>,
+    },
+    Failed(String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Rejected { message, .. } | ScriptError::Failed(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+impl ScriptHooks {
+    pub fn has_on_response(&self) -> bool {
+        self.paths.on_response.is_some()
+    }
+
+    // JSON-RPC のバッチ (配列) は各メッセージに適用する。バッチでは転送先を変えられない
+    pub fn on_request_message(
+        &self,
+        server: &str,
+        message: Value,
+    ) -> Result<ScriptedRequest, ScriptError> {
+        let Value::Array(batch) = message else {
+            return self.on_request(server, message);
+        };
+        let mut messages = Vec::with_capacity(batch.len());
+        for message in batch {
+            let scripted = self.on_request(server, message)?;
+            if let Some(target) = scripted.target {
+                return Err(ScriptError::Failed(format!(
+                    "on_request script cannot route a JSON-RPC batch to '{}'",
+                    target
+                )));
+            }
+            messages.push(scripted.request);
+        }
+        Ok(ScriptedRequest {
+            request: Value::Array(messages),
+            target: None,
+        })
+    }
+
+    // バッチのレスポンスは id が同じリクエストと組にして適用する
+    pub fn on_response_message(
+        &self,
+        server: &str,
+        request: &Value,
+        response: Value,
+    ) -> Result<Value, ScriptError> {
+        let (Value::Array(requests), Value::Array(responses)) = (request, &response) else {
+            return self.on_response(server, request, response);
+        };
+        let mut scripted = Vec::with_capacity(responses.len());
+        for response in responses {
+            let request = requests
+                .iter()
+                .find(|request| request.get("id") == response.get("id"))
+                .unwrap_or(&Value::Null);
+            scripted.push(self.on_response(server, request, response.clone())?);
+        }
+        Ok(Value::Array(scripted))
+    }
+
+    // スコープ: request (書き換え可)、server (受け付けたサーバー名)、target (空なら転送先を変えない)
+    #[cfg(feature = "scripting")]
+    fn on_request(&self, server: &str, request: Value) -> Result<ScriptedRequest, ScriptError> {
+        let Some(ast) = &self.compiled.on_request else {
+            return Ok(ScriptedRequest {
+                request,
+                target: None,
+            });
+        };
+        let mut scope = rhai::Scope::new();
+        scope.push_dynamic("request", to_dynamic(&request)?);
+        scope.push("server", server.to_string());
+        scope.push("target", String::new());
+        self.run(&mut scope, ast, "on_request")?;
+        let request = from_scope(&scope, "request")?;
+        let target = scope
+            .get_value::<String>("target")
+            .filter(|target| !target.is_empty() && target != server);
+        Ok(ScriptedRequest { request, target })
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn on_request(&self, _server: &str, request: Value) -> Result<ScriptedRequest, ScriptError> {
+        Ok(ScriptedRequest {
+            request,
+            target: None,
+        })
+    }
+
+    // スコープ: request (転送したリクエスト)、response (書き換え可)、server
+    #[cfg(feature = "scripting")]
+    fn on_response(
+        &self,
+        server: &str,
+        request: &Value,
+        response: Value,
+    ) -> Result<Value, ScriptError> {
+        let Some(ast) = &self.compiled.on_response else {
+            return Ok(response);
+        };
+        let mut scope = rhai::Scope::new();
+        scope.push_dynamic("request", to_dynamic(request)?);
+        scope.push_dynamic("response", to_dynamic(&response)?);
+        scope.push("server", server.to_string());
+        self.run(&mut scope, ast, "on_response")?;
+        from_scope(&scope, "response")
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn on_response(
+        &self,
+        _server: &str,
+        _request: &Value,
+        response: Value,
+    ) -> Result<Value, ScriptError> {
+        Ok(response)
+    }
+
+    #[cfg(feature = "scripting")]
+    fn run(&self, scope: &mut rhai::Scope, ast: &rhai::AST, hook: &str) -> Result<(), ScriptError> {
+        self.compiled
+            .engine
+            .run_ast_with_scope(scope, ast)
+            .map_err(|e| script_error(*e, hook))
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn to_dynamic(value: &Value) -> Result<rhai::Dynamic, ScriptError> {
+    rhai::serde::to_dynamic(value)
+        .map_err(|e| ScriptError::Failed(format!("Failed to pass JSON to script: {}", e)))
+}
+
+#[cfg(feature = "scripting")]
+fn from_scope(scope: &rhai::Scope, name: &str) -> Result<Value, ScriptError> {
+    let value = scope
+        .get_value::<rhai::Dynamic>(name)
+        .ok_or_else(|| ScriptError::Failed(format!("Script removed '{}'", name)))?;
+    rhai::serde::from_dynamic(&value)
+        .map_err(|e| ScriptError::Failed(format!("Script left an invalid '{}': {}", name, e)))
+}
+
+// throw "message" または throw #{ message, code, status, data } を拒否として扱う
+#[cfg(feature = "scripting")]
+fn script_error(error: rhai::EvalAltResult, hook: &str) -> ScriptError {
+    match error {
+        rhai::EvalAltResult::ErrorRuntime(thrown, _) => rejection(thrown),
+        // 関数の中で throw した場合
+        rhai::EvalAltResult::ErrorInFunctionCall(_, _, inner, _) => script_error(*inner, hook),
+        other => ScriptError::Failed(format!("{} script failed: {}", hook, other)),
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn rejection(thrown: rhai::Dynamic) -> ScriptError {
+    let default_message = || "Request rejected by script".to_string();
+    if thrown.is_string() {
+        return ScriptError::Rejected {
+            status: SCRIPT_REJECTED_STATUS,
+            code: SCRIPT_REJECTED_ERROR_CODE,
+            message: thrown.to_string(),
+            data: None,
+        };
+    }
+    let fields: Value = rhai::serde::from_dynamic(&thrown).unwrap_or(Value::Null);
+    ScriptError::Rejected {
+        status: fields
+            .get("status")
+            .and_then(Value::as_u64)
+            .and_then(|status| u16::try_from(status).ok())
+            .filter(|status| (400..600).contains(status))
+            .unwrap_or(SCRIPT_REJECTED_STATUS),
+        code: fields
+            .get("code")
+            .and_then(Value::as_i64)
+            .unwrap_or(SCRIPT_REJECTED_ERROR_CODE),
+        message: fields
+            .get("message")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(default_message),
+        data: fields.get("data").cloned(),
+    }
+}
+
+// --- scripts と wasm_plugins をまとめたフック ---
+// rhai スクリプトの後に WASM プラグインを実行する (プラグインはスクリプトが書き換えた後のメッセージと、
+// スクリプトが選んだ転送先のサーバー名を受け取る)
+#[derive(Clone)]
+pub struct RequestHooks {
+    scripts: Option<ScriptHooks>,
+    plugins: Option<WasmPlugins>,
+}
+
+impl RequestHooks {
+    // どちらも設定されていなければ None
+    pub fn new(scripts: Option<ScriptHooks>, plugins: Option<WasmPlugins>) -> Option<Self> {
+        (scripts.is_some() || plugins.is_some()).then_some(RequestHooks { scripts, plugins })
+    }
+
+    pub fn has_on_response(&self) -> bool {
+        self.scripts
+            .as_ref()
+            .is_some_and(ScriptHooks::has_on_response)
+            || self
+                .plugins
+                .as_ref()
+                .is_some_and(WasmPlugins::has_on_response)
+    }
+
+    pub async fn on_request_message(
+        &self,
+        server: &str,
+        tenant: Option<&str>,
+        message: Value,
+    ) -> Result<ScriptedRequest, ScriptError> {
+        let mut scripted = match &self.scripts {
+            Some(scripts) => scripts.on_request_message(server, message)?,
+            None => ScriptedRequest {
+                request: message,
+                target: None,
+            },
+        };
+        if let Some(plugins) = &self.plugins {
+            let server = scripted.target.as_deref().unwrap_or(server);
+            scripted.request = plugins
+                .on_request_message(server, tenant, scripted.request)
+                .await?;
+        }
+        Ok(scripted)
+    }
+
+    pub async fn on_response_message(
+        &self,
+        server: &str,
+        tenant: Option<&str>,
+        request: &Value,
+        mut response: Value,
+    ) -> Result<Value, ScriptError> {
+        if let Some(scripts) = &self.scripts {
+            response = scripts.on_response_message(server, request, response)?;
+        }
+        if let Some(plugins) = &self.plugins {
+            response = plugins
+                .on_response_message(server, tenant, request, response)
+                .await?;
+        }
+        Ok(response)
+    }
+}
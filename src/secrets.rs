@@ -0,0 +1,212 @@
+use aes_gcm::{
+    Aes256Gcm, Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use std::{collections::HashMap, env, io::Read};
+
+// 設定の env で暗号化した値を表す接頭辞
+const ENCRYPTED_PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 12;
+
+// --- 設定ファイルの暗号化した値 ---
+// env の値を "enc:<base64>" (12バイトの nonce と AES-256-GCM の暗号文) で書けるようにする。
+// 鍵は MCP_CONFIG_KEY_FILE の 32 バイト (base64 または 16進数) で、子プロセス・フック・
+// ヘルスチェックのコマンドを起動するときに復号する。
+// "<サーバー名>/<変数名>" を AAD にするため、値を別のサーバーや変数にコピーしても復号できない
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+// enc: の値を復号した env (暗号化した値がなければそのまま)
+pub fn decrypt_env(
+    server_key: &str,
+    env: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, String> {
+    if !env.values().any(|value| is_encrypted(value)) {
+        return Ok(env.clone());
+    }
+    let cipher = load_key()?;
+    env.iter()
+        .map(|(name, value)| {
+            let value = match value.strip_prefix(ENCRYPTED_PREFIX) {
+                Some(encoded) => {
+                    decrypt(&cipher, &aad(server_key, name), encoded).map_err(|e| {
+                        format!(
+                            "Failed to decrypt env '{}' of MCP server '{}': {}",
+                            name, server_key, e
+                        )
+                    })?
+                }
+                None => value.clone(),
+            };
+            Ok((name.clone(), value))
+        })
+        .collect()
+}
+
+// 設定の読み込み時に、暗号化した値を復号できるか確認する
+pub fn validate(server_key: &str, env: &HashMap<String, String>) -> Result<(), String> {
+    decrypt_env(server_key, env).map(|_| ())
+}
+
+// `mcp-http-server encrypt --name <server>/<ENV_VAR>`: 標準入力の値 (末尾の改行は除く) を暗号化する
+pub fn encrypt_stdin(name: Option<&str>) -> Result<String, String> {
+    let (server_key, env_name) = name.and_then(|name| name.rsplit_once('/')).ok_or_else(|| {
+        "encrypt requires --name <server>/<ENV_VAR>, the server and env variable the value is for"
+            .to_string()
+    })?;
+    if server_key.is_empty() || env_name.is_empty() {
+        return Err(format!(
+            "Invalid --name '{}', expected <server>/<ENV_VAR>",
+            name.unwrap_or_default()
+        ));
+    }
+    let mut plaintext = String::new();
+    std::io::stdin()
+        .read_to_string(&mut plaintext)
+        .map_err(|e| format!("Failed to read value from stdin: {}", e))?;
+    encrypt(
+        &load_key()?,
+        &aad(server_key, env_name),
+        plaintext.trim_end_matches(['\r', '\n']),
+    )
+}
+
+// 暗号文を書かれたサーバーと変数に結び付ける追加認証データ
+fn aad(server_key: &str, env_name: &str) -> String {
+    format!("{}/{}", server_key, env_name)
+}
+
+fn encrypt(cipher: &Aes256Gcm, aad: &str, plaintext: &str) -> Result<String, String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: plaintext.as_bytes(),
+        aad: aad.as_bytes(),
+    };
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| "Failed to encrypt value".to_string())?;
+    let mut data = nonce.to_vec();
+    data.extend(ciphertext);
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(data)))
+}
+
+fn decrypt(cipher: &Aes256Gcm, aad: &str, encoded: &str) -> Result<String, String> {
+    let data = STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("invalid base64: {}", e))?;
+    if data.len() <= NONCE_LEN {
+        return Err("value is too short".to_string());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: aad.as_bytes(),
+    };
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| {
+            format!(
+                "wrong key, corrupted value, or value encrypted for another server or variable (re-encrypt with `encrypt --name {}`)",
+                aad
+            )
+        })?;
+    String::from_utf8(plaintext).map_err(|_| "decrypted value is not UTF-8".to_string())
+}
+
+// 鍵はファイルを差し替えれば再起動なしで使われるよう、毎回読み込む
+fn load_key() -> Result<Aes256Gcm, String> {
+    let path = env::var("MCP_CONFIG_KEY_FILE")
+        .ok()
+        .filter(|path| !path.is_empty())
+        .ok_or_else(|| "Encrypted config values require MCP_CONFIG_KEY_FILE".to_string())?;
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read MCP_CONFIG_KEY_FILE '{}': {}", path, e))?;
+    parse_key(&content).ok_or_else(|| {
+        format!(
+            "MCP_CONFIG_KEY_FILE '{}' must contain a 32-byte key as base64 or hex",
+            path
+        )
+    })
+}
+
+// 64桁の16進数、または base64 の 32 バイト
+fn parse_key(content: &str) -> Option<Aes256Gcm> {
+    let content = content.trim();
+    let key = match content.len() {
+        64 if content.is_ascii() => (0..64)
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&content[index..index + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>(),
+        _ => STANDARD.decode(content).ok(),
+    };
+    key.and_then(|key| Aes256Gcm::new_from_slice(&key).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn key() -> Aes256Gcm {
+        parse_key(KEY_HEX).unwrap()
+    }
+
+    fn encrypted(aad: &str, plaintext: &str) -> String {
+        let value = encrypt(&key(), aad, plaintext).unwrap();
+        value.strip_prefix(ENCRYPTED_PREFIX).unwrap().to_string()
+    }
+
+    #[test]
+    fn round_trip_with_the_same_server_and_variable() {
+        let aad = aad("github", "GITHUB_TOKEN");
+        let encoded = encrypted(&aad, "ghp_secret");
+        assert_eq!(decrypt(&key(), &aad, &encoded).unwrap(), "ghp_secret");
+        // nonce が毎回変わるため、同じ値でも暗号文は異なる
+        assert_ne!(encoded, encrypted(&aad, "ghp_secret"));
+        assert_eq!(decrypt(&key(), &aad, &encrypted(&aad, "")).unwrap(), "");
+    }
+
+    #[test]
+    fn values_do_not_decrypt_for_another_server_or_variable() {
+        let encoded = encrypted(&aad("github", "GITHUB_TOKEN"), "ghp_secret");
+        for other in [aad("gitlab", "GITHUB_TOKEN"), aad("github", "OTHER_TOKEN")] {
+            let error = decrypt(&key(), &other, &encoded).unwrap_err();
+            assert!(error.contains("another server or variable"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn wrong_key_and_malformed_values_fail() {
+        let aad = aad("github", "GITHUB_TOKEN");
+        let encoded = encrypted(&aad, "ghp_secret");
+        let other_key = parse_key(&STANDARD.encode([7u8; 32])).unwrap();
+        assert!(decrypt(&other_key, &aad, &encoded).is_err());
+
+        assert_eq!(
+            decrypt(&key(), &aad, &STANDARD.encode([0u8; NONCE_LEN])).unwrap_err(),
+            "value is too short"
+        );
+        assert!(
+            decrypt(&key(), &aad, "not base64!")
+                .unwrap_err()
+                .starts_with("invalid base64")
+        );
+        // 暗号文を1バイトでも変えると認証に失敗する
+        let mut data = STANDARD.decode(&encoded).unwrap();
+        *data.last_mut().unwrap() ^= 1;
+        assert!(decrypt(&key(), &aad, &STANDARD.encode(data)).is_err());
+    }
+
+    #[test]
+    fn keys_are_32_bytes_of_hex_or_base64() {
+        assert!(parse_key(&format!("{}\n", KEY_HEX)).is_some());
+        assert!(parse_key(&KEY_HEX.to_uppercase()).is_some());
+        assert!(parse_key(&STANDARD.encode([1u8; 32])).is_some());
+        assert!(parse_key(&STANDARD.encode([1u8; 16])).is_none());
+        assert!(parse_key(&KEY_HEX.replace('0', "g")).is_none());
+        assert!(parse_key("").is_none());
+    }
+}
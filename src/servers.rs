@@ -0,0 +1,114 @@
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use crate::mcp_process::McpServer;
+
+// MCP_SERVER_NAME にこの名前を指定すると (同じ名前・別名の定義がなければ) 全サーバーを起動する
+pub const ALL_SERVERS: &str = "all";
+
+// --- ブリッジしているサーバーの一覧 ---
+// MCP_SERVER_NAME=all (または MCP_START_ALL=true) の場合は設定ファイルの全サーバー、
+// それ以外は MCP_SERVER_NAME の1つだけを名前 (または aliases の別名) で引けるようにする。
+// パスにサーバー名を含まないリクエストは既定のサーバーが受け付ける
+pub struct ServerSet {
+    // 名前順
+    servers: BTreeMap<String, Arc<McpServer>>,
+    // 別名 → サーバー名 (起動時の設定から作る)
+    aliases: HashMap<String, String>,
+    default_key: String,
+}
+
+impl ServerSet {
+    // 既定のサーバーは default: true の定義、なければ preferred (MCP_SERVER_NAME)、
+    // それもなければ名前順で最初のサーバー。servers は空でないこと
+    pub fn new(servers: Vec<Arc<McpServer>>, preferred: &str) -> Self {
+        let servers: BTreeMap<String, Arc<McpServer>> = servers
+            .into_iter()
+            .map(|server| (server.server_key.clone(), server))
+            .collect();
+        let aliases: HashMap<String, String> = servers
+            .values()
+            .flat_map(|server| {
+                server
+                    .config()
+                    .aliases
+                    .iter()
+                    .map(|alias| (alias.clone(), server.server_key.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let default_key = servers
+            .values()
+            .find(|server| server.config().default)
+            .map(|server| server.server_key.clone())
+            .or_else(|| {
+                servers
+                    .contains_key(preferred)
+                    .then(|| preferred.to_string())
+                    .or_else(|| aliases.get(preferred).cloned())
+            })
+            .or_else(|| servers.keys().next().cloned())
+            .unwrap_or_default();
+        ServerSet {
+            servers,
+            aliases,
+            default_key,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<McpServer>> {
+        self.servers.get(name).or_else(|| {
+            self.aliases
+                .get(name)
+                .and_then(|server_key| self.servers.get(server_key))
+        })
+    }
+
+    pub fn default_server(&self) -> &Arc<McpServer> {
+        &self.servers[&self.default_key]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<McpServer>> {
+        self.servers.values()
+    }
+}
+
+// --- 起動するサーバーの選択 ---
+// 前処理したサーバー定義 (名前 → JSON) から起動するサーバーの名前を選ぶ。
+// requested (MCP_SERVER_NAME) は名前・別名の順に探し、どちらでもない "all" は全サーバーを意味する。
+// 未設定の場合は default: true の定義、なければ fallback。見つからなければ None
+pub fn select(
+    definitions: &Map<String, Value>,
+    requested: Option<&str>,
+    fallback: &str,
+    start_all: bool,
+) -> Option<Vec<String>> {
+    let all = || definitions.keys().cloned().collect::<Vec<_>>();
+    if start_all {
+        return Some(all());
+    }
+    let Some(name) = requested else {
+        let default_key = definitions
+            .iter()
+            .find(|(_, entry)| entry.get("default").and_then(Value::as_bool) == Some(true))
+            .map_or(fallback, |(server_key, _)| server_key.as_str());
+        return Some(vec![default_key.to_string()]);
+    };
+    if definitions.contains_key(name) {
+        return Some(vec![name.to_string()]);
+    }
+    let aliased = definitions.iter().find(|(_, entry)| {
+        entry
+            .get("aliases")
+            .and_then(Value::as_array)
+            .is_some_and(|aliases| aliases.iter().any(|alias| alias.as_str() == Some(name)))
+    });
+    match aliased {
+        Some((server_key, _)) => Some(vec![server_key.clone()]),
+        None if name == ALL_SERVERS => Some(all()),
+        None => None,
+    }
+}
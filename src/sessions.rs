@@ -0,0 +1,374 @@
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    env,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{events::EventBus, mcp_process::McpServer};
+
+// MCP の Streamable HTTP と同じセッションIDのヘッダー
+pub const SESSION_ID_HEADER: &str = "mcp-session-id";
+
+// アイドルなセッションを確認する間隔 (SESSION_IDLE_TTL_SECS がこれより短ければその値)
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// --- 子プロセスの割り当て方 ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionMode {
+    // すべてのクライアントで1つの子プロセスを共有する
+    Shared,
+    // initialize ごとに専用の子プロセスを起動し、Mcp-Session-Id で振り分ける
+    PerSession,
+}
+
+// --- セッションの設定 (環境変数) ---
+#[derive(Clone, Debug)]
+pub struct SessionConfig {
+    pub mode: SessionMode,
+    // 同時に保持するセッション数の上限 (超える場合は最も長く使われていないものを終了する)
+    pub max_sessions: usize,
+    // None の場合はアイドルなセッションを終了しない
+    pub idle_ttl: Option<Duration>,
+}
+
+impl SessionConfig {
+    pub fn from_env() -> Result<Self, String> {
+        let mode = match env::var("SESSION_MODE").ok().as_deref() {
+            None | Some("") | Some("shared") => SessionMode::Shared,
+            Some("per_session") => SessionMode::PerSession,
+            Some(other) => {
+                return Err(format!(
+                    "Invalid SESSION_MODE '{}' (expected 'shared' or 'per_session')",
+                    other
+                ));
+            }
+        };
+        let parse = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        Ok(SessionConfig {
+            mode,
+            max_sessions: parse("SESSION_MAX", 100).max(1) as usize,
+            idle_ttl: Some(parse("SESSION_IDLE_TTL_SECS", 1800))
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+        })
+    }
+}
+
+// --- /stats で返すセッションの状態 ---
+#[derive(Serialize, Debug)]
+pub struct SessionStats {
+    pub active: usize,
+    pub max_sessions: usize,
+    pub idle_ttl_secs: Option<u64>,
+    pub created_total: u64,
+    // DELETE で終了したセッション数
+    pub closed_total: u64,
+    // SESSION_IDLE_TTL_SECS を超えて終了したセッション数
+    pub expired_total: u64,
+    // SESSION_MAX を超えたために終了したセッション数
+    pub evicted_total: u64,
+    pub sessions: Vec<SessionSnapshot>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SessionSnapshot {
+    pub session_id: String,
+    pub pid: Option<u32>,
+    pub running: bool,
+    pub age_secs: u64,
+    pub idle_secs: u64,
+    pub request_count: u64,
+}
+
+struct Session {
+    server: Arc<McpServer>,
+    created_at: Instant,
+    last_used: Instant,
+}
+
+// --- セッションごとの子プロセス ---
+// 共有のサーバーと同じ設定 (レジストリで更新された場合は作成時点の設定) で McpServer を作る
+pub struct SessionManager {
+    template: Arc<McpServer>,
+    events: EventBus,
+    config: SessionConfig,
+    sessions: std::sync::Mutex<HashMap<String, Session>>,
+    created_total: AtomicU64,
+    closed_total: AtomicU64,
+    expired_total: AtomicU64,
+    evicted_total: AtomicU64,
+}
+
+impl SessionManager {
+    pub fn new(template: Arc<McpServer>, events: EventBus, config: SessionConfig) -> Self {
+        SessionManager {
+            template,
+            events,
+            config,
+            sessions: std::sync::Mutex::new(HashMap::new()),
+            created_total: AtomicU64::new(0),
+            closed_total: AtomicU64::new(0),
+            expired_total: AtomicU64::new(0),
+            evicted_total: AtomicU64::new(0),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // 専用の子プロセスを起動してセッションを作成する
+    pub async fn create(&self) -> Result<(String, Arc<McpServer>), String> {
+        // 新しい子プロセスを起動する前に空きを作る
+        self.evict_over(self.config.max_sessions - 1).await;
+
+        let session_id = Uuid::new_v4().to_string();
+        let server = Arc::new(McpServer::new(
+            &self.template.server_key,
+            (*self.template.config()).clone(),
+            self.events.clone(),
+        ));
+        server.start().await?;
+        let now = Instant::now();
+        self.lock().insert(
+            session_id.clone(),
+            Session {
+                server: Arc::clone(&server),
+                created_at: now,
+                last_used: now,
+            },
+        );
+        self.created_total.fetch_add(1, Ordering::Relaxed);
+        info!(server = %server.server_key, session_id = %session_id, "Created MCP session");
+
+        // 同時に作成された場合も上限を超えたままにしない
+        self.evict_over(self.config.max_sessions).await;
+        Ok((session_id, server))
+    }
+
+    // セッションのサーバーを返し、最終利用時刻を更新する
+    pub fn get(&self, session_id: &str) -> Option<Arc<McpServer>> {
+        let mut sessions = self.lock();
+        let session = sessions.get_mut(session_id)?;
+        session.last_used = Instant::now();
+        Some(Arc::clone(&session.server))
+    }
+
+    // セッションを削除して子プロセスを停止する (存在しなければ false)
+    pub async fn close(&self, session_id: &str) -> bool {
+        let Some(session) = self.lock().remove(session_id) else {
+            return false;
+        };
+        self.closed_total.fetch_add(1, Ordering::Relaxed);
+        shutdown(session_id, session, "closed").await;
+        true
+    }
+
+    // ブリッジの終了時にすべてのセッションの子プロセスを終了させる
+    pub async fn close_all(&self) {
+        let sessions: Vec<Session> = self.lock().drain().map(|(_, session)| session).collect();
+        join_all(sessions.iter().map(|session| session.server.shutdown())).await;
+    }
+
+    // セッション数が limit 以下になるまで、最も長く使われていないものから終了する
+    async fn evict_over(&self, limit: usize) {
+        loop {
+            let evicted = {
+                let mut sessions = self.lock();
+                if sessions.len() <= limit {
+                    return;
+                }
+                let Some(session_id) = sessions
+                    .iter()
+                    .min_by_key(|(_, session)| session.last_used)
+                    .map(|(session_id, _)| session_id.clone())
+                else {
+                    return;
+                };
+                sessions
+                    .remove(&session_id)
+                    .map(|session| (session_id, session))
+            };
+            if let Some((session_id, session)) = evicted {
+                self.evicted_total.fetch_add(1, Ordering::Relaxed);
+                shutdown(&session_id, session, "evicted").await;
+            }
+        }
+    }
+
+    // SESSION_IDLE_TTL_SECS を超えて使われていないセッションを定期的に終了する
+    pub fn spawn_reaper(self: &Arc<Self>) {
+        let Some(idle_ttl) = self.config.idle_ttl else {
+            return;
+        };
+        info!(
+            idle_ttl_secs = idle_ttl.as_secs(),
+            max_sessions = self.config.max_sessions,
+            "Session idle expiry enabled"
+        );
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SESSION_CHECK_INTERVAL.min(idle_ttl));
+            loop {
+                interval.tick().await;
+                let expired: Vec<(String, Session)> = {
+                    let mut sessions = manager.lock();
+                    let expired_ids: Vec<String> = sessions
+                        .iter()
+                        .filter(|(_, session)| session.last_used.elapsed() >= idle_ttl)
+                        .map(|(session_id, _)| session_id.clone())
+                        .collect();
+                    expired_ids
+                        .into_iter()
+                        .filter_map(|session_id| {
+                            sessions
+                                .remove(&session_id)
+                                .map(|session| (session_id, session))
+                        })
+                        .collect()
+                };
+                for (session_id, session) in expired {
+                    manager.expired_total.fetch_add(1, Ordering::Relaxed);
+                    shutdown(&session_id, session, "expired").await;
+                }
+            }
+        });
+    }
+
+    pub async fn snapshot(&self) -> SessionStats {
+        // is_running() を待つ間はセッションの表をロックしない
+        let current: Vec<(String, Arc<McpServer>, u64, u64)> = self
+            .lock()
+            .iter()
+            .map(|(session_id, session)| {
+                (
+                    session_id.clone(),
+                    Arc::clone(&session.server),
+                    session.created_at.elapsed().as_secs(),
+                    session.last_used.elapsed().as_secs(),
+                )
+            })
+            .collect();
+        let mut sessions = Vec::with_capacity(current.len());
+        for (session_id, server, age_secs, idle_secs) in current {
+            let stats = server.stats_snapshot().await;
+            sessions.push(SessionSnapshot {
+                session_id,
+                pid: stats.pid,
+                running: stats.running,
+                age_secs,
+                idle_secs,
+                request_count: stats.request_count,
+            });
+        }
+        SessionStats {
+            active: sessions.len(),
+            max_sessions: self.config.max_sessions,
+            idle_ttl_secs: self.config.idle_ttl.map(|ttl| ttl.as_secs()),
+            created_total: self.created_total.load(Ordering::Relaxed),
+            closed_total: self.closed_total.load(Ordering::Relaxed),
+            expired_total: self.expired_total.load(Ordering::Relaxed),
+            evicted_total: self.evicted_total.load(Ordering::Relaxed),
+            sessions,
+        }
+    }
+}
+
+// 処理中のリクエストが終わるのを待ってから子プロセスを停止する
+async fn shutdown(session_id: &str, session: Session, reason: &str) {
+    let server = session.server;
+    if let Err(e) = server.stop().await {
+        warn!(server = %server.server_key, session_id = %session_id, error = %e, "Failed to stop MCP session process");
+    }
+    info!(
+        server = %server.server_key,
+        session_id = %session_id,
+        reason,
+        idle_secs = session.last_used.elapsed().as_secs(),
+        "Stopped MCP session"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(max_sessions: usize, idle_ttl: Option<Duration>) -> Arc<SessionManager> {
+        let events = EventBus::default();
+        let template = Arc::new(McpServer::new(
+            "mock",
+            crate::mcp_process::mock_config().unwrap(),
+            events.clone(),
+        ));
+        Arc::new(SessionManager::new(
+            template,
+            events,
+            SessionConfig {
+                mode: SessionMode::PerSession,
+                max_sessions,
+                idle_ttl,
+            },
+        ))
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_session_is_evicted_over_the_limit() {
+        let manager = manager(2, None);
+        let (first, _) = manager.create().await.unwrap();
+        let (second, _) = manager.create().await.unwrap();
+        // first を使うと、最も長く使われていないのは second になる
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert!(manager.get(&first).is_some());
+
+        let (third, _) = manager.create().await.unwrap();
+        assert!(manager.get(&second).is_none());
+        assert!(manager.get(&first).is_some() && manager.get(&third).is_some());
+        let stats = manager.snapshot().await;
+        assert_eq!(stats.active, 2);
+        assert_eq!(stats.created_total, 3);
+        assert_eq!(stats.evicted_total, 1);
+        manager.close_all().await;
+    }
+
+    #[tokio::test]
+    async fn close_removes_only_known_sessions() {
+        let manager = manager(10, None);
+        let (session_id, _) = manager.create().await.unwrap();
+        assert!(manager.close(&session_id).await);
+        assert!(!manager.close(&session_id).await);
+        assert!(manager.get(&session_id).is_none());
+        let stats = manager.snapshot().await;
+        assert_eq!((stats.active, stats.closed_total), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn idle_sessions_expire() {
+        let manager = manager(10, Some(Duration::from_millis(100)));
+        manager.spawn_reaper();
+        let (idle, _) = manager.create().await.unwrap();
+        let (busy, _) = manager.create().await.unwrap();
+        for _ in 0..15 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            manager.get(&busy);
+        }
+        assert!(manager.get(&idle).is_none());
+        assert!(manager.get(&busy).is_some());
+        assert_eq!(manager.snapshot().await.expired_total, 1);
+        manager.close_all().await;
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use crate::mcp_process::McpProcessConfig;
+
+// サーバーの作業ディレクトリに書き出すセットアップの記録
+pub const MANIFEST_FILE: &str = ".mcp-setup.json";
+
+// --- post_install の実行記録 (.mcp-setup.json) ---
+// repo / commit / install_hash が前回と同じであれば、ブリッジを再起動しても post_install を繰り返さない
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetupManifest {
+    pub server: String,
+    // 作業ディレクトリが git リポジトリの場合の remote.origin.url と HEAD のコミット
+    pub repo: Option<String>,
+    pub commit: Option<String>,
+    // post_install のコマンドと env の SHA-256 (変わったら再実行する)
+    pub install_hash: String,
+    pub installed_at_ms: u64,
+    // 最後に記録と一致することを確認した時刻
+    pub checked_at_ms: u64,
+}
+
+impl SetupManifest {
+    // 現在の設定と作業ディレクトリの状態から記録を作る
+    pub async fn current(server_key: &str, config: &McpProcessConfig, cwd: &Path) -> Self {
+        let now = now_ms();
+        SetupManifest {
+            server: server_key.to_string(),
+            repo: git(cwd, &["config", "--get", "remote.origin.url"]).await,
+            commit: git(cwd, &["rev-parse", "HEAD"]).await,
+            install_hash: install_hash(config),
+            installed_at_ms: now,
+            checked_at_ms: now,
+        }
+    }
+
+    pub fn path(cwd: &Path) -> PathBuf {
+        cwd.join(MANIFEST_FILE)
+    }
+
+    // 記録がない・読めない場合は None (post_install を実行する)
+    pub async fn load(cwd: &Path) -> Option<Self> {
+        let path = Self::path(cwd);
+        let content = tokio::fs::read_to_string(&path).await.ok()?;
+        match serde_json::from_str(&content) {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "Ignoring invalid setup manifest");
+                None
+            }
+        }
+    }
+
+    // 前回の post_install と同じ内容・同じコミットか
+    pub fn is_up_to_date(&self, current: &SetupManifest) -> bool {
+        self.server == current.server
+            && self.repo == current.repo
+            && self.commit == current.commit
+            && self.install_hash == current.install_hash
+    }
+
+    // 一時ファイルに書いてから置き換える。書き込めなくても子プロセスの起動は続ける
+    pub async fn save(&self, cwd: &Path) {
+        let path = Self::path(cwd);
+        let temp_path = cwd.join(format!("{}.tmp", MANIFEST_FILE));
+        let result = async {
+            let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+            tokio::fs::write(&temp_path, content)
+                .await
+                .map_err(|e| e.to_string())?;
+            tokio::fs::rename(&temp_path, &path)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        .await;
+        match result {
+            Ok(()) => debug!(path = %path.display(), "Wrote setup manifest"),
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "Failed to write setup manifest")
+            }
+        }
+    }
+}
+
+// post_install のコマンドと env を順序が一定になるよう並べてハッシュする
+fn install_hash(config: &McpProcessConfig) -> String {
+    let mut env: Vec<(&String, &String)> = config.env.iter().collect();
+    env.sort();
+    let input = serde_json::json!({
+        "post_install": config.hooks.post_install,
+        "env": env,
+    });
+    format!("{:x}", Sha256::digest(input.to_string().as_bytes()))
+}
+
+// git が無い・リポジトリでない場合は None
+pub async fn git(cwd: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(cwd)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
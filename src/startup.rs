@@ -0,0 +1,123 @@
+use futures_util::future::join_all;
+use std::{env, sync::Arc, time::Duration};
+use tracing::{error, info, warn};
+
+use crate::mcp_process::McpServer;
+
+// --- 起動時の子プロセス起動の再試行 ---
+// npm のキャッシュや git の一時的な失敗で最初の起動に失敗しても、指数バックオフ (ジッター付き) で
+// STARTUP_RETRIES 回まで起動し直す。STARTUP_DEGRADED_MODE=true の場合は起動を待たずに HTTP サーバーを
+// 立ち上げ、再試行している間は 503 を返す
+#[derive(Clone, Copy)]
+pub struct StartupRetryConfig {
+    // 最初の起動に失敗した後に再試行する回数 (0 で再試行しない)
+    pub retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub degraded_mode: bool,
+}
+
+impl StartupRetryConfig {
+    // STARTUP_RETRIES / STARTUP_RETRY_INITIAL_MS / STARTUP_RETRY_MAX_SECS / STARTUP_DEGRADED_MODE
+    pub fn from_env() -> Self {
+        let env_u64 = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        StartupRetryConfig {
+            retries: env_u64("STARTUP_RETRIES", 0).try_into().unwrap_or(u32::MAX),
+            initial_backoff: Duration::from_millis(env_u64("STARTUP_RETRY_INITIAL_MS", 1000)),
+            max_backoff: Duration::from_secs(env_u64("STARTUP_RETRY_MAX_SECS", 30)),
+            degraded_mode: env::var("STARTUP_DEGRADED_MODE")
+                .map(|v| v.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+        }
+    }
+
+    // attempt 回目の失敗後の待ち時間: initial * 2^(attempt-1) を max で打ち切り、
+    // 複数のインスタンスが同時に再試行しないよう 50〜100% の範囲でばらつかせる
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+        // 乱数用の依存を増やさないよう、UUID v4 のランダムなビットを使う
+        let random = (uuid::Uuid::new_v4().as_u128() % 1000) as f64 / 1000.0;
+        exponential.mul_f64(0.5 + random * 0.5)
+    }
+}
+
+// 起動時に lazy 以外のサーバーを並行して起動する。
+// 1つでも起動できなければエラー (起動済みのサーバーの停止は呼び出し側で行う)。
+// degraded_mode の場合は起動を待たずに戻り、バックグラウンドで起動する
+pub async fn start_servers(
+    servers: &[Arc<McpServer>],
+    config: StartupRetryConfig,
+) -> Result<(), String> {
+    let mut starting = Vec::new();
+    for server in servers {
+        // lazy 設定のサーバーは最初のリクエストまで起動しない
+        if server.is_lazy() {
+            info!(server = %server.server_key, "Lazy MCP server, deferring spawn until first request");
+            continue;
+        }
+        let server = Arc::clone(server);
+        starting.push(tokio::spawn(async move {
+            let result = start_with_retries(&server, &config).await;
+            match &result {
+                Ok(()) => info!(server = %server.server_key, "MCP server started successfully"),
+                // 起動を待っていない場合は、ここで諦めたことを記録する
+                Err(e) if config.degraded_mode => {
+                    error!(server = %server.server_key, error = %e, "Failed to start MCP server process, giving up (use the admin API to start it)")
+                }
+                Err(_) => {}
+            }
+            result.map_err(|e| format!("MCP server '{}': {}", server.server_key, e))
+        }));
+    }
+    if config.degraded_mode {
+        // 起動を待たずに HTTP サーバーを立ち上げ、起動するまでは 503 を返す
+        if !starting.is_empty() {
+            warn!("Starting in degraded mode, MCP servers will be started in the background");
+        }
+        return Ok(());
+    }
+    for result in join_all(starting).await {
+        result.map_err(|e| format!("MCP server startup task failed: {}", e))??;
+    }
+    Ok(())
+}
+
+// 起動に成功するか、再試行の回数を使い切るまで start を繰り返す (最後のエラーを返す)
+async fn start_with_retries(
+    server: &Arc<McpServer>,
+    config: &StartupRetryConfig,
+) -> Result<(), String> {
+    let mut attempt = 0;
+    loop {
+        // 再試行を待っている間に管理APIなどで起動された場合はそれを使う
+        if server.is_running().await {
+            return Ok(());
+        }
+        let Err(e) = server.start().await else {
+            return Ok(());
+        };
+        attempt += 1;
+        if attempt > config.retries {
+            return Err(e);
+        }
+        let backoff = config.backoff(attempt);
+        warn!(
+            server = %server.server_key,
+            error = %e,
+            attempt,
+            retries = config.retries,
+            backoff_ms = backoff.as_millis() as u64,
+            "Failed to start MCP server process, retrying"
+        );
+        tokio::time::sleep(backoff).await;
+        info!(server = %server.server_key, attempt, "Retrying MCP server startup");
+    }
+}
@@ -0,0 +1,679 @@
+use serde::Serialize;
+
+use crate::{
+    circuit_breaker::CircuitSnapshot, health_check::HealthSnapshot,
+    resource_monitor::ResourceUsage, response_cache::ResponseCacheSnapshot, sessions::SessionStats,
+};
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{
+        Mutex,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// p95 計算用に保持する直近レイテンシのサンプル数
+const LATENCY_SAMPLE_WINDOW: usize = 1024;
+// /stats で返す直近エラーの件数
+const RECENT_ERRORS_LIMIT: usize = 20;
+// レイテンシのヒストグラムのバケットの上限 (ミリ秒)。これを超えた値は +Inf のバケットに入る
+const LATENCY_BUCKETS_MS: [f64; 17] = [
+    0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+    30000.0, 60000.0, 120000.0,
+];
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ErrorRecord {
+    pub timestamp_ms: u64,
+    pub message: String,
+}
+
+// --- サーバーごとの統計情報 ---
+// プロセスの再起動をまたいで共有され、/stats から参照される
+pub struct ServerStats {
+    server_key: String,
+    pid: AtomicU32,
+    started_at: Mutex<Instant>,
+    request_count: AtomicU64,
+    error_count: AtomicU64,
+    timeout_count: AtomicU64,
+    restart_count: AtomicU64,
+    // limits の上限を超えて kill された回数
+    limit_breach_count: AtomicU64,
+    total_latency_ms: AtomicU64,
+    // 最終アクティビティ (UNIXミリ秒、0 は未使用)
+    last_activity_ms: AtomicU64,
+    recent_latencies_ms: Mutex<VecDeque<u64>>,
+    recent_errors: Mutex<VecDeque<ErrorRecord>>,
+    // 同時実行枠の空き (と lazy の場合は起動) を待った時間
+    queue_wait: LatencyHistogram,
+    // 子プロセスに送信してから応答を受け取るまでの時間
+    round_trip: LatencyHistogram,
+    // 待機を含めたリクエスト全体の時間
+    total: LatencyHistogram,
+    // resource_monitor が最後に読み取った使用量 (停止中は None)
+    resources: Mutex<Option<ResourceUsage>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StatsSnapshot {
+    pub server: String,
+    pub pid: Option<u32>,
+    pub uptime_secs: u64,
+    pub request_count: u64,
+    pub error_count: u64,
+    pub timeout_count: u64,
+    pub mean_latency_ms: f64,
+    pub p95_latency_ms: u64,
+    pub last_activity_ms: Option<u64>,
+    pub restart_count: u64,
+    pub limit_breach_count: u64,
+    pub running: bool,
+    pub recent_errors: Vec<ErrorRecord>,
+    pub latency: LatencySnapshot,
+    // resource_monitor による子プロセスのリソース使用量 (停止中や Linux 以外では省略)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceUsage>,
+    // サーキットブレーカーの状態 (McpServer 側で設定する)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker: Option<CircuitSnapshot>,
+    // response_cache 設定時のキャッシュの状態 (main 側で設定する)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_cache: Option<ResponseCacheSnapshot>,
+    // health_check / keep_alive 設定時の直近の結果 (main 側で設定する)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthSnapshot>,
+    // SESSION_MODE=per_session の場合のセッションの状態 (main 側で設定する)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sessions: Option<SessionStats>,
+}
+
+impl ServerStats {
+    pub fn new(server_key: &str) -> Self {
+        ServerStats {
+            server_key: server_key.to_string(),
+            pid: AtomicU32::new(0),
+            started_at: Mutex::new(Instant::now()),
+            request_count: AtomicU64::new(0),
+            error_count: AtomicU64::new(0),
+            timeout_count: AtomicU64::new(0),
+            restart_count: AtomicU64::new(0),
+            limit_breach_count: AtomicU64::new(0),
+            total_latency_ms: AtomicU64::new(0),
+            last_activity_ms: AtomicU64::new(0),
+            recent_latencies_ms: Mutex::new(VecDeque::with_capacity(LATENCY_SAMPLE_WINDOW)),
+            recent_errors: Mutex::new(VecDeque::with_capacity(RECENT_ERRORS_LIMIT)),
+            queue_wait: LatencyHistogram::default(),
+            round_trip: LatencyHistogram::default(),
+            total: LatencyHistogram::default(),
+            resources: Mutex::new(None),
+        }
+    }
+
+    // プロセス起動時に呼び出す (PID と稼働時間の起点を更新)
+    pub fn record_spawn(&self, pid: Option<u32>) {
+        self.pid.store(pid.unwrap_or(0), Ordering::SeqCst);
+        if let Ok(mut started_at) = self.started_at.lock() {
+            *started_at = Instant::now();
+        }
+    }
+
+    // 稼働中として記録されているプロセスが終了した場合のみ PID をクリアする
+    pub fn record_exit(&self, pid: Option<u32>) {
+        let _ = self
+            .pid
+            .compare_exchange(pid.unwrap_or(0), 0, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    pub fn record_restart(&self) {
+        self.restart_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_limit_breach(&self) {
+        self.limit_breach_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_resources(&self, usage: Option<ResourceUsage>) {
+        if let Ok(mut resources) = self.resources.lock() {
+            *resources = usage;
+        }
+    }
+
+    pub fn record_queue_wait(&self, elapsed: Duration) {
+        self.queue_wait.record(elapsed);
+    }
+
+    pub fn record_total(&self, elapsed: Duration) {
+        self.total.record(elapsed);
+    }
+
+    pub fn record_request(&self, elapsed: Duration, error: Option<&str>, timed_out: bool) {
+        let latency_ms = elapsed.as_millis() as u64;
+        self.round_trip.record(elapsed);
+        self.request_count.fetch_add(1, Ordering::Relaxed);
+        if timed_out {
+            self.timeout_count.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_latency_ms
+            .fetch_add(latency_ms, Ordering::Relaxed);
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.last_activity_ms.store(now_ms, Ordering::Relaxed);
+
+        if let Some(message) = error {
+            self.error_count.fetch_add(1, Ordering::Relaxed);
+            if let Ok(mut errors) = self.recent_errors.lock() {
+                if errors.len() == RECENT_ERRORS_LIMIT {
+                    errors.pop_front();
+                }
+                errors.push_back(ErrorRecord {
+                    timestamp_ms: now_ms,
+                    message: message.to_string(),
+                });
+            }
+        }
+
+        if let Ok(mut samples) = self.recent_latencies_ms.lock() {
+            if samples.len() == LATENCY_SAMPLE_WINDOW {
+                samples.pop_front();
+            }
+            samples.push_back(latency_ms);
+        }
+    }
+
+    pub fn get_stats(&self) -> StatsSnapshot {
+        let request_count = self.request_count.load(Ordering::Relaxed);
+        let total_latency_ms = self.total_latency_ms.load(Ordering::Relaxed);
+        let mean_latency_ms = if request_count == 0 {
+            0.0
+        } else {
+            total_latency_ms as f64 / request_count as f64
+        };
+
+        let p95_latency_ms = self
+            .recent_latencies_ms
+            .lock()
+            .map(|samples| {
+                let mut sorted: Vec<u64> = samples.iter().copied().collect();
+                sorted.sort_unstable();
+                percentile(&sorted, 0.95)
+            })
+            .unwrap_or(0);
+
+        let uptime_secs = self
+            .started_at
+            .lock()
+            .map(|started_at| started_at.elapsed().as_secs())
+            .unwrap_or(0);
+
+        let pid = self.pid.load(Ordering::SeqCst);
+        let last_activity_ms = self.last_activity_ms.load(Ordering::Relaxed);
+
+        StatsSnapshot {
+            server: self.server_key.clone(),
+            pid: (pid != 0).then_some(pid),
+            uptime_secs,
+            request_count,
+            error_count: self.error_count.load(Ordering::Relaxed),
+            timeout_count: self.timeout_count.load(Ordering::Relaxed),
+            mean_latency_ms,
+            p95_latency_ms,
+            last_activity_ms: (last_activity_ms != 0).then_some(last_activity_ms),
+            restart_count: self.restart_count.load(Ordering::Relaxed),
+            limit_breach_count: self.limit_breach_count.load(Ordering::Relaxed),
+            // PID の無いサーバー (remote / mock / aggregate / replay) もあるため、
+            // McpServer::stats_snapshot でプロセスの有無から設定する
+            running: false,
+            recent_errors: self
+                .recent_errors
+                .lock()
+                .map(|errors| errors.iter().rev().cloned().collect())
+                .unwrap_or_default(),
+            latency: LatencySnapshot {
+                queue_wait: self.queue_wait.snapshot(),
+                round_trip: self.round_trip.snapshot(),
+                total: self.total.snapshot(),
+            },
+            resources: self
+                .resources
+                .lock()
+                .map(|resources| resources.clone())
+                .unwrap_or_default(),
+            circuit_breaker: None,
+            response_cache: None,
+            health_check: None,
+            sessions: None,
+        }
+    }
+}
+
+// ソート済みサンプルから nearest-rank 法でパーセンタイルを求める
+fn percentile(sorted: &[u64], quantile: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (quantile * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+// --- 固定バケットのレイテンシヒストグラム ---
+// 平均や直近サンプルの p95 では見えない裾の遅さを、起動時からの全リクエストで追う
+pub struct LatencyHistogram {
+    // LATENCY_BUCKETS_MS の各バケットと +Inf のバケットの件数 (累積ではない)
+    buckets: [AtomicU64; LATENCY_BUCKETS_MS.len() + 1],
+    count: AtomicU64,
+    sum_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum_us: AtomicU64::new(0),
+            max_us: AtomicU64::new(0),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    pub sum_ms: f64,
+    pub max_ms: f64,
+    // バケット内を線形補間した推定値
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    // 累積件数 (le_ms が null のバケットは +Inf)
+    pub buckets: Vec<HistogramBucket>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HistogramBucket {
+    pub le_ms: Option<f64>,
+    pub count: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LatencySnapshot {
+    pub queue_wait: HistogramSnapshot,
+    pub round_trip: HistogramSnapshot,
+    pub total: HistogramSnapshot,
+}
+
+impl LatencyHistogram {
+    pub fn record(&self, elapsed: Duration) {
+        let us = elapsed.as_micros() as u64;
+        let ms = us as f64 / 1000.0;
+        let index = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|le| ms <= *le)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let count: u64 = counts.iter().sum();
+        let max_ms = self.max_us.load(Ordering::Relaxed) as f64 / 1000.0;
+        let quantile = |quantile: f64| estimate_quantile(&counts, count, max_ms, quantile);
+        let mut cumulative = 0;
+        let buckets = counts
+            .iter()
+            .enumerate()
+            .map(|(index, bucket)| {
+                cumulative += bucket;
+                HistogramBucket {
+                    le_ms: LATENCY_BUCKETS_MS.get(index).copied(),
+                    count: cumulative,
+                }
+            })
+            .collect();
+        HistogramSnapshot {
+            count,
+            sum_ms: self.sum_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            max_ms,
+            p50_ms: quantile(0.5),
+            p90_ms: quantile(0.9),
+            p95_ms: quantile(0.95),
+            p99_ms: quantile(0.99),
+            buckets,
+        }
+    }
+}
+
+// 順位が入るバケットの下限と上限 (+Inf のバケットは最大値) の間を線形補間する
+fn estimate_quantile(counts: &[u64], count: u64, max_ms: f64, quantile: f64) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+    let rank = (quantile * count as f64).ceil().max(1.0);
+    let mut cumulative = 0.0;
+    for (index, bucket) in counts.iter().enumerate() {
+        let bucket = *bucket as f64;
+        if bucket > 0.0 && cumulative + bucket >= rank {
+            let lower = index
+                .checked_sub(1)
+                .map_or(0.0, |index| LATENCY_BUCKETS_MS[index]);
+            let upper = LATENCY_BUCKETS_MS
+                .get(index)
+                .copied()
+                .unwrap_or(max_ms)
+                .min(max_ms);
+            return lower + (upper - lower).max(0.0) * (rank - cumulative) / bucket;
+        }
+        cumulative += bucket;
+    }
+    max_ms
+}
+
+// (名前, HELP, 値)
+type CounterMetric = (&'static str, &'static str, fn(&StatsSnapshot) -> u64);
+// (名前, HELP, TYPE, 値)
+type ResourceMetric = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&ResourceUsage) -> f64,
+);
+
+// GET /metrics 用の Prometheus テキスト形式。
+// 同じメトリクスの HELP / TYPE は1回だけ出力し、その下に全サーバー分を server ラベルで並べる
+pub fn to_prometheus(snapshots: &[StatsSnapshot]) -> String {
+    let mut out = String::new();
+    let counters: [CounterMetric; 4] = [
+        (
+            "mcp_requests_total",
+            "Requests sent to the MCP server",
+            |snapshot| snapshot.request_count,
+        ),
+        (
+            "mcp_request_errors_total",
+            "Requests that failed",
+            |snapshot| snapshot.error_count,
+        ),
+        (
+            "mcp_request_timeouts_total",
+            "Requests that timed out",
+            |snapshot| snapshot.timeout_count,
+        ),
+        (
+            "mcp_restarts_total",
+            "Restarts of the MCP server",
+            |snapshot| snapshot.restart_count,
+        ),
+    ];
+    for (name, help, value) in counters {
+        write_header(&mut out, name, help, "counter");
+        for snapshot in snapshots {
+            let server = escape_label(&snapshot.server);
+            let _ = writeln!(out, "{}{{server=\"{}\"}} {}", name, server, value(snapshot));
+        }
+    }
+
+    let name = "mcp_request_duration_seconds";
+    write_header(
+        &mut out,
+        name,
+        "Request latency by phase (queue_wait, round_trip, total)",
+        "histogram",
+    );
+    for snapshot in snapshots {
+        let server = escape_label(&snapshot.server);
+        let phases = [
+            ("queue_wait", &snapshot.latency.queue_wait),
+            ("round_trip", &snapshot.latency.round_trip),
+            ("total", &snapshot.latency.total),
+        ];
+        for (phase, histogram) in phases {
+            let labels = format!("server=\"{}\",phase=\"{}\"", server, phase);
+            for bucket in &histogram.buckets {
+                let le = bucket
+                    .le_ms
+                    .map_or("+Inf".to_string(), |le_ms| (le_ms / 1000.0).to_string());
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{{},le=\"{}\"}} {}",
+                    name, labels, le, bucket.count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_sum{{{}}} {}",
+                name,
+                labels,
+                histogram.sum_ms / 1000.0
+            );
+            let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+        }
+    }
+
+    // 子プロセスのリソース使用量は resource_monitor の値があるサーバーだけ
+    let gauges: [ResourceMetric; 4] = [
+        (
+            "mcp_child_resident_memory_bytes",
+            "Resident memory of the MCP process and its descendants",
+            "gauge",
+            |resources| resources.rss_bytes as f64,
+        ),
+        (
+            "mcp_child_cpu_seconds_total",
+            "CPU time of the MCP process and its descendants",
+            "counter",
+            |resources| resources.cpu_seconds,
+        ),
+        (
+            "mcp_child_open_fds",
+            "Open file descriptors of the MCP process and its descendants",
+            "gauge",
+            |resources| resources.open_fds as f64,
+        ),
+        (
+            "mcp_child_processes",
+            "Processes in the MCP process's session",
+            "gauge",
+            |resources| resources.processes as f64,
+        ),
+    ];
+    let sampled: Vec<(String, &ResourceUsage)> = snapshots
+        .iter()
+        .filter_map(|snapshot| {
+            let resources = snapshot.resources.as_ref()?;
+            Some((escape_label(&snapshot.server), resources))
+        })
+        .collect();
+    if !sampled.is_empty() {
+        for (name, help, kind, value) in gauges {
+            write_header(&mut out, name, help, kind);
+            for (server, resources) in &sampled {
+                let _ = writeln!(
+                    out,
+                    "{}{{server=\"{}\"}} {}",
+                    name,
+                    server,
+                    value(resources)
+                );
+            }
+        }
+    }
+    out
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative_with_an_inf_bucket() {
+        let histogram = LatencyHistogram::default();
+        for elapsed in [ms(1), ms(1), ms(7), ms(200_000)] {
+            histogram.record(elapsed);
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 4);
+        assert_eq!(snapshot.sum_ms, 200_009.0);
+        assert_eq!(snapshot.max_ms, 200_000.0);
+        assert_eq!(snapshot.buckets.len(), LATENCY_BUCKETS_MS.len() + 1);
+        let cumulative = |le_ms: Option<f64>| {
+            snapshot
+                .buckets
+                .iter()
+                .find(|bucket| bucket.le_ms == le_ms)
+                .unwrap()
+                .count
+        };
+        // 上限ちょうどの値はそのバケットに入る
+        assert_eq!(cumulative(Some(0.5)), 0);
+        assert_eq!(cumulative(Some(1.0)), 2);
+        assert_eq!(cumulative(Some(5.0)), 2);
+        assert_eq!(cumulative(Some(10.0)), 3);
+        assert_eq!(cumulative(Some(120_000.0)), 3);
+        assert_eq!(cumulative(None), 4);
+    }
+
+    #[test]
+    fn quantiles_interpolate_within_the_bucket() {
+        let histogram = LatencyHistogram::default();
+        assert_eq!(histogram.snapshot().p99_ms, 0.0);
+        // 100 件とも (50, 100] のバケットに入り、最大値は 100ms
+        for _ in 0..100 {
+            histogram.record(ms(100));
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.p50_ms, 75.0);
+        assert_eq!(snapshot.p90_ms, 95.0);
+        assert_eq!(snapshot.p99_ms, 99.5);
+
+        // +Inf のバケットは最大値までで補間する
+        let slow = LatencyHistogram::default();
+        slow.record(ms(150_000));
+        assert_eq!(slow.snapshot().p50_ms, 150_000.0);
+    }
+
+    #[test]
+    fn nearest_rank_percentile() {
+        assert_eq!(percentile(&[], 0.95), 0);
+        assert_eq!(percentile(&[7], 0.95), 7);
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&sorted, 0.95), 95);
+        assert_eq!(percentile(&sorted, 0.0), 1);
+    }
+
+    #[test]
+    fn counters_and_recent_errors() {
+        let stats = ServerStats::new("test");
+        stats.record_request(ms(10), None, false);
+        stats.record_request(ms(30), Some("first"), false);
+        stats.record_request(ms(50), Some("timed out"), true);
+        let snapshot = stats.get_stats();
+        assert_eq!(snapshot.request_count, 3);
+        assert_eq!(snapshot.error_count, 2);
+        assert_eq!(snapshot.timeout_count, 1);
+        assert_eq!(snapshot.mean_latency_ms, 30.0);
+        assert_eq!(snapshot.p95_latency_ms, 50);
+        assert!(snapshot.last_activity_ms.is_some());
+        // 新しい順
+        let messages: Vec<&str> = snapshot
+            .recent_errors
+            .iter()
+            .map(|error| error.message.as_str())
+            .collect();
+        assert_eq!(messages, ["timed out", "first"]);
+        assert_eq!(snapshot.latency.round_trip.count, 3);
+        assert_eq!(snapshot.latency.queue_wait.count, 0);
+
+        for index in 0..RECENT_ERRORS_LIMIT + 5 {
+            stats.record_request(ms(1), Some(&index.to_string()), false);
+        }
+        let snapshot = stats.get_stats();
+        assert_eq!(snapshot.recent_errors.len(), RECENT_ERRORS_LIMIT);
+        assert_eq!(
+            snapshot.recent_errors[0].message,
+            (RECENT_ERRORS_LIMIT + 4).to_string()
+        );
+    }
+
+    #[test]
+    fn exit_of_an_old_process_keeps_the_new_pid() {
+        let stats = ServerStats::new("test");
+        stats.record_spawn(Some(100));
+        stats.record_spawn(Some(200));
+        stats.record_exit(Some(100));
+        assert_eq!(stats.get_stats().pid, Some(200));
+        stats.record_exit(Some(200));
+        assert_eq!(stats.get_stats().pid, None);
+    }
+
+    #[test]
+    fn prometheus_output_escapes_labels_and_uses_seconds() {
+        let stats = ServerStats::new("a\"b");
+        stats.record_request(ms(5), None, false);
+        let text = to_prometheus(&[stats.get_stats()]);
+        assert!(text.contains("mcp_requests_total{server=\"a\\\"b\"} 1\n"));
+        assert!(text.contains(
+            "mcp_request_duration_seconds_bucket{server=\"a\\\"b\",phase=\"round_trip\",le=\"0.005\"} 1\n"
+        ));
+        assert!(text.contains(
+            "mcp_request_duration_seconds_bucket{server=\"a\\\"b\",phase=\"round_trip\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(text.contains(
+            "mcp_request_duration_seconds_sum{server=\"a\\\"b\",phase=\"round_trip\"} 0.005\n"
+        ));
+    }
+
+    #[test]
+    fn prometheus_output_lists_every_server_under_one_header() {
+        let a = ServerStats::new("a");
+        let b = ServerStats::new("b");
+        a.record_request(ms(5), None, false);
+        b.record_request(ms(5), None, false);
+        b.record_request(ms(5), None, false);
+        let text = to_prometheus(&[a.get_stats(), b.get_stats()]);
+        assert_eq!(
+            text.matches("# TYPE mcp_requests_total counter\n").count(),
+            1
+        );
+        assert_eq!(
+            text.matches("# TYPE mcp_request_duration_seconds histogram\n")
+                .count(),
+            1
+        );
+        assert!(text.contains("mcp_requests_total{server=\"a\"} 1\n"));
+        assert!(text.contains("mcp_requests_total{server=\"b\"} 2\n"));
+        assert!(
+            text.contains(
+                "mcp_request_duration_seconds_count{server=\"b\",phase=\"round_trip\"} 2\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+struct BufferState {
+    lines: VecDeque<String>,
+    bytes: usize,
+}
+
+// --- 子プロセスの stderr のリングバッファ ---
+// 直近 capacity バイト分の行を保持する。プロセスの再起動をまたいで共有され、
+// GET /admin/servers/{name}/stderr とデバッグ時のエラーレスポンスから参照される
+pub struct StderrBuffer {
+    capacity: usize,
+    state: Mutex<BufferState>,
+}
+
+impl StderrBuffer {
+    pub fn new(capacity: usize) -> Self {
+        StderrBuffer {
+            capacity,
+            state: Mutex::new(BufferState {
+                lines: VecDeque::new(),
+                bytes: 0,
+            }),
+        }
+    }
+
+    pub fn push(&self, line: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        // 1行で容量を超える場合は行末側だけを残す
+        let start = line.len().saturating_sub(self.capacity);
+        let start = (start..=line.len())
+            .find(|index| line.is_char_boundary(*index))
+            .unwrap_or(line.len());
+        let line = &line[start..];
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.bytes += line.len();
+        state.lines.push_back(line.to_string());
+        while state.bytes > self.capacity {
+            match state.lines.pop_front() {
+                Some(evicted) => state.bytes -= evicted.len(),
+                None => break,
+            }
+        }
+    }
+
+    // 保持している行のうち新しい方から最大 count 行 (古い順)
+    pub fn tail(&self, count: usize) -> Vec<String> {
+        self.state
+            .lock()
+            .map(|state| {
+                let skip = state.lines.len().saturating_sub(count);
+                state.lines.iter().skip(skip).cloned().collect()
+            })
+            .unwrap_or_default()
+    }
+}
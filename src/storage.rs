@@ -0,0 +1,571 @@
+use async_trait::async_trait;
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tracing::info;
+
+// --- ストレージの名前空間 ---
+// 各機能はこの名前空間ごとにキーを分けて同じバックエンドを共有する
+pub const NAMESPACE_IDEMPOTENCY: &str = "idempotency";
+pub const NAMESPACE_REQUESTS: &str = "requests";
+
+// --- ストレージ抽象化トレイト ---
+// 冪等性キャッシュや直近のリクエストなど、複数のレプリカで共有したいデータの永続化はこのトレイト経由で行う。
+// 応答のキャッシュ (response_cache.rs) は子プロセスの起動ごとに破棄するため、プロセスのメモリに置く
+#[async_trait]
+pub trait Storage: Send + Sync {
+    fn backend_name(&self) -> &'static str;
+
+    async fn get(&self, namespace: &str, key: &str) -> Result<Option<String>, String>;
+
+    async fn put(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<(), String>;
+
+    // リストの先頭に追加し、新しいものから max_len 件だけを残す
+    async fn push(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &str,
+        max_len: usize,
+    ) -> Result<(), String>;
+
+    // リストの新しいものから最大 limit 件
+    async fn latest(&self, namespace: &str, key: &str, limit: usize)
+    -> Result<Vec<String>, String>;
+}
+
+pub type SharedStorage = Arc<dyn Storage>;
+
+// --- インメモリバックエンド ---
+struct MemoryEntry {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl MemoryEntry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: Mutex<HashMap<(String, String), MemoryEntry>>,
+    lists: Mutex<HashMap<(String, String), VecDeque<String>>>,
+}
+
+// 期限切れエントリを掃除する間隔 (put 回数)
+const MEMORY_SWEEP_INTERVAL: usize = 256;
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    fn backend_name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, namespace: &str, key: &str) -> Result<Option<String>, String> {
+        let mut entries = self.entries.lock().await;
+        let map_key = (namespace.to_string(), key.to_string());
+        match entries.get(&map_key) {
+            Some(entry) if entry.is_expired(Instant::now()) => {
+                entries.remove(&map_key);
+                Ok(None)
+            }
+            Some(entry) => Ok(Some(entry.value.clone())),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &str,
+        ttl: Option<Duration>,
+    ) -> Result<(), String> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().await;
+        if entries.len() % MEMORY_SWEEP_INTERVAL == 0 {
+            entries.retain(|_, entry| !entry.is_expired(now));
+        }
+        entries.insert(
+            (namespace.to_string(), key.to_string()),
+            MemoryEntry {
+                value: value.to_string(),
+                expires_at: ttl.map(|ttl| now + ttl),
+            },
+        );
+        Ok(())
+    }
+
+    async fn push(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: &str,
+        max_len: usize,
+    ) -> Result<(), String> {
+        let mut lists = self.lists.lock().await;
+        let list = lists
+            .entry((namespace.to_string(), key.to_string()))
+            .or_default();
+        list.push_front(value.to_string());
+        list.truncate(max_len);
+        Ok(())
+    }
+
+    async fn latest(
+        &self,
+        namespace: &str,
+        key: &str,
+        limit: usize,
+    ) -> Result<Vec<String>, String> {
+        let lists = self.lists.lock().await;
+        Ok(lists
+            .get(&(namespace.to_string(), key.to_string()))
+            .map(|list| list.iter().take(limit).cloned().collect())
+            .unwrap_or_default())
+    }
+}
+
+// --- SQLiteバックエンド ---
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::Storage;
+    use async_trait::async_trait;
+    use rusqlite::{Connection, OptionalExtension, params};
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    pub struct SqliteStorage {
+        connection: Arc<Mutex<Connection>>,
+    }
+
+    fn now_millis() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64
+    }
+
+    impl SqliteStorage {
+        pub fn open(path: &str) -> Result<Self, String> {
+            let connection = Connection::open(path)
+                .map_err(|e| format!("Failed to open SQLite database '{}': {}", path, e))?;
+            connection
+                .execute_batch(
+                    "PRAGMA journal_mode=WAL;
+                     CREATE TABLE IF NOT EXISTS kv (
+                         namespace TEXT NOT NULL,
+                         key TEXT NOT NULL,
+                         value TEXT NOT NULL,
+                         expires_at INTEGER,
+                         PRIMARY KEY (namespace, key)
+                     );
+                     CREATE TABLE IF NOT EXISTS list_entries (
+                         seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                         namespace TEXT NOT NULL,
+                         key TEXT NOT NULL,
+                         value TEXT NOT NULL
+                     );
+                     CREATE INDEX IF NOT EXISTS list_entries_by_key
+                         ON list_entries (namespace, key, seq);",
+                )
+                .map_err(|e| format!("Failed to initialize SQLite schema: {}", e))?;
+            Ok(SqliteStorage {
+                connection: Arc::new(Mutex::new(connection)),
+            })
+        }
+
+        // rusqlite は同期APIなのでブロッキングスレッドで実行する
+        async fn with_connection<T, F>(&self, f: F) -> Result<T, String>
+        where
+            T: Send + 'static,
+            F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+        {
+            let connection = Arc::clone(&self.connection);
+            tokio::task::spawn_blocking(move || {
+                let guard = connection
+                    .lock()
+                    .map_err(|_| "SQLite connection mutex poisoned".to_string())?;
+                f(&guard).map_err(|e| format!("SQLite error: {}", e))
+            })
+            .await
+            .map_err(|e| format!("SQLite task failed: {}", e))?
+        }
+    }
+
+    #[async_trait]
+    impl Storage for SqliteStorage {
+        fn backend_name(&self) -> &'static str {
+            "sqlite"
+        }
+
+        async fn get(&self, namespace: &str, key: &str) -> Result<Option<String>, String> {
+            let namespace = namespace.to_string();
+            let key = key.to_string();
+            self.with_connection(move |conn| {
+                conn.query_row(
+                    "SELECT value FROM kv
+                     WHERE namespace = ?1 AND key = ?2
+                       AND (expires_at IS NULL OR expires_at > ?3)",
+                    params![namespace, key, now_millis()],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+            })
+            .await
+        }
+
+        async fn put(
+            &self,
+            namespace: &str,
+            key: &str,
+            value: &str,
+            ttl: Option<Duration>,
+        ) -> Result<(), String> {
+            let namespace = namespace.to_string();
+            let key = key.to_string();
+            let value = value.to_string();
+            self.with_connection(move |conn| {
+                let now = now_millis();
+                let expires_at = ttl.map(|ttl| now + ttl.as_millis() as i64);
+                conn.execute(
+                    "DELETE FROM kv WHERE expires_at IS NOT NULL AND expires_at <= ?1",
+                    params![now],
+                )?;
+                conn.execute(
+                    "INSERT OR REPLACE INTO kv (namespace, key, value, expires_at)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![namespace, key, value, expires_at],
+                )?;
+                Ok(())
+            })
+            .await
+        }
+
+        async fn push(
+            &self,
+            namespace: &str,
+            key: &str,
+            value: &str,
+            max_len: usize,
+        ) -> Result<(), String> {
+            let namespace = namespace.to_string();
+            let key = key.to_string();
+            let value = value.to_string();
+            self.with_connection(move |conn| {
+                conn.execute(
+                    "INSERT INTO list_entries (namespace, key, value) VALUES (?1, ?2, ?3)",
+                    params![namespace, key, value],
+                )?;
+                conn.execute(
+                    "DELETE FROM list_entries
+                     WHERE namespace = ?1 AND key = ?2 AND seq NOT IN (
+                         SELECT seq FROM list_entries
+                         WHERE namespace = ?1 AND key = ?2
+                         ORDER BY seq DESC LIMIT ?3
+                     )",
+                    params![namespace, key, max_len as i64],
+                )?;
+                Ok(())
+            })
+            .await
+        }
+
+        async fn latest(
+            &self,
+            namespace: &str,
+            key: &str,
+            limit: usize,
+        ) -> Result<Vec<String>, String> {
+            let namespace = namespace.to_string();
+            let key = key.to_string();
+            // SQLite の LIMIT は i64 のため、usize::MAX などは上限で丸める
+            let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+            self.with_connection(move |conn| {
+                let mut statement = conn.prepare(
+                    "SELECT value FROM list_entries
+                     WHERE namespace = ?1 AND key = ?2
+                     ORDER BY seq DESC LIMIT ?3",
+                )?;
+                let rows = statement.query_map(params![namespace, key, limit], |row| {
+                    row.get::<_, String>(0)
+                })?;
+                rows.collect()
+            })
+            .await
+        }
+    }
+}
+
+// --- Redisバックエンド ---
+#[cfg(feature = "redis")]
+mod redis_backend {
+    use super::Storage;
+    use async_trait::async_trait;
+    use redis::aio::MultiplexedConnection;
+    use std::time::Duration;
+
+    pub struct RedisStorage {
+        connection: MultiplexedConnection,
+        key_prefix: String,
+    }
+
+    impl RedisStorage {
+        pub async fn connect(url: &str, key_prefix: String) -> Result<Self, String> {
+            let client = redis::Client::open(url)
+                .map_err(|e| format!("Invalid Redis URL '{}': {}", url, e))?;
+            let connection = client
+                .get_multiplexed_async_connection()
+                .await
+                .map_err(|e| format!("Failed to connect to Redis '{}': {}", url, e))?;
+            Ok(RedisStorage {
+                connection,
+                key_prefix,
+            })
+        }
+
+        fn redis_key(&self, namespace: &str, key: &str) -> String {
+            format!("{}:{}:{}", self.key_prefix, namespace, key)
+        }
+    }
+
+    #[async_trait]
+    impl Storage for RedisStorage {
+        fn backend_name(&self) -> &'static str {
+            "redis"
+        }
+
+        async fn get(&self, namespace: &str, key: &str) -> Result<Option<String>, String> {
+            let mut connection = self.connection.clone();
+            redis::cmd("GET")
+                .arg(self.redis_key(namespace, key))
+                .query_async::<Option<String>>(&mut connection)
+                .await
+                .map_err(|e| format!("Redis GET failed: {}", e))
+        }
+
+        async fn put(
+            &self,
+            namespace: &str,
+            key: &str,
+            value: &str,
+            ttl: Option<Duration>,
+        ) -> Result<(), String> {
+            let mut connection = self.connection.clone();
+            let mut command = redis::cmd("SET");
+            command.arg(self.redis_key(namespace, key)).arg(value);
+            if let Some(ttl) = ttl {
+                command.arg("PX").arg(ttl.as_millis().max(1) as u64);
+            }
+            command
+                .query_async::<()>(&mut connection)
+                .await
+                .map_err(|e| format!("Redis SET failed: {}", e))
+        }
+
+        async fn push(
+            &self,
+            namespace: &str,
+            key: &str,
+            value: &str,
+            max_len: usize,
+        ) -> Result<(), String> {
+            let mut connection = self.connection.clone();
+            let redis_key = self.redis_key(namespace, key);
+            if max_len == 0 {
+                return redis::cmd("DEL")
+                    .arg(&redis_key)
+                    .query_async::<()>(&mut connection)
+                    .await
+                    .map_err(|e| format!("Redis DEL failed: {}", e));
+            }
+            // LPUSH と LTRIM を MULTI/EXEC でまとめ、他のレプリカと交互に実行されないようにする
+            redis::pipe()
+                .atomic()
+                .cmd("LPUSH")
+                .arg(&redis_key)
+                .arg(value)
+                .ignore()
+                .cmd("LTRIM")
+                .arg(&redis_key)
+                .arg(0)
+                .arg(max_len as i64 - 1)
+                .ignore()
+                .query_async::<()>(&mut connection)
+                .await
+                .map_err(|e| format!("Redis LPUSH failed: {}", e))
+        }
+
+        async fn latest(
+            &self,
+            namespace: &str,
+            key: &str,
+            limit: usize,
+        ) -> Result<Vec<String>, String> {
+            if limit == 0 {
+                return Ok(Vec::new());
+            }
+            let mut connection = self.connection.clone();
+            redis::cmd("LRANGE")
+                .arg(self.redis_key(namespace, key))
+                .arg(0)
+                .arg(i64::try_from(limit).unwrap_or(i64::MAX) - 1)
+                .query_async::<Vec<String>>(&mut connection)
+                .await
+                .map_err(|e| format!("Redis LRANGE failed: {}", e))
+        }
+    }
+}
+
+// --- 環境変数からストレージを作成する関数 ---
+// STORAGE_BACKEND=memory|sqlite|redis, STORAGE_URL でパスまたは接続URLを指定
+pub async fn create_storage_from_env() -> Result<SharedStorage, String> {
+    let backend = env::var("STORAGE_BACKEND").unwrap_or_else(|_| "memory".to_string());
+
+    let storage: SharedStorage = match backend.to_ascii_lowercase().as_str() {
+        "memory" => Arc::new(MemoryStorage::default()),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let path = env::var("STORAGE_URL").unwrap_or_else(|_| "mcp-http-server.db".to_string());
+            Arc::new(sqlite::SqliteStorage::open(&path)?)
+        }
+        #[cfg(feature = "redis")]
+        "redis" => {
+            let url = env::var("STORAGE_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_string());
+            let prefix =
+                env::var("STORAGE_KEY_PREFIX").unwrap_or_else(|_| "mcp-http-server".to_string());
+            Arc::new(redis_backend::RedisStorage::connect(&url, prefix).await?)
+        }
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => {
+            return Err(
+                "Storage backend 'sqlite' is not compiled in; rebuild with `--features sqlite`"
+                    .to_string(),
+            );
+        }
+        #[cfg(not(feature = "redis"))]
+        "redis" => {
+            return Err(
+                "Storage backend 'redis' is not compiled in; rebuild with `--features redis`"
+                    .to_string(),
+            );
+        }
+        other => {
+            return Err(format!(
+                "Unknown STORAGE_BACKEND '{}' (expected memory, sqlite, or redis)",
+                other
+            ));
+        }
+    };
+
+    info!(
+        backend = storage.backend_name(),
+        "Storage backend initialized"
+    );
+    Ok(storage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // どのバックエンドでも同じ振る舞いになることを確かめる (key は実行ごとに変え、共有の Redis でも衝突させない)
+    async fn exercise(storage: &dyn Storage) {
+        let key = uuid::Uuid::new_v4().to_string();
+
+        assert_eq!(storage.get("test", &key).await.unwrap(), None);
+        storage.put("test", &key, "v1", None).await.unwrap();
+        storage.put("test", &key, "v2", None).await.unwrap();
+        assert_eq!(
+            storage.get("test", &key).await.unwrap().as_deref(),
+            Some("v2")
+        );
+        // 名前空間ごとに別のキーになる
+        assert_eq!(storage.get("other", &key).await.unwrap(), None);
+
+        storage
+            .put("test", "expiring", "v", Some(Duration::from_millis(20)))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(storage.get("test", "expiring").await.unwrap(), None);
+
+        assert!(storage.latest("test", &key, 10).await.unwrap().is_empty());
+        for value in ["a", "b", "c", "d"] {
+            storage.push("test", &key, value, 3).await.unwrap();
+        }
+        assert_eq!(
+            storage.latest("test", &key, 10).await.unwrap(),
+            ["d", "c", "b"]
+        );
+        assert_eq!(storage.latest("test", &key, 2).await.unwrap(), ["d", "c"]);
+        assert_eq!(
+            storage
+                .latest("test", &key, usize::MAX)
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_backend() {
+        exercise(&MemoryStorage::default()).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_backend_persists_across_reopen() {
+        let path = env::temp_dir().join(format!("mcp-storage-{}.db", uuid::Uuid::new_v4()));
+        let path_str = path.to_str().unwrap();
+        {
+            let storage = sqlite::SqliteStorage::open(path_str).unwrap();
+            exercise(&storage).await;
+            storage.put("test", "kept", "value", None).await.unwrap();
+            storage.push("test", "list", "entry", 10).await.unwrap();
+        }
+        let reopened = sqlite::SqliteStorage::open(path_str).unwrap();
+        assert_eq!(
+            reopened.get("test", "kept").await.unwrap().as_deref(),
+            Some("value")
+        );
+        assert_eq!(
+            reopened.latest("test", "list", 10).await.unwrap(),
+            ["entry"]
+        );
+        drop(reopened);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path_str, suffix));
+        }
+    }
+
+    // 接続できる Redis が必要なため、STORAGE_TEST_REDIS_URL を設定したときだけ実行する
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    async fn redis_backend() {
+        let Ok(url) = env::var("STORAGE_TEST_REDIS_URL") else {
+            eprintln!("STORAGE_TEST_REDIS_URL is not set, skipping the Redis backend test");
+            return;
+        };
+        let storage =
+            redis_backend::RedisStorage::connect(&url, "mcp-http-server-test".to_string())
+                .await
+                .unwrap();
+        exercise(&storage).await;
+    }
+}
@@ -0,0 +1,134 @@
+use std::{env, time::Duration};
+use tracing::info;
+#[cfg(unix)]
+use tracing::{debug, warn};
+
+// --- systemd 連携 (ソケットアクティベーションと sd_notify) ---
+// systemd 以外から起動された場合は環境変数が無いため、すべて何もしない
+
+// systemd が渡すファイルディスクリプタの先頭番号 (SD_LISTEN_FDS_START)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+// systemd から受け取った待ち受け済みのソケット
+#[cfg_attr(not(unix), allow(dead_code))]
+pub enum ActivatedListener {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+// LISTEN_PID / LISTEN_FDS で渡されたソケットを受け取る
+#[cfg(unix)]
+pub fn listen_fds() -> Result<Vec<ActivatedListener>, String> {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    let Some(count) = env::var("LISTEN_FDS").ok() else {
+        return Ok(Vec::new());
+    };
+    // 別プロセス向けの変数を継承しただけの場合は無視する
+    let for_this_process = env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_some_and(|pid| pid == std::process::id());
+    if !for_this_process {
+        debug!("Ignoring LISTEN_FDS intended for another process");
+        return Ok(Vec::new());
+    }
+    let count: i32 = count
+        .parse()
+        .map_err(|e| format!("Invalid LISTEN_FDS '{}': {}", count, e))?;
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // systemd が開いたまま渡した fd を所有する (他では使われていない)
+            let owned = unsafe { OwnedFd::from_raw_fd(fd) };
+            let listener = classify_socket(owned)
+                .map_err(|e| format!("Unsupported socket passed as fd {}: {}", fd, e))?;
+            Ok(listener)
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+pub fn listen_fds() -> Result<Vec<ActivatedListener>, String> {
+    Ok(Vec::new())
+}
+
+// Unix ソケットとして解釈できなければ TCP ソケットとして扱う
+#[cfg(unix)]
+fn classify_socket(fd: std::os::fd::OwnedFd) -> Result<ActivatedListener, String> {
+    let unix = std::os::unix::net::UnixListener::from(fd);
+    if unix.local_addr().is_ok() {
+        return Ok(ActivatedListener::Unix(unix));
+    }
+    let tcp = std::net::TcpListener::from(std::os::fd::OwnedFd::from(unix));
+    tcp.local_addr().map_err(|e| e.to_string())?;
+    Ok(ActivatedListener::Tcp(tcp))
+}
+
+// --- sd_notify ---
+// NOTIFY_SOCKET へ状態を送る ("@" で始まる場合は抽象名前空間)
+#[cfg(unix)]
+fn notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = env::var("NOTIFY_SOCKET")
+        .ok()
+        .filter(|path| !path.is_empty())
+    else {
+        return;
+    };
+    let result = UnixDatagram::unbound().and_then(|socket| match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)
+        }
+        _ => socket.send_to(state.as_bytes(), &path),
+    });
+    if let Err(e) = result {
+        warn!(error = %e, state = %state, "Failed to send systemd notification");
+    }
+}
+
+#[cfg(not(unix))]
+fn notify(_state: &str) {}
+
+// 起動完了 (MCP サーバーの initialize とリスナーの準備が済んだ) ことを通知する
+pub fn notify_ready() {
+    if env::var_os("NOTIFY_SOCKET").is_none() {
+        return;
+    }
+    notify("READY=1\nSTATUS=Accepting requests");
+    info!("Notified systemd of readiness");
+    spawn_watchdog();
+}
+
+// WatchdogSec= が設定されていれば、その半分の間隔で WATCHDOG=1 を送り続ける
+fn spawn_watchdog() {
+    let for_this_process = env::var("WATCHDOG_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_none_or(|pid| pid == std::process::id());
+    let Some(interval) = env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+        .filter(|usec| *usec > 0 && for_this_process)
+        .map(|usec| Duration::from_micros(usec / 2))
+    else {
+        return;
+    };
+    info!(
+        interval_ms = interval.as_millis() as u64,
+        "Starting systemd watchdog"
+    );
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
@@ -0,0 +1,163 @@
+use serde::Deserialize;
+use std::{collections::HashMap, env, fmt, sync::Arc};
+use tracing::info;
+
+use crate::{
+    api_keys::{StoredKey, Verification},
+    mcp_process::McpServer,
+    tool_policy::ToolPolicy,
+};
+
+// --- APIキーごとの利用可能なサーバー (マルチテナント) ---
+// API_KEYS_FILE の JSON でテナントごとにキーと利用できるサーバーを定義する
+// {
+//   "tenant-a": { "key": "...", "servers": ["readability"] },
+//   "tenant-b": { "key": "...", "servers": ["github", "readability"], "default_server": "github" },
+//   "tenant-c": { "key": "...", "servers": ["github"], "tools": { "deny": ["delete_repository"] } }
+// }
+#[derive(Deserialize, Debug)]
+struct TenantEntry {
+    key: String,
+    servers: Vec<String>,
+    #[serde(default)]
+    default_server: Option<String>,
+    #[serde(default)]
+    tools: TenantTools,
+}
+
+// テナントが呼び出せるツール (サーバーの allowed_tools / blocked_tools に加えて適用する)
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct TenantTools {
+    #[serde(default)]
+    allow: Option<Vec<String>>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+// 認証に成功したテナント (リクエストの Extension として渡す)
+#[derive(Debug)]
+pub struct Tenant {
+    pub name: String,
+    pub servers: Vec<String>,
+    // サーバーを指定しないリクエストの対象 (省略時は servers の先頭)
+    pub default_server: String,
+    // tools.allow / tools.deny (未指定の場合は is_active() が false)
+    pub tools: ToolPolicy,
+}
+
+impl Tenant {
+    pub fn allows(&self, server: &str) -> bool {
+        self.servers.iter().any(|allowed| allowed == server)
+    }
+
+    // servers にはサーバーの名前と別名のどちらを書いてもよい
+    pub fn allows_server(&self, server: &McpServer) -> bool {
+        self.allows(&server.server_key)
+            || server
+                .config()
+                .aliases
+                .iter()
+                .any(|alias| self.allows(alias))
+    }
+}
+
+// APIキーからテナントを引く表 (Debug でキーを出力しない)。
+// key には平文のキーのほか argon2 / bcrypt のハッシュも書ける (api_keys::StoredKey を参照)
+#[derive(Clone, Default)]
+pub struct TenantKeys {
+    plain: Arc<HashMap<String, Arc<Tenant>>>,
+    hashed: Arc<Vec<(StoredKey, Arc<Tenant>)>>,
+}
+
+impl fmt::Debug for TenantKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantKeys")
+            .field("tenants", &(self.plain.len() + self.hashed.len()))
+            .finish()
+    }
+}
+
+impl TenantKeys {
+    pub fn is_empty(&self) -> bool {
+        self.plain.is_empty() && self.hashed.is_empty()
+    }
+
+    pub fn has_hashed(&self) -> bool {
+        !self.hashed.is_empty()
+    }
+
+    // ハッシュのキーがある場合は照合に時間がかかる (StoredKey::matches を参照)
+    pub fn get(&self, key: &str, verification: Verification) -> Option<Arc<Tenant>> {
+        self.plain.get(key).cloned().or_else(|| {
+            self.hashed
+                .iter()
+                .find(|(stored, _)| stored.matches(key, verification))
+                .map(|(_, tenant)| Arc::clone(tenant))
+        })
+    }
+
+    // API_KEYS_FILE が未設定の場合は空 (HTTP_API_KEY のみ)
+    pub fn from_env() -> Result<Self, String> {
+        let Some(path) = env::var("API_KEYS_FILE")
+            .ok()
+            .filter(|path| !path.is_empty())
+        else {
+            return Ok(TenantKeys::default());
+        };
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read API keys file '{}': {}", path, e))?;
+        let entries: HashMap<String, TenantEntry> = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse API keys file '{}': {}", path, e))?;
+
+        let mut plain = HashMap::new();
+        let mut hashed: Vec<(StoredKey, Arc<Tenant>)> = Vec::new();
+        for (name, entry) in entries {
+            if entry.key.is_empty() {
+                return Err(format!("Tenant '{}' has an empty key", name));
+            }
+            if entry.servers.is_empty() {
+                return Err(format!("Tenant '{}' has no servers", name));
+            }
+            let default_server = match entry.default_server {
+                Some(server) if !entry.servers.contains(&server) => {
+                    return Err(format!(
+                        "Tenant '{}' has default_server '{}' that is not in its servers",
+                        name, server
+                    ));
+                }
+                Some(server) => server,
+                None => entry.servers[0].clone(),
+            };
+            let tenant = Arc::new(Tenant {
+                name: name.clone(),
+                servers: entry.servers,
+                default_server,
+                tools: ToolPolicy::from_lists(entry.tools.allow, entry.tools.deny),
+            });
+            let reused = match StoredKey::parse(&entry.key)
+                .map_err(|e| format!("Tenant '{}' has an {}", name, e))?
+            {
+                StoredKey::Plain(key) => plain.insert(key, tenant).is_some(),
+                stored if hashed.iter().any(|(existing, _)| *existing == stored) => true,
+                stored => {
+                    hashed.push((stored, tenant));
+                    false
+                }
+            };
+            if reused {
+                return Err(format!("Tenant '{}' reuses another tenant's key", name));
+            }
+        }
+        info!(
+            path = %path,
+            tenants = plain.len() + hashed.len(),
+            hashed = hashed.len(),
+            "Loaded per-tenant API keys"
+        );
+        Ok(TenantKeys {
+            plain: Arc::new(plain),
+            hashed: Arc::new(hashed),
+        })
+    }
+}
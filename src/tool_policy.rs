@@ -0,0 +1,400 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tracing::warn;
+
+// ブロックされたツール呼び出しに返す JSON-RPC エラーコード (サーバー定義エラーの範囲)
+pub const TOOL_BLOCKED_ERROR_CODE: i64 = -32001;
+
+// read_only モードで変更系とみなすツール名の単語 (destructive_patterns 未指定時)
+const DEFAULT_DESTRUCTIVE_PATTERNS: &[&str] = &[
+    "create", "update", "delete", "remove", "write", "edit", "add", "set", "push", "merge", "move",
+    "rename", "fork", "close", "assign", "upload", "put", "patch", "insert", "drop",
+];
+
+// check_request の結果
+pub enum PolicyCheck {
+    Forward,
+    // 子プロセスに転送せずに返すエラーレスポンス (バッチの場合はすべての要素が拒否対象)
+    Reject(String),
+    // バッチの一部だけが拒否対象
+    Partial(PartialBatch),
+}
+
+// 許可された要素だけのバッチ (forwarded) と、元のバッチの順序での各要素の扱い
+pub struct PartialBatch {
+    pub forwarded: String,
+    items: Vec<BatchItem>,
+}
+
+enum BatchItem {
+    // 拒否した要素のエラーレスポンス
+    Blocked(Value),
+    // 転送した要素の id (通知は None)
+    Forwarded(Option<Value>),
+}
+
+impl PartialBatch {
+    // 転送した要素への応答と拒否した要素のエラーを、元のバッチの順序で1つのバッチの応答にまとめる
+    pub fn merge(&self, response: &str) -> String {
+        let mut responses = match serde_json::from_str::<Value>(response) {
+            Ok(Value::Array(responses)) => responses,
+            // バッチ全体へのエラー (Invalid Request など) は1件の応答として扱う
+            Ok(single) => vec![single],
+            Err(_) => return response.to_string(),
+        };
+        let mut merged = Vec::with_capacity(self.items.len());
+        for item in &self.items {
+            match item {
+                BatchItem::Blocked(error) => merged.push(error.clone()),
+                BatchItem::Forwarded(Some(id)) => {
+                    if let Some(position) = responses
+                        .iter()
+                        .position(|response| response.get("id") == Some(id))
+                    {
+                        merged.push(responses.remove(position));
+                    }
+                }
+                BatchItem::Forwarded(None) => {}
+            }
+        }
+        // id で対応付けられなかった応答は末尾に残す
+        merged.extend(responses);
+        Value::Array(merged).to_string()
+    }
+}
+
+// --- ツールの許可・拒否リスト ---
+// allowed_tools が指定された場合はそれ以外をすべて拒否し、blocked_tools は常に拒否する
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ToolPolicy {
+    #[serde(default)]
+    pub allowed_tools: Option<Vec<String>>,
+    #[serde(default)]
+    pub blocked_tools: Vec<String>,
+    // true の場合、変更系のツール呼び出しを拒否する
+    #[serde(default)]
+    pub read_only: bool,
+    // read_only モードで変更系とみなすツール名の単語 (名前を英数字以外で区切った単語と比較)
+    #[serde(default)]
+    pub destructive_patterns: Option<Vec<String>>,
+    // tools/list の annotations から判定した、ツール名ごとの変更系かどうか (プロセス間で共有)
+    #[serde(skip)]
+    annotated_tools: Arc<Mutex<HashMap<String, bool>>>,
+}
+
+impl ToolPolicy {
+    // 許可・拒否リストだけのポリシー (API_KEYS_FILE のテナントの tools.allow / tools.deny)
+    pub fn from_lists(allowed_tools: Option<Vec<String>>, blocked_tools: Vec<String>) -> Self {
+        ToolPolicy {
+            allowed_tools,
+            blocked_tools,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.allowed_tools.is_some() || !self.blocked_tools.is_empty() || self.read_only
+    }
+
+    pub fn is_allowed(&self, tool_name: &str) -> bool {
+        self.is_listed(tool_name) && !(self.read_only && self.is_destructive(tool_name))
+    }
+
+    // allowed_tools / blocked_tools による判定
+    fn is_listed(&self, tool_name: &str) -> bool {
+        let allowed = self
+            .allowed_tools
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|name| name == tool_name));
+        allowed && !self.blocked_tools.iter().any(|name| name == tool_name)
+    }
+
+    // annotations で判定済みならその結果を、未判定なら名前の単語で判定する
+    fn is_destructive(&self, tool_name: &str) -> bool {
+        let annotated = self
+            .annotated_tools
+            .lock()
+            .ok()
+            .and_then(|tools| tools.get(tool_name).copied());
+        annotated.unwrap_or_else(|| self.is_destructive_name(tool_name))
+    }
+
+    fn is_destructive_name(&self, tool_name: &str) -> bool {
+        tool_name
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|word| match &self.destructive_patterns {
+                Some(patterns) => patterns.iter().any(|p| p.eq_ignore_ascii_case(word)),
+                None => DEFAULT_DESTRUCTIVE_PATTERNS
+                    .iter()
+                    .any(|p| p.eq_ignore_ascii_case(word)),
+            })
+    }
+
+    // ツール定義で判定する (readOnlyHint を優先し、次に destructiveHint と名前)
+    fn is_allowed_tool(&self, tool: &Value) -> bool {
+        let Some(name) = tool.get("name").and_then(Value::as_str) else {
+            return false;
+        };
+        if !self.read_only {
+            return self.is_listed(name);
+        }
+        let hint = |key: &str| {
+            tool.get("annotations")
+                .and_then(|annotations| annotations.get(key))
+                .and_then(Value::as_bool)
+        };
+        let destructive = match hint("readOnlyHint") {
+            Some(true) => false,
+            _ => hint("destructiveHint") == Some(true) || self.is_destructive_name(name),
+        };
+        // tools/call では名前しか分からないため、判定結果を記録しておく
+        if let Ok(mut tools) = self.annotated_tools.lock() {
+            tools.insert(name.to_string(), destructive);
+        }
+        self.is_listed(name) && !destructive
+    }
+
+    // tools/list の結果から許可されていないツールを取り除く
+    pub fn filter_tools(&self, tools: Vec<Value>) -> Vec<Value> {
+        if !self.is_active() {
+            return tools;
+        }
+        tools
+            .into_iter()
+            .filter(|tool| self.is_allowed_tool(tool))
+            .collect()
+    }
+
+    // 生の JSON-RPC メッセージ中の tools/call を検査する
+    pub fn check_request(&self, message: &str) -> PolicyCheck {
+        if !self.is_active() {
+            return PolicyCheck::Forward;
+        }
+        let Ok(value) = serde_json::from_str::<Value>(message) else {
+            return PolicyCheck::Forward;
+        };
+        let Value::Array(batch) = value else {
+            return match self.blocked_call_error(&value) {
+                Some(error) => PolicyCheck::Reject(error.to_string()),
+                None => PolicyCheck::Forward,
+            };
+        };
+        let items: Vec<BatchItem> = batch
+            .iter()
+            .map(|request| match self.blocked_call_error(request) {
+                Some(error) => BatchItem::Blocked(error),
+                None => BatchItem::Forwarded(request.get("id").cloned()),
+            })
+            .collect();
+        if !items
+            .iter()
+            .any(|item| matches!(item, BatchItem::Blocked(_)))
+        {
+            return PolicyCheck::Forward;
+        }
+        // バッチ内の拒否対象だけを取り除き、残りは転送する
+        let forwarded: Vec<Value> = batch
+            .into_iter()
+            .zip(&items)
+            .filter(|(_, item)| matches!(item, BatchItem::Forwarded(_)))
+            .map(|(request, _)| request)
+            .collect();
+        if forwarded.is_empty() {
+            let errors: Vec<Value> = items
+                .into_iter()
+                .filter_map(|item| match item {
+                    BatchItem::Blocked(error) => Some(error),
+                    BatchItem::Forwarded(_) => None,
+                })
+                .collect();
+            return PolicyCheck::Reject(Value::Array(errors).to_string());
+        }
+        PolicyCheck::Partial(PartialBatch {
+            forwarded: Value::Array(forwarded).to_string(),
+            items,
+        })
+    }
+
+    fn blocked_call_error(&self, request: &Value) -> Option<Value> {
+        if request.get("method").and_then(Value::as_str) != Some("tools/call") {
+            return None;
+        }
+        let tool_name = request
+            .get("params")
+            .and_then(|params| params.get("name"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if self.is_allowed(tool_name) {
+            return None;
+        }
+        warn!(tool = %tool_name, "Rejected call to blocked tool");
+        Some(json!({
+            "jsonrpc": "2.0",
+            "id": request.get("id").cloned().unwrap_or(Value::Null),
+            "error": {
+                "code": TOOL_BLOCKED_ERROR_CODE,
+                "message": format!("Tool '{}' is not allowed", tool_name),
+            },
+        }))
+    }
+
+    // tools/list へのレスポンスであれば、許可されていないツールを取り除いたレスポンスを返す。
+    // バッチの場合は tools/list の要素への応答を id で対応付けてそれぞれ取り除く
+    pub fn filter_list_response(&self, request: &str, response: &str) -> Option<String> {
+        if !self.is_active() {
+            return None;
+        }
+        let request: Value = serde_json::from_str(request).ok()?;
+        let mut response: Value = serde_json::from_str(response).ok()?;
+        match (&request, &mut response) {
+            (Value::Array(requests), Value::Array(responses)) => {
+                let ids: Vec<&Value> = requests
+                    .iter()
+                    .filter(|request| is_tools_list(request))
+                    .filter_map(|request| request.get("id"))
+                    .collect();
+                if ids.is_empty() {
+                    return None;
+                }
+                for response in responses
+                    .iter_mut()
+                    .filter(|response| response.get("id").is_some_and(|id| ids.contains(&id)))
+                {
+                    self.filter_list_result(response);
+                }
+            }
+            (request, response) if is_tools_list(request) => {
+                self.filter_list_result(response)?;
+            }
+            _ => return None,
+        }
+        Some(response.to_string())
+    }
+
+    fn filter_list_result(&self, response: &mut Value) -> Option<()> {
+        let Some(Value::Array(tools)) = response.get_mut("result")?.get_mut("tools") else {
+            return None;
+        };
+        *tools = self.filter_tools(std::mem::take(tools));
+        Some(())
+    }
+}
+
+fn is_tools_list(request: &Value) -> bool {
+    request.get("method").and_then(Value::as_str) == Some("tools/list")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ToolPolicy {
+        ToolPolicy {
+            blocked_tools: vec!["delete_repo".to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn call(id: i64, tool: &str) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "method": "tools/call", "params": {"name": tool}})
+    }
+
+    #[test]
+    fn single_blocked_call_is_rejected() {
+        let PolicyCheck::Reject(rejection) =
+            policy().check_request(&call(1, "delete_repo").to_string())
+        else {
+            panic!("expected a rejection");
+        };
+        let rejection: Value = serde_json::from_str(&rejection).unwrap();
+        assert_eq!(rejection["id"], 1);
+        assert_eq!(rejection["error"]["code"], TOOL_BLOCKED_ERROR_CODE);
+        assert!(matches!(
+            policy().check_request(&call(1, "get_repo").to_string()),
+            PolicyCheck::Forward
+        ));
+    }
+
+    #[test]
+    fn partial_batch_forwards_allowed_items_and_merges_in_order() {
+        let batch = json!([
+            call(1, "get_repo"),
+            call(2, "delete_repo"),
+            {"jsonrpc": "2.0", "method": "notifications/cancelled", "params": {"requestId": 9}},
+            call(3, "list_repos"),
+        ]);
+        let PolicyCheck::Partial(partial) = policy().check_request(&batch.to_string()) else {
+            panic!("expected a partial batch");
+        };
+        let forwarded: Vec<Value> = serde_json::from_str(&partial.forwarded).unwrap();
+        assert_eq!(forwarded.len(), 3);
+        assert!(
+            forwarded
+                .iter()
+                .all(|request| request["params"]["name"] != "delete_repo")
+        );
+
+        // 子プロセスの応答の順序は問わない
+        let response = json!([
+            {"jsonrpc": "2.0", "id": 3, "result": {"content": []}},
+            {"jsonrpc": "2.0", "id": 1, "result": {"content": []}},
+        ]);
+        let merged: Vec<Value> =
+            serde_json::from_str(&partial.merge(&response.to_string())).unwrap();
+        let ids: Vec<&Value> = merged.iter().map(|response| &response["id"]).collect();
+        assert_eq!(ids, [&json!(1), &json!(2), &json!(3)]);
+        assert_eq!(merged[1]["error"]["code"], TOOL_BLOCKED_ERROR_CODE);
+    }
+
+    #[test]
+    fn fully_blocked_batch_is_rejected() {
+        let batch = json!([call(1, "delete_repo"), call(2, "delete_repo")]);
+        let PolicyCheck::Reject(rejection) = policy().check_request(&batch.to_string()) else {
+            panic!("expected a rejection");
+        };
+        let errors: Vec<Value> = serde_json::from_str(&rejection).unwrap();
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn list_responses_are_filtered_in_batches() {
+        let policy = ToolPolicy::from_lists(None, vec!["delete_repo".to_string()]);
+        let tools = json!({"tools": [{"name": "get_repo"}, {"name": "delete_repo"}]});
+        let list = json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"});
+        let response = json!({"jsonrpc": "2.0", "id": 1, "result": tools});
+        let filtered: Value = serde_json::from_str(
+            &policy
+                .filter_list_response(&list.to_string(), &response.to_string())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(filtered["result"]["tools"], json!([{"name": "get_repo"}]));
+
+        let batch = json!([list, {"jsonrpc": "2.0", "id": 2, "method": "resources/list"}]);
+        let responses = json!([
+            {"jsonrpc": "2.0", "id": 2, "result": tools},
+            response,
+        ]);
+        let filtered: Value = serde_json::from_str(
+            &policy
+                .filter_list_response(&batch.to_string(), &responses.to_string())
+                .unwrap(),
+        )
+        .unwrap();
+        // tools/list 以外の応答はそのまま
+        assert_eq!(filtered[0]["result"], tools);
+        assert_eq!(
+            filtered[1]["result"]["tools"],
+            json!([{"name": "get_repo"}])
+        );
+        assert!(
+            policy
+                .filter_list_response(&call(1, "get_repo").to_string(), &response.to_string())
+                .is_none()
+        );
+    }
+}
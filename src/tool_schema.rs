@@ -0,0 +1,83 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::warn;
+
+// --- ツール定義の出力形式 ---
+// GET /api/v1/tools?format=... で指定する
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolFormat {
+    // tools/list の結果そのまま
+    #[default]
+    Mcp,
+    // OpenAI の function calling 形式
+    Openai,
+    // Anthropic の tool use 形式
+    Anthropic,
+}
+
+pub fn convert(tools: Vec<Value>, format: ToolFormat) -> Vec<Value> {
+    match format {
+        ToolFormat::Mcp => tools,
+        ToolFormat::Openai => tools.iter().map(to_openai).collect(),
+        ToolFormat::Anthropic => tools.iter().map(to_anthropic).collect(),
+    }
+}
+
+fn input_schema(tool: &Value) -> Value {
+    tool.get("inputSchema")
+        .cloned()
+        .unwrap_or_else(|| json!({ "type": "object", "properties": {} }))
+}
+
+fn description(tool: &Value) -> Value {
+    tool.get("description").cloned().unwrap_or(json!(""))
+}
+
+fn to_openai(tool: &Value) -> Value {
+    json!({
+        "type": "function",
+        "function": {
+            "name": tool.get("name"),
+            "description": description(tool),
+            "parameters": input_schema(tool),
+        },
+    })
+}
+
+fn to_anthropic(tool: &Value) -> Value {
+    json!({
+        "name": tool.get("name"),
+        "description": description(tool),
+        "input_schema": input_schema(tool),
+    })
+}
+
+// --- tools/call の引数を inputSchema で検証する ---
+// 違反内容を "/path: message" 形式で返す。スキーマ自体が不正な場合は検証しない
+pub fn validate_arguments(schema: &Value, arguments: &Value) -> Result<(), Vec<String>> {
+    let validator = match jsonschema::validator_for(schema) {
+        Ok(validator) => validator,
+        Err(e) => {
+            warn!(error = %e, "Skipping argument validation, invalid inputSchema");
+            return Ok(());
+        }
+    };
+    let violations: Vec<String> = validator
+        .iter_errors(arguments)
+        .map(|error| {
+            let path = error.instance_path().to_string();
+            let path = if path.is_empty() {
+                "/".to_string()
+            } else {
+                path
+            };
+            format!("{}: {}", path, error.masked())
+        })
+        .collect();
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
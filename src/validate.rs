@@ -0,0 +1,142 @@
+use std::env;
+
+use crate::{
+    config_file, integrity,
+    language::Language,
+    mcp_process::{McpProcessConfig, ServerType},
+    program, remote, sandbox, wasm_server,
+};
+
+// --- `mcp-http-server validate` ---
+// 子プロセスを起動せずに設定ファイルを読み込み、サーバーごとの結果を標準出力に書き出す。
+// 問題があれば 1 を返す (CI でデプロイ前に設定の誤りを検出するため)
+pub async fn run(config_file: &str, resolve: bool) -> i32 {
+    // 読み込み・${VAR} の展開・型や値の検証・enc: の復号はサーバーの起動時と同じ処理で行う
+    let servers = match config_file::read(config_file).await {
+        Ok(Some(servers)) => servers,
+        Ok(None) => {
+            println!("FAIL  MCP config file '{}' does not exist", config_file);
+            return 1;
+        }
+        Err(e) => {
+            println!("FAIL  {}", e);
+            return 1;
+        }
+    };
+    let configs = match config_file::parse_servers(servers, config_file) {
+        Ok(configs) => configs,
+        Err(e) => {
+            println!("FAIL  {}", e);
+            return 1;
+        }
+    };
+
+    println!("MCP config '{}': {} server(s)", config_file, configs.len());
+    let mut names: Vec<&String> = configs.keys().collect();
+    names.sort();
+    let mut failed = 0;
+    for name in names {
+        let config = &configs[name];
+        let problems = check(name, config, resolve).await;
+        let server_type = format!("{:?}", config.server_type).to_lowercase();
+        if problems.is_empty() {
+            println!("ok    {} ({})", name, server_type);
+        } else {
+            failed += 1;
+            println!("FAIL  {} ({})", name, server_type);
+            for problem in problems {
+                println!("      {}", problem);
+            }
+        }
+    }
+    // 既定値 (brave-search) は設定ファイルに無くてもよいため、明示された場合だけ確認する
+    // (別名と、全サーバーを起動する "all" も受け付ける)
+    if let Some(server_key) = env::var("MCP_SERVER_NAME").ok().filter(|server_key| {
+        !configs.contains_key(server_key)
+            && !configs
+                .values()
+                .any(|config| config.aliases.contains(server_key))
+            && server_key != crate::servers::ALL_SERVERS
+    }) {
+        failed += 1;
+        println!(
+            "FAIL  MCP_SERVER_NAME '{}' is not defined in the config",
+            server_key
+        );
+    }
+
+    if failed > 0 {
+        println!("{} problem(s) found", failed);
+        1
+    } else {
+        println!("Config is valid");
+        0
+    }
+}
+
+// 設定の値だけでは分からない、実行環境に依存する問題を確認する
+async fn check(server_key: &str, config: &McpProcessConfig, resolve: bool) -> Vec<String> {
+    let mut problems = Vec::new();
+    match config.server_type {
+        ServerType::Stdio => {
+            let cwd = sandbox::host_cwd(config);
+            if let Some(cwd) = cwd.as_ref().filter(|cwd| !cwd.is_dir()) {
+                problems.push(format!(
+                    "Working directory '{}' does not exist",
+                    cwd.display()
+                ));
+            }
+            if config.language == Some(Language::Wasm) {
+                problems.extend(wasm_server::missing_entrypoint(config));
+            }
+            for name in host_programs(config) {
+                if program::find(name, cwd.as_deref()).is_none() {
+                    problems.push(format!("Command '{}' was not found", name));
+                }
+            }
+            // --resolve: cwd のチェックアウトが commit / checksum と一致するか確認する
+            if resolve {
+                problems.extend(integrity::verify(server_key, config).await.err());
+            }
+        }
+        ServerType::Remote => {
+            if let Some(Err(e)) = config.remote.url.as_deref().map(reqwest::Url::parse) {
+                problems.push(format!("Invalid url: {}", e));
+            }
+            problems.extend(remote::parse_headers(&config.remote.headers).err());
+        }
+        // 集約のメンバーは設定ファイルの各サーバーとして確認する
+        ServerType::Aggregate | ServerType::Mock => {}
+    }
+    problems
+}
+
+// 子プロセス・フック・ヘルスチェックで実行するプログラム
+// (sandbox.root がある場合は chroot の中で探すため、ホストからは確認できないので含めない)
+pub fn host_programs(config: &McpProcessConfig) -> Vec<&String> {
+    let jailed = config
+        .sandbox
+        .as_ref()
+        .is_some_and(|sandbox| sandbox.root.is_some());
+    if jailed || config.server_type != ServerType::Stdio {
+        return Vec::new();
+    }
+    let hooks = config
+        .hooks
+        .post_install
+        .iter()
+        .chain(&config.hooks.pre_start);
+    let health_check = config
+        .health_check
+        .as_ref()
+        .and_then(|health_check| health_check.command.as_ref());
+    // language のビルドで作る実行ファイルはビルド前には無い
+    std::iter::once(&config.command)
+        .filter(|command| !command.is_empty() && !config.built_command)
+        .chain(
+            hooks
+                .chain(health_check)
+                .filter_map(|command| command.first()),
+        )
+        .collect()
+}
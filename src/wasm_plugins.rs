@@ -0,0 +1,572 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::{fmt, path::PathBuf};
+
+#[cfg(feature = "wasm")]
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+#[cfg(feature = "wasm")]
+use tracing::{info, warn};
+#[cfg(feature = "wasm")]
+use wasmtime::{Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+#[cfg(feature = "wasm")]
+use wasmtime_wasi::{
+    I32Exit, WasiCtxBuilder,
+    pipe::{MemoryInputPipe, MemoryOutputPipe},
+    preview1::WasiP1Ctx,
+};
+
+#[cfg(feature = "wasm")]
+use crate::scripting::SCRIPT_REJECTED_ERROR_CODE;
+use crate::scripting::ScriptError;
+
+// プラグインが 0 以外の終了コードで拒否した場合の既定の HTTP ステータス
+#[cfg(feature = "wasm")]
+const PLUGIN_REJECTED_STATUS: u16 = 403;
+// プラグインの標準出力・標準エラー出力から受け取る最大バイト数
+#[cfg(feature = "wasm")]
+const MAX_STDOUT_BYTES: usize = 16 * 1024 * 1024;
+#[cfg(feature = "wasm")]
+const MAX_STDERR_BYTES: usize = 64 * 1024;
+// この量の燃料を消費するごとに実行を中断し、他のタスクに譲る
+#[cfg(feature = "wasm")]
+const FUEL_YIELD_INTERVAL: u64 = 10_000_000;
+
+// 1回の実行で消費できる燃料 (おおよそ実行する命令数) の上限
+fn default_fuel() -> u64 {
+    1_000_000_000
+}
+
+fn default_max_memory_mb() -> usize {
+    64
+}
+
+// --- 設定ファイルの WASM プラグインの指定 ---
+// "wasm_plugins": { "on_request": "plugins/guard.wasm", "on_response": "plugins/redact.wasm" }
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct PluginPaths {
+    #[serde(default)]
+    on_request: Option<PathBuf>,
+    #[serde(default)]
+    on_response: Option<PathBuf>,
+    #[serde(default = "default_fuel")]
+    fuel: u64,
+    #[serde(default = "default_max_memory_mb")]
+    max_memory_mb: usize,
+}
+
+// --- WASM (WASI) プラグインによるリクエスト・レスポンスのフック ---
+// 各プラグインは _start を持つ WASI の command モジュール。標準入力で
+// {"hook", "server", "tenant", "request", "response"} の JSON を受け取り、書き換えたメッセージを
+// 標準出力に書く (何も書かなければ変更しない)。0 以外の終了コードは拒否で、標準エラー出力か
+// 標準出力の {message, code, status, data} がエラーの内容になる。
+// ファイル・環境変数・ネットワークには触れられず、燃料とメモリの上限の中で実行する。
+// ファイルの更新日時が変わったら、次の呼び出しの前に読み込み直す
+#[derive(Deserialize, Clone)]
+#[serde(try_from = "PluginPaths")]
+pub struct WasmPlugins {
+    paths: PluginPaths,
+    #[cfg(feature = "wasm")]
+    runtime: Arc<Runtime>,
+}
+
+impl fmt::Debug for WasmPlugins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmPlugins")
+            .field("on_request", &self.paths.on_request)
+            .field("on_response", &self.paths.on_response)
+            .field("fuel", &self.paths.fuel)
+            .field("max_memory_mb", &self.paths.max_memory_mb)
+            .finish()
+    }
+}
+
+#[cfg(feature = "wasm")]
+struct Runtime {
+    engine: Engine,
+    linker: Linker<PluginState>,
+    on_request: Option<Plugin>,
+    on_response: Option<Plugin>,
+}
+
+// 1回の実行ごとの Store の中身
+#[cfg(feature = "wasm")]
+struct PluginState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+#[cfg(feature = "wasm")]
+struct Plugin {
+    path: PathBuf,
+    loaded: Mutex<LoadedModule>,
+}
+
+#[cfg(feature = "wasm")]
+struct LoadedModule {
+    modified: Option<SystemTime>,
+    module: Module,
+}
+
+#[cfg(feature = "wasm")]
+impl Plugin {
+    fn load(engine: &Engine, path: &Path) -> Result<Self, String> {
+        let modified = modified(path);
+        let module = compile(engine, path)?;
+        Ok(Plugin {
+            path: path.to_path_buf(),
+            loaded: Mutex::new(LoadedModule { modified, module }),
+        })
+    }
+
+    // 更新日時が変わっていればコンパイルし直す (失敗したら前のモジュールを使い続ける)
+    fn module(&self, engine: &Engine) -> Module {
+        let mut loaded = self
+            .loaded
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let modified = modified(&self.path);
+        if modified.is_some() && modified != loaded.modified {
+            loaded.modified = modified;
+            match compile(engine, &self.path) {
+                Ok(module) => {
+                    info!(plugin = %self.path.display(), "Reloaded WASM plugin");
+                    loaded.module = module;
+                }
+                Err(e) => warn!(error = %e, "Keeping the previous WASM plugin"),
+            }
+        }
+        loaded.module.clone()
+    }
+}
+
+#[cfg(feature = "wasm")]
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+// .wasm のほか、テキスト形式の .wat も読み込める
+#[cfg(feature = "wasm")]
+fn compile(engine: &Engine, path: &Path) -> Result<Module, String> {
+    Module::from_file(engine, path)
+        .map_err(|e| format!("Failed to load WASM plugin '{}': {:#}", path.display(), e))
+}
+
+#[cfg(feature = "wasm")]
+impl TryFrom<PluginPaths> for WasmPlugins {
+    type Error = String;
+
+    fn try_from(paths: PluginPaths) -> Result<Self, String> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true).consume_fuel(true);
+        let engine = Engine::new(&config)
+            .map_err(|e| format!("Failed to create the WASM engine: {:#}", e))?;
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::preview1::add_to_linker_async(&mut linker, |state: &mut PluginState| {
+            &mut state.wasi
+        })
+        .map_err(|e| format!("Failed to link WASI for WASM plugins: {:#}", e))?;
+        let load = |path: &Option<PathBuf>| -> Result<Option<Plugin>, String> {
+            path.as_deref()
+                .map(|path| Plugin::load(&engine, path))
+                .transpose()
+        };
+        let on_request = load(&paths.on_request)?;
+        let on_response = load(&paths.on_response)?;
+        Ok(WasmPlugins {
+            paths,
+            runtime: Arc::new(Runtime {
+                engine,
+                linker,
+                on_request,
+                on_response,
+            }),
+        })
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+impl TryFrom<PluginPaths> for WasmPlugins {
+    type Error = String;
+
+    fn try_from(_paths: PluginPaths) -> Result<Self, String> {
+        Err("WASM plugins are not compiled in; rebuild with `--features wasm`".to_string())
+    }
+}
+
+impl WasmPlugins {
+    pub fn has_on_response(&self) -> bool {
+        self.paths.on_response.is_some()
+    }
+
+    // JSON-RPC のバッチ (配列) は各メッセージに適用する
+    pub async fn on_request_message(
+        &self,
+        server: &str,
+        tenant: Option<&str>,
+        message: Value,
+    ) -> Result<Value, ScriptError> {
+        let Value::Array(batch) = message else {
+            return self.on_request(server, tenant, message).await;
+        };
+        let mut messages = Vec::with_capacity(batch.len());
+        for message in batch {
+            messages.push(self.on_request(server, tenant, message).await?);
+        }
+        Ok(Value::Array(messages))
+    }
+
+    // バッチのレスポンスは id が同じリクエストと組にして適用する
+    pub async fn on_response_message(
+        &self,
+        server: &str,
+        tenant: Option<&str>,
+        request: &Value,
+        response: Value,
+    ) -> Result<Value, ScriptError> {
+        let (Value::Array(requests), Value::Array(responses)) = (request, &response) else {
+            return self.on_response(server, tenant, request, response).await;
+        };
+        let mut transformed = Vec::with_capacity(responses.len());
+        for response in responses {
+            let request = requests
+                .iter()
+                .find(|request| request.get("id") == response.get("id"))
+                .unwrap_or(&Value::Null);
+            transformed.push(
+                self.on_response(server, tenant, request, response.clone())
+                    .await?,
+            );
+        }
+        Ok(Value::Array(transformed))
+    }
+
+    #[cfg(feature = "wasm")]
+    async fn on_request(
+        &self,
+        server: &str,
+        tenant: Option<&str>,
+        request: Value,
+    ) -> Result<Value, ScriptError> {
+        let Some(plugin) = &self.runtime.on_request else {
+            return Ok(request);
+        };
+        let input = serde_json::json!({
+            "hook": "on_request",
+            "server": server,
+            "tenant": tenant,
+            "request": request,
+        });
+        Ok(self
+            .run(plugin, "on_request", &input)
+            .await?
+            .unwrap_or(request))
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    async fn on_request(
+        &self,
+        _server: &str,
+        _tenant: Option<&str>,
+        request: Value,
+    ) -> Result<Value, ScriptError> {
+        Ok(request)
+    }
+
+    #[cfg(feature = "wasm")]
+    async fn on_response(
+        &self,
+        server: &str,
+        tenant: Option<&str>,
+        request: &Value,
+        response: Value,
+    ) -> Result<Value, ScriptError> {
+        let Some(plugin) = &self.runtime.on_response else {
+            return Ok(response);
+        };
+        let input = serde_json::json!({
+            "hook": "on_response",
+            "server": server,
+            "tenant": tenant,
+            "request": request,
+            "response": response,
+        });
+        Ok(self
+            .run(plugin, "on_response", &input)
+            .await?
+            .unwrap_or(response))
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    async fn on_response(
+        &self,
+        _server: &str,
+        _tenant: Option<&str>,
+        _request: &Value,
+        response: Value,
+    ) -> Result<Value, ScriptError> {
+        Ok(response)
+    }
+
+    // 標準出力が空なら None (メッセージを変更しない)
+    #[cfg(feature = "wasm")]
+    async fn run(
+        &self,
+        plugin: &Plugin,
+        hook: &str,
+        input: &Value,
+    ) -> Result<Option<Value>, ScriptError> {
+        let failed = |e: wasmtime::Error| {
+            ScriptError::Failed(format!(
+                "{} WASM plugin '{}' failed: {:#}",
+                hook,
+                plugin.path.display(),
+                e
+            ))
+        };
+        let module = plugin.module(&self.runtime.engine);
+        let stdout = MemoryOutputPipe::new(MAX_STDOUT_BYTES);
+        let stderr = MemoryOutputPipe::new(MAX_STDERR_BYTES);
+        // 引数・環境変数・ディレクトリは渡さない
+        let wasi = WasiCtxBuilder::new()
+            .stdin(MemoryInputPipe::new(input.to_string()))
+            .stdout(stdout.clone())
+            .stderr(stderr.clone())
+            .build_p1();
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.paths.max_memory_mb * 1024 * 1024)
+            .build();
+        let mut store = Store::new(&self.runtime.engine, PluginState { wasi, limits });
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.paths.fuel).map_err(failed)?;
+        store
+            .fuel_async_yield_interval(Some(FUEL_YIELD_INTERVAL))
+            .map_err(failed)?;
+        let instance = self
+            .runtime
+            .linker
+            .instantiate_async(&mut store, &module)
+            .await
+            .map_err(failed)?;
+        let start = instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .map_err(failed)?;
+        let exit_code = match start.call_async(&mut store, ()).await {
+            Ok(()) => 0,
+            Err(e) => match e.downcast_ref::<I32Exit>() {
+                Some(I32Exit(code)) => *code,
+                None => return Err(failed(e)),
+            },
+        };
+        let output = stdout.contents();
+        if exit_code != 0 {
+            return Err(rejection(exit_code, &output, &stderr.contents()));
+        }
+        if output.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        serde_json::from_slice(&output).map(Some).map_err(|e| {
+            ScriptError::Failed(format!(
+                "{} WASM plugin '{}' wrote invalid JSON: {}",
+                hook,
+                plugin.path.display(),
+                e
+            ))
+        })
+    }
+}
+
+// 標準出力が {message, code, status, data} の JSON ならそれを、そうでなければ標準エラー出力をメッセージにする
+#[cfg(feature = "wasm")]
+fn rejection(exit_code: i32, stdout: &[u8], stderr: &[u8]) -> ScriptError {
+    let fields: Value = serde_json::from_slice(stdout).unwrap_or(Value::Null);
+    let stderr = String::from_utf8_lossy(stderr).trim().to_string();
+    ScriptError::Rejected {
+        status: fields
+            .get("status")
+            .and_then(Value::as_u64)
+            .and_then(|status| u16::try_from(status).ok())
+            .filter(|status| (400..600).contains(status))
+            .unwrap_or(PLUGIN_REJECTED_STATUS),
+        code: fields
+            .get("code")
+            .and_then(Value::as_i64)
+            .unwrap_or(SCRIPT_REJECTED_ERROR_CODE),
+        message: fields
+            .get("message")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .or_else(|| (!stderr.is_empty()).then_some(stderr))
+            .unwrap_or_else(|| {
+                format!("Request rejected by WASM plugin (exit code {})", exit_code)
+            }),
+        data: fields.get("data").cloned(),
+    }
+}
+
+#[cfg(all(test, feature = "wasm"))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    // 文字列を fd 1 (または 2) に書き、終了コードで終える WASI モジュール
+    fn writer_module(fd: u32, text: &str, exit_code: i32) -> String {
+        format!(
+            r#"(module
+                (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 8) "{text}")
+                (func (export "_start")
+                    (i32.store (i32.const 0) (i32.const 8))
+                    (i32.store (i32.const 4) (i32.const {len}))
+                    (drop (call $fd_write (i32.const {fd}) (i32.const 0) (i32.const 1) (i32.const 1024)))
+                    (call $proc_exit (i32.const {exit_code}))))"#,
+            text = text.replace('\\', "\\\\").replace('"', "\\\""),
+            len = text.len(),
+        )
+    }
+
+    // 標準入力をそのまま fd 2 に書き、終了コード 1 で拒否する (入力の確認用)
+    const ECHO_STDIN_TO_STDERR: &str = r#"(module
+        (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+        (memory (export "memory") 1)
+        (func (export "_start")
+            (i32.store (i32.const 0) (i32.const 64))
+            (i32.store (i32.const 4) (i32.const 60000))
+            (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+            (i32.store (i32.const 4) (i32.load (i32.const 8)))
+            (drop (call $fd_write (i32.const 2) (i32.const 0) (i32.const 1) (i32.const 8)))
+            (call $proc_exit (i32.const 1))))"#;
+
+    const INFINITE_LOOP: &str =
+        r#"(module (memory (export "memory") 1) (func (export "_start") (loop (br 0))))"#;
+
+    fn write_plugin(name: &str, source: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mcp-wasm-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, source).unwrap();
+        path
+    }
+
+    fn plugins(config: Value) -> WasmPlugins {
+        serde_json::from_value(config).unwrap()
+    }
+
+    fn request() -> Value {
+        json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": {"name": "echo"}})
+    }
+
+    #[tokio::test]
+    async fn on_response_output_replaces_the_response() {
+        let redacted = r#"{"jsonrpc":"2.0","id":1,"result":{"redacted":true}}"#;
+        let path = write_plugin("redact.wat", &writer_module(1, redacted, 0));
+        let plugins = plugins(json!({ "on_response": path }));
+        let response = json!({"jsonrpc": "2.0", "id": 1, "result": {"secret": "x"}});
+        let transformed = plugins
+            .on_response_message("a", None, &request(), response)
+            .await
+            .unwrap();
+        assert_eq!(transformed["result"], json!({"redacted": true}));
+    }
+
+    #[tokio::test]
+    async fn empty_output_leaves_the_request_unchanged() {
+        let path = write_plugin("noop.wat", &writer_module(1, "", 0));
+        let plugins = plugins(json!({ "on_request": path }));
+        let unchanged = plugins
+            .on_request_message("a", None, request())
+            .await
+            .unwrap();
+        assert_eq!(unchanged, request());
+    }
+
+    #[tokio::test]
+    async fn non_zero_exit_rejects_with_the_input_envelope_available() {
+        let path = write_plugin("echo.wat", ECHO_STDIN_TO_STDERR);
+        let plugins = plugins(json!({ "on_request": path }));
+        let Err(ScriptError::Rejected {
+            status,
+            code,
+            message,
+            ..
+        }) = plugins
+            .on_request_message("a", Some("acme"), request())
+            .await
+        else {
+            panic!("expected a rejection");
+        };
+        assert_eq!(status, PLUGIN_REJECTED_STATUS);
+        assert_eq!(code, SCRIPT_REJECTED_ERROR_CODE);
+        let input: Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(input["hook"], "on_request");
+        assert_eq!(input["server"], "a");
+        assert_eq!(input["tenant"], "acme");
+        assert_eq!(input["request"], request());
+    }
+
+    #[tokio::test]
+    async fn rejection_fields_come_from_stdout() {
+        let fields = r#"{"message":"tenant may not call echo","status":451,"code":-32010}"#;
+        let path = write_plugin("deny.wat", &writer_module(1, fields, 3));
+        let plugins = plugins(json!({ "on_request": path }));
+        let Err(ScriptError::Rejected {
+            status,
+            code,
+            message,
+            ..
+        }) = plugins.on_request_message("a", None, request()).await
+        else {
+            panic!("expected a rejection");
+        };
+        assert_eq!((status, code), (451, -32010));
+        assert_eq!(message, "tenant may not call echo");
+    }
+
+    #[tokio::test]
+    async fn running_out_of_fuel_fails_instead_of_hanging() {
+        let path = write_plugin("loop.wat", INFINITE_LOOP);
+        let plugins = plugins(json!({ "on_request": path, "fuel": 1_000_000 }));
+        let result = plugins.on_request_message("a", None, request()).await;
+        assert!(matches!(result, Err(ScriptError::Failed(_))));
+    }
+
+    #[tokio::test]
+    async fn modified_plugin_is_reloaded() {
+        let path = write_plugin("hot.wat", &writer_module(1, "", 0));
+        let plugins = plugins(json!({ "on_request": path }));
+        assert_eq!(
+            plugins
+                .on_request_message("a", None, request())
+                .await
+                .unwrap(),
+            request()
+        );
+
+        std::fs::write(&path, writer_module(1, r#"{"replaced":true}"#, 0)).unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        let replaced = plugins.on_request_message("a", None, request()).await;
+        assert_eq!(replaced.unwrap(), json!({"replaced": true}));
+    }
+
+    #[test]
+    fn invalid_module_fails_at_load() {
+        let path = write_plugin("broken.wat", "(module");
+        let error = serde_json::from_value::<WasmPlugins>(json!({ "on_request": path }))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Failed to load WASM plugin"), "{}", error);
+    }
+}
@@ -0,0 +1,376 @@
+use std::path::PathBuf;
+use tokio::{io::DuplexStream, task::JoinHandle};
+
+use crate::{mcp_process::McpProcessConfig, sandbox};
+
+#[cfg(feature = "wasm")]
+use std::{sync::LazyLock, time::Duration};
+#[cfg(feature = "wasm")]
+use tracing::info;
+#[cfg(feature = "wasm")]
+use wasmtime::{
+    Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder,
+    component::{Component, ResourceTable},
+};
+#[cfg(feature = "wasm")]
+use wasmtime_wasi::{
+    AsyncStdinStream, AsyncStdoutStream, I32Exit, IoView, WasiCtx, WasiCtxBuilder, WasiView,
+    bindings::Command,
+    pipe::{AsyncReadStream, AsyncWriteStream},
+    preview1::WasiP1Ctx,
+};
+
+// ゲストとの間の標準入出力のバッファのバイト数
+#[cfg(feature = "wasm")]
+const PIPE_BUFFER_BYTES: usize = 64 * 1024;
+// 計算だけを続けるゲストも停止できるよう、この間隔で実行を中断してタスクに譲る
+#[cfg(feature = "wasm")]
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+// 起動したゲストの標準入出力 (ブリッジ側の端) と、終了を待つタスク
+pub struct WasmGuest {
+    pub stdin: DuplexStream,
+    pub stdout: DuplexStream,
+    pub stderr: DuplexStream,
+    // 終了コード (トラップした場合はエラー)。abort するとゲストを止める
+    pub exit: JoinHandle<Result<i32, String>>,
+}
+
+// --- language: "wasm" のサーバー ---
+// cwd の entrypoint (WASI の command モジュールまたはコンポーネント) を組み込みの wasmtime で実行し、
+// 標準入出力をブリッジにつなぐ。ゲストに渡すのは args と env だけで、ファイル・ネットワークには
+// 触れられない。limits.memory_mb はゲストのメモリの上限になる
+#[cfg(feature = "wasm")]
+pub async fn start(server_key: &str, config: &McpProcessConfig) -> Result<WasmGuest, String> {
+    let path = entrypoint(config)?;
+    info!(server = %server_key, entrypoint = %path.display(), "Starting WASM MCP server");
+    let guest = compile(path.clone()).await?;
+
+    let (stdin, guest_stdin) = tokio::io::duplex(PIPE_BUFFER_BYTES);
+    let (guest_stdout, stdout) = tokio::io::duplex(PIPE_BUFFER_BYTES);
+    let (guest_stderr, stderr) = tokio::io::duplex(PIPE_BUFFER_BYTES);
+    let program = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut wasi = WasiCtxBuilder::new();
+    wasi.stdin(AsyncStdinStream::new(AsyncReadStream::new(guest_stdin)))
+        .stdout(AsyncStdoutStream::new(AsyncWriteStream::new(
+            PIPE_BUFFER_BYTES,
+            guest_stdout,
+        )))
+        .stderr(AsyncStdoutStream::new(AsyncWriteStream::new(
+            PIPE_BUFFER_BYTES,
+            guest_stderr,
+        )))
+        .arg(program)
+        .args(&config.args)
+        .envs(
+            &config
+                .child_env(server_key)?
+                .into_iter()
+                .collect::<Vec<_>>(),
+        );
+    let mut limits = StoreLimitsBuilder::new();
+    if let Some(memory_mb) = config.limits.as_ref().and_then(|limits| limits.memory_mb) {
+        let bytes = memory_mb.saturating_mul(1024 * 1024);
+        limits = limits.memory_size(usize::try_from(bytes).unwrap_or(usize::MAX));
+    }
+    let limits = limits.build();
+
+    let exit = tokio::spawn(async move {
+        let result = match guest {
+            Guest::Module(module) => run_module(&module, wasi, limits).await,
+            Guest::Component(component) => run_component(&component, wasi, limits).await,
+        };
+        result.or_else(|e| match e.downcast_ref::<I32Exit>() {
+            Some(I32Exit(code)) => Ok(*code),
+            None => Err(format!("WASM MCP server trapped: {:#}", e)),
+        })
+    });
+    Ok(WasmGuest {
+        stdin,
+        stdout,
+        stderr,
+        exit,
+    })
+}
+
+#[cfg(not(feature = "wasm"))]
+pub async fn start(_server_key: &str, _config: &McpProcessConfig) -> Result<WasmGuest, String> {
+    Err(NOT_COMPILED_IN.to_string())
+}
+
+pub const NOT_COMPILED_IN: &str =
+    "WASM servers are not compiled in; rebuild with `--features wasm`";
+
+// entrypoint は cwd からの相対パス
+fn entrypoint(config: &McpProcessConfig) -> Result<PathBuf, String> {
+    let entrypoint = config
+        .entrypoint
+        .as_deref()
+        .ok_or("'entrypoint' (the .wasm file) is required for wasm servers")?;
+    Ok(sandbox::host_cwd(config)
+        .unwrap_or_default()
+        .join(entrypoint))
+}
+
+// 全サーバーで共有するエンジン。エポックを進めるスレッドを1つだけ起動する
+#[cfg(feature = "wasm")]
+static ENGINE: LazyLock<Result<Engine, String>> = LazyLock::new(|| {
+    let mut config = wasmtime::Config::new();
+    config.async_support(true).epoch_interruption(true);
+    let engine =
+        Engine::new(&config).map_err(|e| format!("Failed to create the WASM engine: {:#}", e))?;
+    let ticker = engine.weak();
+    std::thread::Builder::new()
+        .name("wasm-epoch".to_string())
+        .spawn(move || {
+            while let Some(engine) = ticker.upgrade() {
+                engine.increment_epoch();
+                drop(engine);
+                std::thread::sleep(EPOCH_TICK);
+            }
+        })
+        .map_err(|e| format!("Failed to start the WASM epoch thread: {}", e))?;
+    Ok(engine)
+});
+
+#[cfg(feature = "wasm")]
+fn engine() -> Result<&'static Engine, String> {
+    ENGINE.as_ref().map_err(Clone::clone)
+}
+
+#[cfg(feature = "wasm")]
+enum Guest {
+    Module(Module),
+    Component(Component),
+}
+
+// コンパイルは CPU を使い続けるため、ブロッキング用のスレッドで行う
+#[cfg(feature = "wasm")]
+async fn compile(path: PathBuf) -> Result<Guest, String> {
+    let engine = engine()?;
+    tokio::task::spawn_blocking(move || {
+        let bytes = std::fs::read(&path)
+            .map_err(|e| format!("Failed to read WASM entrypoint '{}': {}", path.display(), e))?;
+        let guest = if is_component(&bytes) {
+            Component::new(engine, &bytes).map(Guest::Component)
+        } else {
+            Module::new(engine, &bytes).map(Guest::Module)
+        };
+        guest.map_err(|e| {
+            format!(
+                "Failed to compile WASM entrypoint '{}': {:#}",
+                path.display(),
+                e
+            )
+        })
+    })
+    .await
+    .map_err(|e| format!("WASM compile task failed: {}", e))?
+}
+
+// バイナリ形式はヘッダーの layer (コンポーネントは 1)、テキスト形式は先頭の (component で見分ける
+#[cfg(feature = "wasm")]
+fn is_component(bytes: &[u8]) -> bool {
+    match bytes.strip_prefix(b"\0asm") {
+        Some(header) => header.get(2..4) == Some(&[1, 0]),
+        None => String::from_utf8_lossy(bytes)
+            .trim_start()
+            .starts_with("(component"),
+    }
+}
+
+// WASI preview1 の command モジュール (_start)
+#[cfg(feature = "wasm")]
+struct ModuleState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+#[cfg(feature = "wasm")]
+async fn run_module(
+    module: &Module,
+    mut wasi: WasiCtxBuilder,
+    limits: StoreLimits,
+) -> wasmtime::Result<i32> {
+    let engine = module.engine();
+    let mut linker = Linker::new(engine);
+    wasmtime_wasi::preview1::add_to_linker_async(&mut linker, |state: &mut ModuleState| {
+        &mut state.wasi
+    })?;
+    let state = ModuleState {
+        wasi: wasi.build_p1(),
+        limits,
+    };
+    let mut store = Store::new(engine, state);
+    store.limiter(|state| &mut state.limits);
+    store.epoch_deadline_async_yield_and_update(1);
+    let instance = linker.instantiate_async(&mut store, module).await?;
+    let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+    start.call_async(&mut store, ()).await?;
+    Ok(0)
+}
+
+// WASI preview2 のコンポーネント (wasi:cli/run)
+#[cfg(feature = "wasm")]
+struct ComponentState {
+    wasi: WasiCtx,
+    table: ResourceTable,
+    limits: StoreLimits,
+}
+
+#[cfg(feature = "wasm")]
+impl IoView for ComponentState {
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl WasiView for ComponentState {
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+}
+
+#[cfg(feature = "wasm")]
+async fn run_component(
+    component: &Component,
+    mut wasi: WasiCtxBuilder,
+    limits: StoreLimits,
+) -> wasmtime::Result<i32> {
+    let engine = component.engine();
+    let mut linker = wasmtime::component::Linker::new(engine);
+    wasmtime_wasi::add_to_linker_async(&mut linker)?;
+    let state = ComponentState {
+        wasi: wasi.build(),
+        table: ResourceTable::new(),
+        limits,
+    };
+    let mut store = Store::new(engine, state);
+    store.limiter(|state| &mut state.limits);
+    store.epoch_deadline_async_yield_and_update(1);
+    let command = Command::instantiate_async(&mut store, component, &linker).await?;
+    match command.wasi_cli_run().call_run(&mut store).await? {
+        Ok(()) => Ok(0),
+        Err(()) => Ok(1),
+    }
+}
+
+// entrypoint のファイルが無ければその問題 (validate 用)
+pub fn missing_entrypoint(config: &McpProcessConfig) -> Option<String> {
+    let path = entrypoint(config).ok()?;
+    (!path.is_file()).then(|| format!("WASM entrypoint '{}' does not exist", path.display()))
+}
+
+#[cfg(all(test, feature = "wasm"))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    // 標準入力を 1 回ずつ読んでそのまま標準出力に書く (EOF で終わる) WASI モジュール
+    const CAT_MODULE: &str = r#"(module
+        (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (func (export "_start")
+            (loop $copy
+                (i32.store (i32.const 0) (i32.const 64))
+                (i32.store (i32.const 4) (i32.const 4096))
+                (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+                (br_if 1 (i32.eqz (i32.load (i32.const 8))))
+                (i32.store (i32.const 4) (i32.load (i32.const 8)))
+                (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 12)))
+                (br $copy))))"#;
+
+    fn guest_config(source: &str) -> McpProcessConfig {
+        let dir = std::env::temp_dir().join(format!("mcp-wasm-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("server.wat"), source).unwrap();
+        serde_json::from_value(json!({
+            "language": "wasm",
+            "cwd": dir,
+            "entrypoint": "server.wat",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn guest_speaks_over_stdin_and_stdout() {
+        let mut guest = start("cat", &guest_config(CAT_MODULE)).await.unwrap();
+        let line = "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"tools/list\"}\n";
+        guest.stdin.write_all(line.as_bytes()).await.unwrap();
+        let mut stdout = BufReader::new(guest.stdout);
+        let mut echoed = String::new();
+        stdout.read_line(&mut echoed).await.unwrap();
+        assert_eq!(echoed, line);
+
+        drop(guest.stdin);
+        assert_eq!(guest.exit.await.unwrap(), Ok(0));
+    }
+
+    #[tokio::test]
+    async fn exit_code_and_stderr_are_reported() {
+        let source = r#"(module
+            (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "no config\n")
+            (func (export "_start")
+                (i32.store (i32.const 0) (i32.const 16))
+                (i32.store (i32.const 4) (i32.const 10))
+                (drop (call $fd_write (i32.const 2) (i32.const 0) (i32.const 1) (i32.const 8)))
+                (call $proc_exit (i32.const 3))))"#;
+        let mut guest = start("failing", &guest_config(source)).await.unwrap();
+        let mut stderr = String::new();
+        guest.stderr.read_to_string(&mut stderr).await.unwrap();
+        assert_eq!(stderr, "no config\n");
+        assert_eq!(guest.exit.await.unwrap(), Ok(3));
+    }
+
+    #[tokio::test]
+    async fn busy_guest_can_be_aborted() {
+        let source = r#"(module (func (export "_start") (loop $spin (br $spin))))"#;
+        let guest = start("spin", &guest_config(source)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        guest.exit.abort();
+        let result = tokio::time::timeout(Duration::from_secs(5), guest.exit)
+            .await
+            .expect("the guest should stop when aborted");
+        assert!(result.unwrap_err().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn memory_limit_stops_the_guest() {
+        let mut config = guest_config(r#"(module (memory 32) (func (export "_start")))"#);
+        config.limits = serde_json::from_value(json!({ "memory_mb": 1 })).unwrap();
+        let guest = start("big", &config).await.unwrap();
+        let error = guest.exit.await.unwrap().unwrap_err();
+        assert!(error.contains("trapped"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn missing_entrypoint_is_reported() {
+        let config = guest_config(CAT_MODULE);
+        assert_eq!(missing_entrypoint(&config), None);
+        let config: McpProcessConfig = serde_json::from_value(json!({
+            "language": "wasm",
+            "cwd": "/nonexistent",
+            "entrypoint": "server.wasm",
+        }))
+        .unwrap();
+        assert!(missing_entrypoint(&config).is_some());
+        assert!(start("missing", &config).await.is_err());
+    }
+
+    #[test]
+    fn components_are_told_apart_from_modules() {
+        assert!(is_component(b"\0asm\x0d\0\x01\0"));
+        assert!(!is_component(b"\0asm\x01\0\0\0"));
+        assert!(is_component(b"  (component)"));
+        assert!(!is_component(CAT_MODULE.as_bytes()));
+    }
+}
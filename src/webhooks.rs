@@ -0,0 +1,424 @@
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{Value, json};
+use std::{
+    env,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    config_source,
+    events::{EventBus, LifecycleEventKind},
+    mcp_process::McpServer,
+};
+
+// 子プロセスからの通知のイベント名 (ライフサイクルイベントの名前は LifecycleEventKind::NAMES)
+const NOTIFICATION_EVENT: &str = "notification";
+// URL ごとに送信待ちとして保持するイベント数 (超えたら捨てる)
+const QUEUE_CAPACITY: usize = 1024;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+const EVENT_HEADER: &str = "x-webhook-event";
+// 再送しても変わらない配信ごとの ID (受信側の重複排除用)
+const DELIVERY_HEADER: &str = "x-webhook-delivery";
+const SIGNATURE_HEADER: &str = "x-webhook-signature-256";
+
+// --- 通知とライフサイクルイベントの Webhook 配信 ---
+// WEBHOOK_URLS (カンマ区切り) の各 URL に、子プロセスの通知と起動・終了・再起動などのイベントを
+// 1件ずつ JSON で POST する。失敗した場合は指数バックオフで WEBHOOK_MAX_RETRIES 回まで再送し、
+// URL ごとに発生順に配信する。WEBHOOK_SECRET を設定すると本文の HMAC-SHA256 で署名する
+pub struct WebhookConfig {
+    urls: Vec<String>,
+    secret: Option<String>,
+    // 配信するイベント名 (未設定ならすべて)
+    events: Option<Vec<String>>,
+    max_retries: u32,
+    timeout: Duration,
+}
+
+impl WebhookConfig {
+    // WEBHOOK_URLS が未設定なら None。URL やイベント名が不正な場合はエラー
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let urls: Vec<String> = env::var("WEBHOOK_URLS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_string)
+            .collect();
+        if urls.is_empty() {
+            return Ok(None);
+        }
+        for url in &urls {
+            let parsed = reqwest::Url::parse(url)
+                .map_err(|e| format!("Invalid WEBHOOK_URLS entry '{}': {}", url, e))?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                return Err(format!(
+                    "Invalid WEBHOOK_URLS entry '{}': only http:// and https:// are supported",
+                    url
+                ));
+            }
+        }
+        let events = match env::var("WEBHOOK_EVENTS") {
+            Ok(value) if !value.trim().is_empty() => {
+                let events: Vec<String> = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|event| !event.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                if let Some(unknown) = events.iter().find(|event| {
+                    *event != NOTIFICATION_EVENT
+                        && !LifecycleEventKind::NAMES.contains(&event.as_str())
+                }) {
+                    return Err(format!(
+                        "Unknown WEBHOOK_EVENTS entry '{}' (expected '{}' or one of: {})",
+                        unknown,
+                        NOTIFICATION_EVENT,
+                        LifecycleEventKind::NAMES.join(", ")
+                    ));
+                }
+                Some(events)
+            }
+            _ => None,
+        };
+        let env_u64 = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        Ok(Some(WebhookConfig {
+            urls,
+            secret: env::var("WEBHOOK_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+            events,
+            max_retries: env_u64("WEBHOOK_MAX_RETRIES", 5) as u32,
+            timeout: Duration::from_secs(env_u64("WEBHOOK_TIMEOUT_SECS", 10).max(1)),
+        }))
+    }
+
+    fn wants(&self, event: &str) -> bool {
+        self.events
+            .as_ref()
+            .is_none_or(|events| events.iter().any(|wanted| wanted == event))
+    }
+}
+
+// 1件の配信 (イベント名と、署名する本文)
+struct Delivery {
+    event: String,
+    body: Arc<String>,
+}
+
+// URL ごとの送信待ちの列
+#[derive(Clone)]
+struct Dispatcher {
+    config: Arc<WebhookConfig>,
+    queues: Arc<Vec<(String, mpsc::Sender<Delivery>)>>,
+}
+
+impl Dispatcher {
+    fn enqueue(&self, event: &str, payload: &Value) {
+        if !self.config.wants(event) {
+            return;
+        }
+        let body = Arc::new(payload.to_string());
+        for (url, queue) in self.queues.iter() {
+            let delivery = Delivery {
+                event: event.to_string(),
+                body: Arc::clone(&body),
+            };
+            if queue.try_send(delivery).is_err() {
+                warn!(url = %url, event, "Webhook queue is full, dropping event");
+            }
+        }
+    }
+}
+
+// 購読を開始し、URL ごとの配信タスクを起動する。
+// 起動時のイベントも送れるよう、サーバーを起動する前に呼ぶ
+pub fn spawn(config: WebhookConfig, events: &EventBus, servers: &[Arc<McpServer>]) {
+    info!(
+        urls = config.urls.len(),
+        events = ?config.events,
+        signed = config.secret.is_some(),
+        "Webhook delivery enabled"
+    );
+    let config = Arc::new(config);
+    let client = reqwest::Client::new();
+    let queues = config
+        .urls
+        .iter()
+        .map(|url| {
+            let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+            tokio::spawn(run_worker(
+                client.clone(),
+                Arc::clone(&config),
+                url.clone(),
+                receiver,
+            ));
+            (url.clone(), sender)
+        })
+        .collect();
+    let dispatcher = Dispatcher {
+        config,
+        queues: Arc::new(queues),
+    };
+
+    let mut receiver = events.subscribe();
+    let lifecycle = dispatcher.clone();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let payload = serde_json::to_value(&event).unwrap_or_default();
+                    lifecycle.enqueue(event.kind.name(), &payload);
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "Webhook delivery lagged, lifecycle events dropped");
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    });
+
+    // 集約サーバーのメンバーの通知は、メンバーの名前で送る
+    let mut watched: Vec<&Arc<McpServer>> = Vec::new();
+    let mut pending: Vec<&Arc<McpServer>> = servers.iter().collect();
+    while let Some(server) = pending.pop() {
+        pending.extend(server.members());
+        watched.push(server);
+    }
+    for server in watched {
+        let mut receiver = server.notifications.subscribe();
+        let server_key = server.server_key.clone();
+        let notifications = dispatcher.clone();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(message) => {
+                        let payload = json!({
+                            "server": server_key,
+                            "timestamp_ms": now_ms(),
+                            "event": NOTIFICATION_EVENT,
+                            "message": message,
+                        });
+                        notifications.enqueue(NOTIFICATION_EVENT, &payload);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(server = %server_key, skipped, "Webhook delivery lagged, notifications dropped");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+}
+
+// 1つの URL への配信を発生順に行う (再送中は後続のイベントを待たせる)
+async fn run_worker(
+    client: reqwest::Client,
+    config: Arc<WebhookConfig>,
+    url: String,
+    mut receiver: mpsc::Receiver<Delivery>,
+) {
+    while let Some(delivery) = receiver.recv().await {
+        deliver(&client, &config, &url, &delivery).await;
+    }
+}
+
+async fn deliver(client: &reqwest::Client, config: &WebhookConfig, url: &str, delivery: &Delivery) {
+    let delivery_id = uuid::Uuid::new_v4().to_string();
+    let signature = config
+        .secret
+        .as_ref()
+        .map(|secret| signature(secret, &delivery.body));
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        let mut request = client
+            .post(url)
+            .timeout(config.timeout)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &delivery.event)
+            .header(DELIVERY_HEADER, &delivery_id)
+            .body(delivery.body.to_string());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                debug!(url = %url, event = %delivery.event, "Delivered webhook");
+                return;
+            }
+            // 受信側が拒否した (再送しても結果が変わらない) 場合は諦める
+            Ok(response) if !is_retryable(response.status()) => {
+                warn!(url = %url, event = %delivery.event, status = %response.status(), "Webhook rejected, not retrying");
+                return;
+            }
+            Ok(response) => format!("HTTP {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt >= config.max_retries {
+            error!(url = %url, event = %delivery.event, attempts = attempt + 1, error = %error, "Webhook delivery failed, giving up");
+            return;
+        }
+        attempt += 1;
+        warn!(
+            url = %url,
+            event = %delivery.event,
+            error = %error,
+            retry_in_secs = backoff.as_secs(),
+            "Webhook delivery failed, retrying"
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+// X-Webhook-Signature-256 の値 (GitHub の Webhook と同じ "sha256=<16進数>")
+fn signature(secret: &str, body: &str) -> String {
+    let mac = config_source::hmac_sha256(secret.as_bytes(), body.as_bytes());
+    let hex: String = mac.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", hex)
+}
+
+// 5xx と 408 / 429 は一時的な失敗として再送する
+fn is_retryable(status: reqwest::StatusCode) -> bool {
+    status.is_server_error()
+        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+        || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, http::HeaderMap, routing::post};
+    use std::sync::Mutex;
+
+    fn config(secret: Option<&str>, max_retries: u32) -> WebhookConfig {
+        WebhookConfig {
+            urls: Vec::new(),
+            secret: secret.map(str::to_string),
+            events: None,
+            max_retries,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn signature_is_hmac_sha256_of_the_body() {
+        // RFC 4231 のテストケース 2 と 6 (ブロック長より長い鍵)
+        assert_eq!(
+            signature("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        let mac = config_source::hmac_sha256(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+        );
+        let hex: String = mac.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(
+            hex,
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+        assert_ne!(signature("secret", "a"), signature("secret", "b"));
+    }
+
+    #[test]
+    fn event_filter_and_retryable_statuses() {
+        let mut filtered = config(None, 0);
+        assert!(filtered.wants("server_started"));
+        filtered.events = Some(vec![NOTIFICATION_EVENT.to_string()]);
+        assert!(filtered.wants(NOTIFICATION_EVENT));
+        assert!(!filtered.wants("server_started"));
+
+        for status in [500, 503, 408, 429] {
+            assert!(is_retryable(reqwest::StatusCode::from_u16(status).unwrap()));
+        }
+        for status in [400, 401, 404, 410] {
+            assert!(!is_retryable(
+                reqwest::StatusCode::from_u16(status).unwrap()
+            ));
+        }
+    }
+
+    // 受け取ったリクエストのヘッダーと本文を記録し、statuses の順に応答する
+    async fn receiver(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<(HeaderMap, String)>>>) {
+        let received: Arc<Mutex<Vec<(HeaderMap, String)>>> = Arc::default();
+        let log = Arc::clone(&received);
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: String| {
+                let log = Arc::clone(&log);
+                let statuses = statuses.clone();
+                async move {
+                    let mut log = log.lock().unwrap();
+                    let status = statuses.get(log.len()).copied().unwrap_or(200);
+                    log.push((headers, body));
+                    axum::http::StatusCode::from_u16(status).unwrap()
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, received)
+    }
+
+    fn delivery(body: &str) -> Delivery {
+        Delivery {
+            event: "server_started".to_string(),
+            body: Arc::new(body.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_keep_the_delivery_id_and_signature() {
+        let (url, received) = receiver(vec![503]).await;
+        let body = r#"{"event":"server_started","server":"mock"}"#;
+        deliver(
+            &reqwest::Client::new(),
+            &config(Some("secret"), 2),
+            &url,
+            &delivery(body),
+        )
+        .await;
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (first, second) = (&received[0].0, &received[1].0);
+        assert_eq!(first[SIGNATURE_HEADER], signature("secret", body).as_str());
+        assert_eq!(first[EVENT_HEADER], "server_started");
+        assert_eq!(first[DELIVERY_HEADER], second[DELIVERY_HEADER]);
+        assert_eq!(first[SIGNATURE_HEADER], second[SIGNATURE_HEADER]);
+        assert_eq!(received[1].1, body);
+    }
+
+    #[tokio::test]
+    async fn rejected_deliveries_are_not_retried_or_signed_without_a_secret() {
+        let (url, received) = receiver(vec![400]).await;
+        deliver(
+            &reqwest::Client::new(),
+            &config(None, 5),
+            &url,
+            &delivery("{}"),
+        )
+        .await;
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(!received[0].0.contains_key(SIGNATURE_HEADER));
+    }
+}
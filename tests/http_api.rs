@@ -0,0 +1,619 @@
+// --- HTTP 層の結合テスト ---
+// type: "mock" のサーバーを設定したバイナリを空いているポートで起動し、実際に HTTP で呼び出す
+use serde_json::{Value, json};
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+// 起動を待つ上限 (MCP サーバーの initialize が済むとポートを開く)
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(20);
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+struct Bridge {
+    child: Child,
+    base_url: String,
+    dir: PathBuf,
+    client: reqwest::Client,
+}
+
+impl Bridge {
+    // config はサーバー名 → 設定。tenants を渡すと API_KEYS_FILE として読ませる
+    async fn start(config: Value, tenants: Option<Value>, env: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "mcp-http-server-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("servers.json");
+        std::fs::write(&config_path, config.to_string()).unwrap();
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut command = Command::new(env!("CARGO_BIN_EXE_mcp-http-server"));
+        // 開発環境の .env や環境変数を読まないよう、一時ディレクトリで環境変数を空にして起動する
+        command
+            .current_dir(&dir)
+            .env_clear()
+            .env("PORT", port.to_string())
+            .env("MCP_CONFIG_FILE", &config_path)
+            // 設定したすべてのサーバーを起動する ("default": true のサーバーがパスの無いルートを受け付ける)
+            .env("MCP_SERVER_NAME", "all")
+            .env("RUST_LOG", "warn")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        if let Some(tenants) = tenants {
+            let keys_path = dir.join("api-keys.json");
+            std::fs::write(&keys_path, tenants.to_string()).unwrap();
+            command.env("API_KEYS_FILE", keys_path);
+        }
+        for (name, value) in env {
+            command.env(name, value);
+        }
+        let bridge = Bridge {
+            child: command.spawn().unwrap(),
+            base_url: format!("http://127.0.0.1:{}", port),
+            dir,
+            client: reqwest::Client::new(),
+        };
+
+        let started = Instant::now();
+        // 認証の有無に関わらず、何か応答が返れば受け付けを始めている
+        while bridge
+            .client
+            .get(bridge.url("/healthz"))
+            .send()
+            .await
+            .is_err()
+        {
+            assert!(
+                started.elapsed() < STARTUP_TIMEOUT,
+                "mcp-http-server did not start listening"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        bridge
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn post(&self, path: &str, token: Option<&str>, body: Value) -> (u16, Value) {
+        let mut request = self.client.post(self.url(path)).json(&body);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        response_json(request.send().await.unwrap()).await
+    }
+
+    async fn get(&self, path: &str, token: Option<&str>) -> (u16, Value) {
+        let mut request = self.client.get(self.url(path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        response_json(request.send().await.unwrap()).await
+    }
+
+    // POST /api/v1/rpc で JSON-RPC メッセージをそのまま送る
+    async fn rpc(&self, message: Value) -> (u16, Value) {
+        self.post("/api/v1/rpc", None, message).await
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+// 空のボディ (202 など) は Null にする
+async fn response_json(response: reqwest::Response) -> (u16, Value) {
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap();
+    let value = match body.is_empty() {
+        true => Value::Null,
+        false => serde_json::from_str(&body).unwrap_or(Value::String(body)),
+    };
+    (status, value)
+}
+
+fn mock_config() -> Value {
+    json!({ "m": { "type": "mock", "max_concurrent_requests": 4 } })
+}
+
+fn tool_call(id: Value, name: &str, arguments: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": "tools/call",
+        "params": { "name": name, "arguments": arguments },
+    })
+}
+
+fn first_text(response: &Value) -> &str {
+    response["result"]["content"][0]["text"]
+        .as_str()
+        .unwrap_or_default()
+}
+
+fn tool_names(tools: &Value) -> Vec<&str> {
+    tools
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|tool| tool["name"].as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn initialize_list_and_call_tools() {
+    let bridge = Bridge::start(mock_config(), None, &[("DISABLE_AUTH", "true")]).await;
+
+    let (status, initialized) = bridge
+        .rpc(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": { "name": "http-api-test", "version": "1.0.0" },
+            },
+        }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(initialized["id"], 1);
+    assert_eq!(initialized["result"]["serverInfo"]["name"], "m");
+
+    // /api/v1 は command に文字列化した JSON-RPC メッセージを受け取り、result に文字列で返す
+    let (status, listed) = bridge
+        .post(
+            "/api/v1",
+            None,
+            json!({ "command": json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }).to_string() }),
+        )
+        .await;
+    assert_eq!(status, 200);
+    let listed: Value = serde_json::from_str(listed["result"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        tool_names(&listed["result"]["tools"]),
+        ["echo", "add", "sleep", "fail"]
+    );
+
+    let (status, called) = bridge
+        .rpc(tool_call(json!(3), "add", json!({ "a": 1, "b": 2 })))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(called["id"], 3);
+    assert_eq!(first_text(&called), "3");
+
+    let (status, tools) = bridge.get("/api/v1/tools", None).await;
+    assert_eq!(status, 200);
+    assert!(tool_names(&tools).contains(&"echo"));
+    let (status, content) = bridge
+        .post("/api/v1/tools/echo", None, json!({ "message": "hello" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(content, json!([{ "type": "text", "text": "hello" }]));
+}
+
+#[tokio::test]
+async fn concurrent_requests_with_the_same_id_get_their_own_responses() {
+    let bridge = Bridge::start(mock_config(), None, &[("DISABLE_AUTH", "true")]).await;
+
+    // 同じ id の2つのリクエストを同時に送り、後から送った echo が先に返る
+    let slow = bridge.rpc(tool_call(json!(1), "sleep", json!({ "ms": 300 })));
+    let fast = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        bridge
+            .rpc(tool_call(json!(1), "echo", json!({ "message": "fast" })))
+            .await
+    };
+    let ((slow_status, slow), (fast_status, fast)) = tokio::join!(slow, fast);
+    assert_eq!((slow_status, fast_status), (200, 200));
+    assert_eq!(slow["id"], 1);
+    assert_eq!(first_text(&slow), "Slept 300 ms");
+    assert_eq!(fast["id"], 1);
+    assert_eq!(first_text(&fast), "fast");
+
+    // バッチ内の id もクライアントが送った値のまま返る
+    let (status, batch) = bridge
+        .rpc(json!([
+            tool_call(json!("a"), "echo", json!({ "message": "first" })),
+            tool_call(json!(7), "add", json!({ "a": 2, "b": 5 })),
+        ]))
+        .await;
+    assert_eq!(status, 200);
+    let batch = batch.as_array().unwrap();
+    let by_id = |id: Value| {
+        batch
+            .iter()
+            .find(|response| response["id"] == id)
+            .map(first_text)
+    };
+    assert_eq!(by_id(json!("a")), Some("first"));
+    assert_eq!(by_id(json!(7)), Some("7"));
+}
+
+#[tokio::test]
+async fn requests_with_a_null_id_get_a_reply_with_a_null_id() {
+    let bridge = Bridge::start(mock_config(), None, &[("DISABLE_AUTH", "true")]).await;
+
+    let (status, called) = bridge
+        .rpc(tool_call(
+            Value::Null,
+            "echo",
+            json!({ "message": "null id" }),
+        ))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(called["id"], Value::Null);
+    assert_eq!(first_text(&called), "null id");
+
+    let (status, batch) = bridge
+        .rpc(json!([
+            tool_call(Value::Null, "add", json!({ "a": 1, "b": 1 })),
+            tool_call(json!(2), "echo", json!({ "message": "two" })),
+        ]))
+        .await;
+    assert_eq!(status, 200);
+    let batch = batch.as_array().unwrap();
+    assert_eq!(batch.len(), 2);
+    let null_reply = batch.iter().find(|reply| reply["id"].is_null()).unwrap();
+    assert_eq!(first_text(null_reply), "2");
+
+    let (status, results) = bridge
+        .post(
+            "/api/v1/batch",
+            None,
+            json!({ "commands": [tool_call(Value::Null, "echo", json!({ "message": "batched" })).to_string()] }),
+        )
+        .await;
+    assert_eq!(status, 200);
+    let reply: Value =
+        serde_json::from_str(results["results"][0]["result"].as_str().unwrap()).unwrap();
+    assert_eq!(reply["id"], Value::Null);
+    assert_eq!(first_text(&reply), "batched");
+}
+
+#[tokio::test]
+async fn malformed_json_rpc_is_rejected_with_400() {
+    let bridge = Bridge::start(mock_config(), None, &[("DISABLE_AUTH", "true")]).await;
+
+    let (status, error) = bridge
+        .post("/api/v1", None, json!({ "command": "{not json" }))
+        .await;
+    assert_eq!(status, 400);
+    assert!(
+        error["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("command is not valid JSON")
+    );
+
+    let (status, error) = bridge.rpc(json!({ "jsonrpc": "2.0", "id": 1 })).await;
+    assert_eq!(status, 400);
+    assert_eq!(error["message"], "\"method\" is required");
+
+    let (status, error) = bridge.rpc(json!([])).await;
+    assert_eq!(status, 400);
+    assert_eq!(error["message"], "command is an empty JSON-RPC batch");
+
+    // 引数は inputSchema で検証してから転送する
+    let (status, error) = bridge.rpc(tool_call(json!(1), "echo", json!({}))).await;
+    assert_eq!(status, 400);
+    assert!(error["violations"].is_array());
+
+    // 通知には応答が無い
+    let (status, body) = bridge
+        .rpc(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+        .await;
+    assert_eq!(status, 202);
+    assert_eq!(body, Value::Null);
+}
+
+#[tokio::test]
+async fn tool_policy_filters_lists_and_rejects_calls() {
+    let config = json!({
+        "m": { "type": "mock", "allowed_tools": ["echo", "add", "fail"], "blocked_tools": ["fail"] }
+    });
+    let bridge = Bridge::start(config, None, &[("DISABLE_AUTH", "true")]).await;
+
+    let (status, listed) = bridge
+        .rpc(json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(tool_names(&listed["result"]["tools"]), ["echo", "add"]);
+    let (_, tools) = bridge.get("/api/v1/tools", None).await;
+    assert_eq!(tool_names(&tools), ["echo", "add"]);
+
+    // blocked_tools と allowed_tools 以外のツールは子プロセスに届かない
+    for tool in ["fail", "sleep"] {
+        let (status, error) = bridge.rpc(tool_call(json!(2), tool, json!({}))).await;
+        assert_eq!(status, 403, "{}", tool);
+        assert_eq!(error["code"], -32001);
+    }
+    let (status, _) = bridge.post("/api/v1/tools/fail", None, json!({})).await;
+    assert_eq!(status, 403);
+
+    // バッチは許可されたものだけを転送し、拒否したもののエラーを元の順序で差し込む
+    let (status, batch) = bridge
+        .rpc(json!([
+            tool_call(json!(1), "echo", json!({ "message": "allowed" })),
+            tool_call(json!(2), "fail", json!({})),
+            tool_call(json!(3), "add", json!({ "a": 1, "b": 1 })),
+        ]))
+        .await;
+    assert_eq!(status, 200);
+    let batch = batch.as_array().unwrap();
+    let ids: Vec<&Value> = batch.iter().map(|response| &response["id"]).collect();
+    assert_eq!(ids, [&json!(1), &json!(2), &json!(3)]);
+    assert_eq!(first_text(&batch[0]), "allowed");
+    assert_eq!(batch[1]["error"]["code"], -32001);
+    assert_eq!(first_text(&batch[2]), "2");
+}
+
+#[tokio::test]
+async fn tenant_keys_reach_only_their_servers() {
+    let config = json!({
+        "a": { "type": "mock", "default": true },
+        "b": { "type": "mock" },
+    });
+    let tenants = json!({
+        "tenant-b": { "key": "tenant-b-key", "servers": ["b"] },
+    });
+    let bridge = Bridge::start(config, Some(tenants), &[("HTTP_API_KEY", "admin-key")]).await;
+
+    let (status, _) = bridge
+        .get("/servers/a/api/v1/info", Some("tenant-b-key"))
+        .await;
+    assert_eq!(status, 403);
+    let (status, info) = bridge
+        .get("/servers/b/api/v1/info", Some("tenant-b-key"))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(info["server"], "b");
+
+    // サーバー名の無いパスはテナントの default_server (servers の先頭) に振り分ける
+    let (status, info) = bridge.get("/api/v1/info", Some("tenant-b-key")).await;
+    assert_eq!(status, 200);
+    assert_eq!(info["server"], "b");
+    let (_, info) = bridge.get("/api/v1/info", Some("admin-key")).await;
+    assert_eq!(info["server"], "a");
+
+    let (status, _) = bridge.get("/api/v1/info", Some("wrong-key")).await;
+    assert_eq!(status, 401);
+    let (status, _) = bridge.get("/api/v1/info", None).await;
+    assert_eq!(status, 401);
+}
+
+#[tokio::test]
+async fn tenant_tools_restrict_every_call_path() {
+    let tenants = json!({
+        "tenant-a": { "key": "tenant-a-key", "servers": ["m"], "tools": { "deny": ["fail"] } },
+        "tenant-b": { "key": "tenant-b-key", "servers": ["m"], "tools": { "allow": ["echo"] } },
+    });
+    let bridge = Bridge::start(
+        mock_config(),
+        Some(tenants),
+        &[("HTTP_API_KEY", "admin-key")],
+    )
+    .await;
+
+    // tools/list と /api/v1/tools はテナントが呼び出せるツールだけを返す
+    let list = json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" });
+    let (_, listed) = bridge
+        .post("/api/v1/rpc", Some("tenant-a-key"), list.clone())
+        .await;
+    assert_eq!(
+        tool_names(&listed["result"]["tools"]),
+        ["echo", "add", "sleep"]
+    );
+    let (_, tools) = bridge.get("/api/v1/tools", Some("tenant-b-key")).await;
+    assert_eq!(tool_names(&tools), ["echo"]);
+    let (_, listed) = bridge.post("/api/v1/rpc", Some("admin-key"), list).await;
+    assert_eq!(
+        tool_names(&listed["result"]["tools"]),
+        ["echo", "add", "sleep", "fail"]
+    );
+
+    // JSON-RPC / REST のどちらの tools/call も子プロセスに届かない
+    let (status, error) = bridge
+        .post(
+            "/api/v1/rpc",
+            Some("tenant-a-key"),
+            tool_call(json!(2), "fail", json!({})),
+        )
+        .await;
+    assert_eq!(status, 403);
+    assert_eq!(error["code"], -32001);
+    let (status, _) = bridge
+        .post(
+            "/api/v1/tools/add",
+            Some("tenant-b-key"),
+            json!({ "a": 1, "b": 2 }),
+        )
+        .await;
+    assert_eq!(status, 403);
+    let (status, called) = bridge
+        .post(
+            "/api/v1/rpc",
+            Some("admin-key"),
+            tool_call(json!(3), "add", json!({ "a": 1, "b": 2 })),
+        )
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(first_text(&called), "3");
+
+    // JSON-RPC バッチは許可されたものだけを転送する
+    let (status, batch) = bridge
+        .post(
+            "/api/v1/rpc",
+            Some("tenant-b-key"),
+            json!([
+                tool_call(json!(1), "echo", json!({ "message": "allowed" })),
+                tool_call(json!(2), "add", json!({ "a": 1, "b": 1 })),
+            ]),
+        )
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(first_text(&batch[0]), "allowed");
+    assert_eq!(batch[1]["error"]["code"], -32001);
+
+    // /api/v1/batch では拒否したコマンドの結果だけがエラーになる
+    let commands = [
+        tool_call(json!(1), "echo", json!({ "message": "allowed" })).to_string(),
+        tool_call(json!(2), "fail", json!({})).to_string(),
+    ];
+    let (status, batch) = bridge
+        .post(
+            "/api/v1/batch",
+            Some("tenant-a-key"),
+            json!({ "commands": commands }),
+        )
+        .await;
+    assert_eq!(status, 200);
+    let results: Vec<Value> = batch["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| serde_json::from_str(result["result"].as_str().unwrap()).unwrap())
+        .collect();
+    assert_eq!(first_text(&results[0]), "allowed");
+    assert_eq!(results[1]["error"]["code"], -32001);
+
+    // GraphQL の tools と callTool も同じ
+    let (status, graphql) = bridge
+        .post(
+            "/graphql",
+            Some("tenant-b-key"),
+            json!({ "query": "{ tools { name } }" }),
+        )
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(graphql["data"]["tools"], json!([{ "name": "echo" }]));
+    let (_, graphql) = bridge
+        .post(
+            "/graphql",
+            Some("tenant-b-key"),
+            json!({ "query": r#"mutation { callTool(name: "add", arguments: {a: 1, b: 2}) { isError } }"# }),
+        )
+        .await;
+    assert_eq!(graphql["errors"][0]["extensions"]["code"], "FORBIDDEN");
+}
+
+#[tokio::test]
+async fn stats_cover_every_server() {
+    let config = json!({
+        "a": { "type": "mock", "default": true },
+        "b": { "type": "mock" },
+    });
+    let tenants = json!({
+        "tenant-b": { "key": "tenant-b-key", "servers": ["b"] },
+    });
+    let bridge = Bridge::start(config, Some(tenants), &[("HTTP_API_KEY", "admin-key")]).await;
+
+    for path in ["/stats", "/servers/b/stats"] {
+        let (status, stats) = bridge.get(path, Some("admin-key")).await;
+        assert_eq!(status, 200);
+        let mut servers: Vec<&String> = stats.as_object().unwrap().keys().collect();
+        servers.sort();
+        assert_eq!(servers, ["a", "b"], "{}", path);
+    }
+    // mock サーバーには PID が無いが、起動中かどうかはプロセスの有無で判定する
+    let (_, stats) = bridge.get("/stats", Some("admin-key")).await;
+    assert_eq!(stats["a"]["pid"], Value::Null);
+    assert_eq!(stats["a"]["running"], true);
+    let (status, _) = bridge
+        .post("/admin/servers/a/stop", Some("admin-key"), json!({}))
+        .await;
+    assert_eq!(status, 200);
+    let (_, stats) = bridge.get("/stats", Some("admin-key")).await;
+    assert_eq!(stats["a"]["running"], false);
+    assert_eq!(stats["b"]["running"], true);
+
+    let (_, stats) = bridge.get("/stats", Some("tenant-b-key")).await;
+    assert_eq!(stats.as_object().unwrap().len(), 1);
+    assert_eq!(stats["b"]["server"], "b");
+
+    let (status, metrics) = bridge.get("/metrics", Some("admin-key")).await;
+    assert_eq!(status, 200);
+    let metrics = metrics.as_str().unwrap();
+    assert!(metrics.contains("mcp_requests_total{server=\"a\"}"));
+    assert!(metrics.contains("mcp_requests_total{server=\"b\"}"));
+}
+
+#[tokio::test]
+async fn admin_update_reruns_post_install() {
+    let work_dir = std::env::temp_dir().join(format!(
+        "mcp-http-server-test-update-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&work_dir).unwrap();
+    let config = json!({
+        // lazy のため子プロセスは起動しない
+        "a": {
+            "command": "true",
+            "lazy": true,
+            "default": true,
+            "cwd": work_dir,
+            "post_install": [["sh", "-c", "echo installed >> install.log"]],
+        },
+        "b": { "type": "mock" },
+    });
+    let bridge = Bridge::start(config, None, &[("HTTP_API_KEY", "admin-key")]).await;
+
+    let (status, updated) = bridge
+        .post("/admin/servers/a/update", Some("admin-key"), json!({}))
+        .await;
+    assert_eq!(status, 200, "{}", updated);
+    assert_eq!(updated["action"], "update");
+    let log = std::fs::read_to_string(work_dir.join("install.log")).unwrap();
+    assert_eq!(log, "installed\n");
+
+    // git の作業ディレクトリも post_install も無いサーバーは更新できない
+    let (status, _) = bridge
+        .post("/admin/servers/b/update", Some("admin-key"), json!({}))
+        .await;
+    assert_eq!(status, 409);
+    let _ = std::fs::remove_dir_all(&work_dir);
+}
+
+#[tokio::test]
+async fn rewrite_rules_apply_before_validation() {
+    // add の inputSchema は b を必須とするため、書き換えの前に検証すると 400 になる
+    let config = json!({
+        "m": {
+            "type": "mock",
+            "rewrite": [{ "match": { "tool": "add" }, "set": { "/params/arguments/b": 10 } }],
+        }
+    });
+    let bridge = Bridge::start(config, None, &[("DISABLE_AUTH", "true")]).await;
+
+    let (status, called) = bridge
+        .rpc(tool_call(json!(1), "add", json!({ "a": 1 })))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(first_text(&called), "11");
+
+    let (status, content) = bridge
+        .post("/api/v1/tools/add", None, json!({ "a": 5 }))
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(content[0]["text"], "15");
+
+    // 一致しないツールは書き換えない
+    let (status, _) = bridge.rpc(tool_call(json!(2), "echo", json!({}))).await;
+    assert_eq!(status, 400);
+}